@@ -1,103 +1,21 @@
-/// Advanced patterns and best practices for the content resolution system
-///
-/// This example demonstrates:
-/// - Custom content source implementation
-/// - Advanced error handling and retry logic
-/// - Content validation and transformation
-/// - Metrics and observability
-/// - Production deployment patterns
+//! Advanced patterns and best practices for the content resolution system
+//!
+//! This example demonstrates:
+//! - Custom content source implementation
+//! - Advanced error handling and retry logic
+//! - Content validation and transformation
+//! - Metrics and observability
+//! - Production deployment patterns
+//! - Recording GitHub traffic and replaying it offline
 
 use content_resolver::{
-    Cache, ContentError, ContentSource, DirectoryListing, FileContent, MemoryCache,
-    ResourceResolver,
+    ContentError, ContentSource, DirectoryListing, FileContent, LocalFileSource, MemoryCache,
+    RecordingSource, ReplaySource, ResourceResolver, RetryConfig,
 };
 use async_trait::async_trait;
 use bytes::Bytes;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
-
-// ============================================================================
-// Custom Content Source: Local Filesystem
-// ============================================================================
-
-/// A content source that reads from the local filesystem
-/// This is useful for development or for reading local configuration overrides
-pub struct LocalFileSource {
-    root_path: std::path::PathBuf,
-}
-
-impl LocalFileSource {
-    pub fn new(root_path: std::path::PathBuf) -> Self {
-        Self { root_path }
-    }
-
-    fn resolve_path(&self, path: &str) -> std::path::PathBuf {
-        self.root_path.join(path.trim_start_matches('/'))
-    }
-}
-
-#[async_trait]
-impl ContentSource for LocalFileSource {
-    async fn fetch_file(&self, path: &str) -> content_resolver::Result<FileContent> {
-        let full_path = self.resolve_path(path);
-
-        let content = tokio::fs::read(&full_path).await.map_err(|e| {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                ContentError::NotFound {
-                    path: path.to_string(),
-                }
-            } else {
-                ContentError::Io(e)
-            }
-        })?;
-
-        Ok(FileContent {
-            content: Bytes::from(content),
-            source_path: full_path.to_string_lossy().to_string(),
-            etag: None,
-        })
-    }
-
-    async fn list_directory(&self, path: &str) -> content_resolver::Result<DirectoryListing> {
-        let full_path = self.resolve_path(path);
-
-        let mut entries = Vec::new();
-        let mut read_dir = tokio::fs::read_dir(&full_path).await.map_err(|e| {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                ContentError::NotFound {
-                    path: path.to_string(),
-                }
-            } else {
-                ContentError::Io(e)
-            }
-        })?;
-
-        while let Some(entry) = read_dir.next_entry().await? {
-            let metadata = entry.metadata().await?;
-            let name = entry.file_name().to_string_lossy().to_string();
-            let entry_path = format!("{}/{}", path.trim_end_matches('/'), name);
-
-            entries.push(content_resolver::DirectoryEntry {
-                name,
-                path: entry_path,
-                entry_type: if metadata.is_dir() {
-                    content_resolver::EntryType::Dir
-                } else {
-                    content_resolver::EntryType::File
-                },
-            });
-        }
-
-        Ok(DirectoryListing {
-            path: path.to_string(),
-            entries,
-        })
-    }
-
-    fn identifier(&self) -> String {
-        format!("local://{}", self.root_path.display())
-    }
-}
+use std::time::Duration;
 
 // ============================================================================
 // Metrics and Observability
@@ -176,25 +94,6 @@ impl ContentSource for InstrumentedSource {
 // Advanced Retry Logic
 // ============================================================================
 
-/// Retry configuration
-pub struct RetryConfig {
-    pub max_attempts: u32,
-    pub initial_delay: Duration,
-    pub max_delay: Duration,
-    pub backoff_factor: f64,
-}
-
-impl Default for RetryConfig {
-    fn default() -> Self {
-        Self {
-            max_attempts: 3,
-            initial_delay: Duration::from_millis(100),
-            max_delay: Duration::from_secs(10),
-            backoff_factor: 2.0,
-        }
-    }
-}
-
 /// Fetch with exponential backoff retry
 pub async fn fetch_with_retry(
     resolver: &ResourceResolver,
@@ -209,9 +108,7 @@ pub async fn fetch_with_retry(
 
         match resolver.fetch_file(path).await {
             Ok(content) => return Ok(content),
-            Err(ContentError::Network(_)) | Err(ContentError::RateLimited { .. })
-                if attempts < config.max_attempts =>
-            {
+            Err(e) if attempts < config.max_attempts && config.should_retry(&e) => {
                 println!(
                     "Attempt {}/{} failed, retrying in {:?}...",
                     attempts, config.max_attempts, delay
@@ -236,17 +133,25 @@ pub async fn fetch_with_retry(
 
 /// Validator for content
 pub trait ContentValidator: Send + Sync {
-    fn validate(&self, content: &[u8]) -> Result<(), String>;
+    fn validate(&self, path: &str, content: &[u8]) -> content_resolver::Result<()>;
+}
+
+fn validation_error(path: &str, message: String) -> ContentError {
+    ContentError::Validation {
+        path: path.to_string(),
+        message,
+        pointers: Vec::new(),
+    }
 }
 
 /// Validate that content is valid UTF-8
 pub struct Utf8Validator;
 
 impl ContentValidator for Utf8Validator {
-    fn validate(&self, content: &[u8]) -> Result<(), String> {
+    fn validate(&self, path: &str, content: &[u8]) -> content_resolver::Result<()> {
         std::str::from_utf8(content)
             .map(|_| ())
-            .map_err(|e| format!("Invalid UTF-8: {}", e))
+            .map_err(|e| validation_error(path, format!("Invalid UTF-8: {}", e)))
     }
 }
 
@@ -254,10 +159,10 @@ impl ContentValidator for Utf8Validator {
 pub struct JsonValidator;
 
 impl ContentValidator for JsonValidator {
-    fn validate(&self, content: &[u8]) -> Result<(), String> {
+    fn validate(&self, path: &str, content: &[u8]) -> content_resolver::Result<()> {
         serde_json::from_slice::<serde_json::Value>(content)
             .map(|_| ())
-            .map_err(|e| format!("Invalid JSON: {}", e))
+            .map_err(|e| validation_error(path, format!("Invalid JSON: {}", e)))
     }
 }
 
@@ -273,13 +178,13 @@ impl SizeValidator {
 }
 
 impl ContentValidator for SizeValidator {
-    fn validate(&self, content: &[u8]) -> Result<(), String> {
+    fn validate(&self, path: &str, content: &[u8]) -> content_resolver::Result<()> {
         if content.len() > self.max_size {
-            Err(format!(
-                "Content too large: {} bytes (max: {})",
-                content.len(),
-                self.max_size
-            ))
+            Err(ContentError::TooLarge {
+                path: path.to_string(),
+                size: Some(content.len() as u64),
+                limit: self.max_size as u64,
+            })
         } else {
             Ok(())
         }
@@ -295,11 +200,7 @@ pub async fn fetch_and_validate(
     let content = resolver.fetch_file(path).await?;
 
     for validator in validators {
-        validator.validate(&content.content).map_err(|msg| {
-            ContentError::InvalidStructure {
-                message: format!("Validation failed for {}: {}", path, msg),
-            }
-        })?;
+        validator.validate(path, &content.content)?;
     }
 
     Ok(content)
@@ -383,7 +284,8 @@ impl ProductionContentSystem {
         path: &str,
         max_size: usize,
     ) -> content_resolver::Result<FileContent> {
-        let validators: Vec<&dyn ContentValidator> = vec![&SizeValidator::new(max_size)];
+        let size_validator = SizeValidator::new(max_size);
+        let validators: Vec<&dyn ContentValidator> = vec![&size_validator];
 
         fetch_with_retry(
             &self.resolver,
@@ -393,16 +295,13 @@ impl ProductionContentSystem {
                 initial_delay: Duration::from_millis(500),
                 max_delay: Duration::from_secs(30),
                 backoff_factor: 2.0,
+                ..RetryConfig::default()
             },
         )
         .await
         .and_then(|content| {
             for validator in &validators {
-                validator.validate(&content.content).map_err(|msg| {
-                    ContentError::InvalidStructure {
-                        message: format!("Validation failed: {}", msg),
-                    }
-                })?;
+                validator.validate(path, &content.content)?;
             }
             Ok(content)
         })
@@ -427,9 +326,30 @@ impl ProductionContentSystem {
 // Example Usage
 // ============================================================================
 
+/// The GitHub repository the example reads, recorded or replayed on request
+///
+/// Set `CASSETTE_RECORD=<file>` to record the example's GitHub traffic, and
+/// `CASSETTE_REPLAY=<file>` to serve it back without network access.
+async fn github_source() -> content_resolver::Result<Arc<dyn ContentSource>> {
+    if let Ok(cassette) = std::env::var("CASSETTE_REPLAY") {
+        return Ok(Arc::new(ReplaySource::open(cassette).await?));
+    }
+    let github = Arc::new(content_resolver::GitHubSource::new(
+        "anthropics".to_string(),
+        "anthropic-sdk-python".to_string(),
+        "main".to_string(),
+        "".to_string(),
+    ));
+    Ok(match std::env::var("CASSETTE_RECORD") {
+        Ok(cassette) => Arc::new(RecordingSource::wrap(github, cassette)),
+        Err(_) => github,
+    })
+}
+
 #[tokio::main]
 async fn main() -> content_resolver::Result<()> {
     println!("=== Advanced Patterns Example ===\n");
+    let github = github_source().await?;
 
     // 1. Local filesystem source
     println!("1. Local Filesystem Source");
@@ -445,14 +365,7 @@ async fn main() -> content_resolver::Result<()> {
     println!("2. Metrics and Observability");
     println!("----------------------------");
 
-    let source = Arc::new(content_resolver::GitHubSource::new(
-        "anthropics".to_string(),
-        "anthropic-sdk-python".to_string(),
-        "main".to_string(),
-        "".to_string(),
-    )) as Arc<dyn ContentSource>;
-
-    let instrumented = Arc::new(InstrumentedSource::new(source));
+    let instrumented = Arc::new(InstrumentedSource::new(github.clone()));
     let resolver = Arc::new(ResourceResolver::new(vec![
         instrumented.clone() as Arc<dyn ContentSource>
     ]));
@@ -470,10 +383,8 @@ async fn main() -> content_resolver::Result<()> {
     println!("3. Content Validation");
     println!("---------------------");
 
-    let validators: Vec<&dyn ContentValidator> = vec![
-        &Utf8Validator,
-        &SizeValidator::new(1_000_000),
-    ];
+    let size_validator = SizeValidator::new(1_000_000);
+    let validators: Vec<&dyn ContentValidator> = vec![&Utf8Validator, &size_validator];
 
     match fetch_and_validate(&resolver, "README.md", &validators).await {
         Ok(content) => {
@@ -489,14 +400,7 @@ async fn main() -> content_resolver::Result<()> {
     println!("4. Production Deployment");
     println!("------------------------");
 
-    let prod_sources: Vec<Arc<dyn ContentSource>> = vec![
-        Arc::new(content_resolver::GitHubSource::new(
-            "anthropics".to_string(),
-            "anthropic-sdk-python".to_string(),
-            "main".to_string(),
-            "".to_string(),
-        )),
-    ];
+    let prod_sources: Vec<Arc<dyn ContentSource>> = vec![github];
 
     match ProductionContentSystem::new(prod_sources, None).await {
         Ok(system) => {
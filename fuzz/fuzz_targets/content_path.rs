@@ -0,0 +1,23 @@
+//! Path normalization must never panic, and its output must be a fixed point
+//! that stays inside the root
+
+#![no_main]
+
+use content_resolver::{ContentPath, SeparatorPolicy};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|raw: &str| {
+    for separators in [SeparatorPolicy::Slash, SeparatorPolicy::SlashOrBackslash] {
+        let Ok(path) = ContentPath::parse_with(raw, separators) else {
+            continue;
+        };
+        assert_eq!(ContentPath::parse(path.as_str()).unwrap(), path);
+        assert!(path.segments().all(|s| !s.is_empty() && s != "." && s != ".."));
+
+        if let Some(parent) = path.parent() {
+            let name = path.file_name().unwrap();
+            assert_eq!(parent.join(name).unwrap(), path);
+            assert!(path.strip_prefix(&parent).is_some());
+        }
+    }
+});
@@ -0,0 +1,27 @@
+//! Directory listings and file contents read back from JSON, as written by
+//! `content-resolver ls --json` or a serialized cache, must parse without
+//! panicking and survive a round trip
+
+#![no_main]
+
+use content_resolver::{DirectoryListing, FileContent};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(listing) = serde_json::from_slice::<DirectoryListing>(data) {
+        let json = serde_json::to_vec(&listing).unwrap();
+        let again: DirectoryListing = serde_json::from_slice(&json).unwrap();
+        assert_eq!(again.path, listing.path);
+        assert_eq!(again.entries.len(), listing.entries.len());
+        for (a, b) in again.entries.iter().zip(&listing.entries) {
+            assert_eq!((&a.name, &a.path, &a.entry_type), (&b.name, &b.path, &b.entry_type));
+        }
+    }
+
+    if let Ok(file) = serde_json::from_slice::<FileContent>(data) {
+        let json = serde_json::to_vec(&file).unwrap();
+        let again: FileContent = serde_json::from_slice(&json).unwrap();
+        assert_eq!(again.content, file.content);
+        assert_eq!(again.sha256(), file.sha256());
+    }
+});
@@ -0,0 +1,105 @@
+//! Policies deciding which fetched files the resolver caches
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::error::{ContentError, Result};
+use crate::types::FileContent;
+
+/// Decides whether a fetched file is written to the resolver's cache
+/// 
+/// Consulted with the normalized path and the fetched content before every
+/// cache write, so the decision can depend on the path, the size or the
+/// source's metadata. A rejected file is still returned to the caller; it
+/// is just fetched from the sources again next time.
+pub trait CacheAdmission: Send + Sync {
+    /// Whether to cache `content`, fetched for `path`
+    fn admit(&self, path: &str, content: &FileContent) -> bool;
+}
+
+/// Cache everything; the default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AdmitAll;
+
+impl CacheAdmission for AdmitAll {
+    fn admit(&self, _path: &str, _content: &FileContent) -> bool {
+        true
+    }
+}
+
+/// Cache files of at most this many bytes
+#[derive(Debug, Clone, Copy)]
+pub struct MaxSize(pub usize);
+
+impl CacheAdmission for MaxSize {
+    fn admit(&self, _path: &str, content: &FileContent) -> bool {
+        content.content.len() <= self.0
+    }
+}
+
+/// Cache files whose paths match none of the globs
+#[derive(Debug, Clone)]
+pub struct GlobDeny(pub GlobSet);
+
+impl GlobDeny {
+    /// Build from glob patterns such as `tmp/**` or `**/*.log`
+    /// 
+    /// Fails with `InvalidConfig` if a pattern doesn't parse.
+    pub fn new<I, P>(patterns: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<str>,
+    {
+        let invalid = |e: globset::Error| ContentError::InvalidConfig {
+            message: format!("invalid cache admission glob: {}", e),
+        };
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder.add(Glob::new(pattern.as_ref()).map_err(invalid)?);
+        }
+        Ok(Self(builder.build().map_err(invalid)?))
+    }
+}
+
+impl CacheAdmission for GlobDeny {
+    fn admit(&self, path: &str, _content: &FileContent) -> bool {
+        !self.0.is_match(path)
+    }
+}
+
+/// Cache files every policy admits
+pub struct All(pub Vec<Box<dyn CacheAdmission>>);
+
+impl CacheAdmission for All {
+    fn admit(&self, path: &str, content: &FileContent) -> bool {
+        self.0.iter().all(|policy| policy.admit(path, content))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policies() {
+        let small = FileContent::new("abc", "a");
+        let large = FileContent::new(vec![0u8; 10], "a");
+
+        assert!(AdmitAll.admit("tmp/a", &large));
+        assert!(MaxSize(3).admit("a", &small));
+        assert!(!MaxSize(3).admit("a", &large));
+
+        let deny = GlobDeny::new(["tmp/**", "**/*.log"]).unwrap();
+        assert!(!deny.admit("tmp/a/b.txt", &small));
+        assert!(!deny.admit("logs/run.log", &small));
+        assert!(deny.admit("docs/tmp.txt", &small));
+        assert_eq!(
+            GlobDeny::new(["a/[b"]).unwrap_err().kind(),
+            crate::error::ErrorKind::InvalidConfig
+        );
+
+        let all = All(vec![Box::new(MaxSize(3)), Box::new(deny)]);
+        assert!(all.admit("docs/a.txt", &small));
+        assert!(!all.admit("docs/a.txt", &large));
+        assert!(!all.admit("tmp/a.txt", &small));
+    }
+}
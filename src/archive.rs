@@ -0,0 +1,586 @@
+//! Read-only sources over tar and zip archives held in memory
+//!
+//! Available with the `archive` feature.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use flate2::read::{DeflateDecoder, MultiGzDecoder};
+use flate2::Crc;
+use std::collections::BTreeMap;
+use std::io::Read;
+
+use crate::{
+    error::{ContentError, Result},
+    source::ContentSource,
+    types::{ContentPath, DirectoryEntry, DirectoryListing, EntryType, FileContent, FileMetadata},
+};
+
+/// Unpacked size `ArchiveSource::new` accepts, 1 GiB
+pub const DEFAULT_MAX_UNPACKED_SIZE: u64 = 1 << 30;
+
+/// Source serving the files of a tar, gzipped tar or zip archive
+/// 
+/// The archive is unpacked into memory once, when the source is built.
+/// Directories are derived from the file paths, so empty directories,
+/// symlinks and other special entries aren't visible. Use
+/// `ResourceResolver::fetch_archive` to open an archive stored in a source.
+#[derive(Debug, Clone)]
+pub struct ArchiveSource {
+    name: String,
+    files: BTreeMap<String, Bytes>,
+}
+
+impl ArchiveSource {
+    /// Unpack `bytes`, telling the format from its first bytes
+    /// 
+    /// `name` identifies the source in errors and logs. Fails with
+    /// `InvalidStructure` if the archive is malformed, uses an unsupported
+    /// feature such as encryption or zip64, or holds a path that climbs out
+    /// of the root, and with `TooLarge` if its files add up to more than
+    /// `DEFAULT_MAX_UNPACKED_SIZE`.
+    pub fn new(name: impl Into<String>, bytes: impl Into<Bytes>) -> Result<Self> {
+        Self::with_max_unpacked_size(name, bytes, DEFAULT_MAX_UNPACKED_SIZE)
+    }
+
+    /// Unpack `bytes` like `new`, allowing at most `limit` bytes of files
+    pub fn with_max_unpacked_size(
+        name: impl Into<String>,
+        bytes: impl Into<Bytes>,
+        limit: u64,
+    ) -> Result<Self> {
+        let name = name.into();
+        let bytes = bytes.into();
+        let mut unpacker = Unpacker {
+            name: &name,
+            files: BTreeMap::new(),
+            total: 0,
+            limit,
+        };
+
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            // Headers and padding come on top of the files; stop a gzip bomb
+            // well before it fills memory
+            let cap = limit.saturating_add(TAR_OVERHEAD);
+            let mut tar = Vec::new();
+            MultiGzDecoder::new(&bytes[..])
+                .take(cap.saturating_add(1))
+                .read_to_end(&mut tar)
+                .map_err(|e| unpacker.invalid(format!("bad gzip stream: {}", e)))?;
+            if tar.len() as u64 > cap {
+                return Err(ContentError::TooLarge {
+                    path: name,
+                    size: None,
+                    limit,
+                });
+            }
+            unpacker.read_tar(&tar)?;
+        } else if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+            unpacker.read_zip(&bytes)?;
+        } else {
+            unpacker.read_tar(&bytes)?;
+        }
+
+        let files = unpacker.files;
+        Ok(Self { name, files })
+    }
+
+    /// Paths of the files in the archive, sorted
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+}
+
+#[async_trait]
+impl ContentSource for ArchiveSource {
+    async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+        self.files
+            .get(ContentPath::parse(path)?.as_str())
+            .map(|content| FileContent::new(content.clone(), format!("{}:{}", self.name, path)))
+            .ok_or_else(|| ContentError::NotFound {
+                path: path.to_string(),
+            })
+    }
+
+    async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+        let dir = ContentPath::parse(path)?;
+        let dir = dir.as_str();
+        let mut children: BTreeMap<String, EntryType> = BTreeMap::new();
+
+        for file in self.files.keys() {
+            let rest = if dir.is_empty() {
+                Some(file.as_str())
+            } else {
+                file.strip_prefix(dir)
+                    .and_then(|rest| rest.strip_prefix('/'))
+            };
+            let Some(rest) = rest else { continue };
+            match rest.split_once('/') {
+                Some((name, _)) => children.insert(name.to_string(), EntryType::Dir),
+                None => children.insert(rest.to_string(), EntryType::File),
+            };
+        }
+
+        if children.is_empty() {
+            return Err(ContentError::NotFound {
+                path: path.to_string(),
+            });
+        }
+
+        Ok(DirectoryListing {
+            path: path.to_string(),
+            entries: children
+                .into_iter()
+                .map(|(name, entry_type)| {
+                    let path = if dir.is_empty() {
+                        name.clone()
+                    } else {
+                        format!("{}/{}", dir, name)
+                    };
+                    let entry = DirectoryEntry::new(name, path.clone(), entry_type);
+                    match self.files.get(&path) {
+                        Some(content) => entry.with_metadata("size", content.len() as u64),
+                        None => entry,
+                    }
+                })
+                .collect(),
+            truncated: false,
+        })
+    }
+
+    fn identifier(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn stat(&self, path: &str) -> Result<FileMetadata> {
+        match self.files.get(ContentPath::parse(path)?.as_str()) {
+            Some(content) => Ok(FileMetadata::file().with_size(content.len() as u64)),
+            None => self.list_directory(path).await.map(|_| FileMetadata::dir()),
+        }
+    }
+}
+
+const TAR_BLOCK: usize = 512;
+
+/// Room for tar headers and padding when unpacking a gzipped tar
+const TAR_OVERHEAD: u64 = 64 << 20;
+
+/// Collects an archive's files, enforcing the size limit
+struct Unpacker<'a> {
+    name: &'a str,
+    files: BTreeMap<String, Bytes>,
+    total: u64,
+    limit: u64,
+}
+
+impl Unpacker<'_> {
+    fn invalid(&self, message: impl std::fmt::Display) -> ContentError {
+        ContentError::InvalidStructure {
+            message: format!("{}: {}", self.name, message),
+        }
+    }
+
+    fn add(&mut self, path: &str, content: Bytes) -> Result<()> {
+        self.total += content.len() as u64;
+        if self.total > self.limit {
+            return Err(ContentError::TooLarge {
+                path: self.name.to_string(),
+                size: None,
+                limit: self.limit,
+            });
+        }
+        let path = ContentPath::parse(path)
+            .map_err(|_| self.invalid(format!("entry {:?} is outside the archive root", path)))?;
+        if !path.is_root() {
+            self.files.insert(path.into(), content);
+        }
+        Ok(())
+    }
+
+    /// Read a ustar, GNU or pax tar archive
+    fn read_tar(&mut self, data: &[u8]) -> Result<()> {
+        let mut offset = 0;
+        let mut long_name: Option<String> = None;
+
+        while offset + TAR_BLOCK <= data.len() {
+            let header = &data[offset..offset + TAR_BLOCK];
+            if header.iter().all(|&b| b == 0) {
+                return Ok(());
+            }
+            if !tar_checksum_matches(header) {
+                return Err(self.invalid("not a tar or zip archive, or a corrupt one"));
+            }
+
+            let size =
+                tar_number(&header[124..136]).ok_or_else(|| self.invalid("bad tar entry size"))?;
+            let start = offset + TAR_BLOCK;
+            let end = usize::try_from(size)
+                .ok()
+                .and_then(|size| start.checked_add(size))
+                .filter(|&end| end <= data.len())
+                .ok_or_else(|| self.invalid("truncated tar archive"))?;
+            let body = &data[start..end];
+            offset = start + (end - start).div_ceil(TAR_BLOCK) * TAR_BLOCK;
+
+            let name = match long_name.take() {
+                Some(name) => name,
+                None => {
+                    let name = tar_string(&header[0..100]);
+                    let prefix = tar_string(&header[345..500]);
+                    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                        format!("{}/{}", prefix, name)
+                    } else {
+                        name
+                    }
+                }
+            };
+
+            match header[156] {
+                b'0' | b'\0' | b'7' => self.add(&name, Bytes::copy_from_slice(body))?,
+                // GNU long name of the next entry
+                b'L' => long_name = Some(tar_string(body)),
+                // pax extended header; only the path is used
+                b'x' => long_name = pax_path(body),
+                // Directories, links, devices and global pax headers
+                _ => {}
+            }
+        }
+
+        // Archives cut off before the end-of-archive blocks still hold whole entries
+        if offset == data.len() {
+            Ok(())
+        } else {
+            Err(self.invalid("truncated tar archive"))
+        }
+    }
+
+    /// Read a zip archive with stored or deflated entries
+    fn read_zip(&mut self, data: &[u8]) -> Result<()> {
+        const END_OF_DIRECTORY: &[u8] = b"PK\x05\x06";
+        const DIRECTORY_ENTRY: &[u8] = b"PK\x01\x02";
+        const LOCAL_HEADER: &[u8] = b"PK\x03\x04";
+
+        // The end record is the last thing in the file, before a comment of up to 64 KiB
+        let search_from = data.len().saturating_sub(22 + u16::MAX as usize);
+        let end = (search_from..data.len().saturating_sub(21))
+            .rev()
+            .find(|&i| data[i..].starts_with(END_OF_DIRECTORY))
+            .ok_or_else(|| self.invalid("zip end of central directory not found"))?;
+
+        let count = le16(data, end + 10);
+        let directory = le32(data, end + 16);
+        if count == u16::MAX || directory == u32::MAX {
+            return Err(self.invalid("zip64 archives aren't supported"));
+        }
+
+        let mut offset = directory as usize;
+        for _ in 0..count {
+            if data.len() < offset + 46 || !data[offset..].starts_with(DIRECTORY_ENTRY) {
+                return Err(self.invalid("bad zip central directory"));
+            }
+            let flags = le16(data, offset + 8);
+            let method = le16(data, offset + 10);
+            let crc = le32(data, offset + 16);
+            let compressed = le32(data, offset + 20) as usize;
+            let size = le32(data, offset + 24) as u64;
+            let name_len = le16(data, offset + 28) as usize;
+            let extra_len = le16(data, offset + 30) as usize;
+            let comment_len = le16(data, offset + 32) as usize;
+            let local = le32(data, offset + 42) as usize;
+            let name = data
+                .get(offset + 46..offset + 46 + name_len)
+                .ok_or_else(|| self.invalid("bad zip central directory"))?;
+            let name = String::from_utf8_lossy(name).into_owned();
+            offset += 46 + name_len + extra_len + comment_len;
+
+            if name.ends_with('/') {
+                continue;
+            }
+            if flags & 1 != 0 {
+                return Err(self.invalid(format!("{} is encrypted", name)));
+            }
+            if size > self.limit.saturating_sub(self.total) {
+                return Err(ContentError::TooLarge {
+                    path: self.name.to_string(),
+                    size: None,
+                    limit: self.limit,
+                });
+            }
+
+            if data.len() < local + 30 || !data[local..].starts_with(LOCAL_HEADER) {
+                return Err(self.invalid(format!("bad zip local header for {}", name)));
+            }
+            let start =
+                local + 30 + le16(data, local + 26) as usize + le16(data, local + 28) as usize;
+            let raw = data
+                .get(start..start + compressed)
+                .ok_or_else(|| self.invalid(format!("truncated zip entry {}", name)))?;
+
+            let content = match method {
+                0 => raw.to_vec(),
+                8 => {
+                    let mut content = Vec::new();
+                    DeflateDecoder::new(raw)
+                        .take(size + 1)
+                        .read_to_end(&mut content)
+                        .map_err(|e| {
+                            self.invalid(format!("bad deflate data in {}: {}", name, e))
+                        })?;
+                    content
+                }
+                other => {
+                    return Err(self.invalid(format!(
+                        "{} uses unsupported compression method {}",
+                        name, other
+                    )))
+                }
+            };
+            if content.len() as u64 != size {
+                return Err(self.invalid(format!("{} doesn't have its declared size", name)));
+            }
+            let mut sum = Crc::new();
+            sum.update(&content);
+            if sum.sum() != crc {
+                return Err(self.invalid(format!("{} doesn't match its CRC-32", name)));
+            }
+            self.add(&name, content.into())?;
+        }
+        Ok(())
+    }
+}
+
+fn le16(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn le32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+/// A NUL-terminated tar header field
+fn tar_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// An octal tar header number, or a base-256 one for large values
+fn tar_number(field: &[u8]) -> Option<u64> {
+    if field[0] & 0x80 != 0 {
+        return field[1..]
+            .iter()
+            .try_fold(u64::from(field[0] & 0x7f), |n, &b| {
+                n.checked_mul(256).map(|n| n + u64::from(b))
+            });
+    }
+    let digits = tar_string(field);
+    let digits = digits.trim_matches(|c: char| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(digits, 8).ok()
+}
+
+/// Whether a header's checksum, summed with the checksum field as spaces, matches
+fn tar_checksum_matches(header: &[u8]) -> bool {
+    let sum: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| {
+            if (148..156).contains(&i) {
+                u64::from(b' ')
+            } else {
+                u64::from(b)
+            }
+        })
+        .sum();
+    tar_number(&header[148..156]) == Some(sum)
+}
+
+/// The `path` record of a pax extended header
+fn pax_path(body: &[u8]) -> Option<String> {
+    let body = String::from_utf8_lossy(body);
+    let mut rest = body.as_ref();
+    // Records are "<length> <key>=<value>\n", the length counting the whole record
+    while let Some((length, _)) = rest.split_once(' ') {
+        let record = rest.get(..length.parse::<usize>().ok()?)?;
+        rest = &rest[record.len()..];
+        let (_, field) = record.split_once(' ')?;
+        if let Some(path) = field.strip_prefix("path=") {
+            return Some(path.trim_end_matches('\n').to_string());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::DeflateEncoder, write::GzEncoder, Compression};
+    use std::io::Write;
+
+    /// Build a ustar archive from `(path, content)` pairs
+    fn tar(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (path, content) in files {
+            let mut header = [0u8; TAR_BLOCK];
+            header[..path.len()].copy_from_slice(path.as_bytes());
+            header[100..107].copy_from_slice(b"0000644");
+            header[124..135].copy_from_slice(format!("{:011o}", content.len()).as_bytes());
+            header[156] = b'0';
+            header[257..263].copy_from_slice(b"ustar\0");
+            header[148..156].copy_from_slice(b"        ");
+            let sum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+            header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+            out.extend_from_slice(&header);
+            out.extend_from_slice(content.as_bytes());
+            out.resize(out.len().div_ceil(TAR_BLOCK) * TAR_BLOCK, 0);
+        }
+        out.resize(out.len() + 2 * TAR_BLOCK, 0);
+        out
+    }
+
+    /// Build a zip archive of deflated entries
+    fn zip(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut directory = Vec::new();
+        for (path, content) in files {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(content.as_bytes()).unwrap();
+            let data = encoder.finish().unwrap();
+
+            let local = out.len() as u32;
+            let mut fields = Vec::new();
+            fields.extend_from_slice(&20u16.to_le_bytes()); // version needed
+            fields.extend_from_slice(&0u16.to_le_bytes()); // flags
+            fields.extend_from_slice(&8u16.to_le_bytes()); // deflate
+            let mut crc = flate2::Crc::new();
+            crc.update(content.as_bytes());
+            fields.extend_from_slice(&[0; 4]); // time, date
+            fields.extend_from_slice(&crc.sum().to_le_bytes());
+            fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(content.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(path.len() as u16).to_le_bytes());
+            fields.extend_from_slice(&0u16.to_le_bytes()); // extra
+
+            out.extend_from_slice(b"PK\x03\x04");
+            out.extend_from_slice(&fields);
+            out.extend_from_slice(path.as_bytes());
+            out.extend_from_slice(&data);
+
+            directory.extend_from_slice(b"PK\x01\x02");
+            directory.extend_from_slice(&20u16.to_le_bytes()); // version made by
+            directory.extend_from_slice(&fields);
+            directory.extend_from_slice(&[0; 10]); // comment, disk, attributes
+            directory.extend_from_slice(&local.to_le_bytes());
+            directory.extend_from_slice(path.as_bytes());
+        }
+        let offset = out.len() as u32;
+        out.extend_from_slice(&directory);
+        out.extend_from_slice(b"PK\x05\x06");
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        out.extend_from_slice(&offset.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    const FILES: &[(&str, &str)] = &[
+        ("./skill/SKILL.md", "# Skill"),
+        ("skill/scripts/run.py", "print('hi')"),
+    ];
+
+    #[tokio::test]
+    async fn test_formats() {
+        for bytes in [tar(FILES), gzip(&tar(FILES)), zip(FILES)] {
+            let source = ArchiveSource::new("archive", bytes).unwrap();
+            assert_eq!(
+                source.paths().collect::<Vec<_>>(),
+                vec!["skill/SKILL.md", "skill/scripts/run.py"]
+            );
+
+            let file = source.fetch_file("skill/scripts/run.py").await.unwrap();
+            assert_eq!(file.text().unwrap(), "print('hi')");
+            assert_eq!(file.source_path, "archive:skill/scripts/run.py");
+
+            let listing = source.list_directory("skill").await.unwrap();
+            let entries: Vec<_> = listing
+                .entries
+                .iter()
+                .map(|e| (e.name.as_str(), e.size()))
+                .collect();
+            assert_eq!(entries, vec![("SKILL.md", Some(7)), ("scripts", None)]);
+            assert!(source
+                .fetch_file("skill/missing")
+                .await
+                .unwrap_err()
+                .is_not_found());
+            assert!(source.stat("skill/scripts").await.unwrap().is_dir());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_archive() {
+        use crate::{cache::Cache, testing::MemorySource, MemoryCache, ResourceResolver};
+        use std::sync::Arc;
+
+        let source = MemorySource::new().with_file("skills/pdf.tar.gz", gzip(&tar(FILES)));
+        let cache = Arc::new(MemoryCache::new());
+        let resolver = ResourceResolver::with_cache(vec![Arc::new(source)], cache.clone());
+
+        let archive = resolver.fetch_archive("/skills//pdf.tar.gz").await.unwrap();
+        assert_eq!(archive.identifier(), "archive(skills/pdf.tar.gz)");
+        let file = archive.fetch_file("skill/SKILL.md").await.unwrap();
+        assert_eq!(
+            file.source_path,
+            "archive(skills/pdf.tar.gz):skill/SKILL.md"
+        );
+
+        // The archive itself is cached, not its files
+        assert!(cache.contains("file:skills/pdf.tar.gz").await);
+        assert_eq!(cache.export().await.len(), 1);
+
+        let error = resolver
+            .fetch_archive("skills/missing.zip")
+            .await
+            .unwrap_err();
+        assert!(error.is_not_found());
+    }
+
+    #[test]
+    fn test_rejects_bad_archives() {
+        let kind = |bytes: Vec<u8>| ArchiveSource::new("a", bytes).unwrap_err().kind();
+        use crate::error::ErrorKind;
+
+        assert_eq!(
+            kind(b"not an archive".repeat(100)),
+            ErrorKind::InvalidStructure
+        );
+        assert_eq!(
+            kind(tar(&[("../escape", "x")])),
+            ErrorKind::InvalidStructure
+        );
+        let mut truncated = tar(FILES);
+        truncated.truncate(TAR_BLOCK + 3);
+        assert_eq!(kind(truncated), ErrorKind::InvalidStructure);
+
+        // A damaged entry that still inflates to the declared size
+        let mut damaged = zip(FILES);
+        let directory = damaged.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        damaged[directory + 16] ^= 1;
+        let error = ArchiveSource::new("a", damaged).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidStructure);
+        assert!(error.to_string().contains("CRC-32"), "{}", error);
+
+        let large = ArchiveSource::with_max_unpacked_size("a", zip(FILES), 10).unwrap_err();
+        assert_eq!(large.kind(), ErrorKind::TooLarge);
+        let large = ArchiveSource::with_max_unpacked_size("a", gzip(&tar(FILES)), 10).unwrap_err();
+        assert_eq!(large.kind(), ErrorKind::TooLarge);
+    }
+}
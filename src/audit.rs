@@ -0,0 +1,290 @@
+//! Records of the content that enters through a resolver
+
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+use tokio::sync::mpsc;
+
+use crate::error::{ContentError, Result};
+use crate::hook::RequestContext;
+
+#[cfg(feature = "fs")]
+pub use file::JsonlFileSink;
+
+/// Receives an `AuditEvent` for every file a resolver fetches from a source
+/// 
+/// Register with `ResourceResolver::with_audit_sink`. `record` is called on
+/// the fetching task, so hand the event off rather than doing I/O in it. An
+/// error doesn't fail the fetch; the resolver counts it in
+/// `ResolverStats::audit_failures`.
+pub trait AuditSink: Send + Sync {
+    /// Take one event
+    fn record(&self, event: AuditEvent) -> Result<()>;
+}
+
+/// A file that came from a source, or from the cache if those are audited too
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct AuditEvent {
+    /// The normalized path the file was fetched for
+    pub path: String,
+    /// Identifier of the source it came from, or `cache`
+    pub source: String,
+    /// Hex encoded SHA-256 of the content
+    pub sha256: String,
+    /// Size of the content in bytes
+    pub size: u64,
+    /// When it was fetched, in milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    /// The `AuditPurpose` of the request, if it had one
+    pub purpose: Option<String>,
+    /// Whether this was a cache hit, see `ResourceResolver::with_audit_cache_hits`
+    pub from_cache: bool,
+}
+
+impl AuditEvent {
+    /// An event stamped now, with the purpose from `ctx`
+    pub(crate) fn new(
+        path: &str,
+        source: String,
+        sha256: String,
+        size: u64,
+        ctx: &RequestContext,
+    ) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        Self {
+            path: path.to_string(),
+            source,
+            sha256,
+            size,
+            timestamp_ms,
+            purpose: ctx.get::<AuditPurpose>().map(|purpose| purpose.0.clone()),
+            from_cache: false,
+        }
+    }
+}
+
+/// Why a request was made, copied into its `AuditEvent`s
+/// 
+/// Put it in the `RequestContext` of `fetch_file_with_ctx`, or have a
+/// request hook add it:
+/// 
+/// ```no_run
+/// # async fn run(resolver: content_resolver::ResourceResolver) -> content_resolver::Result<()> {
+/// use content_resolver::{AuditPurpose, FetchOptions, RequestContext};
+/// 
+/// let mut ctx = RequestContext::new();
+/// ctx.insert(AuditPurpose("nightly-export".to_string()));
+/// resolver
+///     .fetch_file_with_ctx("reports/q3.csv", &FetchOptions::default(), &mut ctx)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditPurpose(pub String);
+
+/// Audit sink handing events to a channel, for processing them yourself
+/// 
+/// Holds up to `capacity` events; while the receiver lags that far behind,
+/// or once it is dropped, events are rejected and counted as failures.
+#[derive(Debug, Clone)]
+pub struct ChannelSink {
+    sender: mpsc::Sender<AuditEvent>,
+}
+
+impl ChannelSink {
+    /// A sink and the receiving end of its channel, holding `capacity` events (at least one)
+    pub fn new(capacity: usize) -> (Self, mpsc::Receiver<AuditEvent>) {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        (Self { sender }, receiver)
+    }
+}
+
+impl AuditSink for ChannelSink {
+    fn record(&self, event: AuditEvent) -> Result<()> {
+        self.sender.try_send(event).map_err(|e| {
+            let reason = match e {
+                mpsc::error::TrySendError::Full(_) => "audit channel is full",
+                mpsc::error::TrySendError::Closed(_) => "audit channel is closed",
+            };
+            ContentError::Io(std::io::Error::other(reason))
+        })
+    }
+}
+
+#[cfg(feature = "fs")]
+mod file {
+    use std::path::Path;
+    use std::pin::pin;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    use futures::future::{select, Either};
+    use tokio::io::{AsyncWriteExt, BufWriter};
+    use tokio::sync::{mpsc, oneshot};
+
+    use super::{AuditEvent, AuditSink};
+    use crate::error::{ContentError, Result, ResultExt};
+    use crate::task::TaskSet;
+
+    /// Events `JsonlFileSink::open` lets wait for the writer
+    const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+    enum Message {
+        Event(AuditEvent),
+        Flush(oneshot::Sender<std::io::Result<()>>),
+    }
+
+    /// Audit sink appending one JSON object per line to a file
+    /// 
+    /// Events are written by a task on `tasks`, usually the resolver's
+    /// `tasks()`, buffered and flushed whenever no more are waiting, and once
+    /// more when the tasks shut down. Events the task couldn't write are
+    /// counted in `write_failures`, since `record` has already returned by
+    /// then; after shutdown, `record` fails.
+    /// 
+    /// At most a fixed number of events wait for the writer. While that many
+    /// do, e.g. because the disk stalls, `record` drops new events, counts
+    /// them in `write_failures` and fails.
+    /// 
+    /// ```no_run
+    /// # async fn run(resolver: content_resolver::ResourceResolver) -> content_resolver::Result<()> {
+    /// use content_resolver::JsonlFileSink;
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    /// 
+    /// let sink = JsonlFileSink::open("/var/log/content/audit.jsonl", resolver.tasks()).await?;
+    /// let resolver = resolver.with_audit_sink(Arc::new(sink));
+    /// // ... serve requests ...
+    /// resolver.shutdown(Duration::from_secs(5)).await;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[derive(Debug, Clone)]
+    pub struct JsonlFileSink {
+        sender: mpsc::Sender<Message>,
+        write_failures: Arc<AtomicU64>,
+    }
+
+    impl JsonlFileSink {
+        /// Open `path` for appending, creating it if needed, and start the writer on `tasks`
+        /// 
+        /// Up to 1024 events wait for the writer.
+        /// 
+        /// # Panics
+        /// 
+        /// Panics outside a tokio runtime, since it spawns the writer task.
+        pub async fn open(path: impl AsRef<Path>, tasks: &TaskSet) -> Result<Self> {
+            Self::open_with_capacity(path, tasks, DEFAULT_QUEUE_CAPACITY).await
+        }
+
+        /// Open `path` like `open`, letting up to `capacity` events (at least one) wait for the writer
+        /// 
+        /// # Panics
+        /// 
+        /// Panics outside a tokio runtime, since it spawns the writer task.
+        pub async fn open_with_capacity(
+            path: impl AsRef<Path>,
+            tasks: &TaskSet,
+            capacity: usize,
+        ) -> Result<Self> {
+            let path = path.as_ref();
+            let file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await
+                .map_err(ContentError::from)
+                .with_path(&path.display().to_string())?;
+            let (sender, mut receiver) = mpsc::channel(capacity.max(1));
+            let write_failures = Arc::new(AtomicU64::new(0));
+            let failures = write_failures.clone();
+
+            tasks.spawn(|signal| async move {
+                let mut file = BufWriter::new(file);
+                // Events written since the last successful flush
+                let mut unflushed = 0;
+                loop {
+                    let received = match select(pin!(signal.cancelled()), pin!(receiver.recv())).await {
+                        Either::Left(_) => None,
+                        Either::Right((message, _)) => Some(message),
+                    };
+                    // On shutdown, write what is already queued and stop
+                    let (mut message, done) = match received {
+                        Some(message) => {
+                            let done = message.is_none();
+                            (message, done)
+                        }
+                        None => (receiver.try_recv().ok(), true),
+                    };
+                    let mut replies = Vec::new();
+                    while let Some(current) = message {
+                        match current {
+                            Message::Event(event) => match write_event(&mut file, &event).await {
+                                Ok(()) => unflushed += 1,
+                                Err(_) => {
+                                    failures.fetch_add(1, Ordering::Relaxed);
+                                }
+                            },
+                            Message::Flush(reply) => replies.push(reply),
+                        }
+                        message = receiver.try_recv().ok();
+                    }
+                    let flushed = file.flush().await;
+                    if flushed.is_err() {
+                        failures.fetch_add(unflushed, Ordering::Relaxed);
+                    }
+                    unflushed = 0;
+                    for reply in replies {
+                        let _ = reply.send(match &flushed {
+                            Ok(()) => Ok(()),
+                            Err(e) => Err(std::io::Error::new(e.kind(), e.to_string())),
+                        });
+                    }
+                    if done {
+                        return;
+                    }
+                }
+            });
+
+            Ok(Self { sender, write_failures })
+        }
+
+        /// Wait until every event recorded so far is written and flushed
+        pub async fn flush(&self) -> Result<()> {
+            let (reply, flushed) = oneshot::channel();
+            let stopped = || ContentError::Io(std::io::Error::other("audit log writer stopped"));
+            self.sender.send(Message::Flush(reply)).await.map_err(|_| stopped())?;
+            flushed.await.map_err(|_| stopped())?.map_err(ContentError::from)
+        }
+
+        /// Events that were recorded but couldn't be written to the file
+        pub fn write_failures(&self) -> u64 {
+            self.write_failures.load(Ordering::Relaxed)
+        }
+    }
+
+    impl AuditSink for JsonlFileSink {
+        fn record(&self, event: AuditEvent) -> Result<()> {
+            self.sender.try_send(Message::Event(event)).map_err(|e| {
+                let reason = match e {
+                    mpsc::error::TrySendError::Full(_) => {
+                        self.write_failures.fetch_add(1, Ordering::Relaxed);
+                        "audit log queue is full"
+                    }
+                    mpsc::error::TrySendError::Closed(_) => "audit log writer stopped",
+                };
+                ContentError::Io(std::io::Error::other(reason))
+            })
+        }
+    }
+
+    async fn write_event(file: &mut BufWriter<tokio::fs::File>, event: &AuditEvent) -> Result<()> {
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+        file.write_all(&line).await?;
+        Ok(())
+    }
+}
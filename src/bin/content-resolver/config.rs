@@ -0,0 +1,231 @@
+//! Source configuration from flags and TOML files
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use clap::ArgMatches;
+use serde::Deserialize;
+
+use content_resolver::{ContentError, ContentSource, LocalFileSource, Result};
+
+/// Contents of a `--config` file
+///
+/// ```toml
+/// cache_dir = "cache"
+///
+/// [[sources]]
+/// local = "overrides"
+///
+/// [[sources]]
+/// github = "owner/repo@main"
+/// base_path = "content"
+/// ```
+///
+/// Relative paths are resolved against the file's directory.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(default)]
+    pub sources: Vec<SourceConfig>,
+    pub cache_dir: Option<PathBuf>,
+}
+
+/// One `[[sources]]` table; exactly one of `github` and `local` is set
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceConfig {
+    github: Option<String>,
+    base_path: Option<String>,
+    local: Option<PathBuf>,
+}
+
+impl ConfigFile {
+    /// Read and parse a config file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| ContentError::InvalidConfig {
+            message: format!("Cannot read {}: {}", path.display(), e),
+        })?;
+        let mut config: Self = toml::from_str(&text).map_err(|e| ContentError::InvalidConfig {
+            message: format!("Invalid config {}: {}", path.display(), e),
+        })?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        config.cache_dir = config.cache_dir.map(|p| dir.join(p));
+        for source in &mut config.sources {
+            source.local = source.local.take().map(|p| dir.join(p));
+        }
+        Ok(config)
+    }
+
+    /// Build the configured sources, in order
+    pub fn sources(&self, token: Option<&str>) -> Result<Vec<Arc<dyn ContentSource>>> {
+        self.sources
+            .iter()
+            .map(|source| match (&source.github, &source.local, &source.base_path) {
+                (Some(spec), None, base_path) => {
+                    github_source(spec, base_path.as_deref().unwrap_or(""), token)
+                }
+                (None, Some(dir), None) => Ok(local_source(dir)),
+                _ => Err(ContentError::InvalidConfig {
+                    message: "Each [[sources]] entry needs either `github` (with an optional \
+                              `base_path`) or `local`"
+                        .to_string(),
+                }),
+            })
+            .collect()
+    }
+}
+
+/// Build the sources given as `--github`/`--local` flags, in command-line order
+///
+/// Each `--base-path` applies to the closest `--github` before it.
+pub fn flag_sources(matches: &ArgMatches, token: Option<&str>) -> Result<Vec<Arc<dyn ContentSource>>> {
+    let flags = |id: &str| -> Vec<(usize, String)> {
+        match (matches.indices_of(id), matches.get_raw(id)) {
+            (Some(indices), Some(values)) => indices
+                .zip(values.map(|v| v.to_string_lossy().into_owned()))
+                .collect(),
+            _ => Vec::new(),
+        }
+    };
+    let github = flags("github");
+    let base_paths = flags("base_path");
+    let local = flags("local");
+
+    let mut ordered: Vec<(usize, Arc<dyn ContentSource>)> = Vec::new();
+    for (i, (at, spec)) in github.iter().enumerate() {
+        let next = github.get(i + 1).map_or(usize::MAX, |(next, _)| *next);
+        let mut own = base_paths.iter().filter(|(b, _)| b > at && *b < next);
+        let base_path = own.next().map_or("", |(_, path)| path.as_str());
+        if own.next().is_some() {
+            return Err(ContentError::InvalidConfig {
+                message: format!("More than one --base-path for --github {}", spec),
+            });
+        }
+        ordered.push((*at, github_source(spec, base_path, token)?));
+    }
+    if let Some((at, _)) = base_paths.first() {
+        if github.first().is_none_or(|(first, _)| at < first) {
+            return Err(ContentError::InvalidConfig {
+                message: "--base-path must follow the --github it applies to".to_string(),
+            });
+        }
+    }
+    for (at, dir) in &local {
+        ordered.push((*at, local_source(Path::new(dir))));
+    }
+
+    ordered.sort_by_key(|(at, _)| *at);
+    Ok(ordered.into_iter().map(|(_, source)| source).collect())
+}
+
+/// Parse `owner/repo[@ref]` into a source; the ref defaults to `main`
+#[cfg(feature = "github")]
+fn github_source(spec: &str, base_path: &str, token: Option<&str>) -> Result<Arc<dyn ContentSource>> {
+    let (repo, branch) = spec.split_once('@').unwrap_or((spec, "main"));
+    let parsed = repo
+        .split_once('/')
+        .filter(|(owner, name)| !owner.is_empty() && !name.is_empty() && !name.contains('/'));
+    let Some((owner, name)) = parsed.filter(|_| !branch.is_empty()) else {
+        return Err(ContentError::InvalidConfig {
+            message: format!("Expected OWNER/REPO[@REF], got {:?}", spec),
+        });
+    };
+
+    let mut source = content_resolver::GitHubSource::new(
+        owner.to_string(),
+        name.to_string(),
+        branch.to_string(),
+        base_path.to_string(),
+    );
+    if let Some(token) = token {
+        source = source.with_token(token);
+    }
+    Ok(Arc::new(source))
+}
+
+#[cfg(not(feature = "github"))]
+fn github_source(spec: &str, _base_path: &str, _token: Option<&str>) -> Result<Arc<dyn ContentSource>> {
+    Err(ContentError::InvalidConfig {
+        message: format!("GitHub source {:?} needs a build with the `github` feature", spec),
+    })
+}
+
+fn local_source(dir: &Path) -> Arc<dyn ContentSource> {
+    Arc::new(LocalFileSource::new(dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "github")]
+    fn flag_identifiers(args: &[&str]) -> Result<Vec<String>> {
+        use clap::CommandFactory;
+
+        let argv = std::iter::once("content-resolver")
+            .chain(args.iter().copied())
+            .chain(["ls"]);
+        let matches = crate::Cli::command().try_get_matches_from(argv).unwrap();
+        Ok(flag_sources(&matches, None)?
+            .iter()
+            .map(|source| source.identifier())
+            .collect())
+    }
+
+    #[cfg(feature = "github")]
+    #[test]
+    fn test_flag_sources_keep_order_and_pair_base_paths() {
+        let sources = flag_identifiers(&[
+            "--local", "overrides",
+            "--github", "org/content@v2", "--base-path", "docs",
+            "--github", "org/fallback",
+        ])
+        .unwrap();
+        assert_eq!(
+            sources,
+            vec![
+                "local://overrides",
+                "github://org/content/v2/docs",
+                "github://org/fallback/main/",
+            ]
+        );
+
+        for bad in [
+            &["--base-path", "docs", "--github", "org/repo"][..],
+            &["--github", "org/repo", "--base-path", "a", "--base-path", "b"],
+            &["--github", "org"],
+            &["--github", "org/repo/extra"],
+            &["--github", "org/repo@"],
+        ] {
+            assert!(flag_identifiers(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("resolver.toml");
+        std::fs::write(
+            &path,
+            "cache_dir = \"cache\"\n[[sources]]\nlocal = \"a\"\n[[sources]]\ngithub = \"o/r\"\n",
+        )
+        .unwrap();
+
+        let config = ConfigFile::load(&path).unwrap();
+        assert_eq!(config.cache_dir, Some(dir.path().join("cache")));
+        let sources = config.sources(None);
+        #[cfg(feature = "github")]
+        assert_eq!(
+            sources.unwrap()[0].identifier(),
+            format!("local://{}", dir.path().join("a").display())
+        );
+        #[cfg(not(feature = "github"))]
+        assert!(sources.err().unwrap().to_string().contains("`github` feature"));
+
+        std::fs::write(&path, "[[sources]]\nlocal = \"a\"\nbase_path = \"x\"\n").unwrap();
+        assert!(ConfigFile::load(&path).unwrap().sources(None).is_err());
+        std::fs::write(&path, "[[sources]]\nlocl = \"a\"\n").unwrap();
+        assert!(ConfigFile::load(&path).is_err());
+    }
+}
@@ -0,0 +1,276 @@
+//! Command-line access to content sources
+//!
+//! Sources come from `--github`/`--local` flags, tried in the order given,
+//! and from a `--config` file, whose sources are tried first. Exits with 2
+//! when the content doesn't exist and 1 on any other error.
+
+mod config;
+
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use tokio::io::AsyncWriteExt;
+
+use content_resolver::{
+    Cache, ContentError, ContentPath, DiskCache, EntryType, ResourceResolver, Result, ResultExt,
+};
+
+use config::ConfigFile;
+
+#[derive(Parser)]
+#[command(name = "content-resolver", version, about = "Fetch and inspect content from resolver sources")]
+struct Cli {
+    /// GitHub source; repeatable, sources are tried in the order given
+    #[arg(long, value_name = "OWNER/REPO[@REF]")]
+    github: Vec<String>,
+
+    /// Path inside the repository of the preceding --github
+    #[arg(long, value_name = "PATH")]
+    base_path: Vec<String>,
+
+    /// Local directory source; repeatable, sources are tried in the order given
+    #[arg(long, value_name = "DIR")]
+    local: Vec<PathBuf>,
+
+    /// Token for GitHub sources
+    #[arg(long, env = "GITHUB_TOKEN", hide_env_values = true)]
+    github_token: Option<String>,
+
+    /// TOML file listing sources and the cache directory
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Cache fetched files in this directory
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print a file to stdout
+    Fetch {
+        path: String,
+        /// Write to a file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// List a directory
+    Ls {
+        #[arg(default_value = "")]
+        path: String,
+        /// Print the listing as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Download a directory tree
+    Download { path: String, dir: PathBuf },
+    /// Show where a file would be fetched from, without fetching it
+    Explain {
+        path: String,
+        /// Print the explanation as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Inspect or clear the --cache-dir cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Remove every cached entry
+    Clear,
+    /// Show the number and total size of cached entries
+    Stats,
+    /// Remove cached copies of the files below a prefix
+    Invalidate { prefix: String },
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    // Usage errors exit with 1, keeping 2 for missing content
+    let matches = match Cli::command().try_get_matches() {
+        Ok(matches) => matches,
+        Err(e) => {
+            let _ = e.print();
+            return if e.use_stderr() { ExitCode::FAILURE } else { ExitCode::SUCCESS };
+        }
+    };
+    let cli = match Cli::from_arg_matches(&matches) {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(cli, &matches).await {
+        Ok(()) => ExitCode::SUCCESS,
+        // The reader went away, e.g. `| head`; nothing left to report
+        Err(e) if matches!(e.root(), ContentError::Io(io) if io.kind() == ErrorKind::BrokenPipe) => {
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            if e.is_not_found() {
+                ExitCode::from(2)
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
+
+async fn run(cli: Cli, matches: &ArgMatches) -> Result<()> {
+    let config = match &cli.config {
+        Some(path) => ConfigFile::load(path)?,
+        None => ConfigFile::default(),
+    };
+    let cache_dir = cli.cache_dir.clone().or_else(|| config.cache_dir.clone());
+    let cache = match &cache_dir {
+        Some(dir) => Some(Arc::new(DiskCache::new(dir.clone()).await?)),
+        None => None,
+    };
+
+    let resolver = || -> Result<ResourceResolver> {
+        let token = cli.github_token.as_deref();
+        let mut sources = config.sources(token)?;
+        sources.extend(config::flag_sources(matches, token)?);
+        match &cache {
+            Some(cache) => Ok(ResourceResolver::try_with_cache(sources, cache.clone())?
+                .with_source_cache_scope()),
+            None => ResourceResolver::try_new(sources),
+        }
+    };
+
+    match cli.command {
+        Command::Fetch { path, output } => fetch(&resolver()?, &path, output).await,
+        Command::Ls { path, json } => ls(&resolver()?, &path, json).await,
+        Command::Download { path, dir } => download(&resolver()?, &path, dir).await,
+        Command::Explain { path, json } => explain(&resolver()?, &path, json).await,
+        Command::Cache { action } => {
+            let Some(cache) = &cache else {
+                return Err(ContentError::InvalidConfig {
+                    message: "cache commands need --cache-dir or cache_dir in the config file"
+                        .to_string(),
+                });
+            };
+            match action {
+                CacheAction::Clear => {
+                    cache.clear().await?;
+                    println!("Cache cleared");
+                }
+                CacheAction::Stats => {
+                    let stats = cache.stats().await?;
+                    println!("{} entries, {} bytes", stats.entries, stats.bytes);
+                }
+                CacheAction::Invalidate { prefix } => {
+                    let removed = resolver()?.invalidate_prefix(&prefix).await?;
+                    println!("Invalidated {} entries", removed);
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn fetch(resolver: &ResourceResolver, path: &str, output: Option<PathBuf>) -> Result<()> {
+    match output {
+        Some(output) => {
+            let file = resolver.fetch_file(path).await?;
+            tokio::fs::write(&output, &file.content)
+                .await
+                .map_err(ContentError::from)
+                .with_path(&output.to_string_lossy())
+        }
+        None => {
+            let mut stdout = tokio::io::stdout();
+            resolver.fetch_to_writer(path, &mut stdout).await?;
+            stdout.flush().await?;
+            Ok(())
+        }
+    }
+}
+
+async fn ls(resolver: &ResourceResolver, path: &str, json: bool) -> Result<()> {
+    let listing = resolver.list_directory(path).await?;
+    let mut out = std::io::stdout().lock();
+    if json {
+        writeln!(out, "{}", serde_json::to_string_pretty(&listing)?)?;
+        return Ok(());
+    }
+
+    for entry in &listing.entries {
+        let size = entry.size().map_or_else(|| "-".to_string(), |size| size.to_string());
+        let suffix = if entry.entry_type == EntryType::Dir { "/" } else { "" };
+        writeln!(out, "{:<9} {:>10}  {}{}", entry.entry_type.as_str(), size, entry.name, suffix)?;
+    }
+    Ok(())
+}
+
+async fn explain(resolver: &ResourceResolver, path: &str, json: bool) -> Result<()> {
+    let explanation = resolver.explain(path).await?;
+    let mut out = std::io::stdout().lock();
+    if json {
+        writeln!(out, "{}", serde_json::to_string_pretty(&explanation)?)?;
+    } else {
+        writeln!(out, "{}", explanation)?;
+    }
+    Ok(())
+}
+
+/// Recreate the tree below `path` inside `dir`, reporting progress on stderr
+async fn download(resolver: &ResourceResolver, path: &str, dir: PathBuf) -> Result<()> {
+    let root = ContentPath::parse(path)?;
+    let entries = resolver.list_directory_recursive(root.as_str()).await?;
+    let total = entries.iter().filter(|e| e.entry_type == EntryType::File).count();
+
+    tokio::fs::create_dir_all(&dir).await?;
+    let (mut written, mut bytes) = (0, 0);
+
+    for entry in &entries {
+        if entry.raw_name.is_some() {
+            eprintln!("skipped {} (name is not UTF-8)", entry.path);
+            continue;
+        }
+        let relative = ContentPath::parse(&entry.path)?
+            .strip_prefix(&root)
+            .ok_or_else(|| ContentError::InvalidStructure {
+                message: format!("Entry {} is outside {}", entry.path, root),
+            })?;
+        let target = relative.segments().fold(dir.clone(), |dir, s| dir.join(s));
+
+        match entry.entry_type {
+            EntryType::Dir => tokio::fs::create_dir_all(&target)
+                .await
+                .map_err(ContentError::from)
+                .with_path(&entry.path)?,
+            EntryType::File => {
+                let file = resolver.fetch_file(&entry.path).await?;
+                if let Some(parent) = target.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::write(&target, &file.content)
+                    .await
+                    .map_err(ContentError::from)
+                    .with_path(&entry.path)?;
+
+                written += 1;
+                bytes += file.content.len();
+                eprintln!("[{}/{}] {}", written, total, entry.path);
+            }
+            _ => eprintln!("skipped {} ({})", entry.path, entry.entry_type),
+        }
+    }
+
+    eprintln!("Downloaded {} files ({} bytes) to {}", written, bytes, dir.display());
+    Ok(())
+}
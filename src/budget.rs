@@ -0,0 +1,439 @@
+//! Client-side quotas on upstream requests
+
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncWrite;
+use tokio::time::Instant;
+
+use crate::{
+    error::{ContentError, Result},
+    hook::RequestContext,
+    source::{Capabilities, ContentSource, FetchOptions, Revalidated},
+    types::{ContentVersion, DirectoryEntry, DirectoryListing, EntryType, FileContent, FileMetadata},
+};
+
+/// A quota of upstream requests and bytes per rolling window
+/// 
+/// Enforced as token buckets that start full and refill continuously, so
+/// `with_max_requests(5000)` over an hour allows a burst of 5000 and then
+/// about one request every 0.72 seconds. Once a bucket is empty, requests
+/// fail with `RateLimited` right away, with `retry_after` set to when the
+/// next one would be allowed, instead of going out. A limit of 0 allows
+/// nothing, and requests fail without a `retry_after`. Share one budget
+/// between sources with `Arc` to give them a common quota.
+#[derive(Debug)]
+pub struct RequestBudget {
+    window: Duration,
+    max_requests: Option<u64>,
+    max_bytes: Option<u64>,
+    state: Mutex<BudgetState>,
+}
+
+#[derive(Debug)]
+struct BudgetState {
+    requests: f64,
+    bytes: f64,
+    refilled_at: Instant,
+    /// Set when the upstream reported its own quota as used up
+    blocked_until: Option<Instant>,
+}
+
+/// What is left of a `RequestBudget`, from `RequestBudget::status`
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct BudgetStatus {
+    /// Requests that can be made right away, if requests are limited
+    pub remaining_requests: Option<u64>,
+    /// Bytes that can be downloaded right away, if bytes are limited
+    pub remaining_bytes: Option<u64>,
+    /// How long until the next request is allowed; zero if it is now, and
+    /// `Duration::MAX` if a limit of 0 never allows one
+    pub available_in: Duration,
+}
+
+impl RequestBudget {
+    /// Create a budget refilling over `window`, initially without limits
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            max_requests: None,
+            max_bytes: None,
+            state: Mutex::new(BudgetState {
+                requests: 0.0,
+                bytes: 0.0,
+                refilled_at: Instant::now(),
+                blocked_until: None,
+            }),
+        }
+    }
+
+    /// Allow at most `max` requests per window
+    pub fn with_max_requests(mut self, max: u64) -> Self {
+        self.max_requests = Some(max);
+        self.state_mut().requests = max as f64;
+        self
+    }
+
+    /// Allow at most `max` bytes of file content per window
+    /// 
+    /// A file's size is only known once it has been downloaded, so the
+    /// request that crosses the limit completes, and later ones wait until
+    /// the overdraft has refilled.
+    pub fn with_max_bytes(mut self, max: u64) -> Self {
+        self.max_bytes = Some(max);
+        self.state_mut().bytes = max as f64;
+        self
+    }
+
+    fn state_mut(&mut self) -> &mut BudgetState {
+        self.state.get_mut().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BudgetState> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let refilled =
+            now.duration_since(state.refilled_at).as_secs_f64() / self.window.as_secs_f64();
+        if let Some(max) = self.max_requests {
+            state.requests = (state.requests + refilled * max as f64).min(max as f64);
+        }
+        if let Some(max) = self.max_bytes {
+            state.bytes = (state.bytes + refilled * max as f64).min(max as f64);
+        }
+        state.refilled_at = now;
+        if state.blocked_until.is_some_and(|until| until <= now) {
+            state.blocked_until = None;
+        }
+        state
+    }
+
+    /// How long until `state` allows a request, `Duration::MAX` for never
+    fn wait(&self, state: &BudgetState) -> Duration {
+        let refill = |missing: f64, max: u64| match max {
+            0 => Duration::MAX,
+            _ => Duration::from_secs_f64(missing * self.window.as_secs_f64() / max as f64),
+        };
+        let mut wait = state
+            .blocked_until
+            .map_or(Duration::ZERO, |until| until.duration_since(Instant::now()));
+        if let Some(max) = self.max_requests.filter(|_| state.requests < 1.0) {
+            wait = wait.max(refill(1.0 - state.requests, max));
+        }
+        if let Some(max) = self.max_bytes.filter(|_| state.bytes < 1.0) {
+            wait = wait.max(refill(1.0 - state.bytes, max));
+        }
+        wait
+    }
+
+    /// Take one request from the budget, or fail with `RateLimited` if it is used up
+    pub fn try_acquire(&self) -> Result<()> {
+        let mut state = self.lock();
+        let wait = self.wait(&state);
+        if wait == Duration::MAX {
+            return Err(ContentError::RateLimited {
+                message: "request budget allows no requests".to_string(),
+                retry_after: None,
+            });
+        }
+        if !wait.is_zero() {
+            return Err(ContentError::RateLimited {
+                message: "request budget exhausted".to_string(),
+                retry_after: Some(wait),
+            });
+        }
+        if self.max_requests.is_some() {
+            state.requests -= 1.0;
+        }
+        Ok(())
+    }
+
+    /// Charge downloaded bytes to the budget
+    pub fn record_bytes(&self, bytes: u64) {
+        if self.max_bytes.is_some() {
+            self.lock().bytes -= bytes as f64;
+        }
+    }
+
+    /// Align the budget with the quota the upstream reports
+    /// 
+    /// With `remaining` requests left upstream until `reset_in` has passed,
+    /// the budget allows no more than `remaining` requests, and none at all
+    /// until the reset once `remaining` is zero.
+    pub fn observe_upstream(&self, remaining: u64, reset_in: Duration) {
+        let mut state = self.lock();
+        if self.max_requests.is_some() {
+            state.requests = state.requests.min(remaining as f64);
+        }
+        if remaining == 0 {
+            state.blocked_until = Some(Instant::now() + reset_in);
+        }
+    }
+
+    /// What is left right now
+    pub fn status(&self) -> BudgetStatus {
+        let state = self.lock();
+        BudgetStatus {
+            remaining_requests: self.max_requests.map(|_| state.requests.max(0.0) as u64),
+            remaining_bytes: self.max_bytes.map(|_| state.bytes.max(0.0) as u64),
+            available_in: self.wait(&state),
+        }
+    }
+}
+
+/// Content source decorator that spends a `RequestBudget` on every request
+/// 
+/// Each call to the inner source takes one request from the budget first,
+/// and fetched files are charged by size; listings only cost a request.
+/// The identifier is the inner source's.
+pub struct BudgetedSource {
+    inner: Arc<dyn ContentSource>,
+    budget: Arc<RequestBudget>,
+    /// Added by a resolver, which replaces it when the budget changes
+    pub(crate) managed: bool,
+}
+
+impl BudgetedSource {
+    /// Wrap a source, spending `budget` on its requests
+    pub fn new(inner: Arc<dyn ContentSource>, budget: Arc<RequestBudget>) -> Self {
+        Self {
+            inner,
+            budget,
+            managed: false,
+        }
+    }
+
+    /// The wrapped source
+    pub fn inner(&self) -> &Arc<dyn ContentSource> {
+        &self.inner
+    }
+
+    /// The budget requests are taken from
+    pub fn budget(&self) -> &Arc<RequestBudget> {
+        &self.budget
+    }
+
+    fn charge(&self, file: Result<FileContent>) -> Result<FileContent> {
+        if let Ok(file) = &file {
+            self.budget.record_bytes(file.content.len() as u64);
+        }
+        file
+    }
+}
+
+#[async_trait]
+impl ContentSource for BudgetedSource {
+    async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+        self.budget.try_acquire()?;
+        self.charge(self.inner.fetch_file(path).await)
+    }
+
+    async fn fetch_file_with(&self, path: &str, options: &FetchOptions) -> Result<FileContent> {
+        self.budget.try_acquire()?;
+        self.charge(self.inner.fetch_file_with(path, options).await)
+    }
+
+    async fn fetch_file_at(&self, path: &str, version: &ContentVersion) -> Result<FileContent> {
+        self.budget.try_acquire()?;
+        self.charge(self.inner.fetch_file_at(path, version).await)
+    }
+
+    async fn fetch_to_writer(
+        &self,
+        path: &str,
+        writer: &mut (dyn AsyncWrite + Unpin + Send),
+    ) -> Result<u64> {
+        self.budget.try_acquire()?;
+        let written = self.inner.fetch_to_writer(path, writer).await?;
+        self.budget.record_bytes(written);
+        Ok(written)
+    }
+
+    async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+        self.budget.try_acquire()?;
+        self.inner.list_directory(path).await
+    }
+
+    async fn list_directory_with(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+    ) -> Result<DirectoryListing> {
+        self.budget.try_acquire()?;
+        self.inner.list_directory_with(path, options).await
+    }
+
+    async fn list_directory_if_none_match(
+        &self,
+        path: &str,
+        etag: Option<&str>,
+    ) -> Result<Revalidated<DirectoryListing>> {
+        self.budget.try_acquire()?;
+        self.inner.list_directory_if_none_match(path, etag).await
+    }
+
+    async fn fetch_file_with_ctx(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+        ctx: &RequestContext,
+    ) -> Result<FileContent> {
+        self.budget.try_acquire()?;
+        self.charge(self.inner.fetch_file_with_ctx(path, options, ctx).await)
+    }
+
+    async fn list_directory_with_ctx(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+        ctx: &RequestContext,
+    ) -> Result<DirectoryListing> {
+        self.budget.try_acquire()?;
+        self.inner.list_directory_with_ctx(path, options, ctx).await
+    }
+
+    async fn list_directory_paginated(
+        &self,
+        path: &str,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<(DirectoryListing, Option<String>)> {
+        self.budget.try_acquire()?;
+        self.inner
+            .list_directory_paginated(path, cursor, page_size)
+            .await
+    }
+
+    async fn list_directory_recursive(&self, path: &str) -> Result<Vec<DirectoryEntry>> {
+        self.budget.try_acquire()?;
+        self.inner.list_directory_recursive(path).await
+    }
+
+    fn identifier(&self) -> String {
+        self.inner.identifier()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn cacheable(&self) -> bool {
+        self.inner.cacheable()
+    }
+
+    async fn file_exists(&self, path: &str) -> bool {
+        self.budget.try_acquire().is_ok() && self.inner.file_exists(path).await
+    }
+
+    async fn path_type(&self, path: &str) -> Result<Option<EntryType>> {
+        self.budget.try_acquire()?;
+        self.inner.path_type(path).await
+    }
+
+    async fn stat(&self, path: &str) -> Result<FileMetadata> {
+        self.budget.try_acquire()?;
+        self.inner.stat(path).await
+    }
+}
+
+/// The source inside a `BudgetedSource` a resolver added, or the source itself
+pub(crate) fn unbudgeted(source: Arc<dyn ContentSource>) -> Arc<dyn ContentSource> {
+    match source.clone().into_any_arc().downcast::<BudgetedSource>() {
+        Ok(budgeted) if budgeted.managed => budgeted.inner.clone(),
+        _ => source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FaultInjectingSource, MemorySource};
+
+    fn source() -> Arc<FaultInjectingSource> {
+        let memory = MemorySource::new().with_file("a.txt", "0123456789");
+        Arc::new(FaultInjectingSource::new(Arc::new(memory)))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_request_budget() {
+        let inner = source();
+        let budget = Arc::new(RequestBudget::new(Duration::from_secs(60)).with_max_requests(2));
+        let source = BudgetedSource::new(inner.clone(), budget.clone());
+
+        source.fetch_file("a.txt").await.unwrap();
+        source.list_directory("").await.unwrap();
+        assert_eq!(budget.status().remaining_requests, Some(0));
+
+        // Exhausted: fail fast without reaching the inner source
+        let error = source.fetch_file("a.txt").await.unwrap_err();
+        assert!(error.is_rate_limited());
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(30)));
+        assert_eq!(inner.call_count(), 2);
+        assert_eq!(budget.status().available_in, Duration::from_secs(30));
+
+        // One request refills every 30 seconds
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert_eq!(budget.status().remaining_requests, Some(1));
+        source.fetch_file("a.txt").await.unwrap();
+        assert!(source
+            .fetch_file("a.txt")
+            .await
+            .unwrap_err()
+            .is_rate_limited());
+
+        tokio::time::advance(Duration::from_secs(600)).await;
+        assert_eq!(budget.status().remaining_requests, Some(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_byte_budget() {
+        let budget = Arc::new(RequestBudget::new(Duration::from_secs(10)).with_max_bytes(15));
+        let source = BudgetedSource::new(source(), budget.clone());
+
+        source.fetch_file("a.txt").await.unwrap();
+        assert_eq!(budget.status().remaining_bytes, Some(5));
+        // The request crossing the limit completes...
+        let mut out = Vec::new();
+        source.fetch_to_writer("a.txt", &mut out).await.unwrap();
+        // ...and the overdraft has to refill first
+        let error = source.fetch_file("a.txt").await.unwrap_err();
+        assert!(error.is_rate_limited());
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(4)));
+
+        tokio::time::advance(Duration::from_secs(4)).await;
+        assert!(source.fetch_file("a.txt").await.is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_zero_budget_denies_everything() {
+        let inner = source();
+        let budget = Arc::new(RequestBudget::new(Duration::from_secs(60)).with_max_requests(0));
+        let source = BudgetedSource::new(inner.clone(), budget.clone());
+
+        let error = source.fetch_file("a.txt").await.unwrap_err();
+        assert!(error.is_rate_limited());
+        assert_eq!(error.retry_after(), None);
+        assert_eq!(inner.call_count(), 0);
+        assert_eq!(budget.status().available_in, Duration::MAX);
+
+        tokio::time::advance(Duration::from_secs(600)).await;
+        assert!(source.fetch_file("a.txt").await.is_err());
+
+        let bytes = RequestBudget::new(Duration::from_secs(60)).with_max_bytes(0);
+        assert_eq!(bytes.try_acquire().unwrap_err().retry_after(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_observe_upstream() {
+        let budget = RequestBudget::new(Duration::from_secs(3600)).with_max_requests(5000);
+        budget.observe_upstream(10, Duration::from_secs(100));
+        assert_eq!(budget.status().remaining_requests, Some(10));
+
+        budget.observe_upstream(0, Duration::from_secs(100));
+        let error = budget.try_acquire().unwrap_err();
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(100)));
+
+        tokio::time::advance(Duration::from_secs(100)).await;
+        assert!(budget.try_acquire().is_ok());
+    }
+}
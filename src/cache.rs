@@ -1,12 +1,26 @@
 use async_trait::async_trait;
 use bytes::Bytes;
+#[cfg(feature = "fs")]
+use std::collections::BTreeMap;
 use std::collections::HashMap;
-use std::path::PathBuf;
+#[cfg(feature = "fs")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "fs")]
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+#[cfg(any(feature = "fs", feature = "redis"))]
+use std::time::Duration;
+use std::time::SystemTime;
+#[cfg(feature = "fs")]
 use tokio::fs;
 use tokio::sync::RwLock;
 
-use crate::error::{ContentError, Result};
+#[cfg(any(feature = "fs", feature = "redis"))]
+use crate::error::ContentError;
+use crate::error::Result;
+use crate::eviction::Evictor;
+
+pub use crate::eviction::EvictionPolicy;
 
 /// Cache interface for storing content
 #[async_trait]
@@ -25,19 +39,221 @@ pub trait Cache: Send + Sync {
 
     /// Clear all cached content
     async fn clear(&self) -> Result<()>;
+
+    /// Get cached content even if its TTL has passed
+    /// 
+    /// Used by `ResourceResolver::with_serve_stale_on_error` during outages.
+    /// The default is `get`, for caches that drop entries once they expire.
+    async fn get_stale(&self, key: &str) -> Result<Option<Bytes>> {
+        self.get(key).await
+    }
+
+    /// Store content only if the key isn't cached yet
+    /// 
+    /// Returns whether the value was written. Meant for priming a cache from a
+    /// snapshot or a bulk import while it serves live traffic, so entries
+    /// fetched in the meantime aren't clobbered. The default checks `contains`
+    /// before `set`, which can race with a concurrent write; the built-in
+    /// caches override it to decide atomically.
+    async fn set_if_absent(&self, key: &str, value: Bytes) -> Result<bool> {
+        if self.contains(key).await {
+            return Ok(false);
+        }
+        self.set(key, value).await?;
+        Ok(true)
+    }
+
+    /// Store content unless the cached entry was stored after `as_of`
+    /// 
+    /// `as_of` is when `value` was current, e.g. when the snapshot holding it
+    /// was taken. Missing entries are always written. Returns whether the
+    /// value was written. The default, for caches that don't record when
+    /// entries were stored, is `set_if_absent`.
+    async fn set_if_newer(&self, key: &str, value: Bytes, as_of: SystemTime) -> Result<bool> {
+        let _ = as_of;
+        self.set_if_absent(key, value).await
+    }
+
+    /// Mark an entry as just stored, restarting its TTL
+    /// 
+    /// For use after the source confirms the cached value is still current,
+    /// e.g. with a `304 Not Modified`. Returns whether the entry exists. The
+    /// default rewrites the value; caches with a TTL override it.
+    async fn touch(&self, key: &str) -> Result<bool> {
+        match self.get(key).await? {
+            Some(value) => {
+                self.set(key, value).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
 }
 
 /// In-memory cache implementation
+/// 
+/// Unbounded by default; `MemoryCache::bounded` caps the number of entries
+/// and picks which to evict with an `EvictionPolicy`.
 pub struct MemoryCache {
-    store: Arc<RwLock<HashMap<String, Bytes>>>,
+    store: Arc<RwLock<HashMap<String, MemoryEntry>>>,
+    eviction: Option<std::sync::Mutex<Evictor>>,
+}
+
+/// A `MemoryCache` value and when it was stored
+struct MemoryEntry {
+    value: Bytes,
+    stored_at: SystemTime,
 }
 
+impl MemoryEntry {
+    fn new(value: Bytes) -> Self {
+        Self {
+            value,
+            stored_at: SystemTime::now(),
+        }
+    }
+}
+
+/// Header of a `MemoryCache` snapshot file
+#[cfg(feature = "fs")]
+const SNAPSHOT_MAGIC: &[u8] = b"content-resolver-cache-v1\n";
+
 impl MemoryCache {
     pub fn new() -> Self {
         Self {
             store: Arc::new(RwLock::new(HashMap::new())),
+            eviction: None,
+        }
+    }
+
+    /// Create a cache holding at most `max_entries` entries
+    /// 
+    /// Storing a new key in a full cache evicts the entry `policy` picks.
+    /// Reads with `get` count as uses; `contains` doesn't. For workloads with
+    /// a hot set and scans over a long tail, `EvictionPolicy::SegmentedLru`
+    /// or `EvictionPolicy::Lfu` keep the scans from flushing the hot set out.
+    /// 
+    /// A `max_entries` of 0 is treated as 1, so the most recent entry is
+    /// always kept; use `NoCache` to cache nothing.
+    pub fn bounded(max_entries: usize, policy: EvictionPolicy) -> Self {
+        Self {
+            store: Arc::new(RwLock::new(HashMap::new())),
+            eviction: Some(std::sync::Mutex::new(Evictor::new(policy, max_entries))),
+        }
+    }
+
+    /// Number of cached entries
+    pub async fn len(&self) -> usize {
+        self.store.read().await.len()
+    }
+
+    /// Whether nothing is cached
+    pub async fn is_empty(&self) -> bool {
+        self.store.read().await.is_empty()
+    }
+
+    fn evictor(&self) -> Option<std::sync::MutexGuard<'_, Evictor>> {
+        let eviction = self.eviction.as_ref()?;
+        Some(eviction.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Store an entry, evicting another if the cache is full
+    fn insert_entry(
+        &self,
+        store: &mut HashMap<String, MemoryEntry>,
+        key: String,
+        entry: MemoryEntry,
+    ) {
+        if let Some(mut evictor) = self.evictor() {
+            if store.contains_key(&key) {
+                evictor.touch(&key);
+            } else if let Some(victim) = evictor.insert(&key) {
+                store.remove(&victim);
+            }
+        }
+        store.insert(key, entry);
+    }
+
+    /// Copy every entry out of the cache, sorted by key
+    /// 
+    /// Taken under a single read lock, so concurrent writes are either fully
+    /// included or not at all.
+    pub async fn export(&self) -> Vec<(String, Bytes)> {
+        let store = self.store.read().await;
+        let mut entries: Vec<_> = store
+            .iter()
+            .map(|(key, entry)| (key.clone(), entry.value.clone()))
+            .collect();
+        drop(store);
+
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    /// Insert entries, replacing any existing values for the same keys
+    pub async fn import(&self, entries: impl IntoIterator<Item = (String, Bytes)>) {
+        let mut store = self.store.write().await;
+        for (key, value) in entries {
+            self.insert_entry(&mut store, key, MemoryEntry::new(value));
         }
     }
+
+    /// Insert only the entries whose keys aren't cached yet
+    /// 
+    /// Returns the number of entries inserted. Taken under a single write
+    /// lock, like `import`, so it can't overwrite a concurrent `set`.
+    pub async fn import_if_absent(&self, entries: impl IntoIterator<Item = (String, Bytes)>) -> usize {
+        let mut store = self.store.write().await;
+        let mut inserted = 0;
+        for (key, value) in entries {
+            if !store.contains_key(&key) {
+                self.insert_entry(&mut store, key, MemoryEntry::new(value));
+                inserted += 1;
+            }
+        }
+        inserted
+    }
+
+    /// Write every entry to a single snapshot file
+    /// 
+    /// The snapshot is written to a temporary file next to `path`, flushed to
+    /// disk and renamed into place, so a crash never leaves a truncated
+    /// snapshot behind. Each save uses its own temporary file, so concurrent
+    /// saves to the same path don't mix their contents; the last rename wins.
+    #[cfg(feature = "fs")]
+    pub async fn save_snapshot(&self, path: &Path) -> Result<()> {
+        let entries = self.export().await;
+
+        let size = entries.iter().map(|(k, v)| 12 + k.len() + v.len()).sum::<usize>();
+        let mut buf = Vec::with_capacity(SNAPSHOT_MAGIC.len() + size);
+        buf.extend_from_slice(SNAPSHOT_MAGIC);
+        for (key, value) in &entries {
+            buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+            buf.extend_from_slice(key.as_bytes());
+            buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            buf.extend_from_slice(value);
+        }
+
+        let tmp = temp_path(path);
+        let written = write_durably(&tmp, path, &buf).await;
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp).await;
+        }
+        Ok(written?)
+    }
+
+    /// Import every entry from a snapshot written by `save_snapshot`
+    /// 
+    /// Returns the number of entries loaded. A malformed snapshot fails with
+    /// `ContentError::Cache` without importing anything.
+    #[cfg(feature = "fs")]
+    pub async fn load_snapshot(&self, path: &Path) -> Result<usize> {
+        let data = Bytes::from(fs::read(path).await?);
+        let entries = parse_snapshot(data)?;
+        let count = entries.len();
+        self.import(entries).await;
+        Ok(count)
+    }
 }
 
 impl Default for MemoryCache {
@@ -50,15 +266,38 @@ impl Default for MemoryCache {
 impl Cache for MemoryCache {
     async fn get(&self, key: &str) -> Result<Option<Bytes>> {
         let store = self.store.read().await;
-        Ok(store.get(key).cloned())
+        let value = store.get(key).map(|entry| entry.value.clone());
+        if let (Some(_), Some(mut evictor)) = (&value, self.evictor()) {
+            evictor.touch(key);
+        }
+        Ok(value)
     }
 
     async fn set(&self, key: &str, value: Bytes) -> Result<()> {
         let mut store = self.store.write().await;
-        store.insert(key.to_string(), value);
+        self.insert_entry(&mut store, key.to_string(), MemoryEntry::new(value));
         Ok(())
     }
 
+    async fn set_if_absent(&self, key: &str, value: Bytes) -> Result<bool> {
+        let mut store = self.store.write().await;
+        if store.contains_key(key) {
+            return Ok(false);
+        }
+        self.insert_entry(&mut store, key.to_string(), MemoryEntry::new(value));
+        Ok(true)
+    }
+
+    /// Compares with when the entry was stored; the new entry counts as stored at `as_of`
+    async fn set_if_newer(&self, key: &str, value: Bytes, as_of: SystemTime) -> Result<bool> {
+        let mut store = self.store.write().await;
+        if store.get(key).is_some_and(|entry| entry.stored_at > as_of) {
+            return Ok(false);
+        }
+        self.insert_entry(&mut store, key.to_string(), MemoryEntry { value, stored_at: as_of });
+        Ok(true)
+    }
+
     async fn contains(&self, key: &str) -> bool {
         let store = self.store.read().await;
         store.contains_key(key)
@@ -66,75 +305,749 @@ impl Cache for MemoryCache {
 
     async fn remove(&self, key: &str) -> Result<()> {
         let mut store = self.store.write().await;
-        store.remove(key);
+        if store.remove(key).is_some() {
+            if let Some(mut evictor) = self.evictor() {
+                evictor.remove(key);
+            }
+        }
         Ok(())
     }
 
     async fn clear(&self) -> Result<()> {
         let mut store = self.store.write().await;
         store.clear();
+        if let Some(mut evictor) = self.evictor() {
+            evictor.clear();
+        }
         Ok(())
     }
 }
 
+/// Decode the entries of a snapshot file
+#[cfg(feature = "fs")]
+fn parse_snapshot(mut data: Bytes) -> Result<Vec<(String, Bytes)>> {
+    if !data.starts_with(SNAPSHOT_MAGIC) {
+        return Err(corrupt_snapshot("unknown header"));
+    }
+    let _ = data.split_to(SNAPSHOT_MAGIC.len());
+
+    let mut entries = Vec::new();
+    while !data.is_empty() {
+        let key_len = u32::from_le_bytes(take_array(&mut data)?) as usize;
+        let key = String::from_utf8(take(&mut data, key_len)?.to_vec())
+            .map_err(|_| corrupt_snapshot("key is not UTF-8"))?;
+        let value_len = u64::from_le_bytes(take_array(&mut data)?) as usize;
+        entries.push((key, take(&mut data, value_len)?));
+    }
+    Ok(entries)
+}
+
+#[cfg(feature = "fs")]
+fn take(data: &mut Bytes, len: usize) -> Result<Bytes> {
+    if data.len() < len {
+        return Err(corrupt_snapshot("truncated entry"));
+    }
+    Ok(data.split_to(len))
+}
+
+#[cfg(feature = "fs")]
+fn take_array<const N: usize>(data: &mut Bytes) -> Result<[u8; N]> {
+    let raw = take(data, N)?;
+    Ok(raw[..].try_into().expect("took exactly N bytes"))
+}
+
+#[cfg(feature = "fs")]
+fn corrupt_snapshot(what: &str) -> ContentError {
+    ContentError::Cache {
+        message: format!("Corrupt cache snapshot: {}", what),
+    }
+}
+
+/// Size of a `DiskCache`, as reported by `DiskCache::stats`
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiskCacheStats {
+    /// Number of cached entries
+    pub entries: u64,
+    /// Total size of the cached values, in bytes
+    pub bytes: u64,
+}
+
+/// Entry format versions of a `DiskCache`, from `DiskCache::format_stats`
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DiskCacheFormatStats {
+    /// Number of entries stored in each format version
+    pub versions: BTreeMap<u8, u64>,
+    /// Reads that found an entry written by a newer crate version and
+    /// treated it as a miss, since the cache was opened
+    pub newer_version_reads: u64,
+    /// Reads that found an entry stored under another key mapping to the
+    /// same file, and treated it as a miss, since the cache was opened
+    pub key_collisions: u64,
+}
+
+/// What `DiskCache::migrate_in_place` did
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MigrationReport {
+    /// Entries rewritten in the current format
+    pub migrated: u64,
+    /// Entries already in the current format
+    pub current: u64,
+    /// Entries written by a newer crate version, left alone
+    pub skipped: u64,
+    /// Entries that couldn't be decoded, left alone
+    pub corrupt: u64,
+}
+
+/// How a `DiskCache` maps cache keys to file names
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum KeyStrategy {
+    /// Hex SHA-256 of the key, in 256 subdirectories by its first byte
+    #[default]
+    Sha256,
+    /// Hex BLAKE3 of the key, laid out like `Sha256`
+    /// 
+    /// Cheaper to compute, which shows for many small keys on slow CPUs.
+    #[cfg(feature = "blake3")]
+    Blake3,
+    /// The key itself, with every byte other than ASCII letters, digits, `-`
+    /// and `_` percent-encoded, in a `plain` subdirectory
+    /// 
+    /// Readable file names for development, and no hashing for short keys.
+    /// Names longer than `max_len` fall back to `+` and the SHA-256 of the
+    /// key; most filesystems allow 255 bytes.
+    SanitizedPlain {
+        /// Longest encoded name kept readable
+        max_len: usize,
+    },
+}
+
+#[cfg(feature = "fs")]
+impl KeyStrategy {
+    /// How the strategy is recorded in the marker file
+    fn marker(&self) -> String {
+        match self {
+            KeyStrategy::Sha256 => "sha256".to_string(),
+            #[cfg(feature = "blake3")]
+            KeyStrategy::Blake3 => "blake3".to_string(),
+            KeyStrategy::SanitizedPlain { max_len } => format!("sanitized-plain max_len={}", max_len),
+        }
+    }
+}
+
+/// What a `DiskCache` does when its directory was written with another `KeyStrategy`
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StrategyMismatch {
+    /// Fail every operation with `ContentError::Cache`
+    #[default]
+    Error,
+    /// Delete every entry and start over with the new strategy
+    Clear,
+}
+
+/// File in a `DiskCache` directory recording its `KeyStrategy`
+#[cfg(feature = "fs")]
+const KEY_STRATEGY_MARKER: &str = "key-strategy";
+
+/// Subdirectory of `KeyStrategy::SanitizedPlain` entries
+#[cfg(feature = "fs")]
+const PLAIN_DIR: &str = "plain";
+
+/// Percent-encode every byte of `key` but ASCII letters, digits, `-` and `_`
+/// 
+/// The result has no `.`, so it can't be mistaken for a temporary file.
+#[cfg(feature = "fs")]
+fn sanitize_key(key: &str) -> String {
+    let mut encoded = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Marks a `DiskCache` file holding a versioned entry
+/// 
+/// Files without it are version 0: the raw value, as written before entries
+/// had a header.
+#[cfg(feature = "fs")]
+const ENTRY_MAGIC: &[u8; 8] = b"\x89CRDC\r\n\x1a";
+
+/// Entry format version `DiskCache` writes
+#[cfg(feature = "fs")]
+pub const DISK_FORMAT_VERSION: u8 = 1;
+
+/// Bytes before the metadata block: magic, version and metadata length
+#[cfg(feature = "fs")]
+const ENTRY_PREFIX_LEN: usize = ENTRY_MAGIC.len() + 1 + 4;
+
+/// Metadata block of a version 1 entry
+/// 
+/// Unknown fields are ignored, so later crate versions can add fields
+/// without a new format version.
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+struct EntryMeta {
+    /// Length of the value in bytes
+    size: u64,
+    /// Hex SHA-256 of the value
+    sha256: String,
+    /// When the entry was written, in seconds since the Unix epoch
+    stored_at: u64,
+    /// The cache key, to tell colliding keys apart; unknown for migrated entries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+}
+
+/// A `DiskCache` file, decoded according to its format version
+#[cfg(feature = "fs")]
+#[derive(Debug)]
+enum DiskEntry {
+    V0(Bytes),
+    V1 { meta: EntryMeta, value: Bytes },
+    /// Written by a newer crate version, in a format this one can't read
+    Newer,
+}
+
+/// Encode `value`, stored under `key` if known, as an entry in the current format
+#[cfg(feature = "fs")]
+fn encode_entry(key: Option<&str>, value: &[u8]) -> Vec<u8> {
+    let meta = EntryMeta {
+        size: value.len() as u64,
+        sha256: crate::integrity::sha256_hex_of(value),
+        stored_at: SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        key: key.map(str::to_string),
+    };
+    let meta = serde_json::to_vec(&meta).expect("entry metadata serializes");
+
+    let mut buf = Vec::with_capacity(ENTRY_PREFIX_LEN + meta.len() + value.len());
+    buf.extend_from_slice(ENTRY_MAGIC);
+    buf.push(DISK_FORMAT_VERSION);
+    buf.extend_from_slice(&(meta.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&meta);
+    buf.extend_from_slice(value);
+    buf
+}
+
+/// Decode a `DiskCache` file, checking a version 1 value against its metadata
+#[cfg(feature = "fs")]
+fn decode_entry(mut data: Bytes) -> Result<DiskEntry> {
+    if !data.starts_with(ENTRY_MAGIC) {
+        return Ok(DiskEntry::V0(data));
+    }
+    if data.len() < ENTRY_PREFIX_LEN {
+        return Err(corrupt_entry("truncated header"));
+    }
+    let version = data[ENTRY_MAGIC.len()];
+    match version {
+        1 => {}
+        0 => return Err(corrupt_entry("version 0 has no header")),
+        _ => return Ok(DiskEntry::Newer),
+    }
+    let _ = data.split_to(ENTRY_MAGIC.len() + 1);
+
+    let meta_len = u32::from_le_bytes(data.split_to(4)[..].try_into().expect("4 bytes")) as usize;
+    if data.len() < meta_len {
+        return Err(corrupt_entry("truncated metadata"));
+    }
+    let meta: EntryMeta = serde_json::from_slice(&data.split_to(meta_len))
+        .map_err(|e| corrupt_entry(&format!("bad metadata: {}", e)))?;
+    if data.len() as u64 != meta.size || crate::integrity::sha256_hex_of(&data) != meta.sha256 {
+        return Err(corrupt_entry("value doesn't match its checksum"));
+    }
+    Ok(DiskEntry::V1 { meta, value: data })
+}
+
+#[cfg(feature = "fs")]
+fn corrupt_entry(what: &str) -> ContentError {
+    ContentError::Cache {
+        message: format!("Corrupt disk cache entry: {}", what),
+    }
+}
+
+/// A temporary file name next to `path`, unique within the machine
+/// 
+/// Contains a `.`, which cache keys never do once mapped to file names.
+#[cfg(feature = "fs")]
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.{}.tmp", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed)));
+    PathBuf::from(tmp)
+}
+
+/// Write `data` to `tmp`, sync it and rename it to `path`, then sync the directory
+/// 
+/// The caller removes `tmp` if this fails.
+#[cfg(feature = "fs")]
+async fn write_durably(tmp: &Path, path: &Path, data: &[u8]) -> std::io::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut file = fs::File::create(tmp).await?;
+    file.write_all(data).await?;
+    file.sync_all().await?;
+    drop(file);
+    fs::rename(tmp, path).await?;
+    // Makes the rename itself durable; directories can't be opened on Windows
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::File::open(dir).await?.sync_all().await?;
+    }
+    Ok(())
+}
+
+/// Format version of a `DiskCache` file and the size of the value in it
+/// 
+/// Only reads the header. The value size of an entry from a newer crate
+/// version is taken to be the whole file.
+#[cfg(feature = "fs")]
+async fn entry_version(path: &Path, file_len: u64) -> std::io::Result<(u8, u64)> {
+    use tokio::io::AsyncReadExt;
+
+    let mut prefix = Vec::with_capacity(ENTRY_PREFIX_LEN);
+    fs::File::open(path)
+        .await?
+        .take(ENTRY_PREFIX_LEN as u64)
+        .read_to_end(&mut prefix)
+        .await?;
+    if prefix.len() < ENTRY_PREFIX_LEN || !prefix.starts_with(ENTRY_MAGIC) {
+        return Ok((0, file_len));
+    }
+    let version = prefix[ENTRY_MAGIC.len()];
+    if version > DISK_FORMAT_VERSION {
+        return Ok((version, file_len));
+    }
+    let meta_len = u32::from_le_bytes(prefix[ENTRY_MAGIC.len() + 1..].try_into().expect("4 bytes"));
+    let header_len = ENTRY_PREFIX_LEN as u64 + u64::from(meta_len);
+    Ok((version, file_len.saturating_sub(header_len)))
+}
+
 /// Disk-based cache implementation
+/// 
+/// Each entry is a file starting with a magic string and a format version,
+/// followed by a length-prefixed JSON metadata block and the value. Files
+/// written before the format existed hold only the value; they are read as
+/// version 0 and upgraded by `migrate_in_place`, or on read with
+/// `with_migrate_on_read`. Entries from a newer crate version are misses.
+#[cfg(feature = "fs")]
 pub struct DiskCache {
     root_dir: PathBuf,
+    ttl: Option<Duration>,
+    migrate_on_read: bool,
+    key_strategy: KeyStrategy,
+    on_strategy_mismatch: StrategyMismatch,
+    /// Set once the directory's key strategy marker has been checked
+    marker_checked: tokio::sync::OnceCell<()>,
+    newer_version_reads: AtomicU64,
+    key_collisions: AtomicU64,
+    /// Held shared by writes, and exclusively while an entry is migrated
+    migration: tokio::sync::RwLock<()>,
 }
 
+#[cfg(feature = "fs")]
 impl DiskCache {
     /// Create a new disk cache at the specified directory
     pub async fn new(root_dir: PathBuf) -> Result<Self> {
         fs::create_dir_all(&root_dir).await?;
-        Ok(Self { root_dir })
+        Ok(Self {
+            root_dir,
+            ttl: None,
+            migrate_on_read: false,
+            key_strategy: KeyStrategy::default(),
+            on_strategy_mismatch: StrategyMismatch::default(),
+            marker_checked: tokio::sync::OnceCell::new(),
+            newer_version_reads: AtomicU64::new(0),
+            key_collisions: AtomicU64::new(0),
+            migration: tokio::sync::RwLock::new(()),
+        })
+    }
+
+    /// Choose how keys map to file names; defaults to `KeyStrategy::Sha256`
+    /// 
+    /// The strategy is recorded in a marker file in the directory on first
+    /// use. Opening the directory with another strategy would misread its
+    /// entries, so it fails with `ContentError::Cache` instead, or clears the
+    /// directory with `with_strategy_mismatch(StrategyMismatch::Clear)`.
+    pub fn with_key_strategy(mut self, strategy: KeyStrategy) -> Self {
+        self.key_strategy = strategy;
+        self
+    }
+
+    /// Choose what happens when the directory was written with another key strategy
+    pub fn with_strategy_mismatch(mut self, on_mismatch: StrategyMismatch) -> Self {
+        self.on_strategy_mismatch = on_mismatch;
+        self
+    }
+
+    /// Treat entries written more than `ttl` ago as missing
+    /// 
+    /// Age is taken from the file's modification time. Expired files stay on
+    /// disk until they are overwritten, removed or the cache is cleared.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Rewrite entries in an older format in the current one as they are read
+    /// 
+    /// Spreads the cost of `migrate_in_place` over normal use. The file's
+    /// modification time is kept, so the TTL isn't restarted. A failed
+    /// rewrite leaves the old entry in place and doesn't fail the read.
+    pub fn with_migrate_on_read(mut self, enabled: bool) -> Self {
+        self.migrate_on_read = enabled;
+        self
+    }
+
+    fn is_expired(&self, metadata: &std::fs::Metadata) -> bool {
+        let Some(ttl) = self.ttl else {
+            return false;
+        };
+        metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > ttl)
+    }
+
+    /// Every entry file with its metadata, leaving out temporary files
+    async fn entry_files(&self) -> Result<Vec<(PathBuf, std::fs::Metadata)>> {
+        let mut entries = Vec::new();
+        let mut shards = fs::read_dir(&self.root_dir).await?;
+        while let Some(shard) = shards.next_entry().await? {
+            if !shard.file_type().await?.is_dir() {
+                continue;
+            }
+            let mut files = fs::read_dir(shard.path()).await?;
+            while let Some(file) = files.next_entry().await? {
+                let metadata = file.metadata().await?;
+                if metadata.is_file() && !file.file_name().to_string_lossy().contains('.') {
+                    entries.push((file.path(), metadata));
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Count the entries stored and their total size
+    pub async fn stats(&self) -> Result<DiskCacheStats> {
+        let mut stats = DiskCacheStats::default();
+        for (path, metadata) in self.entry_files().await? {
+            match entry_version(&path, metadata.len()).await {
+                Ok((_, size)) => {
+                    stats.entries += 1;
+                    stats.bytes += size;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Count the entries stored in each format version
+    pub async fn format_stats(&self) -> Result<DiskCacheFormatStats> {
+        let mut stats = DiskCacheFormatStats {
+            newer_version_reads: self.newer_version_reads.load(Ordering::Relaxed),
+            key_collisions: self.key_collisions.load(Ordering::Relaxed),
+            ..Default::default()
+        };
+        for (path, metadata) in self.entry_files().await? {
+            match entry_version(&path, metadata.len()).await {
+                Ok((version, _)) => *stats.versions.entry(version).or_default() += 1,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Rewrite every entry in an older format in the current one
+    /// 
+    /// Modification times, and with them TTLs, are kept. Entries from a newer
+    /// crate version are skipped, and corrupt ones counted and left alone.
+    /// Safe to run while the cache is in use: writes through this cache wait
+    /// while an entry is rewritten, so none is replaced by its old value, and
+    /// entries removed meanwhile are ignored. Writes from other processes
+    /// sharing the directory aren't held off.
+    pub async fn migrate_in_place(&self) -> Result<MigrationReport> {
+        let mut report = MigrationReport::default();
+        for (path, _) in self.entry_files().await? {
+            match self.upgrade(&path, None).await {
+                Ok(Some(DiskEntry::V0(_))) => report.migrated += 1,
+                Ok(Some(DiskEntry::V1 { .. })) => report.current += 1,
+                Ok(Some(DiskEntry::Newer)) => report.skipped += 1,
+                Ok(None) => {}
+                Err(ContentError::Cache { .. }) => report.corrupt += 1,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Rewrite the entry at `path` in the current format if it is a version 0 one
+    /// 
+    /// Returns the entry as it was found, or `None` if there was none. The
+    /// file is read again with writes held off, so a value set since the
+    /// caller last read it is never replaced.
+    async fn upgrade(&self, path: &Path, key: Option<&str>) -> Result<Option<DiskEntry>> {
+        let _writes = self.migration.write().await;
+        let data = match fs::read(path).await {
+            Ok(data) => Bytes::from(data),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let entry = decode_entry(data)?;
+        if let DiskEntry::V0(value) = &entry {
+            let modified = fs::metadata(path).await?.modified()?;
+            self.replace(path, &encode_entry(key, value), modified).await?;
+        }
+        Ok(Some(entry))
+    }
+
+    /// Write `value` to a fresh temporary file next to `path`
+    /// 
+    /// The caller moves it into place and removes it if that fails.
+    async fn write_temp(&self, path: &Path, value: &[u8]) -> Result<PathBuf> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let tmp = temp_path(path);
+
+        fs::write(&tmp, value).await.map_err(disk_write_error)?;
+        Ok(tmp)
+    }
+
+    /// Atomically replace the file at `path` with `data`, modified at `modified`
+    async fn replace(&self, path: &Path, data: &[u8], modified: SystemTime) -> Result<()> {
+        let tmp = self.write_temp(path, data).await?;
+
+        let (moved, path) = (tmp.clone(), path.to_path_buf());
+        let written = tokio::task::spawn_blocking(move || {
+            std::fs::File::options().write(true).open(&moved)?.set_modified(modified)?;
+            std::fs::rename(&moved, &path)
+        })
+        .await
+        .map_err(std::io::Error::other)
+        .and_then(|result| result);
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp).await;
+        }
+        written.map_err(disk_write_error)
     }
 
     /// Convert a cache key to a safe file path
     fn key_to_path(&self, key: &str) -> PathBuf {
-        // Use SHA-256 hash to create a safe filename
-        use sha2::{Digest, Sha256};
-        let mut hasher = Sha256::new();
-        hasher.update(key.as_bytes());
-        let hash = hasher.finalize();
-        let hash_str = format!("{:x}", hash);
-        
-        self.root_dir.join(&hash_str[..2]).join(&hash_str[2..])
+        match self.key_strategy {
+            KeyStrategy::Sha256 => {
+                let hash_str = crate::integrity::sha256_hex_of(key.as_bytes());
+                self.root_dir.join(&hash_str[..2]).join(&hash_str[2..])
+            }
+            #[cfg(feature = "blake3")]
+            KeyStrategy::Blake3 => {
+                let hash_str = blake3::hash(key.as_bytes()).to_hex();
+                self.root_dir.join(&hash_str[..2]).join(&hash_str[2..])
+            }
+            KeyStrategy::SanitizedPlain { max_len } => {
+                let encoded = sanitize_key(key);
+                let name = if encoded.len() <= max_len {
+                    encoded
+                } else {
+                    // `+` never appears in encoded keys
+                    format!("+{}", crate::integrity::sha256_hex_of(key.as_bytes()))
+                };
+                self.root_dir.join(PLAIN_DIR).join(name)
+            }
+        }
+    }
+
+    /// The file path of a key, once the directory is known to use the key strategy
+    async fn entry_path(&self, key: &str) -> Result<PathBuf> {
+        self.marker_checked.get_or_try_init(|| self.check_marker()).await?;
+        Ok(self.key_to_path(key))
+    }
+
+    /// Compare the key strategy recorded in the directory with this cache's
+    /// 
+    /// A directory without a marker holds entries from before strategies
+    /// were recorded, laid out like `Sha256`, unless it is empty.
+    async fn check_marker(&self) -> Result<()> {
+        let recorded = match fs::read_to_string(self.root_dir.join(KEY_STRATEGY_MARKER)).await {
+            Ok(recorded) => recorded.trim().to_string(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                if self.key_strategy == KeyStrategy::Sha256 || self.entry_files().await?.is_empty() {
+                    return self.write_marker().await;
+                }
+                KeyStrategy::Sha256.marker()
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if recorded == self.key_strategy.marker() {
+            return Ok(());
+        }
+
+        match self.on_strategy_mismatch {
+            StrategyMismatch::Error => Err(ContentError::Cache {
+                message: format!(
+                    "Disk cache at {} uses key strategy {}, not {}",
+                    self.root_dir.display(),
+                    recorded,
+                    self.key_strategy.marker()
+                ),
+            }),
+            StrategyMismatch::Clear => {
+                fs::remove_dir_all(&self.root_dir).await?;
+                fs::create_dir_all(&self.root_dir).await?;
+                self.write_marker().await
+            }
+        }
+    }
+
+    async fn write_marker(&self) -> Result<()> {
+        let marker = self.root_dir.join(KEY_STRATEGY_MARKER);
+        fs::write(marker, self.key_strategy.marker()).await.map_err(disk_write_error)
     }
 }
 
+#[cfg(feature = "fs")]
 #[async_trait]
 impl Cache for DiskCache {
     async fn get(&self, key: &str) -> Result<Option<Bytes>> {
-        let path = self.key_to_path(key);
+        let path = self.entry_path(key).await?;
+
+        if self.ttl.is_some() {
+            match fs::metadata(&path).await {
+                Ok(metadata) if self.is_expired(&metadata) => return Ok(None),
+                _ => {}
+            }
+        }
         
-        match fs::read(&path).await {
-            Ok(data) => Ok(Some(Bytes::from(data))),
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
-            Err(e) => Err(ContentError::Cache {
-                message: format!("Failed to read from disk cache: {}", e),
-            }),
+        self.get_stale(key).await
+    }
+
+    /// Read the file regardless of its age, as expired files stay on disk
+    async fn get_stale(&self, key: &str) -> Result<Option<Bytes>> {
+        let path = self.entry_path(key).await?;
+        let data = match fs::read(&path).await {
+            Ok(data) => Bytes::from(data),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(ContentError::Cache {
+                    message: format!("Failed to read from disk cache: {}", e),
+                })
+            }
+        };
+        match decode_entry(data)? {
+            DiskEntry::V0(value) => {
+                if self.migrate_on_read {
+                    let _ = self.upgrade(&path, Some(key)).await;
+                }
+                Ok(Some(value))
+            }
+            DiskEntry::V1 { meta, value } => {
+                if meta.key.is_some_and(|stored| stored != key) {
+                    // Another key mapped to the same file
+                    self.key_collisions.fetch_add(1, Ordering::Relaxed);
+                    return Ok(None);
+                }
+                Ok(Some(value))
+            }
+            DiskEntry::Newer => {
+                self.newer_version_reads.fetch_add(1, Ordering::Relaxed);
+                Ok(None)
+            }
         }
     }
 
     async fn set(&self, key: &str, value: Bytes) -> Result<()> {
-        let path = self.key_to_path(key);
+        let path = self.entry_path(key).await?;
+        let _migration = self.migration.read().await;
         
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent).await?;
         }
         
-        fs::write(&path, &value).await.map_err(|e| ContentError::Cache {
-            message: format!("Failed to write to disk cache: {}", e),
-        })
+        fs::write(&path, encode_entry(Some(key), &value)).await.map_err(disk_write_error)
+    }
+
+    /// Link a fully written temporary file into place, which fails if the
+    /// entry appeared since the check, where a rename would replace it
+    async fn set_if_absent(&self, key: &str, value: Bytes) -> Result<bool> {
+        if self.contains(key).await {
+            return Ok(false);
+        }
+        let path = self.entry_path(key).await?;
+        let _migration = self.migration.read().await;
+        let tmp = self.write_temp(&path, &encode_entry(Some(key), &value)).await?;
+
+        let written = match fs::hard_link(&tmp, &path).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                // An expired entry, or one from a newer crate version, counts as absent
+                if self.contains(key).await {
+                    Ok(false)
+                } else {
+                    fs::rename(&tmp, &path).await.map(|()| true)
+                }
+            }
+            Err(e) => Err(e),
+        };
+        let _ = fs::remove_file(&tmp).await;
+        written.map_err(disk_write_error)
+    }
+
+    /// Compares with the file's modification time, which is set to `as_of`,
+    /// so the TTL counts from `as_of`
+    /// 
+    /// The value is moved into place with a rename, so readers never see a
+    /// partial file, but a `set` landing between the check and the rename is
+    /// still replaced.
+    async fn set_if_newer(&self, key: &str, value: Bytes, as_of: SystemTime) -> Result<bool> {
+        let path = self.entry_path(key).await?;
+        let _migration = self.migration.read().await;
+        if let Ok(metadata) = fs::metadata(&path).await {
+            if metadata.modified().is_ok_and(|stored| stored > as_of) {
+                return Ok(false);
+            }
+        }
+        self.replace(&path, &encode_entry(Some(key), &value), as_of).await?;
+        Ok(true)
     }
 
     async fn contains(&self, key: &str) -> bool {
-        let path = self.key_to_path(key);
-        path.exists()
+        let Ok(path) = self.entry_path(key).await else {
+            return false;
+        };
+        match fs::metadata(&path).await {
+            Ok(metadata) if metadata.is_file() && !self.is_expired(&metadata) => {
+                // Entries from a newer crate version read as misses
+                entry_version(&path, metadata.len())
+                    .await
+                    .is_ok_and(|(version, _)| version <= DISK_FORMAT_VERSION)
+            }
+            _ => false,
+        }
     }
 
     async fn remove(&self, key: &str) -> Result<()> {
-        let path = self.key_to_path(key);
+        let path = self.entry_path(key).await?;
+        let _migration = self.migration.read().await;
         
         match fs::remove_file(&path).await {
             Ok(()) => Ok(()),
@@ -145,14 +1058,254 @@ impl Cache for DiskCache {
         }
     }
 
+    /// Reset the file's modification time, which also revives an expired entry
+    async fn touch(&self, key: &str) -> Result<bool> {
+        let path = self.entry_path(key).await?;
+        let _migration = self.migration.read().await;
+        let touched = tokio::task::spawn_blocking(move || {
+            match std::fs::File::options().write(true).open(&path) {
+                Ok(file) => file.set_modified(std::time::SystemTime::now()).map(|()| true),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+                Err(e) => Err(e),
+            }
+        })
+        .await
+        .map_err(|e| ContentError::Cache {
+            message: format!("Failed to touch disk cache entry: {}", e),
+        })?;
+
+        touched.map_err(|e| ContentError::Cache {
+            message: format!("Failed to touch disk cache entry: {}", e),
+        })
+    }
+
     async fn clear(&self) -> Result<()> {
         // Remove the entire cache directory and recreate it
+        let _migration = self.migration.read().await;
         fs::remove_dir_all(&self.root_dir).await?;
         fs::create_dir_all(&self.root_dir).await?;
+        // Keep the marker, so the directory isn't mistaken for a legacy one
+        if self.marker_checked.initialized() {
+            self.write_marker().await?;
+        }
         Ok(())
     }
 }
 
+#[cfg(feature = "fs")]
+fn disk_write_error(error: std::io::Error) -> ContentError {
+    ContentError::Cache {
+        message: format!("Failed to write to disk cache: {}", error),
+    }
+}
+
+/// Retries made before `RedisCache::new` gives up on an unreachable server
+#[cfg(feature = "redis")]
+const REDIS_CONNECT_RETRIES: usize = 1;
+
+/// Longest wait between connection attempts, in milliseconds
+#[cfg(feature = "redis")]
+const REDIS_MAX_RETRY_DELAY_MS: u64 = 1000;
+
+/// Time allowed for each connection attempt
+#[cfg(feature = "redis")]
+const REDIS_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Cache stored in Redis, shared by every process using the same server
+/// 
+/// Keys are namespaced with a prefix (`content-resolver:` by default), and
+/// entries can expire after a TTL. `clear` only deletes keys under the prefix.
+#[cfg(feature = "redis")]
+#[derive(Clone)]
+pub struct RedisCache {
+    conn: redis::aio::ConnectionManager,
+    prefix: String,
+    ttl: Option<std::time::Duration>,
+}
+
+#[cfg(feature = "redis")]
+impl RedisCache {
+    /// Connect to the Redis server at `url`, e.g. `redis://127.0.0.1/`
+    pub async fn new(url: &str) -> Result<Self> {
+        let client = redis::Client::open(url).map_err(redis_error)?;
+        let config = redis::aio::ConnectionManagerConfig::new()
+            .set_number_of_retries(REDIS_CONNECT_RETRIES)
+            .set_max_delay(REDIS_MAX_RETRY_DELAY_MS)
+            .set_connection_timeout(REDIS_CONNECT_TIMEOUT);
+        let conn = redis::aio::ConnectionManager::new_with_config(client, config)
+            .await
+            .map_err(redis_error)?;
+        Ok(Self::from_connection(conn))
+    }
+
+    /// Use an existing connection
+    pub fn from_connection(conn: redis::aio::ConnectionManager) -> Self {
+        Self {
+            conn,
+            prefix: "content-resolver:".to_string(),
+            ttl: None,
+        }
+    }
+
+    /// Set the prefix prepended to every key
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Expire entries `ttl` after they are set, using Redis `EX`
+    /// 
+    /// Redis expiry has one-second granularity; shorter TTLs round up.
+    pub fn with_ttl(mut self, ttl: std::time::Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+#[cfg(feature = "redis")]
+impl std::fmt::Debug for RedisCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisCache")
+            .field("prefix", &self.prefix)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Redis expiries are whole seconds; round up so entries never expire early
+#[cfg(feature = "redis")]
+fn expiry_secs(ttl: Duration) -> u64 {
+    (ttl.as_secs() + u64::from(ttl.subsec_nanos() > 0)).max(1)
+}
+
+/// `SCAN MATCH` pattern covering every key under `prefix`
+#[cfg(feature = "redis")]
+fn scan_pattern(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('*');
+    pattern
+}
+
+#[cfg(feature = "redis")]
+fn redis_error(error: redis::RedisError) -> ContentError {
+    ContentError::Cache {
+        message: format!("Redis error: {}", error),
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl Cache for RedisCache {
+    async fn get(&self, key: &str) -> Result<Option<Bytes>> {
+        let value: Option<Vec<u8>> = redis::cmd("GET")
+            .arg(self.key(key))
+            .query_async(&mut self.conn.clone())
+            .await
+            .map_err(redis_error)?;
+        Ok(value.map(Bytes::from))
+    }
+
+    async fn set(&self, key: &str, value: Bytes) -> Result<()> {
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(self.key(key)).arg(value.as_ref());
+        if let Some(ttl) = self.ttl {
+            cmd.arg("EX").arg(expiry_secs(ttl));
+        }
+        cmd.query_async::<()>(&mut self.conn.clone())
+            .await
+            .map_err(redis_error)
+    }
+
+    /// `SET ... NX`, so the check and the write are one atomic command
+    async fn set_if_absent(&self, key: &str, value: Bytes) -> Result<bool> {
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(self.key(key)).arg(value.as_ref()).arg("NX");
+        if let Some(ttl) = self.ttl {
+            cmd.arg("EX").arg(expiry_secs(ttl));
+        }
+        let reply: Option<String> = cmd
+            .query_async(&mut self.conn.clone())
+            .await
+            .map_err(redis_error)?;
+        Ok(reply.is_some())
+    }
+
+    async fn contains(&self, key: &str) -> bool {
+        redis::cmd("EXISTS")
+            .arg(self.key(key))
+            .query_async::<bool>(&mut self.conn.clone())
+            .await
+            .unwrap_or(false)
+    }
+
+    async fn remove(&self, key: &str) -> Result<()> {
+        redis::cmd("DEL")
+            .arg(self.key(key))
+            .query_async::<()>(&mut self.conn.clone())
+            .await
+            .map_err(redis_error)
+    }
+
+    /// Restart the `EX` expiry; expired keys are already gone
+    async fn touch(&self, key: &str) -> Result<bool> {
+        let cmd = match self.ttl {
+            Some(ttl) => {
+                let mut cmd = redis::cmd("EXPIRE");
+                cmd.arg(self.key(key)).arg(expiry_secs(ttl));
+                cmd
+            }
+            None => {
+                let mut cmd = redis::cmd("EXISTS");
+                cmd.arg(self.key(key));
+                cmd
+            }
+        };
+        cmd.query_async::<bool>(&mut self.conn.clone())
+            .await
+            .map_err(redis_error)
+    }
+
+    async fn clear(&self) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let pattern = scan_pattern(&self.prefix);
+        let mut cursor: u64 = 0;
+
+        loop {
+            let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(500)
+                .query_async(&mut conn)
+                .await
+                .map_err(redis_error)?;
+
+            if !keys.is_empty() {
+                redis::cmd("DEL")
+                    .arg(&keys)
+                    .query_async::<()>(&mut conn)
+                    .await
+                    .map_err(redis_error)?;
+            }
+            if next == 0 {
+                return Ok(());
+            }
+            cursor = next;
+        }
+    }
+}
+
 /// No-op cache that doesn't cache anything
 pub struct NoCache;
 
@@ -166,6 +1319,10 @@ impl Cache for NoCache {
         Ok(())
     }
 
+    async fn set_if_absent(&self, _key: &str, _value: Bytes) -> Result<bool> {
+        Ok(false)
+    }
+
     async fn contains(&self, _key: &str) -> bool {
         false
     }
@@ -179,10 +1336,134 @@ impl Cache for NoCache {
     }
 }
 
+/// Cache that sends each key to a backing cache chosen by key prefix
+/// 
+/// Lets different kinds of entries get different policies, e.g. file
+/// content on disk and its metadata in memory. The route with the longest
+/// matching prefix wins; keys no route matches go to the fallback. Keys are
+/// matched as the resolver writes them, so with a cache scope the prefixes
+/// must start with the scope: `{scope}:file:` rather than `file:`.
+pub struct RoutingCache {
+    routes: Vec<(String, Arc<dyn Cache>)>,
+    fallback: Arc<dyn Cache>,
+}
+
+impl RoutingCache {
+    /// Create a cache sending every key to `fallback` until routes are added
+    pub fn new(fallback: Arc<dyn Cache>) -> Self {
+        Self {
+            routes: Vec::new(),
+            fallback,
+        }
+    }
+
+    /// Send keys starting with `prefix` to `cache`
+    pub fn with_route(mut self, prefix: impl Into<String>, cache: Arc<dyn Cache>) -> Self {
+        self.routes.push((prefix.into(), cache));
+        self
+    }
+
+    /// The cache a key is stored in
+    fn route(&self, key: &str) -> &dyn Cache {
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| key.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.fallback.as_ref(), |(_, cache)| cache.as_ref())
+    }
+}
+
+#[async_trait]
+impl Cache for RoutingCache {
+    async fn get(&self, key: &str) -> Result<Option<Bytes>> {
+        self.route(key).get(key).await
+    }
+
+    async fn set(&self, key: &str, value: Bytes) -> Result<()> {
+        self.route(key).set(key, value).await
+    }
+
+    async fn contains(&self, key: &str) -> bool {
+        self.route(key).contains(key).await
+    }
+
+    async fn remove(&self, key: &str) -> Result<()> {
+        self.route(key).remove(key).await
+    }
+
+    /// Clear every routed cache and the fallback, each once
+    /// 
+    /// Keeps going after a failure and returns the first error.
+    async fn clear(&self) -> Result<()> {
+        let mut cleared: Vec<&Arc<dyn Cache>> = Vec::new();
+        let mut result = Ok(());
+        let caches = self.routes.iter().map(|(_, cache)| cache).chain([&self.fallback]);
+        for cache in caches {
+            if cleared.iter().any(|done| Arc::ptr_eq(done, cache)) {
+                continue;
+            }
+            cleared.push(cache);
+            if let Err(e) = cache.clear().await {
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+
+    async fn get_stale(&self, key: &str) -> Result<Option<Bytes>> {
+        self.route(key).get_stale(key).await
+    }
+
+    async fn set_if_absent(&self, key: &str, value: Bytes) -> Result<bool> {
+        self.route(key).set_if_absent(key, value).await
+    }
+
+    async fn set_if_newer(&self, key: &str, value: Bytes, as_of: SystemTime) -> Result<bool> {
+        self.route(key).set_if_newer(key, value, as_of).await
+    }
+
+    async fn touch(&self, key: &str) -> Result<bool> {
+        self.route(key).touch(key).await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn test_routing_cache() {
+        let files = Arc::new(MemoryCache::new());
+        let meta = Arc::new(MemoryCache::new());
+        let other = Arc::new(MemoryCache::new());
+        let cache = RoutingCache::new(other.clone())
+            .with_route("file:", files.clone())
+            .with_route("file:tmp/", meta.clone())
+            .with_route("meta:", meta.clone());
+
+        cache.set("file:a", Bytes::from("a")).await.unwrap();
+        cache.set("file:tmp/b", Bytes::from("b")).await.unwrap();
+        cache.set("meta:a", Bytes::from("m")).await.unwrap();
+        cache.set("dir:a", Bytes::from("d")).await.unwrap();
+
+        // Longest prefix wins, unmatched keys fall back
+        assert!(files.contains("file:a").await);
+        assert!(!files.contains("file:tmp/b").await);
+        assert!(meta.contains("file:tmp/b").await);
+        assert!(meta.contains("meta:a").await);
+        assert!(other.contains("dir:a").await);
+        assert_eq!(cache.get("file:tmp/b").await.unwrap(), Some(Bytes::from("b")));
+        assert!(!cache.set_if_absent("meta:a", Bytes::from("x")).await.unwrap());
+
+        cache.remove("file:a").await.unwrap();
+        assert!(!cache.contains("file:a").await);
+
+        cache.clear().await.unwrap();
+        for key in ["file:tmp/b", "meta:a", "dir:a"] {
+            assert!(!cache.contains(key).await, "{}", key);
+        }
+    }
+
     #[tokio::test]
     async fn test_memory_cache() {
         let cache = MemoryCache::new();
@@ -209,4 +1490,551 @@ mod tests {
         assert!(!cache.contains("key1").await);
         assert!(!cache.contains("key2").await);
     }
+
+    #[tokio::test]
+    async fn test_memory_cache_export_import() {
+        let cache = MemoryCache::new();
+        cache.set("b", Bytes::from("2")).await.unwrap();
+        cache.set("a", Bytes::from("1")).await.unwrap();
+
+        let exported = cache.export().await;
+        assert_eq!(
+            exported,
+            vec![
+                ("a".to_string(), Bytes::from("1")),
+                ("b".to_string(), Bytes::from("2")),
+            ]
+        );
+
+        let restored = MemoryCache::new();
+        restored.set("a", Bytes::from("stale")).await.unwrap();
+        restored.set("c", Bytes::from("3")).await.unwrap();
+        restored.import(exported).await;
+        assert_eq!(restored.get("a").await.unwrap().unwrap(), "1");
+        assert_eq!(restored.get("c").await.unwrap().unwrap(), "3");
+        assert_eq!(restored.export().await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_memory_cache_set_if_absent() {
+        let cache = MemoryCache::new();
+        cache.set("live", Bytes::from("fresh")).await.unwrap();
+
+        assert!(!cache.set_if_absent("live", Bytes::from("warm")).await.unwrap());
+        assert!(cache.set_if_absent("cold", Bytes::from("warm")).await.unwrap());
+        assert_eq!(cache.get("live").await.unwrap().unwrap(), "fresh");
+        assert_eq!(cache.get("cold").await.unwrap().unwrap(), "warm");
+
+        let inserted = cache
+            .import_if_absent([
+                ("live".to_string(), Bytes::from("warm")),
+                ("new".to_string(), Bytes::from("warm")),
+            ])
+            .await;
+        assert_eq!(inserted, 1);
+        assert_eq!(cache.get("live").await.unwrap().unwrap(), "fresh");
+
+        // Only entries stored before the snapshot was taken are replaced
+        let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        assert!(!cache.set_if_newer("live", Bytes::from("old"), hour_ago).await.unwrap());
+        assert!(cache.set_if_newer("other", Bytes::from("old"), hour_ago).await.unwrap());
+        assert!(cache.set_if_newer("other", Bytes::from("newer"), SystemTime::now()).await.unwrap());
+        assert!(!cache.set_if_newer("other", Bytes::from("old"), hour_ago).await.unwrap());
+        assert_eq!(cache.get("other").await.unwrap().unwrap(), "newer");
+    }
+
+    #[tokio::test]
+    async fn test_bounded_memory_cache() {
+        let cache = MemoryCache::bounded(2, EvictionPolicy::Lru);
+        cache.set("a", Bytes::from("a")).await.unwrap();
+        cache.set("b", Bytes::from("b")).await.unwrap();
+        cache.get("a").await.unwrap();
+        cache.set("c", Bytes::from("c")).await.unwrap();
+        assert_eq!(cache.len().await, 2);
+        assert!(!cache.contains("b").await);
+
+        // Overwriting doesn't evict; removing frees a slot
+        cache.set("a", Bytes::from("a2")).await.unwrap();
+        assert!(cache.contains("c").await);
+        cache.remove("c").await.unwrap();
+        assert!(cache.set_if_absent("d", Bytes::from("d")).await.unwrap());
+        assert_eq!(cache.get("a").await.unwrap().unwrap(), "a2");
+
+        // Imports are bounded too
+        cache.import((0..5).map(|i| (format!("bulk{}", i), Bytes::from("x")))).await;
+        let keys: Vec<_> = cache.export().await.into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, ["bulk3", "bulk4"]);
+        cache.clear().await.unwrap();
+        assert!(cache.is_empty().await);
+
+        // A bound of 0 keeps one entry
+        let cache = MemoryCache::bounded(0, EvictionPolicy::Lru);
+        cache.set("a", Bytes::from("a")).await.unwrap();
+        cache.set("b", Bytes::from("b")).await.unwrap();
+        assert_eq!(cache.len().await, 1);
+        assert!(cache.contains("b").await);
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_memory_cache_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.snapshot");
+
+        let cache = MemoryCache::new();
+        cache.set("file:a.txt", Bytes::from("alpha")).await.unwrap();
+        cache.set("file:empty", Bytes::new()).await.unwrap();
+        cache.set("file:ü/bin", Bytes::from_static(&[0, 255, 10])).await.unwrap();
+        cache.save_snapshot(&path).await.unwrap();
+        // Overwriting leaves no temporary files behind
+        cache.save_snapshot(&path).await.unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let restored = MemoryCache::new();
+        assert_eq!(restored.load_snapshot(&path).await.unwrap(), 3);
+        assert_eq!(restored.export().await, cache.export().await);
+
+        // Truncated and foreign files are rejected without importing anything
+        let data = std::fs::read(&path).unwrap();
+        for bad in [&data[..data.len() - 1], b"not a snapshot".as_slice()] {
+            std::fs::write(&path, bad).unwrap();
+            let empty = MemoryCache::new();
+            assert!(matches!(
+                empty.load_snapshot(&path).await,
+                Err(ContentError::Cache { .. })
+            ));
+            assert!(empty.export().await.is_empty());
+        }
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_disk_cache_contains() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new(dir.path().to_path_buf()).await.unwrap();
+
+        assert!(!cache.contains("key").await);
+        cache.set("key", Bytes::from("value")).await.unwrap();
+        assert!(cache.contains("key").await);
+        cache.remove("key").await.unwrap();
+        assert!(!cache.contains("key").await);
+    }
+
+    /// Smoke test for blocking calls creeping back into the async paths
+    #[cfg(feature = "fs")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_disk_cache_concurrent_access() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(DiskCache::new(dir.path().to_path_buf()).await.unwrap());
+
+        let tasks = crate::task::TaskSet::new();
+        let (done, mut done_rx) = tokio::sync::mpsc::unbounded_channel();
+        for i in 0..256 {
+            let cache = cache.clone();
+            let done = done.clone();
+            tasks.spawn(|_| async move {
+                let key = format!("file:{}", i);
+                let value = Bytes::from(key.clone());
+                cache.set(&key, value.clone()).await.unwrap();
+                assert!(cache.contains(&key).await);
+                assert_eq!(cache.get(&key).await.unwrap(), Some(value));
+                assert!(!cache.contains(&format!("missing:{}", i)).await);
+                done.send(()).unwrap();
+            });
+        }
+        drop(done);
+
+        // A task that panicked drops its sender without sending
+        let mut finished = 0;
+        let all = async {
+            while done_rx.recv().await.is_some() {
+                finished += 1;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(10), all)
+            .await
+            .expect("disk cache operations stalled");
+        assert_eq!(finished, 256);
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_disk_cache_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new(dir.path().join("cache")).await.unwrap();
+        assert_eq!(cache.stats().await.unwrap(), DiskCacheStats::default());
+
+        cache.set("file:a", Bytes::from("alpha")).await.unwrap();
+        cache.set("file:b", Bytes::from("be")).await.unwrap();
+        assert_eq!(
+            cache.stats().await.unwrap(),
+            DiskCacheStats { entries: 2, bytes: 7 }
+        );
+
+        cache.clear().await.unwrap();
+        assert_eq!(cache.stats().await.unwrap().entries, 0);
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_disk_cache_entry_format() {
+        let value = b"{\"name\": \"pdf\"}\n\x00binary";
+        let DiskEntry::V1 { meta, value: decoded } = decode_entry(encode_entry(Some("file:a"), value).into()).unwrap()
+        else {
+            panic!("expected a version 1 entry");
+        };
+        assert_eq!(decoded, &value[..]);
+        assert_eq!(meta.size, value.len() as u64);
+        assert_eq!(meta.sha256, crate::integrity::sha256_hex_of(value));
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+        assert!(now - meta.stored_at < 60);
+        assert_eq!(meta.key.as_deref(), Some("file:a"));
+        assert!(matches!(
+            decode_entry(encode_entry(None, b"").into()).unwrap(),
+            DiskEntry::V1 { meta: EntryMeta { key: None, .. }, .. }
+        ));
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new(dir.path().to_path_buf()).await.unwrap();
+        cache.set("key", Bytes::from_static(value)).await.unwrap();
+        let raw = std::fs::read(cache.key_to_path("key")).unwrap();
+        assert!(raw.starts_with(ENTRY_MAGIC));
+        assert_eq!(raw[ENTRY_MAGIC.len()], DISK_FORMAT_VERSION);
+        assert_eq!(cache.get("key").await.unwrap().unwrap(), &value[..]);
+        assert_eq!(cache.stats().await.unwrap().bytes, value.len() as u64);
+
+        // A damaged value is an error, not silently wrong content
+        let mut damaged = raw.clone();
+        *damaged.last_mut().unwrap() ^= 1;
+        std::fs::write(cache.key_to_path("key"), &damaged).unwrap();
+        assert!(matches!(cache.get("key").await, Err(ContentError::Cache { .. })));
+
+        // Entries from a newer crate version are counted misses
+        let mut newer = raw;
+        newer[ENTRY_MAGIC.len()] = DISK_FORMAT_VERSION + 1;
+        let path = cache.key_to_path("newer");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, &newer).unwrap();
+        assert!(cache.get("newer").await.unwrap().is_none());
+        assert!(!cache.contains("newer").await);
+        let stats = cache.format_stats().await.unwrap();
+        assert_eq!(stats.versions, BTreeMap::from([(1, 1), (2, 1)]));
+        assert_eq!(stats.newer_version_reads, 1);
+        // ...which a write replaces
+        assert!(cache.set_if_absent("newer", Bytes::from("mine")).await.unwrap());
+        assert_eq!(cache.get("newer").await.unwrap().unwrap(), "mine");
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_disk_cache_reads_v0_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new(dir.path().to_path_buf())
+            .await
+            .unwrap()
+            .with_ttl(Duration::from_secs(3600));
+        let written = SystemTime::now() - Duration::from_secs(600);
+        for (key, value) in [("a", "alpha"), ("b", "be")] {
+            // As written by crate versions without the entry header
+            let path = cache.key_to_path(key);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, value).unwrap();
+            std::fs::File::options().write(true).open(&path).unwrap().set_modified(written).unwrap();
+        }
+        cache.set("c", Bytes::from("current")).await.unwrap();
+
+        assert_eq!(cache.get("a").await.unwrap().unwrap(), "alpha");
+        assert!(cache.contains("b").await);
+        assert_eq!(cache.stats().await.unwrap(), DiskCacheStats { entries: 3, bytes: 14 });
+        assert_eq!(
+            cache.format_stats().await.unwrap().versions,
+            BTreeMap::from([(0, 2), (1, 1)])
+        );
+
+        let report = cache.migrate_in_place().await.unwrap();
+        assert_eq!(
+            report,
+            MigrationReport { migrated: 2, current: 1, skipped: 0, corrupt: 0 }
+        );
+        assert_eq!(
+            cache.format_stats().await.unwrap().versions,
+            BTreeMap::from([(1, 3)])
+        );
+        assert_eq!(cache.get("b").await.unwrap().unwrap(), "be");
+        assert_eq!(cache.stats().await.unwrap().bytes, 14);
+        // The entries keep their age
+        let modified = std::fs::metadata(cache.key_to_path("a")).unwrap().modified().unwrap();
+        assert_eq!(modified, written);
+        assert_eq!(cache.migrate_in_place().await.unwrap().migrated, 0);
+
+        // A corrupt entry is counted and doesn't stop the others
+        for (key, data) in [("broken", &ENTRY_MAGIC[..]), ("old", b"old")] {
+            let path = cache.key_to_path(key);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, data).unwrap();
+        }
+        let report = cache.migrate_in_place().await.unwrap();
+        assert_eq!((report.migrated, report.current, report.corrupt), (1, 3, 1));
+        assert!(std::fs::read(cache.key_to_path("broken")).unwrap() == ENTRY_MAGIC);
+
+        // Lazily, on read
+        let lazy = DiskCache::new(dir.path().join("lazy")).await.unwrap().with_migrate_on_read(true);
+        let path = lazy.key_to_path("a");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "alpha").unwrap();
+        assert_eq!(lazy.get("a").await.unwrap().unwrap(), "alpha");
+        assert!(std::fs::read(&path).unwrap().starts_with(ENTRY_MAGIC));
+        assert_eq!(lazy.get("a").await.unwrap().unwrap(), "alpha");
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_disk_cache_key_strategies() {
+        let mut strategies = vec![KeyStrategy::Sha256, KeyStrategy::SanitizedPlain { max_len: 40 }];
+        #[cfg(feature = "blake3")]
+        strategies.push(KeyStrategy::Blake3);
+        for strategy in strategies {
+            let dir = tempfile::tempdir().unwrap();
+            let cache = DiskCache::new(dir.path().to_path_buf())
+                .await
+                .unwrap()
+                .with_key_strategy(strategy);
+            let long_key = format!("file:{}", "x/".repeat(40));
+            for key in ["file:docs/guide.md", "meta:a b%", long_key.as_str()] {
+                cache.set(key, Bytes::from(key.to_string())).await.unwrap();
+            }
+            for key in ["file:docs/guide.md", "meta:a b%", long_key.as_str()] {
+                assert!(cache.contains(key).await, "{:?}", strategy);
+                assert_eq!(cache.get(key).await.unwrap().unwrap(), key);
+            }
+            assert_eq!(cache.stats().await.unwrap().entries, 3);
+            cache.clear().await.unwrap();
+            assert!(!cache.contains("meta:a b%").await);
+            cache.set("file:a", Bytes::from("a")).await.unwrap();
+            assert_eq!(cache.get("file:a").await.unwrap().unwrap(), "a");
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let plain = DiskCache::new(dir.path().to_path_buf())
+            .await
+            .unwrap()
+            .with_key_strategy(KeyStrategy::SanitizedPlain { max_len: 40 });
+        plain.set("file:docs/guide.md", Bytes::from("guide")).await.unwrap();
+        assert!(dir.path().join("plain/file%3Adocs%2Fguide%2Emd").is_file());
+        let long = plain.key_to_path(&"a".repeat(41));
+        assert!(long.file_name().unwrap().to_str().unwrap().starts_with('+'));
+
+        // An entry stored under another key is a miss, not the wrong content
+        let path = plain.key_to_path("file:b");
+        std::fs::copy(plain.key_to_path("file:docs/guide.md"), &path).unwrap();
+        assert!(plain.get("file:b").await.unwrap().is_none());
+        assert_eq!(plain.format_stats().await.unwrap().key_collisions, 1);
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_disk_cache_strategy_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        let hashed = DiskCache::new(root.clone()).await.unwrap();
+        hashed.set("file:a", Bytes::from("a")).await.unwrap();
+        assert_eq!(std::fs::read_to_string(root.join("key-strategy")).unwrap(), "sha256");
+
+        let plain = KeyStrategy::SanitizedPlain { max_len: 100 };
+        let refused = DiskCache::new(root.clone()).await.unwrap().with_key_strategy(plain);
+        let error = refused.get("file:a").await.unwrap_err();
+        assert!(matches!(error, ContentError::Cache { .. }));
+        assert!(error.to_string().contains("uses key strategy sha256"), "{}", error);
+        assert!(refused.set("file:a", Bytes::from("b")).await.is_err());
+        assert_eq!(hashed.get("file:a").await.unwrap().unwrap(), "a");
+        // Both hashes shard the same way, but name files differently
+        #[cfg(feature = "blake3")]
+        {
+            let blake3 = DiskCache::new(root.clone()).await.unwrap().with_key_strategy(KeyStrategy::Blake3);
+            assert!(blake3.get("file:a").await.unwrap_err().to_string().contains("not blake3"));
+        }
+
+        let cleared = DiskCache::new(root.clone())
+            .await
+            .unwrap()
+            .with_key_strategy(plain)
+            .with_strategy_mismatch(StrategyMismatch::Clear);
+        assert!(cleared.get("file:a").await.unwrap().is_none());
+        cleared.set("file:a", Bytes::from("plain")).await.unwrap();
+        assert_eq!(cleared.stats().await.unwrap().entries, 1);
+
+        // Directories from before the marker count as Sha256
+        let legacy = tempfile::tempdir().unwrap();
+        let old = DiskCache::new(legacy.path().to_path_buf()).await.unwrap();
+        let path = old.key_to_path("file:a");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "old").unwrap();
+        let refused = DiskCache::new(legacy.path().to_path_buf())
+            .await
+            .unwrap()
+            .with_key_strategy(plain);
+        assert!(refused.get("file:a").await.is_err());
+        assert_eq!(old.get("file:a").await.unwrap().unwrap(), "old");
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_disk_cache_ttl() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new(dir.path().to_path_buf())
+            .await
+            .unwrap()
+            .with_ttl(Duration::from_millis(50));
+
+        cache.set("key", Bytes::from("value")).await.unwrap();
+        assert!(cache.contains("key").await);
+        assert_eq!(cache.get("key").await.unwrap().unwrap(), "value");
+
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert!(!cache.contains("key").await);
+        assert!(cache.get("key").await.unwrap().is_none());
+        assert_eq!(cache.get_stale("key").await.unwrap().unwrap(), "value");
+
+        // Writing again restarts the clock
+        cache.set("key", Bytes::from("fresh")).await.unwrap();
+        assert_eq!(cache.get("key").await.unwrap().unwrap(), "fresh");
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_disk_cache_set_if_absent() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new(dir.path().to_path_buf())
+            .await
+            .unwrap()
+            .with_ttl(Duration::from_secs(3600));
+        cache.set("live", Bytes::from("fresh")).await.unwrap();
+
+        assert!(!cache.set_if_absent("live", Bytes::from("warm")).await.unwrap());
+        assert!(cache.set_if_absent("cold", Bytes::from("warm")).await.unwrap());
+        assert_eq!(cache.get("live").await.unwrap().unwrap(), "fresh");
+        assert_eq!(cache.get("cold").await.unwrap().unwrap(), "warm");
+
+        // Concurrent primers agree on a single winner, and leave no temp files
+        let cache = Arc::new(cache);
+        let tasks = crate::task::TaskSet::new();
+        let (won, mut won_rx) = tokio::sync::mpsc::unbounded_channel();
+        for i in 0..8 {
+            let cache = cache.clone();
+            let won = won.clone();
+            tasks.spawn(|_| async move {
+                won.send(cache.set_if_absent("race", Bytes::from(i.to_string())).await.unwrap()).unwrap();
+            });
+        }
+        drop(won);
+        let mut results = Vec::new();
+        while let Some(result) = won_rx.recv().await {
+            results.push(result);
+        }
+        assert_eq!(results.len(), 8);
+        assert_eq!(results.iter().filter(|won| **won).count(), 1);
+        assert_eq!(cache.stats().await.unwrap().entries, 3);
+
+        // The entry's age is compared with the snapshot's, and the TTL counts
+        // from the snapshot
+        let half_hour_ago = SystemTime::now() - Duration::from_secs(1800);
+        assert!(!cache.set_if_newer("live", Bytes::from("old"), half_hour_ago).await.unwrap());
+        assert!(cache.set_if_newer("other", Bytes::from("old"), half_hour_ago).await.unwrap());
+        assert_eq!(cache.get("other").await.unwrap().unwrap(), "old");
+        assert!(cache.set_if_newer("other", Bytes::from("newer"), SystemTime::now()).await.unwrap());
+        assert!(!cache.set_if_newer("other", Bytes::from("old"), half_hour_ago).await.unwrap());
+        assert_eq!(cache.get("other").await.unwrap().unwrap(), "newer");
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_touch_restarts_ttl() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new(dir.path().to_path_buf())
+            .await
+            .unwrap()
+            .with_ttl(Duration::from_secs(3600));
+        let path = cache.entry_path("key").await.unwrap();
+        // Move the entry's modification time back instead of waiting
+        let age = |by: Duration| {
+            let file = std::fs::File::options().write(true).open(&path).unwrap();
+            let modified = file.metadata().unwrap().modified().unwrap();
+            file.set_modified(modified - by).unwrap();
+        };
+
+        cache.set("key", Bytes::from("value")).await.unwrap();
+        age(Duration::from_secs(50 * 60));
+        assert!(cache.touch("key").await.unwrap());
+        age(Duration::from_secs(50 * 60));
+        // 100 minutes after the write, but only 50 after the touch
+        assert_eq!(cache.get("key").await.unwrap().unwrap(), "value");
+
+        age(Duration::from_secs(20 * 60));
+        assert!(!cache.contains("key").await);
+        assert!(cache.touch("key").await.unwrap());
+        assert!(cache.contains("key").await);
+
+        assert!(!cache.touch("missing").await.unwrap());
+
+        // Caches without a native touch fall back to rewriting the value
+        let memory = MemoryCache::new();
+        memory.set("key", Bytes::from("value")).await.unwrap();
+        assert!(memory.touch("key").await.unwrap());
+        assert_eq!(memory.get("key").await.unwrap().unwrap(), "value");
+        assert!(!memory.touch("missing").await.unwrap());
+    }
+
+    #[cfg(feature = "redis")]
+    #[test]
+    fn test_redis_scan_pattern() {
+        assert_eq!(scan_pattern("content-resolver:"), "content-resolver:*");
+        assert_eq!(scan_pattern("a*b?[c]\\:"), "a\\*b\\?\\[c\\]\\\\:*");
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    async fn test_redis_unreachable() {
+        let error = RedisCache::new("redis://:hunter2@127.0.0.1:1/").await.unwrap_err();
+        assert!(matches!(error, ContentError::Cache { .. }));
+        assert!(!error.to_string().contains("hunter2"));
+    }
+
+    /// Runs against the server in `REDIS_URL`; skipped when it isn't set
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    async fn test_redis_cache() {
+        let Ok(url) = std::env::var("REDIS_URL") else {
+            return;
+        };
+        let cache = RedisCache::new(&url)
+            .await
+            .unwrap()
+            .with_prefix("content-resolver-test:[x]:")
+            .with_ttl(std::time::Duration::from_millis(1500));
+        let other = cache.clone().with_prefix("content-resolver-test:other:");
+
+        cache.set("a", Bytes::from("1")).await.unwrap();
+        cache.set("b", Bytes::from("2")).await.unwrap();
+        other.set("a", Bytes::from("other")).await.unwrap();
+        assert!(cache.contains("a").await);
+        assert_eq!(cache.get("a").await.unwrap().unwrap(), "1");
+
+        cache.remove("a").await.unwrap();
+        assert!(cache.get("a").await.unwrap().is_none());
+
+        assert!(cache.set_if_absent("a", Bytes::from("warm")).await.unwrap());
+        assert!(!cache.set_if_absent("b", Bytes::from("warm")).await.unwrap());
+        assert_eq!(cache.get("b").await.unwrap().unwrap(), "2");
+
+        // Clearing one prefix leaves the other alone
+        cache.clear().await.unwrap();
+        assert!(!cache.contains("b").await);
+        assert_eq!(other.get("a").await.unwrap().unwrap(), "other");
+
+        assert!(other.touch("a").await.unwrap());
+        assert!(!cache.touch("b").await.unwrap());
+
+        tokio::time::sleep(std::time::Duration::from_millis(2100)).await;
+        assert!(!other.contains("a").await);
+    }
 }
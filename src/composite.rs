@@ -0,0 +1,324 @@
+//! Virtual files the resolver assembles from other files
+
+use bytes::{Bytes, BytesMut};
+use futures::future::BoxFuture;
+use globset::{Glob, GlobMatcher};
+use std::sync::Arc;
+
+use crate::{
+    error::{ContentError, Result},
+    resolver::ResourceResolver,
+    types::EntryType,
+};
+
+/// How deep virtual files may be built from other virtual files
+pub(crate) const MAX_VIRTUAL_DEPTH: usize = 8;
+
+/// Builds the content of a virtual file from the resolver and its path
+pub type Assembler = Arc<
+    dyn for<'a> Fn(&'a ResourceResolver, &'a str) -> BoxFuture<'a, Result<Bytes>> + Send + Sync,
+>;
+
+/// A family of paths the resolver assembles instead of asking the sources
+/// 
+/// Register with `ResourceResolver::with_virtual_file`. A fetch of a
+/// matching path runs the assembler, which reads what it needs through the
+/// resolver it is given; the files it fetches and the directories it lists
+/// are recorded as dependencies, so invalidating one, or a path in a listed
+/// directory, also invalidates the assembled file.
+#[derive(Clone)]
+pub struct VirtualFileRule {
+    pattern: GlobMatcher,
+    assembler: Assembler,
+}
+
+impl VirtualFileRule {
+    /// Assemble paths matching the glob `pattern` with `assembler`
+    /// 
+    /// Fails with `InvalidConfig` if the pattern doesn't parse.
+    pub fn new<F>(pattern: &str, assembler: F) -> Result<Self>
+    where
+        F: for<'a> Fn(&'a ResourceResolver, &'a str) -> BoxFuture<'a, Result<Bytes>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let pattern = Glob::new(pattern).map_err(|e| ContentError::InvalidConfig {
+            message: format!("invalid virtual file pattern: {}", e),
+        })?;
+        Ok(Self {
+            pattern: pattern.compile_matcher(),
+            assembler: Arc::new(assembler),
+        })
+    }
+
+    /// Whether the rule covers a normalized path
+    pub fn matches(&self, path: &str) -> bool {
+        self.pattern.is_match(path)
+    }
+
+    pub(crate) fn assemble<'a>(
+        &'a self,
+        resolver: &'a ResourceResolver,
+        path: &'a str,
+    ) -> BoxFuture<'a, Result<Bytes>> {
+        (self.assembler)(resolver, path)
+    }
+}
+
+impl std::fmt::Debug for VirtualFileRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VirtualFileRule")
+            .field("pattern", &self.pattern.glob().glob())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Concatenation of the files in a directory whose names match a glob
+/// 
+/// The virtual file itself is left out, so `locale/all.lang` can join
+/// `locale/*.lang`. A directory without matching files assembles to
+/// `NotFound`.
+#[derive(Debug, Clone)]
+pub struct ConcatRule {
+    /// Directory whose files are joined; subdirectories are ignored
+    pub dir: String,
+    /// Glob file names must match, e.g. `*.lang`
+    pub glob: String,
+    /// Bytes put between two files
+    pub separator: Bytes,
+    /// Join in file name order; otherwise in the order the source lists them
+    pub sort: bool,
+}
+
+impl ConcatRule {
+    /// A rule assembling the paths matching `pattern` with this concatenation
+    /// 
+    /// Fails with `InvalidConfig` if `pattern` or `glob` doesn't parse.
+    pub fn into_rule(self, pattern: &str) -> Result<VirtualFileRule> {
+        let names = Glob::new(&self.glob)
+            .map_err(|e| ContentError::InvalidConfig {
+                message: format!("invalid concat glob: {}", e),
+            })?
+            .compile_matcher();
+        let concat = Arc::new((self, names));
+        VirtualFileRule::new(pattern, move |resolver, path| {
+            let concat = concat.clone();
+            Box::pin(async move {
+                let (rule, names) = concat.as_ref();
+                rule.assemble(names, resolver, path).await
+            })
+        })
+    }
+
+    async fn assemble(
+        &self,
+        names: &GlobMatcher,
+        resolver: &ResourceResolver,
+        path: &str,
+    ) -> Result<Bytes> {
+        let listing = resolver.list_directory(&self.dir).await?;
+        let mut members: Vec<_> = listing
+            .entries
+            .into_iter()
+            .filter(|e| {
+                e.entry_type == EntryType::File && e.path != path && names.is_match(&e.name)
+            })
+            .collect();
+        if members.is_empty() {
+            return Err(ContentError::NotFound {
+                path: path.to_string(),
+            });
+        }
+        if self.sort {
+            members.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+
+        let mut out = BytesMut::new();
+        for (i, member) in members.iter().enumerate() {
+            if i > 0 {
+                out.extend_from_slice(&self.separator);
+            }
+            out.extend_from_slice(&resolver.fetch_file(&member.path).await?.content);
+        }
+        Ok(out.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cache::Cache,
+        source::ContentSource,
+        testing::MemorySource,
+        types::{DirectoryListing, FileContent},
+        MemoryCache,
+    };
+    use async_trait::async_trait;
+
+    /// Source listing directories in reverse name order
+    struct Reversed(MemorySource);
+
+    #[async_trait]
+    impl ContentSource for Reversed {
+        async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+            self.0.fetch_file(path).await
+        }
+
+        async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+            let mut listing = self.0.list_directory(path).await?;
+            listing.entries.reverse();
+            Ok(listing)
+        }
+
+        fn identifier(&self) -> String {
+            "reversed".to_string()
+        }
+    }
+
+    fn locale() -> MemorySource {
+        MemorySource::new()
+            .with_file("locale/de.lang", "de")
+            .with_file("locale/en.lang", "en")
+            .with_file("locale/fr.lang", "fr")
+            .with_file("locale/notes.txt", "notes")
+            .with_file("locale/sub/es.lang", "es")
+    }
+
+    fn concat(sort: bool) -> VirtualFileRule {
+        ConcatRule {
+            dir: "locale".to_string(),
+            glob: "*.lang".to_string(),
+            separator: Bytes::from_static(b"\n"),
+            sort,
+        }
+        .into_rule("locale/all.lang")
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_concat_rule() {
+        let resolver =
+            ResourceResolver::new(vec![Arc::new(locale())]).with_virtual_file(concat(true));
+
+        let file = resolver.fetch_file("/locale/all.lang").await.unwrap();
+        assert_eq!(file.text().unwrap(), "de\nen\nfr");
+        assert_eq!(file.source_path, "virtual:locale/all.lang");
+        assert!(resolver.file_exists("locale/all.lang").await);
+        assert_eq!(
+            resolver.stat("locale/all.lang").await.unwrap().size,
+            Some(8)
+        );
+        let mut out = Vec::new();
+        resolver
+            .fetch_to_writer("locale/all.lang", &mut out)
+            .await
+            .unwrap();
+        assert_eq!(out, b"de\nen\nfr");
+
+        // Other paths still come from the sources
+        assert_eq!(
+            resolver
+                .fetch_file("locale/en.lang")
+                .await
+                .unwrap()
+                .text()
+                .unwrap(),
+            "en"
+        );
+
+        let empty = ResourceResolver::new(vec![Arc::new(
+            MemorySource::new().with_file("locale/a.txt", "a"),
+        )])
+        .with_virtual_file(concat(true));
+        assert!(empty
+            .fetch_file("locale/all.lang")
+            .await
+            .unwrap_err()
+            .is_not_found());
+    }
+
+    #[tokio::test]
+    async fn test_concat_order() {
+        let reversed = || Arc::new(Reversed(locale())) as Arc<dyn ContentSource>;
+
+        let sorted = ResourceResolver::new(vec![reversed()]).with_virtual_file(concat(true));
+        for _ in 0..3 {
+            let file = sorted.fetch_file("locale/all.lang").await.unwrap();
+            assert_eq!(file.text().unwrap(), "de\nen\nfr");
+        }
+
+        let listed = ResourceResolver::new(vec![reversed()]).with_virtual_file(concat(false));
+        let file = listed.fetch_file("locale/all.lang").await.unwrap();
+        assert_eq!(file.text().unwrap(), "fr\nen\nde");
+    }
+
+    #[tokio::test]
+    async fn test_dependency_invalidation() {
+        let cache = Arc::new(MemoryCache::new());
+        let resolver = ResourceResolver::with_cache(vec![Arc::new(locale())], cache.clone())
+            .with_virtual_file(concat(true));
+        let nested = VirtualFileRule::new("twice.lang", |resolver, _| {
+            Box::pin(async move {
+                let all = resolver.fetch_file("locale/all.lang").await?.content;
+                Ok([all.clone(), all].concat().into())
+            })
+        })
+        .unwrap();
+        let resolver = resolver.with_virtual_file(nested);
+
+        resolver.fetch_file("twice.lang").await.unwrap();
+        assert!(cache.contains("file:locale/all.lang").await);
+        assert!(cache.contains("file:twice.lang").await);
+
+        // Dropping a member drops the composites built from it, transitively
+        assert!(resolver.invalidate("locale/en.lang").await.unwrap());
+        assert!(!cache.contains("file:locale/all.lang").await);
+        assert!(!cache.contains("file:twice.lang").await);
+        assert!(cache.contains("file:locale/de.lang").await);
+
+        // Files outside the listed directory leave the composites alone
+        resolver.fetch_file("twice.lang").await.unwrap();
+        resolver.fetch_file("locale/sub/es.lang").await.unwrap();
+        resolver.invalidate("locale/sub/es.lang").await.unwrap();
+        assert!(cache.contains("file:twice.lang").await);
+    }
+
+    #[tokio::test]
+    async fn test_listing_dependency() {
+        let cache = Arc::new(MemoryCache::new());
+        let resolver = ResourceResolver::with_cache(vec![Arc::new(locale())], cache.clone())
+            .with_virtual_file(concat(true));
+        resolver.fetch_file("locale/all.lang").await.unwrap();
+
+        // A new file was never cached, but it changes the listing
+        resolver.replace_sources(vec![Arc::new(locale().with_file("locale/nl.lang", "nl"))]);
+        assert!(!resolver.invalidate("locale/nl.lang").await.unwrap());
+        assert!(!cache.contains("file:locale/all.lang").await);
+        let file = resolver.fetch_file("locale/all.lang").await.unwrap();
+        assert_eq!(file.text().unwrap(), "de\nen\nfr\nnl");
+
+        // Any file in the directory may have been added or removed
+        resolver.invalidate("locale/notes.txt").await.unwrap();
+        assert!(!cache.contains("file:locale/all.lang").await);
+    }
+
+    #[tokio::test]
+    async fn test_depth_limit() {
+        let looping = VirtualFileRule::new("loop.txt", |resolver, path| {
+            Box::pin(async move { Ok(resolver.fetch_file(path).await?.content) })
+        })
+        .unwrap();
+        let resolver =
+            ResourceResolver::new(vec![Arc::new(MemorySource::new())]).with_virtual_file(looping);
+
+        let error = resolver.fetch_file("loop.txt").await.unwrap_err();
+        assert_eq!(error.kind(), crate::error::ErrorKind::InvalidConfig);
+        assert!(
+            error.to_string().contains("nest more than 8 deep"),
+            "{}",
+            error
+        );
+    }
+}
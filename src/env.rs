@@ -0,0 +1,305 @@
+//! Resolver configuration from `CONTENT_*` environment variables
+//!
+//! See `ResourceResolver::from_env` for the variables read.
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{
+    cache::Cache,
+    error::{ContentError, Result},
+    resolver::ResourceResolver,
+    secret::SecretString,
+    source::ContentSource,
+};
+
+const SOURCES: &str = "CONTENT_SOURCES";
+const CACHE_DIR: &str = "CONTENT_CACHE_DIR";
+const CACHE_TTL_SECS: &str = "CONTENT_CACHE_TTL_SECS";
+const GITHUB_TOKEN: &str = "CONTENT_GITHUB_TOKEN";
+const TIMEOUT_MS: &str = "CONTENT_TIMEOUT_MS";
+
+/// Settings read from the environment, before anything is built
+struct EnvConfig {
+    sources: Vec<String>,
+    cache_dir: Option<PathBuf>,
+    cache_ttl: Option<Duration>,
+    github_token: Option<SecretString>,
+    timeout: Option<Duration>,
+}
+
+/// Where variables are read from, the process environment outside of tests
+type Lookup<'a> = &'a dyn Fn(&str) -> Option<OsString>;
+
+/// Read a variable, treating an empty or blank value as unset
+fn var(lookup: Lookup<'_>, name: &str) -> Result<Option<String>> {
+    match lookup(name).map(OsString::into_string) {
+        Some(Ok(value)) if value.trim().is_empty() => Ok(None),
+        Some(Ok(value)) => Ok(Some(value.trim().to_string())),
+        None => Ok(None),
+        Some(Err(_)) => Err(ContentError::InvalidConfig {
+            message: format!("{} is not valid UTF-8", name),
+        }),
+    }
+}
+
+/// Read a whole number variable
+fn number_var(lookup: Lookup<'_>, name: &str, unit: &str) -> Result<Option<u64>> {
+    var(lookup, name)?
+        .map(|value| {
+            value.parse().map_err(|_| ContentError::InvalidConfig {
+                message: format!("{}: expected a whole number of {}, got {:?}", name, unit, value),
+            })
+        })
+        .transpose()
+}
+
+impl EnvConfig {
+    fn read(lookup: Lookup<'_>) -> Result<Self> {
+        let sources = var(lookup, SOURCES)?
+            .map(|list| {
+                list.split([',', ';'])
+                    .map(str::trim)
+                    .filter(|url| !url.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let config = Self {
+            sources,
+            cache_dir: var(lookup, CACHE_DIR)?.map(PathBuf::from),
+            cache_ttl: number_var(lookup, CACHE_TTL_SECS, "seconds")?.map(Duration::from_secs),
+            github_token: var(lookup, GITHUB_TOKEN)?.map(SecretString::from),
+            timeout: number_var(lookup, TIMEOUT_MS, "milliseconds")?.map(Duration::from_millis),
+        };
+
+        if config.cache_ttl.is_some() && config.cache_dir.is_none() {
+            return Err(ContentError::InvalidConfig {
+                message: format!("{} needs {} to be set", CACHE_TTL_SECS, CACHE_DIR),
+            });
+        }
+        if config.timeout == Some(Duration::ZERO) {
+            return Err(ContentError::InvalidConfig {
+                message: format!("{}: the timeout must be above 0", TIMEOUT_MS),
+            });
+        }
+        if config.sources.is_empty() {
+            return Err(ContentError::InvalidConfig {
+                message: format!("No content sources configured; read {}", config.describe()),
+            });
+        }
+        Ok(config)
+    }
+
+    /// Build one source per `CONTENT_SOURCES` URL, in order
+    fn sources(&self) -> Result<Vec<Arc<dyn ContentSource>>> {
+        self.sources
+            .iter()
+            .map(|url| -> Result<Arc<dyn ContentSource>> {
+                let bad_url = |e: ContentError| match e {
+                    ContentError::InvalidConfig { message } => ContentError::InvalidConfig {
+                        message: format!("{}: {}", SOURCES, message),
+                    },
+                    other => other,
+                };
+                if url.starts_with("github://") {
+                    self.github_source(url).map_err(bad_url)
+                } else if url.starts_with("local://") {
+                    local_source(url).map_err(bad_url)
+                } else {
+                    Err(ContentError::InvalidConfig {
+                        message: format!(
+                            "{}: unsupported source {:?}; expected github:// or local://",
+                            SOURCES, url
+                        ),
+                    })
+                }
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "github")]
+    fn github_source(&self, url: &str) -> Result<Arc<dyn ContentSource>> {
+        let mut source = crate::github::GitHubSource::from_url(url)?;
+        if let Some(token) = &self.github_token {
+            source = source.with_token(token.clone());
+        }
+        if let Some(timeout) = self.timeout {
+            source = source.with_timeout(timeout);
+        }
+        Ok(Arc::new(source))
+    }
+
+    #[cfg(not(feature = "github"))]
+    fn github_source(&self, url: &str) -> Result<Arc<dyn ContentSource>> {
+        Err(ContentError::InvalidConfig {
+            message: format!("{:?} needs the `github` feature", url),
+        })
+    }
+
+    /// Every variable and the value read, with the token redacted
+    fn describe(&self) -> String {
+        let show = |value: Option<String>| value.unwrap_or_else(|| "<unset>".to_string());
+        [
+            (SOURCES, show((!self.sources.is_empty()).then(|| self.sources.join(",")))),
+            (CACHE_DIR, show(self.cache_dir.as_ref().map(|d| d.display().to_string()))),
+            (CACHE_TTL_SECS, show(self.cache_ttl.map(|t| t.as_secs().to_string()))),
+            (GITHUB_TOKEN, show(self.github_token.as_ref().map(|t| t.to_string()))),
+            (TIMEOUT_MS, show(self.timeout.map(|t| t.as_millis().to_string()))),
+        ]
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+}
+
+#[cfg(feature = "fs")]
+fn local_source(url: &str) -> Result<Arc<dyn ContentSource>> {
+    Ok(Arc::new(crate::local::LocalFileSource::from_url(url)?))
+}
+
+#[cfg(not(feature = "fs"))]
+fn local_source(url: &str) -> Result<Arc<dyn ContentSource>> {
+    Err(ContentError::InvalidConfig {
+        message: format!("{:?} needs the `fs` feature", url),
+    })
+}
+
+#[cfg(feature = "fs")]
+async fn disk_cache(dir: &std::path::Path, ttl: Option<Duration>) -> Result<Arc<dyn Cache>> {
+    let mut cache = crate::cache::DiskCache::new(dir.to_path_buf()).await?;
+    if let Some(ttl) = ttl {
+        cache = cache.with_ttl(ttl);
+    }
+    Ok(Arc::new(cache))
+}
+
+#[cfg(not(feature = "fs"))]
+async fn disk_cache(_dir: &std::path::Path, _ttl: Option<Duration>) -> Result<Arc<dyn Cache>> {
+    Err(ContentError::InvalidConfig {
+        message: format!("{} needs the `fs` feature", CACHE_DIR),
+    })
+}
+
+pub(crate) async fn resolver_from_env() -> Result<ResourceResolver> {
+    let config = EnvConfig::read(&|name| std::env::var_os(name))?;
+    resolver_from_config(config).await
+}
+
+async fn resolver_from_config(config: EnvConfig) -> Result<ResourceResolver> {
+    let sources = config.sources()?;
+
+    match &config.cache_dir {
+        Some(dir) => {
+            let cache = disk_cache(dir, config.cache_ttl).await?;
+            // Different source lists may point at the same cache directory
+            Ok(ResourceResolver::try_with_cache(sources, cache)?.with_source_cache_scope())
+        }
+        None => ResourceResolver::try_new(sources),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    /// Build a resolver from `vars` alone, leaving the process environment alone
+    async fn from_vars(vars: &[(&str, &str)]) -> Result<ResourceResolver> {
+        let lookup = |name: &str| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| OsString::from(value))
+        };
+        resolver_from_config(EnvConfig::read(&lookup)?).await
+    }
+
+    async fn error_message(vars: &[(&str, &str)]) -> String {
+        let error = from_vars(vars).await.err().expect("expected an error");
+        assert_eq!(error.kind(), ErrorKind::InvalidConfig);
+        error.to_string()
+    }
+
+    #[tokio::test]
+    async fn test_from_env() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "local").unwrap();
+        let github = if cfg!(feature = "github") { "github://org/content@v2/docs," } else { "" };
+        let sources = format!(" local://{} ; {}", dir.path().display(), github);
+        let cache_dir = dir.path().join("cache");
+
+        let resolver = from_vars(&[
+            (SOURCES, &sources),
+            (CACHE_DIR, cache_dir.to_str().unwrap()),
+            (CACHE_TTL_SECS, "60"),
+            (GITHUB_TOKEN, "ghp_s3cr3t"),
+            (TIMEOUT_MS, "2500"),
+        ])
+        .await
+        .unwrap();
+
+        let identifiers: Vec<_> = resolver.sources().iter().map(|s| s.identifier()).collect();
+        let mut expected = vec![format!("local://{}", dir.path().display())];
+        if cfg!(feature = "github") {
+            expected.push("github://org/content/v2/docs".to_string());
+        }
+        assert_eq!(identifiers, expected);
+
+        // Fetches go through the disk cache, scoped to these sources
+        assert_eq!(resolver.fetch_file("a.txt").await.unwrap().content, "local");
+        assert!(cache_dir.exists());
+        let scope = resolver.cache_scope().unwrap();
+        assert_eq!(scope.len(), 16);
+        let reversed = ResourceResolver::new(resolver.sources().iter().rev().cloned().collect())
+            .with_source_cache_scope();
+        assert_eq!(reversed.cache_scope().is_some_and(|other| other != scope), expected.len() > 1);
+    }
+
+    #[tokio::test]
+    async fn test_from_env_errors() {
+        let message = error_message(&[]).await;
+        assert!(message.contains("No content sources configured"), "{}", message);
+        assert!(message.contains("CONTENT_SOURCES=<unset>"), "{}", message);
+
+        let message = error_message(&[(CACHE_DIR, "/tmp/c"), (GITHUB_TOKEN, "ghp_s3cr3t")]).await;
+        assert!(message.contains("CONTENT_CACHE_DIR=/tmp/c"), "{}", message);
+        assert!(message.contains("CONTENT_GITHUB_TOKEN=<redacted>"), "{}", message);
+        assert!(!message.contains("s3cr3t"), "{}", message);
+
+        let cases: [(&[(&str, &str)], &str); 6] = [
+            (&[(SOURCES, "s3://bucket")], r#"unsupported source "s3://bucket""#),
+            (
+                &[(SOURCES, "github://org")],
+                if cfg!(feature = "github") {
+                    "CONTENT_SOURCES: Expected github://"
+                } else {
+                    "needs the `github` feature"
+                },
+            ),
+            (&[(SOURCES, "local://")], "CONTENT_SOURCES: Expected local://"),
+            (
+                &[(SOURCES, "local:///x"), (CACHE_DIR, "/tmp/c"), (CACHE_TTL_SECS, "soon")],
+                r#"CONTENT_CACHE_TTL_SECS: expected a whole number of seconds, got "soon""#,
+            ),
+            (
+                &[(SOURCES, "local:///x"), (CACHE_TTL_SECS, "60")],
+                "CONTENT_CACHE_TTL_SECS needs CONTENT_CACHE_DIR",
+            ),
+            (
+                &[(SOURCES, "local:///x"), (TIMEOUT_MS, "-5")],
+                r#"CONTENT_TIMEOUT_MS: expected a whole number of milliseconds, got "-5""#,
+            ),
+        ];
+        for (vars, expected) in cases {
+            let message = error_message(vars).await;
+            assert!(message.contains(expected), "{} does not contain {}", message, expected);
+        }
+
+        let message = error_message(&[(SOURCES, "local:///x"), (TIMEOUT_MS, "0")]).await;
+        assert!(message.contains("must be above 0"), "{}", message);
+    }
+}
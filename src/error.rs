@@ -1,20 +1,87 @@
+use std::time::Duration;
 use thiserror::Error;
 
 /// Errors that can occur during content resolution
+/// 
+/// New variants may be added in minor releases; match on `kind()` or keep a
+/// wildcard arm.
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum ContentError {
     #[error("Content not found: {path}")]
     NotFound { path: String },
 
+    /// A directory listing was asked for a path that is a file
+    #[error("Not a directory: {path}")]
+    NotADirectory { path: String },
+
+    /// Transport failure of an HTTP source; only with the `github` feature
+    #[cfg(feature = "github")]
     #[error("Network error: {0}")]
-    Network(#[from] reqwest::Error),
+    Network(#[source] reqwest::Error),
 
     #[error("Rate limited by remote service: {message}")]
-    RateLimited { message: String },
+    RateLimited {
+        message: String,
+        /// How long the service asked us to wait, when it said so
+        retry_after: Option<Duration>,
+    },
+
+    #[error(
+        "Timed out fetching {path}{}",
+        .after.map(|after| format!(" after {:?}", after)).unwrap_or_default()
+    )]
+    Timeout {
+        path: String,
+        /// Configured timeout, when known
+        after: Option<Duration>,
+    },
+
+    #[error("Unexpected status {status} for {path}: {message}")]
+    UpstreamStatus {
+        path: String,
+        status: u16,
+        /// Response body, as far as it could be read
+        message: String,
+    },
 
     #[error("Invalid remote structure: {message}")]
     InvalidStructure { message: String },
 
+    #[error(
+        "Content too large: {path} exceeds the limit of {limit} bytes{}",
+        .size.map(|size| format!(" ({} bytes)", size)).unwrap_or_default()
+    )]
+    TooLarge {
+        path: String,
+        /// Actual size, when the source declared it
+        size: Option<u64>,
+        limit: u64,
+    },
+
+    #[error(
+        "Validation failed for {path}: {message}{}",
+        pointer_suffix(.pointers)
+    )]
+    Validation {
+        path: String,
+        message: String,
+        /// JSON pointers (or similar locations) of the offending values
+        pointers: Vec<String>,
+    },
+
+    #[error("Integrity check failed for {path}: expected {expected}, got {actual}")]
+    IntegrityMismatch {
+        path: String,
+        /// Expected digest, hex encoded
+        expected: String,
+        /// Digest of the received content, hex encoded
+        actual: String,
+    },
+
+    #[error("Invalid path {path:?}: {reason}")]
+    InvalidPath { path: String, reason: String },
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -26,7 +93,929 @@ pub enum ContentError {
 
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
+
+    /// The source can't perform the operation, e.g. reading past versions
+    #[error("Unsupported operation: {message}")]
+    Unsupported { message: String },
+
+    /// Another error, returned after the alternatives in `paths` failed too
+    /// 
+    /// Rendered as `<inner> (also tried <paths>)`. `fetch_first` returns the
+    /// NotFound of the first candidate wrapped in this, naming the others.
+    #[error("{inner} (also tried {})", .paths.join(", "))]
+    AlsoTried {
+        /// The other paths tried, in order
+        paths: Vec<String>,
+        inner: Box<ContentError>,
+    },
+
+    /// Another error annotated with where it happened
+    /// 
+    /// Rendered as `<source_id>: <path>: <inner>`, omitting missing parts
+    #[error("{}{inner}", context_prefix(.source_id, .path))]
+    WithContext {
+        /// Identifier of the source that produced the error
+        source_id: Option<String>,
+        /// Path being resolved when the error occurred
+        path: Option<String>,
+        inner: Box<ContentError>,
+    },
+}
+
+fn pointer_suffix(pointers: &[String]) -> String {
+    if pointers.is_empty() {
+        String::new()
+    } else {
+        format!(" (at {})", pointers.join(", "))
+    }
+}
+
+fn context_prefix(source_id: &Option<String>, path: &Option<String>) -> String {
+    source_id
+        .iter()
+        .chain(path)
+        .map(|part| format!("{}: ", part))
+        .collect()
 }
 
 /// Result type alias for content operations
 pub type Result<T> = std::result::Result<T, ContentError>;
+
+/// Category of a `ContentError`, for matching without destructuring payloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    NotFound,
+    NotADirectory,
+    Network,
+    Timeout,
+    UpstreamStatus,
+    RateLimited,
+    InvalidStructure,
+    TooLarge,
+    Validation,
+    IntegrityMismatch,
+    InvalidPath,
+    Io,
+    Cache,
+    InvalidConfig,
+    Serialization,
+    Unsupported,
+}
+
+impl ErrorKind {
+    /// Stable snake_case name, e.g. `not_found`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::NotADirectory => "not_a_directory",
+            ErrorKind::Network => "network",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::UpstreamStatus => "upstream_status",
+            ErrorKind::RateLimited => "rate_limited",
+            ErrorKind::InvalidStructure => "invalid_structure",
+            ErrorKind::TooLarge => "too_large",
+            ErrorKind::Validation => "validation",
+            ErrorKind::IntegrityMismatch => "integrity_mismatch",
+            ErrorKind::InvalidPath => "invalid_path",
+            ErrorKind::Io => "io",
+            ErrorKind::Cache => "cache",
+            ErrorKind::InvalidConfig => "invalid_config",
+            ErrorKind::Serialization => "serialization",
+            ErrorKind::Unsupported => "unsupported",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl ContentError {
+    /// Get the category of this error
+    /// 
+    /// Network errors caused by a timeout report `ErrorKind::Timeout`
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ContentError::NotFound { .. } => ErrorKind::NotFound,
+            ContentError::NotADirectory { .. } => ErrorKind::NotADirectory,
+            #[cfg(feature = "github")]
+            ContentError::Network(e) if e.is_timeout() => ErrorKind::Timeout,
+            #[cfg(feature = "github")]
+            ContentError::Network(_) => ErrorKind::Network,
+            ContentError::Timeout { .. } => ErrorKind::Timeout,
+            ContentError::UpstreamStatus { .. } => ErrorKind::UpstreamStatus,
+            ContentError::RateLimited { .. } => ErrorKind::RateLimited,
+            ContentError::InvalidStructure { .. } => ErrorKind::InvalidStructure,
+            ContentError::TooLarge { .. } => ErrorKind::TooLarge,
+            ContentError::Validation { .. } => ErrorKind::Validation,
+            ContentError::IntegrityMismatch { .. } => ErrorKind::IntegrityMismatch,
+            ContentError::InvalidPath { .. } => ErrorKind::InvalidPath,
+            ContentError::Io(_) => ErrorKind::Io,
+            ContentError::Cache { .. } => ErrorKind::Cache,
+            ContentError::InvalidConfig { .. } => ErrorKind::InvalidConfig,
+            ContentError::Serialization(_) => ErrorKind::Serialization,
+            ContentError::Unsupported { .. } => ErrorKind::Unsupported,
+            ContentError::AlsoTried { inner, .. } | ContentError::WithContext { inner, .. } => inner.kind(),
+        }
+    }
+
+    /// Check whether the error is likely transient and worth retrying
+    /// 
+    /// - `Network`: retryable for timeouts, connection failures, failures while
+    ///   sending or reading the body, and 5xx/429 statuses. Not retryable for
+    ///   request-building, redirect, decode, or other 4xx failures, which would
+    ///   fail the same way again.
+    /// - `Timeout`: retryable.
+    /// - `UpstreamStatus`: retryable for 5xx and 408, not for other statuses.
+    /// - `RateLimited`: retryable, ideally after `retry_after()`.
+    /// - `InvalidStructure`, `Validation`: not retryable; the source serves
+    ///   content we can't use.
+    /// - `IntegrityMismatch`: not retryable; a corrupted transfer surfaces as a
+    ///   network error instead, so a mismatch means the content itself differs.
+    /// - `NotFound`, `NotADirectory`, `TooLarge`, `InvalidPath`, `InvalidConfig`,
+    ///   `Serialization`: not retryable; the same request yields the same answer.
+    /// - `Io`, `Cache`: not retryable; local failures aren't fixed by waiting.
+    /// - `Unsupported`: not retryable; the source can't do it at all.
+    /// - `WithContext`: same as the wrapped error.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            #[cfg(feature = "github")]
+            ContentError::Network(e) => {
+                if let Some(status) = e.status() {
+                    return status.is_server_error() || status.as_u16() == 429;
+                }
+                e.is_timeout() || e.is_connect() || e.is_request() || e.is_body()
+            }
+            ContentError::Timeout { .. } | ContentError::RateLimited { .. } => true,
+            ContentError::UpstreamStatus { status, .. } => *status >= 500 || *status == 408,
+            ContentError::NotFound { .. }
+            | ContentError::NotADirectory { .. }
+            | ContentError::InvalidStructure { .. }
+            | ContentError::TooLarge { .. }
+            | ContentError::Validation { .. }
+            | ContentError::IntegrityMismatch { .. }
+            | ContentError::InvalidPath { .. }
+            | ContentError::Io(_)
+            | ContentError::Cache { .. }
+            | ContentError::InvalidConfig { .. }
+            | ContentError::Serialization(_)
+            | ContentError::Unsupported { .. } => false,
+            ContentError::AlsoTried { inner, .. } | ContentError::WithContext { inner, .. } => {
+                inner.is_retryable()
+            }
+        }
+    }
+
+    /// Check whether the error means the content doesn't exist
+    pub fn is_not_found(&self) -> bool {
+        self.kind() == ErrorKind::NotFound
+    }
+
+    /// Check whether the error means the source can't perform the operation
+    pub fn is_unsupported(&self) -> bool {
+        self.kind() == ErrorKind::Unsupported
+    }
+
+    /// Check whether the error means the remote service is rate limiting us
+    pub fn is_rate_limited(&self) -> bool {
+        self.kind() == ErrorKind::RateLimited
+    }
+
+    /// How long to wait before retrying, when the remote service said so
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ContentError::RateLimited { retry_after, .. } => *retry_after,
+            ContentError::AlsoTried { inner, .. } | ContentError::WithContext { inner, .. } => {
+                inner.retry_after()
+            }
+            _ => None,
+        }
+    }
+
+    /// HTTP status code to report when serving this error to a client
+    /// 
+    /// Failures of the upstream source map to gateway statuses (502/504), local
+    /// failures to 500. Rate limiting maps to 429 when we know how long to wait
+    /// and 503 otherwise. Unsupported operations map to 501.
+    pub fn http_status(&self) -> u16 {
+        match self.kind() {
+            ErrorKind::NotFound | ErrorKind::NotADirectory => 404,
+            ErrorKind::RateLimited if self.retry_after().is_some() => 429,
+            ErrorKind::RateLimited => 503,
+            ErrorKind::Timeout => 504,
+            ErrorKind::InvalidPath => 400,
+            ErrorKind::Unsupported => 501,
+            ErrorKind::Network
+            | ErrorKind::UpstreamStatus
+            | ErrorKind::InvalidStructure
+            | ErrorKind::TooLarge
+            | ErrorKind::Validation
+            | ErrorKind::IntegrityMismatch
+            | ErrorKind::Serialization => 502,
+            ErrorKind::Io | ErrorKind::Cache | ErrorKind::InvalidConfig => 500,
+        }
+    }
+
+    /// Value for a `Retry-After` response header, in whole seconds
+    pub fn retry_after_header_value(&self) -> Option<String> {
+        self.retry_after().map(|wait| {
+            let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+            secs.to_string()
+        })
+    }
+
+    /// Annotate the error with the path being resolved
+    /// 
+    /// Context already present is kept, since it was added closer to the failure
+    pub fn with_path(self, path: impl Into<String>) -> Self {
+        self.add_context(None, Some(path.into()))
+    }
+
+    /// Annotate the error with the identifier of the source that produced it
+    /// 
+    /// Context already present is kept, since it was added closer to the failure
+    pub fn with_source(self, source_id: impl Into<String>) -> Self {
+        self.add_context(Some(source_id.into()), None)
+    }
+
+    /// NotFound for the first of `paths`, naming the rest as also tried
+    pub(crate) fn not_found_among<P: AsRef<str>>(paths: &[P]) -> Self {
+        let mut paths = paths.iter().map(|path| path.as_ref().to_string());
+        let error = ContentError::NotFound {
+            path: paths.next().unwrap_or_default(),
+        };
+        let rest: Vec<String> = paths.collect();
+        if rest.is_empty() {
+            return error;
+        }
+        ContentError::AlsoTried {
+            paths: rest,
+            inner: Box::new(error),
+        }
+    }
+
+    fn add_context(self, new_source_id: Option<String>, new_path: Option<String>) -> Self {
+        match self {
+            ContentError::WithContext {
+                source_id,
+                path,
+                inner,
+            } => ContentError::WithContext {
+                source_id: source_id.or(new_source_id),
+                path: path.or(new_path),
+                inner,
+            },
+            inner => ContentError::WithContext {
+                source_id: new_source_id,
+                path: new_path,
+                inner: Box::new(inner),
+            },
+        }
+    }
+
+    /// Identifier of the source that produced the error, if known
+    pub fn source_id(&self) -> Option<&str> {
+        match self {
+            ContentError::WithContext { source_id, .. } => source_id.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Path the error relates to, if known
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            ContentError::WithContext {
+                path: Some(path), ..
+            } => Some(path),
+            ContentError::AlsoTried { inner, .. } | ContentError::WithContext { inner, .. } => inner.path(),
+            ContentError::NotFound { path }
+            | ContentError::NotADirectory { path }
+            | ContentError::Timeout { path, .. }
+            | ContentError::UpstreamStatus { path, .. }
+            | ContentError::TooLarge { path, .. }
+            | ContentError::Validation { path, .. }
+            | ContentError::IntegrityMismatch { path, .. }
+            | ContentError::InvalidPath { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Get the underlying error without any context
+    pub fn root(&self) -> &ContentError {
+        match self {
+            ContentError::AlsoTried { inner, .. } | ContentError::WithContext { inner, .. } => inner.root(),
+            other => other,
+        }
+    }
+
+    /// Take the underlying error without any context
+    pub fn into_root(self) -> ContentError {
+        match self {
+            ContentError::AlsoTried { inner, .. } | ContentError::WithContext { inner, .. } => {
+                inner.into_root()
+            }
+            other => other,
+        }
+    }
+}
+
+/// Network errors keep the request URL, minus any credentials in it
+#[cfg(feature = "github")]
+impl From<reqwest::Error> for ContentError {
+    fn from(error: reqwest::Error) -> Self {
+        ContentError::Network(crate::secret::redact_reqwest_error(error))
+    }
+}
+
+/// Adds context to the error of a `Result`
+pub trait ResultExt<T> {
+    /// Annotate an error with the path being resolved
+    fn with_path(self, path: &str) -> Result<T>;
+
+    /// Annotate an error with the identifier of the source that produced it
+    fn with_source(self, source_id: &str) -> Result<T>;
+}
+
+impl<T> ResultExt<T> for Result<T> {
+    fn with_path(self, path: &str) -> Result<T> {
+        self.map_err(|e| e.with_path(path))
+    }
+
+    fn with_source(self, source_id: &str) -> Result<T> {
+        self.map_err(|e| e.with_source(source_id))
+    }
+}
+
+/// Outcome of an operation over many paths where some may fail
+/// 
+/// Keeps every success and every failure, each failure with the path it
+/// relates to. Serializes failures as `{ path, error, message }` objects,
+/// where `error` is the `ErrorKind`.
+#[derive(Debug)]
+pub struct PartialFailure<T> {
+    /// Items that succeeded, in input order
+    pub succeeded: Vec<T>,
+    /// Paths that failed and why, in input order
+    pub failed: Vec<(String, ContentError)>,
+}
+
+/// Number of failures `PartialFailure`'s Display lists before summarizing
+const DISPLAYED_FAILURES: usize = 5;
+
+impl<T> PartialFailure<T> {
+    /// Create an empty outcome
+    pub fn new() -> Self {
+        Self {
+            succeeded: Vec::new(),
+            failed: Vec::new(),
+        }
+    }
+
+    /// Record the outcome for one path
+    pub fn push(&mut self, path: impl Into<String>, result: Result<T>) {
+        match result {
+            Ok(item) => self.succeeded.push(item),
+            Err(error) => self.failed.push((path.into(), error)),
+        }
+    }
+
+    /// Check whether nothing failed
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// Total number of items, successful or not
+    pub fn len(&self) -> usize {
+        self.succeeded.len() + self.failed.len()
+    }
+
+    /// Check whether there are no items at all
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The successes, or the first failure annotated with its path
+    pub fn ok_or_first_error(self) -> Result<Vec<T>> {
+        match self.failed.into_iter().next() {
+            Some((path, error)) => Err(error.with_path(&path)),
+            None => Ok(self.succeeded),
+        }
+    }
+
+    /// The successes, or the whole outcome if anything failed
+    pub fn into_result(self) -> std::result::Result<Vec<T>, Self> {
+        if self.is_complete() {
+            Ok(self.succeeded)
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl<T> Default for PartialFailure<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, P: Into<String>> FromIterator<(P, Result<T>)> for PartialFailure<T> {
+    fn from_iter<I: IntoIterator<Item = (P, Result<T>)>>(iter: I) -> Self {
+        let mut outcome = Self::new();
+        for (path, result) in iter {
+            outcome.push(path, result);
+        }
+        outcome
+    }
+}
+
+impl<T> std::fmt::Display for PartialFailure<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} of {} failed", self.failed.len(), self.len())?;
+        for (i, (path, error)) in self.failed.iter().take(DISPLAYED_FAILURES).enumerate() {
+            let separator = if i == 0 { ": " } else { "; " };
+            write!(f, "{}{}: {}", separator, path, error.root())?;
+        }
+        if self.failed.len() > DISPLAYED_FAILURES {
+            write!(f, "; and {} more", self.failed.len() - DISPLAYED_FAILURES)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: std::fmt::Debug> std::error::Error for PartialFailure<T> {}
+
+impl<T: serde::Serialize> serde::Serialize for PartialFailure<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        #[derive(serde::Serialize)]
+        struct Failure<'a> {
+            path: &'a str,
+            error: &'static str,
+            message: String,
+        }
+
+        let failed: Vec<_> = self
+            .failed
+            .iter()
+            .map(|(path, error)| Failure {
+                path,
+                error: error.kind().as_str(),
+                message: error.root().to_string(),
+            })
+            .collect();
+
+        let mut state = serializer.serialize_struct("PartialFailure", 2)?;
+        state.serialize_field("succeeded", &self.succeeded)?;
+        state.serialize_field("failed", &failed)?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "github")]
+    async fn network_error(kind: &str) -> reqwest::Error {
+        match kind {
+            "builder" => reqwest::Client::new()
+                .get("not a url")
+                .send()
+                .await
+                .unwrap_err(),
+            "connect" => {
+                // Bind then drop a listener to get a port nothing listens on
+                let port = std::net::TcpListener::bind("127.0.0.1:0")
+                    .unwrap()
+                    .local_addr()
+                    .unwrap()
+                    .port();
+                reqwest::get(format!("http://127.0.0.1:{}/", port))
+                    .await
+                    .unwrap_err()
+            }
+            "timeout" => {
+                // Accept connections but never answer
+                let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let addr = listener.local_addr().unwrap();
+                // Aborted, closing the sockets, once the request has timed out
+                let tasks = crate::task::TaskSet::new();
+                tasks.spawn(|_| async move {
+                    let mut sockets = Vec::new();
+                    while let Ok((socket, _)) = listener.accept().await {
+                        sockets.push(socket);
+                    }
+                });
+                reqwest::Client::builder()
+                    .timeout(Duration::from_millis(50))
+                    .build()
+                    .unwrap()
+                    .get(format!("http://{}/", addr))
+                    .send()
+                    .await
+                    .unwrap_err()
+            }
+            status => {
+                let mut server = mockito::Server::new_async().await;
+                let _mock = server
+                    .mock("GET", "/")
+                    .with_status(status.parse().unwrap())
+                    .create_async()
+                    .await;
+                reqwest::get(server.url())
+                    .await
+                    .unwrap()
+                    .error_for_status()
+                    .unwrap_err()
+            }
+        }
+    }
+
+    fn upstream(status: u16) -> ContentError {
+        ContentError::UpstreamStatus {
+            path: "a".to_string(),
+            status,
+            message: "oops".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_new_variant_display() {
+        let cases = [
+            (
+                ContentError::Timeout { path: "a.txt".to_string(), after: None },
+                "Timed out fetching a.txt",
+            ),
+            (
+                ContentError::Timeout {
+                    path: "a.txt".to_string(),
+                    after: Some(Duration::from_secs(5)),
+                },
+                "Timed out fetching a.txt after 5s",
+            ),
+            (upstream(502), "Unexpected status 502 for a: oops"),
+            (
+                ContentError::Validation {
+                    path: "skill.json".to_string(),
+                    message: "schema mismatch".to_string(),
+                    pointers: Vec::new(),
+                },
+                "Validation failed for skill.json: schema mismatch",
+            ),
+            (
+                ContentError::Validation {
+                    path: "skill.json".to_string(),
+                    message: "schema mismatch".to_string(),
+                    pointers: vec!["/name".to_string(), "/deps/0".to_string()],
+                },
+                "Validation failed for skill.json: schema mismatch (at /name, /deps/0)",
+            ),
+            (
+                ContentError::IntegrityMismatch {
+                    path: "a.bin".to_string(),
+                    expected: "00ff".to_string(),
+                    actual: "ff00".to_string(),
+                },
+                "Integrity check failed for a.bin: expected 00ff, got ff00",
+            ),
+            (
+                ContentError::InvalidPath {
+                    path: "../x".to_string(),
+                    reason: "escapes the root".to_string(),
+                },
+                "Invalid path \"../x\": escapes the root",
+            ),
+            (
+                ContentError::NotADirectory { path: "README.md".to_string() },
+                "Not a directory: README.md",
+            ),
+        ];
+
+        for (error, display) in cases {
+            assert_eq!(error.to_string(), display);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error_classification() {
+        let cases: Vec<(ContentError, ErrorKind, bool)> = vec![
+            (
+                ContentError::NotFound { path: "a".to_string() },
+                ErrorKind::NotFound,
+                false,
+            ),
+            (
+                ContentError::NotADirectory { path: "a".to_string() },
+                ErrorKind::NotADirectory,
+                false,
+            ),
+            (
+                ContentError::RateLimited {
+                    message: "slow down".to_string(),
+                    retry_after: None,
+                },
+                ErrorKind::RateLimited,
+                true,
+            ),
+            (
+                ContentError::InvalidStructure { message: "bad".to_string() },
+                ErrorKind::InvalidStructure,
+                false,
+            ),
+            (
+                ContentError::TooLarge { path: "a".to_string(), size: None, limit: 1 },
+                ErrorKind::TooLarge,
+                false,
+            ),
+            (
+                ContentError::Io(std::io::Error::other("disk")),
+                ErrorKind::Io,
+                false,
+            ),
+            (
+                ContentError::Cache { message: "broken".to_string() },
+                ErrorKind::Cache,
+                false,
+            ),
+            (
+                ContentError::InvalidConfig { message: "bad".to_string() },
+                ErrorKind::InvalidConfig,
+                false,
+            ),
+            (
+                ContentError::Serialization(serde_json::from_str::<u8>("x").unwrap_err()),
+                ErrorKind::Serialization,
+                false,
+            ),
+            (
+                ContentError::Unsupported { message: "history".to_string() },
+                ErrorKind::Unsupported,
+                false,
+            ),
+            (
+                ContentError::Cache { message: "broken".to_string() }.with_path("a"),
+                ErrorKind::Cache,
+                false,
+            ),
+            (
+                ContentError::Timeout { path: "a".to_string(), after: None },
+                ErrorKind::Timeout,
+                true,
+            ),
+            (upstream(503), ErrorKind::UpstreamStatus, true),
+            (upstream(408), ErrorKind::UpstreamStatus, true),
+            (upstream(418), ErrorKind::UpstreamStatus, false),
+            (
+                ContentError::Validation {
+                    path: "a".to_string(),
+                    message: "bad".to_string(),
+                    pointers: Vec::new(),
+                },
+                ErrorKind::Validation,
+                false,
+            ),
+            (
+                ContentError::IntegrityMismatch {
+                    path: "a".to_string(),
+                    expected: "00".to_string(),
+                    actual: "ff".to_string(),
+                },
+                ErrorKind::IntegrityMismatch,
+                false,
+            ),
+            (
+                ContentError::InvalidPath { path: "..".to_string(), reason: "bad".to_string() },
+                ErrorKind::InvalidPath,
+                false,
+            ),
+        ];
+
+        for (error, kind, retryable) in cases {
+            assert_eq!(error.kind(), kind, "{}", error);
+            assert_eq!(error.is_retryable(), retryable, "{}", error);
+            assert_eq!(error.is_not_found(), kind == ErrorKind::NotFound, "{}", error);
+            assert_eq!(error.is_rate_limited(), kind == ErrorKind::RateLimited, "{}", error);
+        }
+    }
+
+    #[cfg(feature = "github")]
+    #[tokio::test]
+    async fn test_network_error_classification() {
+        let cases = [
+            ("connect", ErrorKind::Network, true),
+            ("timeout", ErrorKind::Timeout, true),
+            ("503", ErrorKind::Network, true),
+            ("429", ErrorKind::Network, true),
+            ("400", ErrorKind::Network, false),
+            ("builder", ErrorKind::Network, false),
+        ];
+
+        for (cause, kind, retryable) in cases {
+            let error = ContentError::Network(network_error(cause).await);
+            assert_eq!(error.kind(), kind, "{}", error);
+            assert_eq!(error.is_retryable(), retryable, "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let error = ContentError::RateLimited {
+            message: "slow down".to_string(),
+            retry_after: Some(Duration::from_secs(30)),
+        };
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(30)));
+
+        let error = ContentError::NotFound { path: "a".to_string() };
+        assert_eq!(error.retry_after(), None);
+    }
+
+    #[test]
+    fn test_context_display() {
+        let error = ContentError::InvalidStructure {
+            message: "bad".to_string(),
+        }
+        .with_path("a/b.txt")
+        .with_source("mock");
+        assert_eq!(error.to_string(), "mock: a/b.txt: Invalid remote structure: bad");
+
+        let error = ContentError::Cache {
+            message: "broken".to_string(),
+        }
+        .with_path("a.txt");
+        assert_eq!(error.to_string(), "a.txt: Cache error: broken");
+    }
+
+    #[test]
+    fn test_context_keeps_innermost() {
+        let error = ContentError::RateLimited {
+            message: "slow down".to_string(),
+            retry_after: Some(Duration::from_secs(5)),
+        }
+        .with_source("inner")
+        .with_path("inner/path")
+        .with_source("outer")
+        .with_path("outer/path");
+
+        assert_eq!(error.source_id(), Some("inner"));
+        assert_eq!(error.path(), Some("inner/path"));
+        assert!(matches!(error, ContentError::WithContext { ref inner, .. }
+            if matches!(**inner, ContentError::RateLimited { .. })));
+
+        // Classification sees through the wrapper
+        assert_eq!(error.kind(), ErrorKind::RateLimited);
+        assert!(error.is_rate_limited());
+        assert!(error.is_retryable());
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(5)));
+        assert!(matches!(error.root(), ContentError::RateLimited { .. }));
+        assert!(matches!(error.into_root(), ContentError::RateLimited { .. }));
+    }
+
+    #[test]
+    fn test_not_found_among() {
+        let error = ContentError::not_found_among(&["a.json", "a.msgpack", "a.lang"]);
+        assert_eq!(error.to_string(), "Content not found: a.json (also tried a.msgpack, a.lang)");
+        assert!(error.is_not_found());
+        assert_eq!(error.path(), Some("a.json"));
+        assert!(matches!(error.root(), ContentError::NotFound { path } if path == "a.json"));
+
+        let error = error.with_path("a");
+        assert_eq!(error.path(), Some("a"));
+        assert!(matches!(error.into_root(), ContentError::NotFound { path } if path == "a.json"));
+
+        let single = ContentError::not_found_among(&["a.json"]);
+        assert!(matches!(single, ContentError::NotFound { ref path } if path == "a.json"));
+    }
+
+    #[test]
+    fn test_http_status() {
+        let rate_limited = |retry_after| ContentError::RateLimited {
+            message: "slow down".to_string(),
+            retry_after,
+        };
+        let cases = [
+            (ContentError::NotFound { path: "a".to_string() }, 404),
+            (ContentError::NotADirectory { path: "a".to_string() }, 404),
+            (rate_limited(Some(Duration::from_millis(1500))), 429),
+            (rate_limited(None), 503),
+            (ContentError::InvalidStructure { message: "bad".to_string() }, 502),
+            (ContentError::TooLarge { path: "a".to_string(), size: None, limit: 1 }, 502),
+            (ContentError::Serialization(serde_json::from_str::<u8>("x").unwrap_err()), 502),
+            (ContentError::Io(std::io::Error::other("disk")), 500),
+            (ContentError::Cache { message: "broken".to_string() }, 500),
+            (ContentError::InvalidConfig { message: "bad".to_string() }, 500),
+            (ContentError::Unsupported { message: "history".to_string() }, 501),
+            (ContentError::NotFound { path: "a".to_string() }.with_source("s"), 404),
+            (ContentError::Timeout { path: "a".to_string(), after: None }, 504),
+            (upstream(500), 502),
+            (ContentError::InvalidPath { path: "..".to_string(), reason: "r".to_string() }, 400),
+        ];
+
+        for (error, status) in cases {
+            assert_eq!(error.http_status(), status, "{}", error);
+        }
+
+        assert_eq!(
+            rate_limited(Some(Duration::from_millis(1500))).retry_after_header_value(),
+            Some("2".to_string())
+        );
+        assert_eq!(rate_limited(None).retry_after_header_value(), None);
+    }
+
+    #[cfg(feature = "github")]
+    #[tokio::test]
+    async fn test_http_status_network() {
+        assert_eq!(ContentError::Network(network_error("connect").await).http_status(), 502);
+        assert_eq!(ContentError::Network(network_error("timeout").await).http_status(), 504);
+    }
+
+    #[test]
+    fn test_too_large_display() {
+        let error = ContentError::TooLarge {
+            path: "big.bin".to_string(),
+            size: Some(2048),
+            limit: 1024,
+        };
+        assert_eq!(
+            error.to_string(),
+            "Content too large: big.bin exceeds the limit of 1024 bytes (2048 bytes)"
+        );
+
+        let error = ContentError::TooLarge {
+            path: "stream.bin".to_string(),
+            size: None,
+            limit: 1024,
+        };
+        assert_eq!(
+            error.to_string(),
+            "Content too large: stream.bin exceeds the limit of 1024 bytes"
+        );
+    }
+
+    fn not_found(path: &str) -> ContentError {
+        ContentError::NotFound {
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_partial_failure_conversions() {
+        let complete: PartialFailure<u32> = vec![("a", Ok(1)), ("b", Ok(2))].into_iter().collect();
+        assert!(complete.is_complete());
+        assert_eq!(complete.ok_or_first_error().unwrap(), vec![1, 2]);
+
+        let partial: PartialFailure<u32> = vec![
+            ("a", Ok(1)),
+            ("b", Err(not_found("b"))),
+            ("c", Err(ContentError::Cache { message: "full".to_string() })),
+        ]
+        .into_iter()
+        .collect();
+        assert!(!partial.is_complete());
+        assert_eq!(partial.len(), 3);
+
+        let partial = partial.into_result().unwrap_err();
+        assert_eq!(partial.succeeded, vec![1]);
+
+        let error = partial.ok_or_first_error().unwrap_err();
+        assert!(error.is_not_found());
+        assert_eq!(error.path(), Some("b"));
+    }
+
+    #[test]
+    fn test_partial_failure_display_truncates() {
+        let mut outcome = PartialFailure::<()>::new();
+        outcome.push("ok", Ok(()));
+        for i in 0..8 {
+            let path = format!("f{}", i);
+            outcome.push(path.clone(), Err(not_found(&path)));
+        }
+
+        assert_eq!(
+            outcome.to_string(),
+            "8 of 9 failed: f0: Content not found: f0; f1: Content not found: f1; \
+             f2: Content not found: f2; f3: Content not found: f3; \
+             f4: Content not found: f4; and 3 more"
+        );
+
+        let mut small = PartialFailure::<()>::new();
+        small.push("a", Err(not_found("a")));
+        assert_eq!(small.to_string(), "1 of 1 failed: a: Content not found: a");
+    }
+
+    #[test]
+    fn test_partial_failure_serialize() {
+        let outcome: PartialFailure<&str> =
+            vec![("a", Ok("a")), ("b", Err(not_found("b")))].into_iter().collect();
+        assert_eq!(
+            serde_json::to_value(&outcome).unwrap(),
+            serde_json::json!({
+                "succeeded": ["a"],
+                "failed": [{"path": "b", "error": "not_found", "message": "Content not found: b"}],
+            })
+        );
+    }
+}
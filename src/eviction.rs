@@ -0,0 +1,297 @@
+//! Eviction policies of a bounded `MemoryCache`
+
+use std::collections::{BTreeMap, HashMap};
+
+/// How a bounded `MemoryCache` picks the entry to evict
+/// 
+/// See `MemoryCache::bounded`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[non_exhaustive]
+pub enum EvictionPolicy {
+    /// Evict the least recently used entry
+    #[default]
+    Lru,
+    /// Evict the least frequently used entry, the least recently used of
+    /// those if several tie
+    /// 
+    /// Counts are only kept while an entry is cached, so an evicted entry
+    /// starts over when it comes back.
+    Lfu,
+    /// Evict the least recently used entry read only once, keeping entries
+    /// read again in a protected segment
+    /// 
+    /// New entries start out probationary; a read promotes one to the
+    /// protected segment, which holds up to `protected_ratio` of the capacity
+    /// and demotes its least recently used entry back when full. A scan that
+    /// reads each key once only displaces other probationary entries.
+    SegmentedLru {
+        /// Share of the capacity reserved for protected entries, from 0 to 1
+        protected_ratio: f64,
+    },
+}
+
+/// Keys in the order they were last used
+#[derive(Debug, Default)]
+struct Recency {
+    order: BTreeMap<u64, String>,
+    ticks: HashMap<String, u64>,
+    next_tick: u64,
+}
+
+impl Recency {
+    /// Make `key` the most recently used, adding it if needed
+    fn push(&mut self, key: &str) {
+        self.remove(key);
+        self.order.insert(self.next_tick, key.to_string());
+        self.ticks.insert(key.to_string(), self.next_tick);
+        self.next_tick += 1;
+    }
+
+    fn remove(&mut self, key: &str) -> bool {
+        match self.ticks.remove(key) {
+            Some(tick) => {
+                self.order.remove(&tick);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn pop_oldest(&mut self) -> Option<String> {
+        let (_, key) = self.order.pop_first()?;
+        self.ticks.remove(&key);
+        Some(key)
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.ticks.contains_key(key)
+    }
+
+    fn len(&self) -> usize {
+        self.ticks.len()
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Lru(Recency),
+    Lfu {
+        /// Keys by (use count, last use)
+        order: BTreeMap<(u64, u64), String>,
+        uses: HashMap<String, (u64, u64)>,
+        next_tick: u64,
+    },
+    Segmented {
+        probation: Recency,
+        protected: Recency,
+        protected_capacity: usize,
+    },
+}
+
+/// Tracks the keys of a bounded cache and picks which one to evict
+#[derive(Debug)]
+pub(crate) struct Evictor {
+    policy: EvictionPolicy,
+    capacity: usize,
+    state: State,
+}
+
+impl Evictor {
+    /// Track up to `capacity` keys, at least one
+    pub(crate) fn new(policy: EvictionPolicy, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let state = match policy {
+            EvictionPolicy::Lru => State::Lru(Recency::default()),
+            EvictionPolicy::Lfu => State::Lfu {
+                order: BTreeMap::new(),
+                uses: HashMap::new(),
+                next_tick: 0,
+            },
+            EvictionPolicy::SegmentedLru { protected_ratio } => State::Segmented {
+                probation: Recency::default(),
+                protected: Recency::default(),
+                protected_capacity: (capacity as f64 * protected_ratio.clamp(0.0, 1.0)) as usize,
+            },
+        };
+        Self { policy, capacity, state }
+    }
+
+    /// Number of keys tracked
+    pub(crate) fn len(&self) -> usize {
+        match &self.state {
+            State::Lru(recency) => recency.len(),
+            State::Lfu { uses, .. } => uses.len(),
+            State::Segmented { probation, protected, .. } => probation.len() + protected.len(),
+        }
+    }
+
+    /// Start tracking a new key, returning the key to evict to make room
+    pub(crate) fn insert(&mut self, key: &str) -> Option<String> {
+        let victim = match self.len() >= self.capacity {
+            true => self.evict(),
+            false => None,
+        };
+        match &mut self.state {
+            State::Lru(recency) => recency.push(key),
+            State::Lfu { order, uses, next_tick } => {
+                order.insert((1, *next_tick), key.to_string());
+                uses.insert(key.to_string(), (1, *next_tick));
+                *next_tick += 1;
+            }
+            State::Segmented { probation, .. } => probation.push(key),
+        }
+        victim
+    }
+
+    /// Note a use of a tracked key
+    pub(crate) fn touch(&mut self, key: &str) {
+        match &mut self.state {
+            State::Lru(recency) => {
+                if recency.contains(key) {
+                    recency.push(key);
+                }
+            }
+            State::Lfu { order, uses, next_tick } => {
+                if let Some((count, tick)) = uses.get_mut(key) {
+                    let key = order.remove(&(*count, *tick)).expect("tracked key is ordered");
+                    *count += 1;
+                    *tick = *next_tick;
+                    *next_tick += 1;
+                    order.insert((*count, *tick), key);
+                }
+            }
+            State::Segmented { probation, protected, protected_capacity } => {
+                if probation.remove(key) {
+                    protected.push(key);
+                    if protected.len() > *protected_capacity {
+                        let demoted = protected.pop_oldest().expect("protected is over capacity");
+                        probation.push(&demoted);
+                    }
+                } else if protected.contains(key) {
+                    protected.push(key);
+                }
+            }
+        }
+    }
+
+    /// Stop tracking a key
+    pub(crate) fn remove(&mut self, key: &str) {
+        match &mut self.state {
+            State::Lru(recency) => {
+                recency.remove(key);
+            }
+            State::Lfu { order, uses, .. } => {
+                if let Some(used) = uses.remove(key) {
+                    order.remove(&used);
+                }
+            }
+            State::Segmented { probation, protected, .. } => {
+                if !probation.remove(key) {
+                    protected.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Stop tracking every key
+    pub(crate) fn clear(&mut self) {
+        *self = Self::new(self.policy, self.capacity);
+    }
+
+    /// Pick a key to evict and stop tracking it
+    fn evict(&mut self) -> Option<String> {
+        match &mut self.state {
+            State::Lru(recency) => recency.pop_oldest(),
+            State::Lfu { order, uses, .. } => {
+                let (_, key) = order.pop_first()?;
+                uses.remove(&key);
+                Some(key)
+            }
+            State::Segmented {
+                probation,
+                protected,
+                ..
+            } => probation.pop_oldest().or_else(|| protected.pop_oldest()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Replay `ops` — `+k` inserts, `k` touches, `-k` removes — returning the victims
+    fn run(policy: EvictionPolicy, capacity: usize, ops: &str) -> Vec<String> {
+        let mut evictor = Evictor::new(policy, capacity);
+        let mut victims = Vec::new();
+        for op in ops.split_whitespace() {
+            if let Some(key) = op.strip_prefix('+') {
+                victims.extend(evictor.insert(key));
+            } else if let Some(key) = op.strip_prefix('-') {
+                evictor.remove(key);
+            } else {
+                evictor.touch(op);
+            }
+            assert!(evictor.len() <= capacity, "{:?} over capacity after {}", policy, op);
+        }
+        victims
+    }
+
+    #[test]
+    fn test_lru() {
+        let lru = EvictionPolicy::Lru;
+        assert_eq!(run(lru, 2, "+a +b +c"), ["a"]);
+        assert_eq!(run(lru, 2, "+a +b a +c"), ["b"]);
+        assert_eq!(run(lru, 2, "+a +b -a +c +d"), ["b"]);
+        assert_eq!(run(lru, 3, "+a +b +c a b +d +e"), ["c", "a"]);
+    }
+
+    #[test]
+    fn test_lfu() {
+        let lfu = EvictionPolicy::Lfu;
+        assert_eq!(run(lfu, 2, "+a +b a +c"), ["b"]);
+        // Ties go to the least recently used
+        assert_eq!(run(lfu, 2, "+a +b b a +c"), ["b"]);
+        assert_eq!(run(lfu, 3, "+a +b +c a a b +d +e"), ["c", "d"]);
+        // Counts go with the entry
+        assert_eq!(run(lfu, 2, "+a a a -a +a +b b +c"), ["a"]);
+    }
+
+    #[test]
+    fn test_segmented_lru() {
+        let slru = EvictionPolicy::SegmentedLru { protected_ratio: 0.5 };
+        // Probationary entries go first, oldest first
+        assert_eq!(run(slru, 4, "+a +b a +c +d +e +f"), ["b", "c"]);
+        // A full protected segment demotes its oldest entry
+        assert_eq!(run(slru, 4, "+a +b +c a b c +d +e +f"), ["a", "d"]);
+        // With only protected entries left, the oldest of those goes
+        let all_protected = EvictionPolicy::SegmentedLru { protected_ratio: 1.0 };
+        assert_eq!(run(all_protected, 2, "+a +b b a +c"), ["b"]);
+        // Without a protected segment it is plain LRU
+        let no_protection = EvictionPolicy::SegmentedLru { protected_ratio: 0.0 };
+        assert_eq!(
+            run(no_protection, 3, "+a +b +c a b +d +e"),
+            run(EvictionPolicy::Lru, 3, "+a +b +c a b +d +e")
+        );
+    }
+
+    #[test]
+    fn test_clear_keeps_policy() {
+        let slru = EvictionPolicy::SegmentedLru { protected_ratio: 0.5 };
+        let mut evictor = Evictor::new(slru, 4);
+        evictor.insert("a");
+        evictor.insert("b");
+        evictor.touch("a");
+        evictor.clear();
+        assert_eq!(evictor.len(), 0);
+        assert_eq!(evictor.policy, slru);
+        // Still segmented: a read entry outlives newer unread ones
+        evictor.insert("a");
+        evictor.touch("a");
+        let victims: Vec<_> = ["b", "c", "d", "e"]
+            .iter()
+            .filter_map(|key| evictor.insert(key))
+            .collect();
+        assert_eq!(victims, ["b"]);
+    }
+}
@@ -0,0 +1,174 @@
+//! Dry-run reports of where the resolver would get a file from
+
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+use crate::error::ErrorKind;
+
+/// How `ResourceResolver::explain` expects `fetch_file` to answer for a path
+/// 
+/// Serializes to JSON for tooling; `Display` gives a multi-line summary for
+/// support tickets and the `explain` CLI command.
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct Explanation {
+    /// The path after normalization
+    pub path: String,
+    /// What the cache holds, or `None` if the resolver has no cache
+    pub cache: Option<CacheCheck>,
+    /// Each source's answer, in the order the resolver asks them
+    pub sources: Vec<SourceCheck>,
+    /// Where the file would come from
+    pub resolution: Resolution,
+    /// Why, in a sentence
+    pub reason: String,
+}
+
+/// The cache entry for an explained path
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct CacheCheck {
+    /// Cache key of the file, including the cache scope
+    pub key: String,
+    /// Whether the entry is there and still fresh
+    #[serde(flatten)]
+    pub state: CacheState,
+    /// Size of the cached content
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// ETag the source reported when the entry was cached
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+}
+
+/// State of a cache entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum CacheState {
+    /// Cached and within its TTL
+    Fresh,
+    /// Cached, but expired; only served by `with_serve_stale_on_error`
+    Stale,
+    /// Not cached
+    Missing,
+    /// The cache couldn't be read; a miss unless the resolver's cache is strict
+    Failed { message: String },
+}
+
+/// One source's answer for an explained path
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct SourceCheck {
+    /// Position in the resolver's source list, from 0
+    pub index: usize,
+    /// The source's identifier
+    pub source: String,
+    /// What it said
+    #[serde(flatten)]
+    pub outcome: SourceOutcome,
+}
+
+/// What a source reported for an explained path
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "outcome")]
+pub enum SourceOutcome {
+    /// The source has the file
+    Found {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        size: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        etag: Option<String>,
+    },
+    /// The source has a directory at the path, not a file
+    Directory,
+    /// The source doesn't have the path
+    NotFound,
+    /// The source failed to answer
+    Failed {
+        #[serde(serialize_with = "serialize_kind")]
+        kind: ErrorKind,
+        message: String,
+        retryable: bool,
+    },
+}
+
+/// Where a fetch would get its content from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "from")]
+pub enum Resolution {
+    /// The fresh cache entry, without asking any source
+    Cache,
+    /// The source at `index` in the resolver's source list
+    Source { index: usize, source: String },
+    /// The expired cache entry, because the sources failed
+    StaleCache,
+    /// Nowhere; the fetch fails with `NotFound`
+    NotFound,
+    /// Nowhere; the fetch fails with this error
+    Error {
+        #[serde(serialize_with = "serialize_kind")]
+        kind: ErrorKind,
+        message: String,
+    },
+}
+
+fn serialize_kind<S: Serializer>(kind: &ErrorKind, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(kind.as_str())
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "path: {}", self.path)?;
+        match &self.cache {
+            Some(cache) => {
+                write!(f, "cache: ")?;
+                match &cache.state {
+                    CacheState::Fresh => write!(f, "fresh")?,
+                    CacheState::Stale => write!(f, "stale")?,
+                    CacheState::Missing => write!(f, "missing")?,
+                    CacheState::Failed { message } => write!(f, "failed: {}", message)?,
+                }
+                if let Some(size) = cache.size {
+                    write!(f, ", {} bytes", size)?;
+                }
+                if let Some(etag) = &cache.etag {
+                    write!(f, ", etag {}", etag)?;
+                }
+                writeln!(f, " ({})", cache.key)?;
+            }
+            None => writeln!(f, "cache: none")?,
+        }
+
+        for check in &self.sources {
+            write!(f, "source {} {}: ", check.index + 1, check.source)?;
+            match &check.outcome {
+                SourceOutcome::Found { size, etag } => {
+                    write!(f, "found")?;
+                    if let Some(size) = size {
+                        write!(f, ", {} bytes", size)?;
+                    }
+                    if let Some(etag) = etag {
+                        write!(f, ", etag {}", etag)?;
+                    }
+                    writeln!(f)?;
+                }
+                SourceOutcome::Directory => writeln!(f, "directory")?,
+                SourceOutcome::NotFound => writeln!(f, "not found")?,
+                SourceOutcome::Failed { message, retryable, .. } => {
+                    let retry = if *retryable { " (retryable)" } else { "" };
+                    writeln!(f, "failed{}: {}", retry, message)?;
+                }
+            }
+        }
+
+        write!(f, "resolution: ")?;
+        match &self.resolution {
+            Resolution::Cache => write!(f, "cache")?,
+            Resolution::Source { index, source } => write!(f, "source {} {}", index + 1, source)?,
+            Resolution::StaleCache => write!(f, "stale cache")?,
+            Resolution::NotFound => write!(f, "not found")?,
+            Resolution::Error { kind, .. } => write!(f, "error ({})", kind)?,
+        }
+        write!(f, "\nreason: {}", self.reason)
+    }
+}
@@ -0,0 +1,381 @@
+//! Source decorator enforcing allow and deny path patterns
+
+use async_trait::async_trait;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::sync::Arc;
+use tokio::io::AsyncWrite;
+
+use crate::{
+    error::{ContentError, Result},
+    hook::RequestContext,
+    source::{Capabilities, ContentSource, FetchOptions, Revalidated},
+    types::{ContentPath, ContentVersion, DirectoryEntry, DirectoryListing, EntryType, FileContent, FileMetadata},
+};
+
+/// Content source decorator that hides paths matching deny patterns, or
+/// files matching no allow pattern
+/// 
+/// Hidden paths answer `NotFound` without the inner source being asked, and
+/// are left out of listings, so callers can't tell them from missing ones.
+/// Paths are normalized before matching, and a path is denied if it or any
+/// directory above it matches a deny pattern: denying `**/.git` hides
+/// everything inside `.git` directories. Allow patterns only apply to files,
+/// so directories stay listable while their files are filtered.
+pub struct FilterSource {
+    inner: Arc<dyn ContentSource>,
+    allow: Option<GlobSet>,
+    deny: GlobSet,
+}
+
+impl FilterSource {
+    /// Wrap a source, initially hiding nothing
+    pub fn new(inner: Arc<dyn ContentSource>) -> Self {
+        Self {
+            inner,
+            allow: None,
+            deny: GlobSet::empty(),
+        }
+    }
+
+    /// The wrapped source
+    pub fn inner(&self) -> &Arc<dyn ContentSource> {
+        &self.inner
+    }
+
+    /// Only serve files matching one of these glob patterns
+    /// 
+    /// Fails with `InvalidConfig` if a pattern doesn't parse.
+    pub fn with_allow<I, P>(mut self, patterns: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<str>,
+    {
+        self.allow = Some(glob_set(patterns)?);
+        Ok(self)
+    }
+
+    /// Hide paths matching any of these glob patterns, and everything below them
+    /// 
+    /// Fails with `InvalidConfig` if a pattern doesn't parse.
+    pub fn with_deny<I, P>(mut self, patterns: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<str>,
+    {
+        self.deny = glob_set(patterns)?;
+        Ok(self)
+    }
+
+    /// Whether `path` or a directory above it is denied
+    fn denied(&self, path: &ContentPath) -> bool {
+        let path = path.as_str();
+        path.match_indices('/')
+            .map(|(i, _)| &path[..i])
+            .chain(Some(path).filter(|p| !p.is_empty()))
+            .any(|prefix| self.deny.is_match(prefix))
+    }
+
+    fn allows_file(&self, path: &ContentPath) -> bool {
+        let allowed = |allow: &GlobSet| allow.is_match(path.as_str());
+        !self.denied(path) && self.allow.as_ref().is_none_or(allowed)
+    }
+
+    fn allows_entry(&self, entry: &DirectoryEntry) -> bool {
+        let Ok(path) = ContentPath::parse(&entry.path) else {
+            return false;
+        };
+        match entry.entry_type {
+            EntryType::Dir => !self.denied(&path),
+            _ => self.allows_file(&path),
+        }
+    }
+
+    /// Fail like a missing file unless `path` may be fetched
+    fn check_file(&self, path: &str) -> Result<()> {
+        if self.allows_file(&ContentPath::parse(path)?) {
+            Ok(())
+        } else {
+            Err(not_found(path))
+        }
+    }
+
+    /// Fail like a missing directory if `path` is denied
+    fn check_dir(&self, path: &str) -> Result<()> {
+        if self.denied(&ContentPath::parse(path)?) {
+            Err(not_found(path))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn filter_listing(&self, mut listing: DirectoryListing) -> DirectoryListing {
+        listing.entries.retain(|entry| self.allows_entry(entry));
+        listing
+    }
+}
+
+fn not_found(path: &str) -> ContentError {
+    ContentError::NotFound {
+        path: path.to_string(),
+    }
+}
+
+fn glob_set<I, P>(patterns: I) -> Result<GlobSet>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<str>,
+{
+    let invalid = |e: globset::Error| ContentError::InvalidConfig {
+        message: format!("invalid source filter glob: {}", e),
+    };
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern.as_ref()).map_err(invalid)?);
+    }
+    builder.build().map_err(invalid)
+}
+
+#[async_trait]
+impl ContentSource for FilterSource {
+    async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+        self.check_file(path)?;
+        self.inner.fetch_file(path).await
+    }
+
+    async fn fetch_file_with(&self, path: &str, options: &FetchOptions) -> Result<FileContent> {
+        self.check_file(path)?;
+        self.inner.fetch_file_with(path, options).await
+    }
+
+    async fn fetch_file_at(&self, path: &str, version: &ContentVersion) -> Result<FileContent> {
+        self.check_file(path)?;
+        self.inner.fetch_file_at(path, version).await
+    }
+
+    async fn fetch_to_writer(
+        &self,
+        path: &str,
+        writer: &mut (dyn AsyncWrite + Unpin + Send),
+    ) -> Result<u64> {
+        self.check_file(path)?;
+        self.inner.fetch_to_writer(path, writer).await
+    }
+
+    async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+        self.check_dir(path)?;
+        Ok(self.filter_listing(self.inner.list_directory(path).await?))
+    }
+
+    async fn list_directory_with(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+    ) -> Result<DirectoryListing> {
+        self.check_dir(path)?;
+        Ok(self.filter_listing(self.inner.list_directory_with(path, options).await?))
+    }
+
+    async fn list_directory_if_none_match(
+        &self,
+        path: &str,
+        etag: Option<&str>,
+    ) -> Result<Revalidated<DirectoryListing>> {
+        self.check_dir(path)?;
+        Ok(match self.inner.list_directory_if_none_match(path, etag).await? {
+            Revalidated::Modified { value, etag } => Revalidated::Modified {
+                value: self.filter_listing(value),
+                etag,
+            },
+            not_modified => not_modified,
+        })
+    }
+
+    async fn fetch_file_with_ctx(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+        ctx: &RequestContext,
+    ) -> Result<FileContent> {
+        self.check_file(path)?;
+        self.inner.fetch_file_with_ctx(path, options, ctx).await
+    }
+
+    async fn list_directory_with_ctx(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+        ctx: &RequestContext,
+    ) -> Result<DirectoryListing> {
+        self.check_dir(path)?;
+        Ok(self.filter_listing(
+            self.inner.list_directory_with_ctx(path, options, ctx).await?,
+        ))
+    }
+
+    async fn list_directory_recursive(&self, path: &str) -> Result<Vec<DirectoryEntry>> {
+        self.check_dir(path)?;
+        let mut entries = self.inner.list_directory_recursive(path).await?;
+        entries.retain(|entry| self.allows_entry(entry));
+        Ok(entries)
+    }
+
+    fn identifier(&self) -> String {
+        format!("filter({})", self.inner.identifier())
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn cacheable(&self) -> bool {
+        self.inner.cacheable()
+    }
+
+    async fn file_exists(&self, path: &str) -> bool {
+        self.check_file(path).is_ok() && self.inner.file_exists(path).await
+    }
+
+    async fn path_type(&self, path: &str) -> Result<Option<EntryType>> {
+        if self.check_dir(path).is_err() {
+            return Ok(None);
+        }
+        match self.inner.path_type(path).await? {
+            Some(EntryType::Dir) => Ok(Some(EntryType::Dir)),
+            Some(_) if self.check_file(path).is_err() => Ok(None),
+            other => Ok(other),
+        }
+    }
+
+    async fn stat(&self, path: &str) -> Result<FileMetadata> {
+        self.check_dir(path)?;
+        let metadata = self.inner.stat(path).await?;
+        if metadata.entry_type != EntryType::Dir {
+            self.check_file(path)?;
+        }
+        Ok(metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FaultInjectingSource, MemorySource};
+
+    fn source() -> Arc<FaultInjectingSource> {
+        let memory = MemorySource::new()
+            .with_file("README.md", "readme")
+            .with_file("docs/guide.md", "guide")
+            .with_file("docs/notes.txt", "notes")
+            .with_file(".git/config", "secret")
+            .with_file("app/.git/HEAD", "secret")
+            .with_file("app/.env", "secret");
+        Arc::new(FaultInjectingSource::new(Arc::new(memory)))
+    }
+
+    fn names(listing: DirectoryListing) -> Vec<String> {
+        listing.entries.into_iter().map(|e| e.name).collect()
+    }
+
+    #[tokio::test]
+    async fn test_deny() {
+        let inner = source();
+        let filter = FilterSource::new(inner.clone())
+            .with_deny(["**/.git", "**/.env"])
+            .unwrap();
+
+        assert_eq!(
+            filter
+                .fetch_file("README.md")
+                .await
+                .unwrap()
+                .text()
+                .unwrap(),
+            "readme"
+        );
+        let calls = inner.call_count();
+        for path in [
+            ".git/config",
+            "app/.git/HEAD",
+            "app/.env",
+            "./app/../.git/config",
+        ] {
+            let error = filter.fetch_file(path).await.unwrap_err();
+            assert!(error.is_not_found(), "{}: {}", path, error);
+            assert_eq!(error.to_string(), format!("Content not found: {}", path));
+            assert!(!filter.file_exists(path).await);
+            assert!(filter.stat(path).await.unwrap_err().is_not_found());
+        }
+        assert!(filter
+            .list_directory(".git")
+            .await
+            .unwrap_err()
+            .is_not_found());
+        assert_eq!(filter.path_type("app/.git").await.unwrap(), None);
+        // The inner source never saw the denied paths
+        assert_eq!(inner.call_count(), calls);
+
+        assert_eq!(
+            names(filter.list_directory("").await.unwrap()),
+            vec!["README.md", "app", "docs"]
+        );
+        assert!(names(filter.list_directory("app").await.unwrap()).is_empty());
+        let all: Vec<_> = filter
+            .list_directory_recursive("")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|e| e.path)
+            .collect();
+        assert_eq!(
+            all,
+            vec![
+                "README.md",
+                "app",
+                "docs",
+                "docs/guide.md",
+                "docs/notes.txt"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_allow() {
+        let filter = FilterSource::new(source())
+            .with_allow(["**/*.md"])
+            .unwrap()
+            .with_deny(["README.md"])
+            .unwrap();
+
+        assert!(filter.fetch_file("docs/guide.md").await.is_ok());
+        assert!(filter
+            .fetch_file("docs/notes.txt")
+            .await
+            .unwrap_err()
+            .is_not_found());
+        // Deny wins over allow
+        assert!(filter
+            .fetch_file("README.md")
+            .await
+            .unwrap_err()
+            .is_not_found());
+        // Directories stay listable, with their files filtered
+        assert_eq!(
+            filter.path_type("docs").await.unwrap(),
+            Some(EntryType::Dir)
+        );
+        assert_eq!(
+            names(filter.list_directory("docs").await.unwrap()),
+            vec!["guide.md"]
+        );
+        assert_eq!(
+            names(filter.list_directory("").await.unwrap()),
+            vec![".git", "app", "docs"]
+        );
+
+        let error = FilterSource::new(source())
+            .with_deny(["a/[b"])
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), crate::error::ErrorKind::InvalidConfig);
+    }
+}
@@ -0,0 +1,119 @@
+//! Per-request hooks and the context they share with sources
+
+use std::any::{Any, TypeId};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::error::Result;
+
+/// Values attached to one resolver request, one per type
+/// 
+/// Request hooks fill it in and sources read it in
+/// `ContentSource::fetch_file_with_ctx` and `list_directory_with_ctx`. Wrap
+/// values in a newtype to keep them apart from other users of the same
+/// type:
+/// 
+/// ```
+/// use content_resolver::RequestContext;
+/// 
+/// struct TenantId(String);
+/// 
+/// let mut ctx = RequestContext::new();
+/// ctx.insert(TenantId("acme".to_string()));
+/// assert_eq!(ctx.get::<TenantId>().unwrap().0, "acme");
+/// ```
+#[derive(Default)]
+pub struct RequestContext {
+    values: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl RequestContext {
+    /// An empty context
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store a value, returning the one of the same type it replaces
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) -> Option<T> {
+        self.values
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|old| old.downcast().ok())
+            .map(|old| *old)
+    }
+
+    /// The value of type `T`, if one was stored
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.values.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+
+    /// The value of type `T` for changing in place, if one was stored
+    pub fn get_mut<T: Any + Send + Sync>(&mut self) -> Option<&mut T> {
+        self.values.get_mut(&TypeId::of::<T>())?.downcast_mut()
+    }
+
+    /// Take out the value of type `T`
+    pub fn remove<T: Any + Send + Sync>(&mut self) -> Option<T> {
+        let value = self.values.remove(&TypeId::of::<T>())?;
+        value.downcast().ok().map(|value| *value)
+    }
+
+    /// Whether a value of type `T` was stored
+    pub fn contains<T: Any + Send + Sync>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Number of values stored
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Whether no values were stored
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl std::fmt::Debug for RequestContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestContext")
+            .field("len", &self.values.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Sees every path before the resolver looks it up
+/// 
+/// Register with `ResourceResolver::with_request_hook`. Hooks run in the
+/// order they were added, each on the path the previous one returned.
+pub trait RequestHook: Send + Sync {
+    /// Rewrite a normalized path, and add to the request's context
+    /// 
+    /// Return the path unchanged with `Cow::Borrowed`. The result is
+    /// normalized again, and is what the cache key and the sources see. An
+    /// error aborts the request and is returned as is.
+    fn before_fetch<'a>(&self, path: &'a str, ctx: &mut RequestContext) -> Result<Cow<'a, str>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_context() {
+        #[derive(Debug, PartialEq)]
+        struct Tenant(&'static str);
+
+        let mut ctx = RequestContext::new();
+        assert!(ctx.is_empty());
+        assert_eq!(ctx.insert(Tenant("acme")), None);
+        assert_eq!(ctx.insert(7u32), None);
+        assert_eq!(ctx.insert(Tenant("globex")), Some(Tenant("acme")));
+        assert_eq!(ctx.len(), 2);
+
+        assert_eq!(ctx.get::<Tenant>(), Some(&Tenant("globex")));
+        *ctx.get_mut::<u32>().unwrap() += 1;
+        assert_eq!(ctx.remove::<u32>(), Some(8));
+        assert!(!ctx.contains::<u32>());
+        assert_eq!(ctx.get::<String>(), None);
+    }
+}
@@ -0,0 +1,41 @@
+//! Digest helpers shared by content hashing and integrity checks
+
+use sha2::Digest;
+
+/// SHA-256 hasher for `ResourceResolver::fetch_to_writer_verified`
+pub use sha2::Sha256;
+
+#[cfg(test)]
+thread_local! {
+    /// Digests computed on this thread, for memoization tests
+    pub(crate) static SHA256_CALLS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Hex-encoded SHA-256 of `bytes`
+pub fn sha256_hex_of(bytes: impl AsRef<[u8]>) -> String {
+    #[cfg(test)]
+    SHA256_CALLS.with(|calls| calls.set(calls.get() + 1));
+
+    format!("{:x}", Sha256::digest(bytes.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_known_vectors() {
+        assert_eq!(
+            sha256_hex_of(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256_hex_of("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            sha256_hex_of("abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+}
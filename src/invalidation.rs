@@ -0,0 +1,269 @@
+//! Batched, deduplicated cache invalidation
+
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+
+use crate::{error::Result, resolver::ResourceResolver, task::ShutdownSignal};
+
+/// Collects paths to invalidate and drops them from the cache in batches
+/// 
+/// Meant for bursts of change notifications, such as webhook deliveries
+/// that name the same files many times over. The first path pushed after a
+/// quiet spell opens a batch; `debounce` later, every path pushed meanwhile
+/// is passed to `ResourceResolver::invalidate` once.
+/// 
+/// ```no_run
+/// # async fn run(resolver: content_resolver::ResourceResolver) {
+/// use std::time::Duration;
+/// 
+/// let queue = resolver.invalidation_queue(Duration::from_millis(500));
+/// for path in ["docs/a.md", "docs/b.md", "docs/a.md"] {
+///     queue.push(path);
+/// }
+/// // Both files are invalidated once, half a second later
+/// # }
+/// ```
+/// 
+/// Batches are flushed by a task on the resolver's `tasks()`, so
+/// `ResourceResolver::shutdown` flushes what is pending before it stops.
+/// Clones share the queue; paths still pending when the last one is dropped
+/// are not invalidated.
+#[derive(Clone)]
+pub struct InvalidationQueue {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    resolver: ResourceResolver,
+    pending: Mutex<BTreeSet<String>>,
+    /// Wakes the flush task; shared with it so it outlives the queue
+    wake: Arc<Notify>,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        // Let the flush task notice the queue is gone
+        self.wake.notify_one();
+    }
+}
+
+impl InvalidationQueue {
+    /// Create a queue flushing to `resolver` every `debounce` while paths come in
+    /// 
+    /// # Panics
+    /// 
+    /// Panics outside a tokio runtime, since it spawns the flush task.
+    pub fn new(resolver: ResourceResolver, debounce: Duration) -> Self {
+        let wake = Arc::new(Notify::new());
+        let inner = Arc::new(Inner {
+            resolver: resolver.clone(),
+            pending: Mutex::new(BTreeSet::new()),
+            wake: wake.clone(),
+        });
+
+        let queue = Arc::downgrade(&inner);
+        resolver.tasks().spawn(|signal| async move {
+            loop {
+                unless_cancelled(&signal, wake.notified()).await;
+                if !signal.is_cancelled() {
+                    unless_cancelled(&signal, tokio::time::sleep(debounce)).await;
+                }
+                // The resolver is only reachable while the queue is alive
+                let Some(queue) = Weak::upgrade(&queue) else {
+                    return;
+                };
+                let queue = InvalidationQueue { inner: queue };
+                let flushed = queue.flush().await;
+                if signal.is_cancelled() {
+                    return;
+                }
+                if flushed.is_err() {
+                    // Retry what failed with the next batch
+                    queue.inner.wake.notify_one();
+                }
+            }
+        });
+
+        Self { inner }
+    }
+
+    fn pending(&self) -> std::sync::MutexGuard<'_, BTreeSet<String>> {
+        self.inner.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Queue a path for invalidation
+    /// 
+    /// Paths are normalized first, so `a//b` and `a/b` are invalidated once.
+    pub fn push(&self, path: impl AsRef<str>) {
+        let path = path.as_ref();
+        let path = match self.inner.resolver.normalize_path(path) {
+            Ok(normalized) => normalized.to_string(),
+            // `invalidate` reports the error when the batch is flushed
+            Err(_) => path.to_string(),
+        };
+        if self.pending().insert(path) {
+            self.inner.wake.notify_one();
+        }
+    }
+
+    /// Number of distinct paths waiting for the next batch
+    pub fn len(&self) -> usize {
+        self.pending().len()
+    }
+
+    /// Whether no paths are waiting
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Invalidate every pending path now, without waiting for the debounce
+    /// 
+    /// Returns how many cached files were dropped. Paths that fail to
+    /// invalidate go back in the queue, and the first error is returned
+    /// after the rest of the batch was tried.
+    pub async fn flush(&self) -> Result<usize> {
+        let batch = std::mem::take(&mut *self.pending());
+        let mut removed = 0;
+        let mut first_error = None;
+        for path in batch {
+            match self.inner.resolver.invalidate(&path).await {
+                Ok(was_cached) => removed += usize::from(was_cached),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                    self.pending().insert(path);
+                }
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(removed),
+        }
+    }
+}
+
+/// Wait for `future`, or less if shutdown is requested first
+async fn unless_cancelled(signal: &ShutdownSignal, future: impl Future<Output = ()>) {
+    let cancelled = pin!(signal.cancelled());
+    futures::future::select(cancelled, pin!(future)).await;
+}
+
+impl std::fmt::Debug for InvalidationQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InvalidationQueue").field("pending", &self.len()).finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cache::{Cache, MemoryCache},
+        error::ContentError,
+        testing::MemorySource,
+    };
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Memory cache counting removals and failing them on demand
+    #[derive(Default)]
+    struct CountingCache {
+        inner: MemoryCache,
+        removes: AtomicUsize,
+        failing: std::sync::atomic::AtomicBool,
+    }
+
+    #[async_trait]
+    impl Cache for CountingCache {
+        async fn get(&self, key: &str) -> Result<Option<Bytes>> {
+            self.inner.get(key).await
+        }
+
+        async fn set(&self, key: &str, value: Bytes) -> Result<()> {
+            self.inner.set(key, value).await
+        }
+
+        async fn remove(&self, key: &str) -> Result<()> {
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(ContentError::Cache {
+                    message: "unavailable".to_string(),
+                });
+            }
+            self.removes.fetch_add(1, Ordering::SeqCst);
+            self.inner.remove(key).await
+        }
+
+        async fn clear(&self) -> Result<()> {
+            self.inner.clear().await
+        }
+
+        async fn contains(&self, key: &str) -> bool {
+            self.inner.contains(key).await
+        }
+    }
+
+    async fn setup() -> (ResourceResolver, Arc<CountingCache>) {
+        let source = MemorySource::new().with_file("a.md", "a").with_file("b.md", "b");
+        let cache = Arc::new(CountingCache::default());
+        let resolver = ResourceResolver::with_cache(vec![Arc::new(source)], cache.clone());
+        resolver.fetch_file("a.md").await.unwrap();
+        resolver.fetch_file("b.md").await.unwrap();
+        cache.removes.store(0, Ordering::SeqCst);
+        (resolver, cache)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_debounced_batches() {
+        let (resolver, cache) = setup().await;
+        let queue = resolver.invalidation_queue(Duration::from_secs(1));
+
+        for _ in 0..10 {
+            queue.push("a.md");
+            queue.push("/a.md");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        queue.push("b.md");
+        assert_eq!(queue.len(), 2);
+        assert!(cache.inner.contains("file:a.md").await);
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(queue.is_empty());
+        assert!(!cache.inner.contains("file:a.md").await);
+        assert!(!cache.inner.contains("file:b.md").await);
+        // Per file, its meta and content, and the merged and source listings
+        // of the path and of the root
+        assert_eq!(cache.removes.load(Ordering::SeqCst), 12);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_paths_are_retried() {
+        let (resolver, cache) = setup().await;
+        let queue = resolver.invalidation_queue(Duration::from_secs(1));
+
+        cache.failing.store(true, Ordering::SeqCst);
+        queue.push("a.md");
+        assert!(queue.flush().await.is_err());
+        assert_eq!(queue.len(), 1);
+
+        cache.failing.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert!(queue.is_empty());
+        assert!(!cache.inner.contains("file:a.md").await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_flushes() {
+        let (resolver, cache) = setup().await;
+        let queue = resolver.invalidation_queue(Duration::from_secs(60));
+        queue.push("a.md");
+
+        assert_eq!(resolver.shutdown(Duration::from_secs(1)).await, 0);
+        assert!(queue.is_empty());
+        assert!(!cache.inner.contains("file:a.md").await);
+    }
+}
@@ -1,13 +1,92 @@
+pub mod admission;
+#[cfg(feature = "archive")]
+pub mod archive;
+pub mod audit;
+pub mod budget;
 pub mod cache;
+#[cfg(feature = "fs")]
+pub mod cassette;
+pub mod composite;
+mod env;
 pub mod error;
+mod eviction;
+pub mod explain;
+pub mod filter;
+#[cfg(feature = "github")]
 pub mod github;
+pub mod hook;
+pub mod integrity;
+pub mod invalidation;
+pub mod limit;
+#[cfg(feature = "fs")]
+pub mod local;
+pub mod manifest;
+pub mod providers;
 pub mod resolver;
+pub mod response;
+pub mod retry;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod scoped;
+pub mod secret;
+#[cfg(feature = "github")]
+pub mod signed;
 pub mod source;
+pub mod stream;
+pub mod task;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod types;
+pub mod validate;
 
-pub use cache::{Cache, DiskCache, MemoryCache, NoCache};
-pub use error::{ContentError, Result};
-pub use github::GitHubSource;
-pub use resolver::ResourceResolver;
-pub use source::ContentSource;
-pub use types::{DirectoryEntry, DirectoryListing, EntryType, FileContent};
+pub use admission::CacheAdmission;
+#[cfg(feature = "archive")]
+pub use archive::ArchiveSource;
+pub use audit::{AuditEvent, AuditPurpose, AuditSink, ChannelSink};
+#[cfg(feature = "fs")]
+pub use audit::JsonlFileSink;
+pub use budget::{BudgetStatus, BudgetedSource, RequestBudget};
+pub use cache::{Cache, EvictionPolicy, MemoryCache, NoCache, RoutingCache};
+pub use composite::{ConcatRule, VirtualFileRule};
+#[cfg(feature = "fs")]
+pub use cache::{
+    DiskCache, DiskCacheFormatStats, DiskCacheStats, KeyStrategy, MigrationReport, StrategyMismatch,
+};
+#[cfg(feature = "redis")]
+pub use cache::RedisCache;
+#[cfg(feature = "fs")]
+pub use cassette::{RecordingSource, ReplaySource};
+pub use error::{ContentError, ErrorKind, PartialFailure, Result, ResultExt};
+pub use explain::{CacheCheck, CacheState, Explanation, Resolution, SourceCheck, SourceOutcome};
+pub use filter::FilterSource;
+#[cfg(feature = "github")]
+pub use github::{GitHubSource, PinnedTag, RedirectPolicy};
+pub use hook::{RequestContext, RequestHook};
+pub use integrity::sha256_hex_of;
+pub use invalidation::InvalidationQueue;
+pub use limit::{LimitedSource, DEFAULT_SOURCE_CONCURRENCY};
+#[cfg(feature = "fs")]
+pub use local::LocalFileSource;
+pub use manifest::{ManifestDiff, ManifestEntry, ManifestOptions, TreeManifest};
+pub use providers::{
+    DownloadResult, FailurePolicy, LanguageProvider, SkillInfo, SkillLayout, SkillProvider,
+};
+pub use resolver::{CacheErrorHook, ReadMode, ResolverStats, ResourceResolver};
+pub use response::{ErrorBody, ErrorResponse};
+pub use retry::{RetryConfig, RetryPredicate, RetryingSource};
+#[cfg(feature = "s3")]
+pub use s3::S3Source;
+pub use scoped::ScopedResolver;
+#[cfg(feature = "github")]
+pub use secret::redact_url;
+pub use secret::SecretString;
+#[cfg(feature = "github")]
+pub use signed::{SignedUrlSource, UrlSigner};
+pub use source::{AsAny, Capabilities, ContentSource, FetchOptions, Revalidated};
+pub use stream::ContentStream;
+pub use task::{ShutdownSignal, TaskSet};
+pub use types::{
+    ContentPath, ContentVersion, DataFormat, DedupePolicy, DirectoryEntry, DirectoryListing, EntryType, FileContent,
+    FileMetadata, SeparatorPolicy,
+};
+pub use validate::{Finding, Severity, Subject, ValidationOptions, ValidationReport};
@@ -0,0 +1,293 @@
+//! Bounds on concurrent requests per source
+
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::AsyncWrite;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::{
+    error::Result,
+    hook::RequestContext,
+    source::{Capabilities, ContentSource, FetchOptions, Revalidated},
+    types::{ContentVersion, DirectoryEntry, DirectoryListing, EntryType, FileContent, FileMetadata},
+};
+
+/// Requests a source may have in flight unless configured otherwise
+pub const DEFAULT_SOURCE_CONCURRENCY: usize = 16;
+
+/// Content source decorator bounding the requests in flight to the inner source
+/// 
+/// Each call holds a permit of a shared semaphore until it returns, so
+/// sources wrapped with the same `Arc<Semaphore>` share one bound. Calls
+/// beyond it wait their turn. A recursive listing holds one permit
+/// throughout. A streamed download, `fetch_to_writer`, gives its permit back
+/// once the first bytes arrive, so a slow writer doesn't hold up other
+/// requests; the bound counts downloads waiting for a response, not open
+/// transfers. The identifier is the inner source's.
+/// 
+/// `ResourceResolver` wraps every source in one of these itself; see
+/// `ResourceResolver::with_source_concurrency`.
+pub struct LimitedSource {
+    inner: Arc<dyn ContentSource>,
+    semaphore: Arc<Semaphore>,
+    limit: usize,
+    /// Added by a resolver, which replaces it when the limits change
+    pub(crate) managed: bool,
+}
+
+impl LimitedSource {
+    /// Wrap a source, allowing it `limit` requests in flight (at least one)
+    pub fn new(inner: Arc<dyn ContentSource>, limit: usize) -> Self {
+        let limit = limit.max(1);
+        Self::with_semaphore(inner, Arc::new(Semaphore::new(limit)), limit)
+    }
+
+    /// Wrap a source, taking permits from `semaphore`, which holds `limit`
+    pub fn with_semaphore(inner: Arc<dyn ContentSource>, semaphore: Arc<Semaphore>, limit: usize) -> Self {
+        Self {
+            inner,
+            semaphore,
+            limit,
+            managed: false,
+        }
+    }
+
+    /// The wrapped source
+    pub fn inner(&self) -> &Arc<dyn ContentSource> {
+        &self.inner
+    }
+
+    /// Requests allowed in flight at once
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Requests in flight right now, across everything sharing the semaphore
+    pub fn in_flight(&self) -> usize {
+        self.limit.saturating_sub(self.semaphore.available_permits())
+    }
+
+    async fn permit(&self) -> SemaphorePermit<'_> {
+        // The semaphore is never closed
+        self.semaphore.acquire().await.expect("source semaphore closed")
+    }
+}
+
+/// Writer dropping a permit once the source starts writing the response
+struct ReleasingWriter<'a, 'p> {
+    inner: &'a mut (dyn AsyncWrite + Unpin + Send),
+    permit: Option<SemaphorePermit<'p>>,
+}
+
+impl AsyncWrite for ReleasingWriter<'_, '_> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        self.permit = None;
+        Pin::new(&mut *self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl ContentSource for LimitedSource {
+    async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+        let _permit = self.permit().await;
+        self.inner.fetch_file(path).await
+    }
+
+    async fn fetch_file_with(&self, path: &str, options: &FetchOptions) -> Result<FileContent> {
+        let _permit = self.permit().await;
+        self.inner.fetch_file_with(path, options).await
+    }
+
+    async fn fetch_file_at(&self, path: &str, version: &ContentVersion) -> Result<FileContent> {
+        let _permit = self.permit().await;
+        self.inner.fetch_file_at(path, version).await
+    }
+
+    async fn fetch_to_writer(
+        &self,
+        path: &str,
+        writer: &mut (dyn AsyncWrite + Unpin + Send),
+    ) -> Result<u64> {
+        let mut writer = ReleasingWriter {
+            inner: writer,
+            permit: Some(self.permit().await),
+        };
+        self.inner.fetch_to_writer(path, &mut writer).await
+    }
+
+    async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+        let _permit = self.permit().await;
+        self.inner.list_directory(path).await
+    }
+
+    async fn list_directory_with(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+    ) -> Result<DirectoryListing> {
+        let _permit = self.permit().await;
+        self.inner.list_directory_with(path, options).await
+    }
+
+    async fn list_directory_if_none_match(
+        &self,
+        path: &str,
+        etag: Option<&str>,
+    ) -> Result<Revalidated<DirectoryListing>> {
+        let _permit = self.permit().await;
+        self.inner.list_directory_if_none_match(path, etag).await
+    }
+
+    async fn fetch_file_with_ctx(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+        ctx: &RequestContext,
+    ) -> Result<FileContent> {
+        let _permit = self.permit().await;
+        self.inner.fetch_file_with_ctx(path, options, ctx).await
+    }
+
+    async fn list_directory_with_ctx(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+        ctx: &RequestContext,
+    ) -> Result<DirectoryListing> {
+        let _permit = self.permit().await;
+        self.inner.list_directory_with_ctx(path, options, ctx).await
+    }
+
+    async fn list_directory_paginated(
+        &self,
+        path: &str,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<(DirectoryListing, Option<String>)> {
+        let _permit = self.permit().await;
+        self.inner
+            .list_directory_paginated(path, cursor, page_size)
+            .await
+    }
+
+    async fn list_directory_recursive(&self, path: &str) -> Result<Vec<DirectoryEntry>> {
+        let _permit = self.permit().await;
+        self.inner.list_directory_recursive(path).await
+    }
+
+    fn identifier(&self) -> String {
+        self.inner.identifier()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn cacheable(&self) -> bool {
+        self.inner.cacheable()
+    }
+
+    async fn file_exists(&self, path: &str) -> bool {
+        let _permit = self.permit().await;
+        self.inner.file_exists(path).await
+    }
+
+    async fn path_type(&self, path: &str) -> Result<Option<EntryType>> {
+        let _permit = self.permit().await;
+        self.inner.path_type(path).await
+    }
+
+    async fn stat(&self, path: &str) -> Result<FileMetadata> {
+        let _permit = self.permit().await;
+        self.inner.stat(path).await
+    }
+}
+
+/// The per-source limits of a resolver, and one semaphore per identifier
+#[derive(Debug)]
+pub(crate) struct SourceLimits {
+    default: usize,
+    limits: HashMap<String, usize>,
+    semaphores: Mutex<BTreeMap<String, (Arc<Semaphore>, usize)>>,
+}
+
+impl Default for SourceLimits {
+    fn default() -> Self {
+        Self {
+            default: DEFAULT_SOURCE_CONCURRENCY,
+            limits: HashMap::new(),
+            semaphores: Mutex::default(),
+        }
+    }
+}
+
+impl SourceLimits {
+    /// The same limits with `limit` for `id`, or for every other source if `id` is `None`
+    pub(crate) fn with_limit(&self, id: Option<String>, limit: usize) -> Self {
+        let limit = limit.max(1);
+        let mut limits = Self {
+            default: self.default,
+            limits: self.limits.clone(),
+            semaphores: Mutex::default(),
+        };
+        match id {
+            Some(id) => {
+                limits.limits.insert(id, limit);
+            }
+            None => limits.default = limit,
+        }
+        limits
+    }
+
+    /// Wrap a source in its identifier's limit, replacing a limit added before
+    pub(crate) fn wrap(&self, source: Arc<dyn ContentSource>) -> Arc<dyn ContentSource> {
+        let source = unlimited(source);
+        let id = source.identifier();
+        let (semaphore, limit) = self
+            .semaphores
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(id.clone())
+            .or_insert_with(|| {
+                let limit = self.limits.get(&id).copied().unwrap_or(self.default);
+                (Arc::new(Semaphore::new(limit)), limit)
+            })
+            .clone();
+        let mut limited = LimitedSource::with_semaphore(source, semaphore, limit);
+        limited.managed = true;
+        Arc::new(limited)
+    }
+
+    /// Wrap each source, see `wrap`
+    pub(crate) fn wrap_all(&self, sources: Vec<Arc<dyn ContentSource>>) -> Arc<[Arc<dyn ContentSource>]> {
+        sources.into_iter().map(|source| self.wrap(source)).collect()
+    }
+
+    /// Requests in flight per source identifier
+    pub(crate) fn in_flight(&self) -> BTreeMap<String, usize> {
+        let semaphores = self.semaphores.lock().unwrap_or_else(|e| e.into_inner());
+        semaphores
+            .iter()
+            .map(|(id, (semaphore, limit))| (id.clone(), limit.saturating_sub(semaphore.available_permits())))
+            .collect()
+    }
+}
+
+/// The source inside a `LimitedSource` a resolver added, or the source itself
+pub(crate) fn unlimited(source: Arc<dyn ContentSource>) -> Arc<dyn ContentSource> {
+    match source.clone().into_any_arc().downcast::<LimitedSource>() {
+        Ok(limited) if limited.managed => limited.inner.clone(),
+        _ => source,
+    }
+}
@@ -0,0 +1,328 @@
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::io::ErrorKind as IoErrorKind;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use crate::{
+    error::{ContentError, Result},
+    source::ContentSource,
+    types::{ContentPath, DirectoryEntry, DirectoryListing, EntryType, FileContent, FileMetadata},
+};
+
+/// Content source reading from a directory on the local filesystem
+/// 
+/// Useful for development, tests and local overrides in front of a remote
+/// source. Paths are resolved below the root and can't climb out of it.
+/// Symlinks are listed as `EntryType::Symlink`, and reading through one
+/// follows it as long as it leads to somewhere below the root; one leading
+/// out of it reads as missing. Files carry their path below the root as
+/// `source_path`, e.g. `local:docs/a.md`.
+#[derive(Debug, Clone)]
+pub struct LocalFileSource {
+    root_path: PathBuf,
+    cacheable: bool,
+}
+
+impl LocalFileSource {
+    /// Create a source serving the files below `root_path`
+    pub fn new(root_path: impl Into<PathBuf>) -> Self {
+        Self {
+            root_path: root_path.into(),
+            cacheable: true,
+        }
+    }
+
+    /// Choose whether the resolver may cache files read from this source
+    /// 
+    /// Pass false for a directory of local overrides that is edited while
+    /// the resolver runs, so edits aren't hidden behind cached copies.
+    /// Defaults to true.
+    pub fn with_cacheable(mut self, cacheable: bool) -> Self {
+        self.cacheable = cacheable;
+        self
+    }
+
+    /// Create a source from a `local://` URL, e.g. `local:///opt/content`
+    /// 
+    /// Everything after `local://` is the directory, so `local://content`
+    /// is relative to the working directory.
+    pub fn from_url(url: &str) -> Result<Self> {
+        match url.strip_prefix("local://") {
+            Some(dir) if !dir.is_empty() => Ok(Self::new(dir)),
+            _ => Err(ContentError::InvalidConfig {
+                message: format!("Expected local://DIR, got {:?}", url),
+            }),
+        }
+    }
+
+    /// Directory the source serves
+    pub fn root_path(&self) -> &std::path::Path {
+        &self.root_path
+    }
+
+    /// The file `path` names, with symlinks followed
+    /// 
+    /// `ContentPath` has no `..` segments, but a symlink can still lead out
+    /// of the root; such paths are `NotFound`, like missing ones. `raw` is
+    /// the path as requested, for errors.
+    async fn resolve_path(&self, path: &ContentPath, raw: &str) -> Result<PathBuf> {
+        let joined = path.segments().fold(self.root_path.clone(), |dir, s| dir.join(s));
+        let root = tokio::fs::canonicalize(&self.root_path)
+            .await
+            .map_err(|e| io_error(raw, e))?;
+        let full_path = tokio::fs::canonicalize(&joined)
+            .await
+            .map_err(|e| io_error(raw, e))?;
+        if !full_path.starts_with(&root) {
+            return Err(ContentError::NotFound {
+                path: raw.to_string(),
+            });
+        }
+        Ok(full_path)
+    }
+}
+
+/// Map an IO error, treating a wrong kind of entry as absent
+fn io_error(path: &str, error: std::io::Error) -> ContentError {
+    match error.kind() {
+        IoErrorKind::NotFound | IoErrorKind::NotADirectory | IoErrorKind::IsADirectory => {
+            ContentError::NotFound {
+                path: path.to_string(),
+            }
+        }
+        _ => ContentError::Io(error),
+    }
+}
+
+/// Well-known `DirectoryEntry` metadata for a local file
+fn file_metadata(metadata: &std::fs::Metadata) -> BTreeMap<String, serde_json::Value> {
+    let mut map = BTreeMap::new();
+    map.insert("size".to_string(), metadata.len().into());
+    if let Some(secs) = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+    {
+        map.insert("last_modified".to_string(), secs.as_secs().into());
+    }
+    map
+}
+
+#[async_trait]
+impl ContentSource for LocalFileSource {
+    async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+        let file_path = ContentPath::parse(path)?;
+        let full_path = self.resolve_path(&file_path, path).await?;
+
+        let metadata = tokio::fs::metadata(&full_path)
+            .await
+            .map_err(|e| io_error(path, e))?;
+        if metadata.is_dir() {
+            return Err(ContentError::NotFound {
+                path: path.to_string(),
+            });
+        }
+        let content = tokio::fs::read(&full_path)
+            .await
+            .map_err(|e| io_error(path, e))?;
+
+        let mut file = FileContent::new(content, format!("local:{}", file_path.as_str()));
+        file.last_modified = metadata.modified().ok();
+        Ok(file)
+    }
+
+    async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+        let dir = ContentPath::parse(path)?;
+        let full_path = self.resolve_path(&dir, path).await?;
+
+        let mut read_dir = tokio::fs::read_dir(&full_path)
+            .await
+            .map_err(|e| io_error(path, e))?;
+
+        let mut entries = Vec::new();
+        while let Some(entry) = read_dir.next_entry().await? {
+            // `DirEntry::metadata` doesn't follow links, so symlinks show up as such
+            let metadata = entry.metadata().await?;
+            // Names that aren't UTF-8 are listed lossily, keeping the bytes
+            let file_name = entry.file_name();
+            let (name, raw_name) = match file_name.to_str() {
+                Some(name) => (name.to_string(), None),
+                None => (
+                    file_name.to_string_lossy().into_owned(),
+                    Some(file_name.as_encoded_bytes().to_vec()),
+                ),
+            };
+            let entry_path = dir.join(&name)?;
+
+            let entry_type = if metadata.is_dir() {
+                EntryType::Dir
+            } else if metadata.is_symlink() {
+                EntryType::Symlink
+            } else {
+                EntryType::File
+            };
+            let mut dir_entry = DirectoryEntry::new(name, entry_path, entry_type);
+            dir_entry.raw_name = raw_name;
+            if metadata.is_file() {
+                dir_entry.metadata = file_metadata(&metadata);
+            }
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                dir_entry.mode = Some(metadata.permissions().mode());
+            }
+            entries.push(dir_entry);
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(DirectoryListing::new(path, entries))
+    }
+
+    async fn stat(&self, path: &str) -> Result<FileMetadata> {
+        let full_path = self.resolve_path(&ContentPath::parse(path)?, path).await?;
+        let metadata = tokio::fs::metadata(&full_path)
+            .await
+            .map_err(|e| io_error(path, e))?;
+        if metadata.is_dir() {
+            return Ok(FileMetadata::dir());
+        }
+
+        let mut stat = FileMetadata::file().with_size(metadata.len());
+        stat.last_modified = metadata.modified().ok();
+        Ok(stat)
+    }
+
+    fn identifier(&self) -> String {
+        format!("local://{}", self.root_path.display())
+    }
+
+    fn cacheable(&self) -> bool {
+        self.cacheable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn source() -> (TempDir, LocalFileSource) {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("docs/sub")).unwrap();
+        std::fs::write(dir.path().join("docs/a.md"), "alpha").unwrap();
+        std::fs::write(dir.path().join("docs/sub/b.md"), "beta").unwrap();
+        let source = LocalFileSource::new(dir.path());
+        (dir, source)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_file() {
+        let (_dir, source) = source();
+
+        let file = source.fetch_file("docs/./a.md").await.unwrap();
+        assert_eq!(file.content, "alpha");
+        assert_eq!(file.source_path, "local:docs/a.md");
+        assert!(file.last_modified.is_some());
+
+        for missing in ["docs/missing.md", "docs", "docs/a.md/x"] {
+            let error = source.fetch_file(missing).await.unwrap_err();
+            assert!(error.is_not_found(), "{}: {:?}", missing, error);
+        }
+        assert!(source.fetch_file("../etc/passwd").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_list_directory() {
+        let (_dir, source) = source();
+
+        let listing = source.list_directory("docs").await.unwrap();
+        let paths: Vec<_> = listing.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["docs/a.md", "docs/sub"]);
+        assert_eq!(listing.entries[0].size(), Some(5));
+        assert_eq!(listing.entries[1].entry_type, EntryType::Dir);
+
+        let root = source.list_directory("").await.unwrap();
+        assert_eq!(root.entries[0].path, "docs");
+
+        let all = source.list_directory_recursive("").await.unwrap();
+        assert_eq!(all.len(), 4);
+        assert!(source.list_directory("docs/a.md").await.unwrap_err().is_not_found());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_non_utf8_names() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let (dir, source) = source();
+        let raw = b"caf\xe9";
+        let bad_dir = dir.path().join("docs").join(OsStr::from_bytes(raw));
+        std::fs::create_dir(&bad_dir).unwrap();
+        std::fs::write(bad_dir.join("inner.md"), "hidden").unwrap();
+
+        let listing = source.list_directory("docs").await.unwrap();
+        let entry = listing.find("caf\u{FFFD}").unwrap();
+        assert_eq!(entry.raw_name.as_deref(), Some(&raw[..]));
+        assert_eq!(entry.path, "docs/caf\u{FFFD}");
+        assert_eq!(listing.find("a.md").unwrap().raw_name, None);
+
+        // Listed, but not descended into or reachable by its lossy path
+        let all = source.list_directory_recursive("docs").await.unwrap();
+        assert!(all.iter().all(|e| !e.path.ends_with("inner.md")));
+        assert!(source.list_directory("docs/caf\u{FFFD}").await.unwrap_err().is_not_found());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_symlinks_stay_below_the_root() {
+        use std::os::unix::fs::symlink;
+
+        let (dir, source) = source();
+        let outside = TempDir::new().unwrap();
+        std::fs::write(outside.path().join("secret.md"), "secret").unwrap();
+        symlink(outside.path().join("secret.md"), dir.path().join("docs/secret.md")).unwrap();
+        symlink(outside.path(), dir.path().join("docs/out")).unwrap();
+        symlink(dir.path().join("docs/sub"), dir.path().join("docs/inside")).unwrap();
+
+        let listing = source.list_directory("docs").await.unwrap();
+        assert_eq!(listing.find("secret.md").unwrap().entry_type, EntryType::Symlink);
+        for escaping in ["docs/secret.md", "docs/out/secret.md"] {
+            assert!(source.fetch_file(escaping).await.unwrap_err().is_not_found(), "{}", escaping);
+            assert!(source.stat(escaping).await.unwrap_err().is_not_found(), "{}", escaping);
+        }
+        assert!(source.list_directory("docs/out").await.unwrap_err().is_not_found());
+
+        // Links within the root are followed
+        let file = source.fetch_file("docs/inside/b.md").await.unwrap();
+        assert_eq!(file.content, "beta");
+        assert_eq!(file.source_path, "local:docs/inside/b.md");
+        assert_eq!(source.list_directory("docs/inside").await.unwrap().entries.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stat() {
+        let (_dir, source) = source();
+
+        let file = source.stat("docs/a.md").await.unwrap();
+        assert!(file.is_file());
+        assert_eq!(file.size, Some(5));
+        assert!(file.last_modified.is_some());
+        assert!(source.stat("docs/sub").await.unwrap().is_dir());
+        assert!(source.stat("docs/nope.md").await.unwrap_err().is_not_found());
+    }
+
+    #[test]
+    fn test_from_url() {
+        let source = LocalFileSource::from_url("local:///opt/content").unwrap();
+        assert_eq!(source.root_path(), std::path::Path::new("/opt/content"));
+        assert_eq!(
+            LocalFileSource::from_url("local://content").unwrap().identifier(),
+            "local://content"
+        );
+        for bad in ["local://", "/opt/content", "file:///opt/content"] {
+            assert!(LocalFileSource::from_url(bad).is_err(), "{}", bad);
+        }
+    }
+}
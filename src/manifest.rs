@@ -0,0 +1,358 @@
+//! Manifests of the files below a directory, for sync clients
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{
+    error::Result,
+    resolver::{ResourceResolver, BATCH_CONCURRENCY},
+    types::DirectoryEntry,
+};
+
+/// Every file below a directory, with its size and content hashes
+/// 
+/// Built by `ResourceResolver::tree_manifest`. Entries are sorted by path
+/// and the serialized form has a fixed field order, so the same content
+/// always gives the same bytes from `to_canonical_json`, ready to sign.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct TreeManifest {
+    /// Directory the manifest covers, normalized
+    pub root: String,
+    /// The files, sorted by path
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// One file in a `TreeManifest`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ManifestEntry {
+    /// Path as the resolver serves it, including the manifest's root
+    pub path: String,
+    /// Size in bytes
+    pub size: u64,
+    /// Hex SHA-256 of the content; `None` when only the source's hash was used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Hash the source reported in its listing, such as a git blob sha
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha: Option<String>,
+    /// ETag the source reported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+}
+
+impl ManifestEntry {
+    /// Whether both entries describe the same content
+    /// 
+    /// Compares the strongest hash both have: SHA-256, then the source's
+    /// hash, then the ETag. Entries with nothing in common count as changed.
+    pub fn same_content(&self, other: &ManifestEntry) -> bool {
+        if self.size != other.size {
+            return false;
+        }
+        let pairs = [(&self.sha256, &other.sha256), (&self.sha, &other.sha), (&self.etag, &other.etag)];
+        match pairs.into_iter().find_map(|(a, b)| a.as_ref().zip(b.as_ref())) {
+            Some((a, b)) => a == b,
+            None => false,
+        }
+    }
+
+    /// Whether a listed file is unchanged since this entry, going by its
+    /// ETag or source hash and its size
+    fn still_matches(&self, listed: &DirectoryEntry) -> bool {
+        let same_etag = listed.etag().is_some_and(|etag| self.etag.as_deref() == Some(etag));
+        let same_sha = listed.sha().is_some_and(|sha| self.sha.as_deref() == Some(sha));
+        (same_etag || same_sha) && listed.size().is_none_or(|size| size == self.size)
+    }
+}
+
+/// What changed between two manifests, from `TreeManifest::diff`
+/// 
+/// Each list is sorted by path.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ManifestDiff {
+    /// Paths only in the new manifest
+    pub added: Vec<String>,
+    /// Paths only in the old manifest
+    pub removed: Vec<String>,
+    /// Paths in both whose content differs, per `ManifestEntry::same_content`
+    pub changed: Vec<String>,
+}
+
+impl ManifestDiff {
+    /// Whether the manifests list the same content
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl TreeManifest {
+    /// Look up the entry for a path
+    pub fn get(&self, path: &str) -> Option<&ManifestEntry> {
+        self.entries.iter().find(|e| e.path == path)
+    }
+
+    /// Number of files
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the manifest lists no files
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Compact JSON with a stable layout, the bytes to sign or hash
+    pub fn to_canonical_json(&self) -> Vec<u8> {
+        let mut manifest = self.clone();
+        manifest.entries.sort_by(|a, b| a.path.cmp(&b.path));
+        serde_json::to_vec(&manifest).expect("manifests always serialize")
+    }
+
+    /// Compare two manifests, e.g. the client's last one and the server's
+    pub fn diff(old: &TreeManifest, new: &TreeManifest) -> ManifestDiff {
+        let old: BTreeMap<&str, &ManifestEntry> = old.entries.iter().map(|e| (e.path.as_str(), e)).collect();
+        let new: BTreeMap<&str, &ManifestEntry> = new.entries.iter().map(|e| (e.path.as_str(), e)).collect();
+        let mut diff = ManifestDiff::default();
+        for (path, entry) in &new {
+            match old.get(path) {
+                None => diff.added.push(path.to_string()),
+                Some(previous) if !previous.same_content(entry) => diff.changed.push(path.to_string()),
+                Some(_) => {}
+            }
+        }
+        diff.removed = old.keys().filter(|path| !new.contains_key(*path)).map(|p| p.to_string()).collect();
+        diff
+    }
+}
+
+/// How `ResourceResolver::tree_manifest` builds a manifest
+#[derive(Debug, Clone)]
+pub struct ManifestOptions {
+    pub(crate) concurrency: usize,
+    pub(crate) previous: Option<TreeManifest>,
+    pub(crate) source_hashes: bool,
+}
+
+impl Default for ManifestOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: BATCH_CONCURRENCY,
+            previous: None,
+            source_hashes: false,
+        }
+    }
+}
+
+impl ManifestOptions {
+    /// Hash every file, eight at a time
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Download and hash up to `limit` files at a time (at least one)
+    pub fn with_concurrency(mut self, limit: usize) -> Self {
+        self.concurrency = limit.max(1);
+        self
+    }
+
+    /// Reuse entries of `manifest` whose files kept their ETag or source
+    /// hash and size, instead of downloading and hashing them again
+    pub fn with_previous(mut self, manifest: TreeManifest) -> Self {
+        self.previous = Some(manifest);
+        self
+    }
+
+    /// Take the hash and size from the listing when the source reports both,
+    /// leaving `sha256` unset for those files
+    /// 
+    /// Saves downloading every file from sources like `GitHubSource`, whose
+    /// listings carry git blob shas. Clients then need the same kind of hash
+    /// to compare against.
+    pub fn with_source_hashes(mut self, enabled: bool) -> Self {
+        self.source_hashes = enabled;
+        self
+    }
+}
+
+/// Manifest entry for a listed file, reusing `previous` when it still matches
+pub(crate) async fn manifest_entry(
+    resolver: &ResourceResolver,
+    listed: DirectoryEntry,
+    previous: Option<&ManifestEntry>,
+    options: &ManifestOptions,
+) -> Result<ManifestEntry> {
+    if let Some(previous) = previous {
+        // An entry without SHA-256 is only reusable under the same options
+        if (previous.sha256.is_some() || options.source_hashes) && previous.still_matches(&listed) {
+            return Ok(previous.clone());
+        }
+    }
+
+    let etag = listed.etag().map(String::from);
+    let sha = listed.sha().map(String::from);
+    if let (true, Some(_), Some(size)) = (options.source_hashes, &sha, listed.size()) {
+        return Ok(ManifestEntry {
+            path: listed.path,
+            size,
+            sha256: None,
+            sha,
+            etag,
+        });
+    }
+
+    let mut file = resolver.fetch_file(&listed.path).await?;
+    if etag.is_some() && file.etag.is_some() && file.etag != etag {
+        // A cached copy older than the listing
+        resolver.invalidate(&listed.path).await?;
+        file = resolver.fetch_file(&listed.path).await?;
+    }
+    Ok(ManifestEntry {
+        size: file.content.len() as u64,
+        sha256: Some(file.sha256().to_string()),
+        sha,
+        // The listing's ETag is what the next manifest compares against
+        etag: etag.or_else(|| file.etag.clone()),
+        path: listed.path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::ContentError,
+        source::ContentSource,
+        testing::MemorySource,
+        types::{DirectoryListing, FileContent},
+    };
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// Files with ETags in their listings, counting downloads
+    #[derive(Default)]
+    struct EtagSource {
+        files: Mutex<BTreeMap<String, (&'static str, &'static str)>>,
+        fetches: AtomicUsize,
+    }
+
+    impl EtagSource {
+        fn put(&self, path: &str, content: &'static str, etag: &'static str) {
+            self.files.lock().unwrap().insert(path.to_string(), (content, etag));
+        }
+    }
+
+    #[async_trait]
+    impl ContentSource for EtagSource {
+        async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            let (content, etag) = *self.files.lock().unwrap().get(path).ok_or_else(|| ContentError::NotFound {
+                path: path.to_string(),
+            })?;
+            let mut file = FileContent::new(content, format!("etag:{}", path));
+            file.etag = Some(etag.to_string());
+            Ok(file)
+        }
+
+        async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+            let files = self.files.lock().unwrap().clone();
+            let memory = files.iter().fold(MemorySource::new(), |m, (p, (c, _))| m.with_file(p, *c));
+            let mut listing = memory.list_directory(path).await?;
+            for entry in &mut listing.entries {
+                if let Some((content, etag)) = files.get(&entry.path) {
+                    entry.metadata.insert("etag".into(), (*etag).into());
+                    entry.metadata.insert("size".into(), content.len().into());
+                }
+            }
+            Ok(listing)
+        }
+
+        fn identifier(&self) -> String {
+            "etag".to_string()
+        }
+    }
+
+    fn setup() -> (ResourceResolver, Arc<EtagSource>) {
+        let source = Arc::new(EtagSource::default());
+        source.put("docs/a.md", "alpha", "\"a1\"");
+        source.put("docs/guides/b.md", "beta", "\"b1\"");
+        source.put("docs/c.md", "gamma", "\"c1\"");
+        source.put("other.md", "outside", "\"o1\"");
+        // Cached, so edited files are first served stale
+        let cache = Arc::new(crate::cache::MemoryCache::new());
+        (ResourceResolver::with_cache(vec![source.clone()], cache), source)
+    }
+
+    #[tokio::test]
+    async fn test_manifest_is_deterministic() {
+        let (resolver, source) = setup();
+        let first = resolver.tree_manifest("docs", ManifestOptions::new()).await.unwrap();
+        let paths: Vec<_> = first.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["docs/a.md", "docs/c.md", "docs/guides/b.md"]);
+        let a = first.get("docs/a.md").unwrap();
+        assert_eq!(a.size, 5);
+        assert_eq!(a.sha256.as_deref(), Some(crate::integrity::sha256_hex_of(b"alpha").as_str()));
+        assert_eq!(a.etag.as_deref(), Some("\"a1\""));
+        assert_eq!(source.fetches.load(Ordering::SeqCst), 3);
+
+        let serial = resolver
+            .tree_manifest("/docs/", ManifestOptions::new().with_concurrency(1))
+            .await
+            .unwrap();
+        assert_eq!(first.to_canonical_json(), serial.to_canonical_json());
+
+        let json = first.to_canonical_json();
+        let parsed: TreeManifest = serde_json::from_slice(&json).unwrap();
+        assert_eq!(parsed.to_canonical_json(), json);
+        assert!(String::from_utf8(json).unwrap().starts_with(r#"{"root":"docs","entries":[{"path":"docs/a.md""#));
+
+        assert!(resolver.tree_manifest("missing", ManifestOptions::new()).await.unwrap_err().is_not_found());
+    }
+
+    #[tokio::test]
+    async fn test_previous_manifest_skips_unchanged_files() {
+        let (resolver, source) = setup();
+        let first = resolver.tree_manifest("docs", ManifestOptions::new()).await.unwrap();
+        source.fetches.store(0, Ordering::SeqCst);
+
+        let options = ManifestOptions::new().with_previous(first.clone());
+        let again = resolver.tree_manifest("docs", options.clone()).await.unwrap();
+        assert_eq!(source.fetches.load(Ordering::SeqCst), 0);
+        assert_eq!(again, first);
+
+        source.put("docs/c.md", "gamma, edited", "\"c2\"");
+        source.put("docs/d.md", "delta", "\"d1\"");
+        source.files.lock().unwrap().remove("docs/a.md");
+        let next = resolver.tree_manifest("docs", options).await.unwrap();
+        assert_eq!(source.fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(next.get("docs/c.md").unwrap().size, 13);
+
+        let diff = TreeManifest::diff(&first, &next);
+        assert_eq!(diff.added, vec!["docs/d.md"]);
+        assert_eq!(diff.removed, vec!["docs/a.md"]);
+        assert_eq!(diff.changed, vec!["docs/c.md"]);
+        assert!(TreeManifest::diff(&next, &next).is_empty());
+    }
+
+    #[test]
+    fn test_same_content_uses_strongest_hash() {
+        let entry = |sha256: Option<&str>, sha: Option<&str>, etag: Option<&str>| ManifestEntry {
+            path: "a.md".to_string(),
+            size: 1,
+            sha256: sha256.map(String::from),
+            sha: sha.map(String::from),
+            etag: etag.map(String::from),
+        };
+        // Same SHA-256 wins over differing ETags, e.g. after a re-upload
+        assert!(entry(Some("x"), None, Some("1")).same_content(&entry(Some("x"), None, Some("2"))));
+        assert!(!entry(Some("x"), Some("s"), None).same_content(&entry(Some("y"), Some("s"), None)));
+        assert!(entry(None, Some("s"), None).same_content(&entry(Some("y"), Some("s"), None)));
+        assert!(!entry(Some("x"), None, None).same_content(&entry(None, Some("s"), None)));
+        let mut larger = entry(Some("x"), None, None);
+        larger.size = 2;
+        assert!(!entry(Some("x"), None, None).same_content(&larger));
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use crate::{
+    error::{ContentError, Result, ResultExt},
+    resolver::ResourceResolver,
+};
+
+/// Resolves locale files such as `locales/pt-BR.lang`
+pub struct LanguageProvider {
+    resolver: Arc<ResourceResolver>,
+    base_path: String,
+    strip_bom: bool,
+    extensions: Vec<String>,
+}
+
+impl LanguageProvider {
+    /// Create a provider reading `<base_path>/<code>.lang` files
+    pub fn new(resolver: Arc<ResourceResolver>, base_path: String) -> Self {
+        Self {
+            resolver,
+            base_path,
+            strip_bom: true,
+            extensions: vec!["lang".to_string()],
+        }
+    }
+
+    /// Whether to drop a leading UTF-8 byte order mark from fetched files
+    /// 
+    /// On by default: editors that save with a BOM would otherwise leave an
+    /// invisible `\u{FEFF}` at the start of the string.
+    pub fn with_bom_stripping(mut self, enabled: bool) -> Self {
+        self.strip_bom = enabled;
+        self
+    }
+
+    /// Set the file extensions to look for, in order of preference
+    /// 
+    /// For repositories that mix formats, e.g. `&["lang", "json"]` reads
+    /// `en.lang` where it exists and `en.json` otherwise. Defaults to `lang`.
+    pub fn with_extensions(mut self, extensions: &[&str]) -> Self {
+        self.extensions = extensions.iter().map(|ext| ext.to_string()).collect();
+        self
+    }
+
+    /// Build the path of a language file, without the extension
+    /// 
+    /// Normalized by the resolver, so stray slashes in the base path don't matter
+    fn language_stem(&self, code: &str) -> String {
+        let path = format!("{}/{}", self.base_path, code);
+        self.resolver.normalize_path(&path).map(String::from).unwrap_or(path)
+    }
+
+    /// Paths a language file is looked for at, in order
+    fn language_paths(&self, code: &str) -> Vec<String> {
+        let stem = self.language_stem(code);
+        self.extensions
+            .iter()
+            .map(|ext| format!("{}.{}", stem, ext.trim_start_matches('.')))
+            .collect()
+    }
+
+    /// Fetch a language file as a string
+    /// 
+    /// Takes the first of the configured extensions that exists; fails with
+    /// NotFound for the first path, naming the others tried, when none does.
+    pub async fn fetch_language(&self, code: &str) -> Result<String> {
+        let (path, file) = self.resolver.fetch_first(&self.language_paths(code)).await?;
+        let content = file.into_string().with_path(&path)?;
+        match content.strip_prefix('\u{FEFF}') {
+            Some(stripped) if self.strip_bom => Ok(stripped.to_string()),
+            _ => Ok(content),
+        }
+    }
+
+    /// Fetch a language, falling back to another code if it doesn't exist
+    pub async fn fetch_with_fallback(&self, code: &str, fallback: &str) -> Result<String> {
+        self.fetch_with_fallbacks(&[code, fallback]).await
+    }
+
+    /// Fetch the first language that exists from an ordered list of codes
+    /// 
+    /// Only NotFound moves on to the next code; other errors are returned immediately
+    pub async fn fetch_with_fallbacks(&self, codes: &[&str]) -> Result<String> {
+        for code in codes {
+            match self.fetch_language(code).await {
+                Ok(content) => return Ok(content),
+                Err(e) if e.is_not_found() => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let tried: Vec<String> = codes.iter().flat_map(|code| self.language_paths(code)).collect();
+        Err(ContentError::not_found_among(&tried))
+    }
+}
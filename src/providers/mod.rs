@@ -0,0 +1,7 @@
+//! High-level providers built on top of `ResourceResolver`
+
+pub mod language;
+pub mod skill;
+
+pub use language::LanguageProvider;
+pub use skill::{DownloadResult, FailurePolicy, SkillInfo, SkillLayout, SkillProvider};
@@ -0,0 +1,601 @@
+use std::collections::{BTreeSet, HashSet};
+#[cfg(feature = "fs")]
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[cfg(feature = "fs")]
+use tokio::fs;
+
+use crate::{
+    error::{ContentError, Result, ResultExt},
+    resolver::ResourceResolver,
+    types::{ContentPath, DirectoryEntry, EntryType},
+};
+
+/// Default name of the file that marks a directory as a skill
+pub const DEFAULT_MANIFEST_FILE: &str = "skill.json";
+
+/// How skills are laid out below the provider's base path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SkillLayout {
+    /// Every directory directly under the base path is a skill
+    #[default]
+    Flat,
+    /// Skills sit below `depth` levels of category directories
+    ///
+    /// `Categorized { depth: 1 }` matches `skills/<category>/<skill>`
+    Categorized { depth: usize },
+    /// A directory is a skill iff it contains the manifest file
+    ///
+    /// Directories without a manifest are treated as categories and searched,
+    /// down to `max_depth` levels below the base path. Subdirectories of a
+    /// skill are part of that skill and never reported as skills themselves.
+    Detect { max_depth: usize },
+}
+
+/// What `download_skill` does when a listed file can't be fetched
+/// 
+/// Files often go missing between the listing and the fetch, e.g. when they
+/// are deleted upstream. Local write errors always abort, whatever the policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FailurePolicy {
+    /// Return the first fetch error
+    #[default]
+    Abort,
+    /// Skip files that fail and list them in `DownloadResult::failed`
+    SkipAndReport,
+    /// Skip and report failures, but return the error of the `n`th one
+    AbortAfter(usize),
+}
+
+/// A skill available from the provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkillInfo {
+    /// Category-qualified identifier relative to the base path (e.g. `nlp/summarize`)
+    pub id: String,
+    /// Name of the skill directory
+    pub name: String,
+    /// Path of the skill directory relative to the source root
+    pub path: String,
+}
+
+/// Result of downloading a skill to the local filesystem
+#[derive(Debug)]
+pub struct DownloadResult {
+    /// Local paths of every file written
+    pub files_written: Vec<PathBuf>,
+    /// Total number of bytes written
+    pub total_bytes: u64,
+    /// Entries left out because they are neither files nor directories,
+    /// such as symlinks and submodules, or their names aren't UTF-8
+    pub skipped: Vec<DirectoryEntry>,
+    /// Files that couldn't be fetched, with the error, when the failure
+    /// policy let the download go on; empty otherwise
+    pub failed: Vec<(String, ContentError)>,
+}
+
+/// Lists and downloads multi-file skill bundles
+pub struct SkillProvider {
+    resolver: Arc<ResourceResolver>,
+    base_path: String,
+    layout: SkillLayout,
+    manifest_file: String,
+    failure_policy: FailurePolicy,
+    max_total_bytes: Option<u64>,
+}
+
+impl SkillProvider {
+    /// Create a provider for skills stored under `base_path`
+    /// 
+    /// The base path is normalized like any resolver path, so `""`, `"/"` and
+    /// `"."` all mean the root of the sources.
+    pub fn new(resolver: Arc<ResourceResolver>, base_path: String) -> Self {
+        // Paths that don't normalize fail on first use, when they're joined
+        let base_path = resolver
+            .normalize_path(&base_path)
+            .map(String::from)
+            .unwrap_or(base_path);
+        Self {
+            resolver,
+            base_path,
+            layout: SkillLayout::default(),
+            manifest_file: DEFAULT_MANIFEST_FILE.to_string(),
+            failure_policy: FailurePolicy::default(),
+            max_total_bytes: None,
+        }
+    }
+
+    /// Set how skills are laid out below the base path
+    pub fn with_layout(mut self, layout: SkillLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Set the manifest file name used by `SkillLayout::Detect` and `fetch_manifest`
+    pub fn with_manifest_file(mut self, manifest_file: String) -> Self {
+        self.manifest_file = manifest_file;
+        self
+    }
+
+    /// Set what `download_skill` does when a file can't be fetched
+    /// 
+    /// With anything but `FailurePolicy::Abort`, a download can succeed with
+    /// files missing, so check `DownloadResult::failed` before using it.
+    pub fn with_failure_policy(mut self, policy: FailurePolicy) -> Self {
+        self.failure_policy = policy;
+        self
+    }
+
+    /// Cap the bytes a download may write, across all its files
+    /// 
+    /// `download_skill` refuses a skill whose listed file sizes already add
+    /// up to more than `limit` before writing anything. Sizes the listing
+    /// leaves out are counted as files arrive, and the download aborts before
+    /// writing the file that would go over. Either way it fails with
+    /// `TooLarge` for the skill path and removes the files it wrote.
+    /// `download_skill_with_deps` shares one budget across all the skills.
+    pub fn with_max_total_bytes(mut self, limit: u64) -> Self {
+        self.max_total_bytes = Some(limit);
+        self
+    }
+
+    /// Normalize a qualified skill id, rejecting empty ids and `.` or `..` segments
+    fn qualified_id(skill_id: &str) -> Result<ContentPath> {
+        let invalid = || ContentError::InvalidPath {
+            path: skill_id.to_string(),
+            reason: "skill ids must be non-empty and contain no '.' or '..' segments".to_string(),
+        };
+        if skill_id.split('/').any(|s| s == "." || s == "..") {
+            return Err(invalid());
+        }
+        let id = ContentPath::parse(skill_id)?;
+        if id.is_root() {
+            return Err(invalid());
+        }
+        Ok(id)
+    }
+
+    /// Build the source path of a skill from its qualified id
+    fn skill_path(&self, skill_id: &str) -> Result<String> {
+        let id = Self::qualified_id(skill_id)?;
+        ContentPath::parse(&self.base_path)?.join(id).map(String::from)
+    }
+
+    /// Strip `prefix/` from a source path, or None if it isn't below `prefix`
+    fn relative_to(prefix: &str, path: &str) -> Option<ContentPath> {
+        ContentPath::parse(path)
+            .ok()?
+            .strip_prefix(&ContentPath::parse(prefix).ok()?)
+            .filter(|relative| !relative.is_root())
+    }
+
+    fn skill_info(&self, id: &str) -> SkillInfo {
+        SkillInfo {
+            id: id.to_string(),
+            name: id.rsplit('/').next().unwrap_or(id).to_string(),
+            path: if self.base_path.is_empty() {
+                id.to_string()
+            } else {
+                format!("{}/{}", self.base_path, id)
+            },
+        }
+    }
+
+    /// List the skills available under the base path, sorted by id
+    pub async fn list_skills(&self) -> Result<Vec<SkillInfo>> {
+        let ids: BTreeSet<String> = match self.layout {
+            SkillLayout::Flat => self
+                .resolver
+                .list_directory(&self.base_path)
+                .await?
+                .entries
+                .into_iter()
+                .filter(|e| e.entry_type == EntryType::Dir)
+                .map(|e| e.name)
+                .collect(),
+            SkillLayout::Categorized { depth } => self
+                .list_tree()
+                .await?
+                .iter()
+                .filter(|e| e.entry_type == EntryType::Dir)
+                .filter_map(|e| Self::relative_to(&self.base_path, &e.path))
+                .filter(|rel| rel.segments().count() == depth + 1)
+                .map(String::from)
+                .collect(),
+            SkillLayout::Detect { max_depth } => {
+                let tree = self.list_tree().await?;
+                let candidates: BTreeSet<ContentPath> = tree
+                    .iter()
+                    .filter(|e| e.entry_type == EntryType::File && e.name == self.manifest_file)
+                    .filter_map(|e| Self::relative_to(&self.base_path, &e.path))
+                    .filter_map(|rel| rel.parent())
+                    .filter(|dir| !dir.is_root() && dir.segments().count() <= max_depth)
+                    .collect();
+
+                // A manifest inside an existing skill belongs to that skill
+                candidates
+                    .iter()
+                    .filter(|dir| {
+                        !candidates
+                            .iter()
+                            .any(|other| other != *dir && dir.starts_with(other))
+                    })
+                    .map(|dir| dir.to_string())
+                    .collect()
+            }
+        };
+
+        Ok(ids.iter().map(|id| self.skill_info(id)).collect())
+    }
+
+    /// Recursively list everything below the base path
+    async fn list_tree(&self) -> Result<Vec<DirectoryEntry>> {
+        self.resolver.list_directory_recursive(&self.base_path).await
+    }
+
+    /// Get every file and directory in a skill without downloading it
+    pub async fn get_skill_structure(&self, skill_id: &str) -> Result<Vec<DirectoryEntry>> {
+        let skill_path = self.skill_path(skill_id)?;
+        self.resolver.list_directory_recursive(&skill_path).await
+    }
+
+    /// Fetch and parse a skill's manifest file
+    pub async fn fetch_manifest(&self, skill_id: &str) -> Result<serde_json::Value> {
+        let path = self.manifest_path(skill_id)?;
+        let content = self.resolver.fetch_file(&path).await?;
+        serde_json::from_slice(&content.content)
+            .map_err(ContentError::from)
+            .with_path(&path)
+    }
+
+    /// Resolve every skill `skill_id` depends on, directly or transitively
+    /// 
+    /// Dependencies are read from the `dependencies` array of each skill's
+    /// manifest, as qualified skill ids. A skill without a manifest, or whose
+    /// manifest has no `dependencies`, has none. The result lists each
+    /// dependency once, every skill after the ones it depends on, and leaves
+    /// out `skill_id` itself.
+    /// 
+    /// Fails with `Validation` on a dependency cycle, naming the cycle, or when
+    /// `dependencies` isn't an array of strings, and with `NotFound` when a
+    /// dependency doesn't exist.
+    pub async fn resolve_dependencies(&self, skill_id: &str) -> Result<Vec<String>> {
+        let root = String::from(Self::qualified_id(skill_id)?);
+        let root_deps = self.dependencies_of(&root).await?;
+
+        let mut resolved = Vec::new();
+        let mut done = HashSet::new();
+        // Depth-first, with the chain of skills being resolved and the
+        // dependencies each has left to visit
+        let mut chain = vec![(root, root_deps.into_iter())];
+        while let Some((_, pending)) = chain.last_mut() {
+            let Some(dep) = pending.next() else {
+                let (id, _) = chain.pop().expect("chain is not empty");
+                done.insert(id.clone());
+                resolved.push(id);
+                continue;
+            };
+            if done.contains(&dep) {
+                continue;
+            }
+            if let Some(start) = chain.iter().position(|(id, _)| *id == dep) {
+                let (last, _) = chain.last().expect("chain is not empty");
+                let cycle: Vec<&str> = chain[start..]
+                    .iter()
+                    .map(|(id, _)| id.as_str())
+                    .chain([dep.as_str()])
+                    .collect();
+                return Err(ContentError::Validation {
+                    path: self.manifest_path(last)?,
+                    message: format!("dependency cycle: {}", cycle.join(" -> ")),
+                    pointers: vec!["/dependencies".to_string()],
+                });
+            }
+
+            let deps = self.dependencies_of(&dep).await?;
+            chain.push((dep, deps.into_iter()));
+        }
+
+        // The root is resolved last
+        resolved.pop();
+        Ok(resolved)
+    }
+
+    /// Read the direct dependencies from a skill's manifest
+    async fn dependencies_of(&self, skill_id: &str) -> Result<Vec<String>> {
+        let path = self.manifest_path(skill_id)?;
+        let manifest = match self.fetch_manifest(skill_id).await {
+            Ok(manifest) => manifest,
+            Err(e) if e.is_not_found() => {
+                // Skills don't need a manifest, but they do need to exist
+                let skill_path = self.skill_path(skill_id)?;
+                return match self.resolver.path_type(&skill_path).await? {
+                    Some(EntryType::Dir) => Ok(Vec::new()),
+                    _ => Err(ContentError::NotFound { path: skill_path }),
+                };
+            }
+            Err(e) => return Err(e),
+        };
+
+        let invalid = |message: &str, pointer: String| ContentError::Validation {
+            path: path.clone(),
+            message: message.to_string(),
+            pointers: vec![pointer],
+        };
+        let Some(deps) = manifest.get("dependencies") else {
+            return Ok(Vec::new());
+        };
+        let deps = deps.as_array().ok_or_else(|| {
+            invalid("dependencies must be an array of skill ids", "/dependencies".to_string())
+        })?;
+        deps.iter()
+            .enumerate()
+            .map(|(i, dep)| {
+                let id = dep.as_str().ok_or_else(|| {
+                    invalid("dependencies must be skill ids", format!("/dependencies/{}", i))
+                })?;
+                Self::qualified_id(id).map(String::from)
+            })
+            .collect()
+    }
+
+    fn manifest_path(&self, skill_id: &str) -> Result<String> {
+        Ok(format!("{}/{}", self.skill_path(skill_id)?, self.manifest_file))
+    }
+
+    /// Write a file, creating its parent directories
+    /// 
+    /// Executable files get the execute bit wherever they are readable (Unix only)
+    #[cfg(feature = "fs")]
+    async fn write_file(
+        target: &Path,
+        content: &[u8],
+        executable: bool,
+        written: &mut Rollback,
+    ) -> Result<()> {
+        if let Some(parent) = target.parent() {
+            written.create_dir_all(parent).await?;
+        }
+        written.set_aside(target).await?;
+        fs::write(target, content).await?;
+
+        #[cfg(unix)]
+        if executable {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mut permissions = fs::metadata(target).await?.permissions();
+            let mode = permissions.mode();
+            permissions.set_mode(mode | ((mode & 0o444) >> 2));
+            fs::set_permissions(target, permissions).await?;
+        }
+        #[cfg(not(unix))]
+        let _ = executable;
+
+        Ok(())
+    }
+
+    /// Download every file of a skill into `output_dir`
+    ///
+    /// The skill's directory structure is recreated below `output_dir`.
+    /// Entries of any other type, and entries whose names aren't UTF-8, are
+    /// not downloaded; they are listed in `DownloadResult::skipped`. Files
+    /// that can't be fetched are handled by the provider's `FailurePolicy`.
+    /// 
+    /// With `with_max_total_bytes`, fails with `TooLarge` once the skill is
+    /// found to be over the budget.
+    /// 
+    /// A download that fails deletes the files it wrote and the directories
+    /// it created, and puts back the files it overwrote, so a skill directory
+    /// is either complete or as it was before.
+    #[cfg(feature = "fs")]
+    pub async fn download_skill(
+        &self,
+        skill_id: &str,
+        output_dir: &Path,
+    ) -> Result<DownloadResult> {
+        let mut written = Rollback::default();
+        let result = self.download_within(skill_id, output_dir, 0, &mut written).await;
+        match result {
+            Ok(_) => written.commit().await,
+            Err(_) => written.undo().await,
+        }
+        result
+    }
+
+    /// Download a skill after `spent` bytes of the download budget were used
+    #[cfg(feature = "fs")]
+    async fn download_within(
+        &self,
+        skill_id: &str,
+        output_dir: &Path,
+        spent: u64,
+        written: &mut Rollback,
+    ) -> Result<DownloadResult> {
+        let skill_path = self.skill_path(skill_id)?;
+        let entries = self.resolver.list_directory_recursive(&skill_path).await?;
+
+        let over_budget = |size| ContentError::TooLarge {
+            path: skill_path.clone(),
+            size,
+            limit: self.max_total_bytes.unwrap_or(u64::MAX),
+        };
+        if let Some(limit) = self.max_total_bytes {
+            let listed: u64 = entries
+                .iter()
+                .filter(|e| e.entry_type == EntryType::File && e.raw_name.is_none())
+                .filter_map(|e| e.size())
+                .sum();
+            if spent.saturating_add(listed) > limit {
+                return Err(over_budget(Some(spent.saturating_add(listed))));
+            }
+        }
+
+        let mut result = DownloadResult {
+            files_written: Vec::new(),
+            total_bytes: 0,
+            skipped: Vec::new(),
+            failed: Vec::new(),
+        };
+
+        written.create_dir_all(output_dir).await.with_path(&skill_path)?;
+
+        for entry in entries {
+            // Its lossy path can't be fetched or written faithfully
+            if entry.raw_name.is_some() {
+                result.skipped.push(entry);
+                continue;
+            }
+            let relative = Self::relative_to(&skill_path, &entry.path).ok_or_else(|| {
+                ContentError::InvalidStructure {
+                    message: format!("Entry {} is outside skill {}", entry.path, skill_path),
+                }
+            })?;
+            let target = output_dir.join(relative.as_str());
+
+            match entry.entry_type {
+                EntryType::Dir => written.create_dir_all(&target).await.with_path(&entry.path)?,
+                EntryType::File => {
+                    let content = match self.resolver.fetch_file(&entry.path).await {
+                        Ok(content) => content,
+                        Err(e) => {
+                            let limit = match self.failure_policy {
+                                FailurePolicy::Abort => return Err(e),
+                                FailurePolicy::SkipAndReport => usize::MAX,
+                                FailurePolicy::AbortAfter(n) => n,
+                            };
+                            if result.failed.len() + 1 >= limit {
+                                return Err(e);
+                            }
+                            result.failed.push((entry.path, e));
+                            continue;
+                        }
+                    };
+                    let size = content.content.len() as u64;
+                    if let Some(limit) = self.max_total_bytes {
+                        if spent + result.total_bytes + size > limit {
+                            return Err(over_budget(None));
+                        }
+                    }
+                    Self::write_file(&target, &content.content, entry.is_executable(), written)
+                        .await
+                        .with_path(&entry.path)?;
+
+                    result.total_bytes += size;
+                    result.files_written.push(target);
+                }
+                // Fetching a symlink or submodule as a file would write
+                // something other than what the source holds
+                _ => result.skipped.push(entry),
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Download a skill and everything it depends on
+    /// 
+    /// Dependencies are resolved with `resolve_dependencies` before anything
+    /// is written. Each skill goes to `output_dir/<skill id>`, dependencies
+    /// first, and the results are returned in that order with the skill
+    /// itself last. If any skill fails, the ones already downloaded are
+    /// removed as well.
+    #[cfg(feature = "fs")]
+    pub async fn download_skill_with_deps(
+        &self,
+        skill_id: &str,
+        output_dir: &Path,
+    ) -> Result<Vec<(String, DownloadResult)>> {
+        let mut skills = self.resolve_dependencies(skill_id).await?;
+        skills.push(Self::qualified_id(skill_id)?.into());
+
+        let mut written = Rollback::default();
+        let mut results = Vec::with_capacity(skills.len());
+        let mut spent = 0;
+        for id in skills {
+            match self.download_within(&id, &output_dir.join(&id), spent, &mut written).await {
+                Ok(result) => {
+                    spent += result.total_bytes;
+                    results.push((id, result));
+                }
+                Err(e) => {
+                    written.undo().await;
+                    return Err(e);
+                }
+            }
+        }
+        written.commit().await;
+        Ok(results)
+    }
+}
+
+/// What a download wrote, to undo it when the download fails
+#[cfg(feature = "fs")]
+#[derive(Debug, Default)]
+struct Rollback {
+    files: Vec<PathBuf>,
+    /// Files that were overwritten, and where their old content was moved
+    replaced: Vec<(PathBuf, PathBuf)>,
+    /// Directories that didn't exist before, parents first
+    dirs: Vec<PathBuf>,
+}
+
+#[cfg(feature = "fs")]
+impl Rollback {
+    /// Create a directory and its missing parents, remembering which were missing
+    async fn create_dir_all(&mut self, dir: &Path) -> Result<()> {
+        let mut missing = Vec::new();
+        let mut current = Some(dir);
+        while let Some(path) = current.filter(|path| !path.as_os_str().is_empty()) {
+            if fs::try_exists(path).await? {
+                break;
+            }
+            missing.push(path.to_path_buf());
+            current = path.parent();
+        }
+        fs::create_dir_all(dir).await?;
+        self.dirs.extend(missing.into_iter().rev());
+        Ok(())
+    }
+
+    /// Remember a file about to be written, moving aside what is there now
+    async fn set_aside(&mut self, file: &Path) -> Result<()> {
+        match fs::symlink_metadata(file).await {
+            // Writing over a directory fails anyway
+            Ok(metadata) if !metadata.is_dir() => {
+                let backup = crate::cache::temp_path(file);
+                fs::rename(file, &backup).await?;
+                self.replaced.push((file.to_path_buf(), backup));
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        self.files.push(file.to_path_buf());
+        Ok(())
+    }
+
+    /// Keep what was written, deleting the old content of overwritten files
+    async fn commit(self) {
+        for (_, backup) in &self.replaced {
+            let _ = fs::remove_file(backup).await;
+        }
+    }
+
+    /// Delete the files written and the directories created, and put back
+    /// the files overwritten, as far as possible
+    /// 
+    /// Directories are only removed once empty, so anything else put in them
+    /// meanwhile stays.
+    async fn undo(self) {
+        for file in self.files.iter().rev() {
+            let _ = fs::remove_file(file).await;
+        }
+        for (file, backup) in self.replaced.iter().rev() {
+            let _ = fs::rename(backup, file).await;
+        }
+        for dir in self.dirs.iter().rev() {
+            let _ = fs::remove_dir(dir).await;
+        }
+    }
+}
@@ -1,27 +1,217 @@
-use std::sync::Arc;
+use bytes::{Bytes, BytesMut};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::digest::DynDigest;
+use sha2::Sha256;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 
 use crate::{
+    admission::{AdmitAll, CacheAdmission},
+    audit::{AuditEvent, AuditSink},
+    budget::{unbudgeted, BudgetStatus, BudgetedSource, RequestBudget},
     cache::Cache,
-    error::{ContentError, Result},
-    source::ContentSource,
-    types::{DirectoryListing, FileContent},
+    composite::{VirtualFileRule, MAX_VIRTUAL_DEPTH},
+    error::{ContentError, ErrorKind, PartialFailure, Result, ResultExt},
+    hook::{RequestContext, RequestHook},
+    explain::{CacheCheck, CacheState, Explanation, Resolution, SourceCheck, SourceOutcome},
+    integrity::sha256_hex_of,
+    invalidation::InvalidationQueue,
+    limit::{unlimited, SourceLimits},
+    manifest::{manifest_entry, ManifestEntry, ManifestOptions, TreeManifest},
+    scoped::ScopedResolver,
+    source::{ContentSource, FetchOptions, Revalidated},
+    stream::ContentStream,
+    task::TaskSet,
+    validate::{Finding, Severity, Subject, ValidationOptions, ValidationReport},
+    types::{ContentPath, ContentVersion, DataFormat, SeparatorPolicy, DirectoryEntry, DirectoryListing, EntryType, FileContent, FileMetadata},
 };
 
+#[cfg(feature = "archive")]
+use crate::archive::ArchiveSource;
+
+/// Number of requests `exists_many` and `fetch_files` keep in flight
+pub(crate) const BATCH_CONCURRENCY: usize = 8;
+
+/// Largest file `fetch_to_writer` copies into the cache by default, 8 MiB
+const DEFAULT_STREAM_CACHE_LIMIT: u64 = 8 * 1024 * 1024;
+
 /// Resolves content from multiple sources with fallback support
 /// 
 /// Searches sources in order and returns the first match.
 /// Optionally caches results to reduce network requests.
+/// 
+/// Paths are normalized with `ContentPath` before they reach a source or the
+/// cache, so `/a//b` and `a/./b` resolve alike. Paths that climb out of the
+/// root fail with `InvalidPath`.
+/// 
+/// Clones are cheap and share the sources and the cache.
+#[derive(Clone)]
 pub struct ResourceResolver {
-    sources: Vec<Arc<dyn ContentSource>>,
+    /// Shared with clones, and swapped whole by `replace_sources`
+    sources: Arc<RwLock<SourceList>>,
+    /// The sources this value was built with, without concurrency limits,
+    /// for `sources()`
+    configured: SourceList,
     cache: Option<Arc<dyn Cache>>,
+    treat_empty_as_not_found: bool,
+    serve_stale_on_error: bool,
+    separators: SeparatorPolicy,
+    existence: Option<Arc<ExistenceCache>>,
+    entry_limit: Option<usize>,
+    cache_scope: Option<String>,
+    extension_map: Option<Arc<HashMap<String, String>>>,
+    admission: Arc<dyn CacheAdmission>,
+    counters: Arc<Counters>,
+    strict_cache: bool,
+    cache_error_hook: Option<CacheErrorHook>,
+    virtual_files: Arc<Vec<VirtualFileRule>>,
+    /// How many virtual files are being assembled around this call
+    virtual_depth: usize,
+    /// Paths fetched while assembling a virtual file
+    assembly: Option<Arc<Mutex<Assembly>>>,
+    /// Held while the dependents recorded in the cache are read and rewritten
+    dependents_lock: Arc<tokio::sync::Mutex<()>>,
+    uncacheable_first: bool,
+    budget: Option<Arc<RequestBudget>>,
+    source_limits: Arc<SourceLimits>,
+    request_hooks: Arc<Vec<Arc<dyn RequestHook>>>,
+    read_mode: ReadMode,
+    tasks: Arc<TaskSet>,
+    audit_sink: Option<Arc<dyn AuditSink>>,
+    audit_cache_hits: bool,
+    stream_cache_limit: u64,
+}
+
+/// The sources of a resolver, in order
+type SourceList = Arc<[Arc<dyn ContentSource>]>;
+
+/// Called with each cache error the resolver recovers from
+/// 
+/// See `ResourceResolver::with_cache_error_hook`.
+pub type CacheErrorHook = Arc<dyn Fn(&ContentError) + Send + Sync>;
+
+/// Whether `fetch_file` asks the cache or the sources first
+/// 
+/// See `ResourceResolver::with_read_mode`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReadMode {
+    /// Serve cache hits without asking the sources
+    #[default]
+    CacheFirst,
+    /// Ask the sources first, and serve the cached copy if they haven't
+    /// answered within `timeout` or fail
+    SourceFirstThenCache {
+        /// How long to wait for the sources before falling back
+        timeout: Duration,
+    },
+}
+
+/// Counts of what a resolver and its clones have done, from `ResourceResolver::stats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ResolverStats {
+    /// Fetched files the cache admission policy kept out of the cache
+    pub cache_rejections: u64,
+    /// Cache reads that failed and were treated as misses
+    pub cache_read_errors: u64,
+    /// Cache writes that failed; the fetched file was returned anyway
+    pub cache_write_errors: u64,
+    /// Audit events the sink rejected; the fetches succeeded anyway
+    pub audit_failures: u64,
+}
+
+/// Shared counters behind `ResolverStats`
+#[derive(Default)]
+struct Counters {
+    cache_rejections: AtomicU64,
+    cache_read_errors: AtomicU64,
+    cache_write_errors: AtomicU64,
+    audit_failures: AtomicU64,
+}
+
+/// What a virtual file being assembled has read so far
+#[derive(Default)]
+struct Assembly {
+    /// Normalized paths of the files it fetched
+    dependencies: BTreeSet<String>,
+    /// Set once one of them came from a source that isn't cacheable
+    uncacheable: bool,
+}
+
+/// Recent `file_exists` answers, positive and negative, timed on tokio's clock
+struct ExistenceCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (bool, tokio::time::Instant)>>,
+}
+
+impl ExistenceCache {
+    fn get(&self, path: &str) -> Option<bool> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .get(path)
+            .filter(|(_, at)| at.elapsed() < self.ttl)
+            .map(|(exists, _)| *exists)
+    }
+
+    fn insert(&self, path: &str, exists: bool) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        // Drop expired answers so paths probed once don't pile up
+        entries.retain(|_, (_, at)| at.elapsed() < self.ttl);
+        entries.insert(path.to_string(), (exists, tokio::time::Instant::now()));
+    }
+
+    fn clear(&self) {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    fn remove_prefix(&self, prefix: &ContentPath) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|path, _| {
+            ContentPath::parse(path).map_or(true, |path| !path.starts_with(prefix))
+        });
+    }
 }
 
 impl ResourceResolver {
     /// Create a new resolver with the given sources
     pub fn new(sources: Vec<Arc<dyn ContentSource>>) -> Self {
+        let source_limits = Arc::new(SourceLimits::default());
+        let sources = source_limits.wrap_all(sources);
         Self {
-            sources,
+            configured: without_limits(&sources),
+            sources: Arc::new(RwLock::new(sources)),
             cache: None,
+            treat_empty_as_not_found: false,
+            serve_stale_on_error: false,
+            separators: SeparatorPolicy::default(),
+            existence: None,
+            entry_limit: None,
+            cache_scope: None,
+            extension_map: None,
+            admission: Arc::new(AdmitAll),
+            counters: Arc::default(),
+            strict_cache: false,
+            cache_error_hook: None,
+            virtual_files: Arc::default(),
+            virtual_depth: 0,
+            assembly: None,
+            dependents_lock: Arc::default(),
+            uncacheable_first: false,
+            budget: None,
+            source_limits,
+            request_hooks: Arc::new(Vec::new()),
+            read_mode: ReadMode::CacheFirst,
+            tasks: Arc::default(),
+            audit_sink: None,
+            audit_cache_hits: false,
+            stream_cache_limit: DEFAULT_STREAM_CACHE_LIMIT,
         }
     }
 
@@ -31,227 +221,4999 @@ impl ResourceResolver {
         cache: Arc<dyn Cache>,
     ) -> Self {
         Self {
-            sources,
             cache: Some(cache),
+            ..Self::new(sources)
         }
     }
 
-    /// Fetch a file by path, searching sources in order
+    /// Create a new resolver, failing on an empty source list
     /// 
-    /// Returns the first successful match, or NotFound if none match
-    pub async fn fetch_file(&self, path: &str) -> Result<FileContent> {
-        // Generate cache key from path
-        let cache_key = format!("file:{}", path);
+    /// A resolver without sources answers NotFound for everything, which is
+    /// almost always a configuration mistake. This reports it up front as
+    /// `InvalidConfig`.
+    pub fn try_new(sources: Vec<Arc<dyn ContentSource>>) -> Result<Self> {
+        ensure_sources(&sources)?;
+        Ok(Self::new(sources))
+    }
 
-        // Check cache first if enabled
-        if let Some(cache) = &self.cache {
-            if let Some(cached) = cache.get(&cache_key).await? {
-                return Ok(FileContent {
-                    content: cached,
-                    source_path: format!("cache:{}", path),
-                    etag: None,
-                });
-            }
-        }
+    /// Create a new resolver with caching enabled, failing on an empty source list
+    pub fn try_with_cache(
+        sources: Vec<Arc<dyn ContentSource>>,
+        cache: Arc<dyn Cache>,
+    ) -> Result<Self> {
+        ensure_sources(&sources)?;
+        Ok(Self::with_cache(sources, cache))
+    }
 
-        // Try each source in order
-        let mut last_error = None;
+    /// Build a resolver from environment variables
+    /// 
+    /// - `CONTENT_SOURCES`: source URLs separated by `,` or `;`, tried in
+    ///   order, e.g. `local:///opt/content,github://owner/repo@main/path`
+    /// - `CONTENT_CACHE_DIR`: directory for a `DiskCache`; no cache when unset
+    /// - `CONTENT_CACHE_TTL_SECS`: expire cached files after this many seconds
+    /// - `CONTENT_GITHUB_TOKEN`: token for every GitHub source
+    /// - `CONTENT_TIMEOUT_MS`: request timeout for every GitHub source
+    /// 
+    /// Blank variables count as unset. Bad values, and an empty source list,
+    /// fail with `InvalidConfig` naming the variable. The cache is scoped to
+    /// the sources with `with_source_cache_scope`.
+    pub async fn from_env() -> Result<Self> {
+        crate::env::resolver_from_env().await
+    }
 
-        for source in &self.sources {
-            match source.fetch_file(path).await {
-                Ok(content) => {
-                    // Cache the result if caching is enabled
-                    if let Some(cache) = &self.cache {
-                        let _ = cache.set(&cache_key, content.content.clone()).await;
-                    }
-                    return Ok(content);
-                }
-                Err(ContentError::NotFound { .. }) => {
-                    // Continue to next source on not found
-                    continue;
-                }
-                Err(e) => {
-                    // Store other errors but continue trying
-                    last_error = Some(e);
-                }
-            }
-        }
+    /// Treat zero-byte files as missing in `fetch_file`
+    /// 
+    /// An empty file then falls through to the next source, or to NotFound.
+    /// Off by default, since empty files are often valid. Listings, existence
+    /// checks and `fetch_to_writer` are not affected.
+    pub fn with_empty_as_not_found(mut self, enabled: bool) -> Self {
+        self.treat_empty_as_not_found = enabled;
+        self
+    }
 
-        // If we got a non-NotFound error, return it
-        if let Some(error) = last_error {
-            return Err(error);
-        }
+    /// Serve an expired cached copy from `fetch_file` when the sources are down
+    /// 
+    /// If no source has the file and the error returned is retryable, such as
+    /// a timeout, a network failure or a rate limit, the cache is read with
+    /// `Cache::get_stale` and any copy found is returned with
+    /// `FileContent::stale` set. Off by default. Only caches that keep expired
+    /// entries, like `DiskCache` with a TTL, have anything to serve.
+    pub fn with_serve_stale_on_error(mut self, enabled: bool) -> Self {
+        self.serve_stale_on_error = enabled;
+        self
+    }
 
-        // Nothing found in any source
-        Err(ContentError::NotFound {
-            path: path.to_string(),
-        })
+    /// Choose whether `fetch_file` asks the cache or the sources first
+    /// 
+    /// With `ReadMode::SourceFirstThenCache`, every fetch goes to the sources
+    /// to get the latest content, which is cached as usual. If they take
+    /// longer than the timeout, or fail with anything but NotFound, the cached
+    /// copy is served instead, falling back to an expired one with
+    /// `FileContent::stale` set. When nothing is cached the fetch carries on
+    /// past the timeout and its result is returned. A fetch abandoned for the
+    /// cache is cancelled, not finished in the background.
+    /// 
+    /// Only `fetch_file` and its variants read this way; `fetch_to_writer`
+    /// and the other methods stay cache-first. Without a cache it has no
+    /// effect. Defaults to `ReadMode::CacheFirst`.
+    pub fn with_read_mode(mut self, mode: ReadMode) -> Self {
+        self.read_mode = mode;
+        self
     }
 
-    /// List directory contents, searching sources in order
+    /// Remember `file_exists` answers for `ttl`
     /// 
-    /// Returns the first successful match
-    pub async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
-        let mut last_error = None;
+    /// Both hits and misses are kept, which suits probing the same optional
+    /// overrides on every request. `invalidate` and `invalidate_prefix` forget
+    /// the answers for their paths. The TTL runs on tokio's clock, so
+    /// `tokio::time::pause` stops it in tests. Off by default.
+    pub fn with_existence_ttl(mut self, ttl: Duration) -> Self {
+        self.existence = Some(Arc::new(ExistenceCache {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }));
+        self
+    }
 
-        for source in &self.sources {
-            match source.list_directory(path).await {
-                Ok(listing) => return Ok(listing),
-                Err(ContentError::NotFound { .. }) => {
-                    continue;
-                }
-                Err(e) => {
-                    last_error = Some(e);
-                }
-            }
-        }
+    /// Prefix every cache key with `scope`
+    /// 
+    /// Resolvers sharing a cache see each other's entries under the same path,
+    /// so two resolvers over different branches of one repository would serve
+    /// each other's files. Giving each a different scope keeps them apart.
+    /// Entries written without a scope, or under another one, aren't found,
+    /// so an existing cache appears cold after a scope is set. An empty scope
+    /// means no scope.
+    pub fn with_cache_scope(mut self, scope: impl Into<String>) -> Self {
+        let scope = scope.into();
+        self.cache_scope = (!scope.is_empty()).then_some(scope);
+        self
+    }
 
-        if let Some(error) = last_error {
-            return Err(error);
-        }
+    /// Scope the cache to this resolver's sources
+    /// 
+    /// Uses a hash of the source identifiers, in order, as the cache scope, so
+    /// resolvers over the same sources share entries and any others don't.
+    /// `from_env` sets it; see `with_cache_scope` for what changes.
+    pub fn with_source_cache_scope(self) -> Self {
+        let identifiers: Vec<String> = self.live_sources().iter().map(|s| s.identifier()).collect();
+        let digest = sha256_hex_of(identifiers.join("\n").as_bytes());
+        self.with_cache_scope(&digest[..16])
+    }
 
-        Err(ContentError::NotFound {
-            path: path.to_string(),
-        })
+    /// The prefix of this resolver's cache keys, if any
+    pub fn cache_scope(&self) -> Option<&str> {
+        self.cache_scope.as_deref()
     }
 
-    /// List directory contents across all sources, merging results
+    /// Set the MIME type of files by extension, overriding the sources
     /// 
-    /// This aggregates entries from all sources that successfully list the directory
-    pub async fn list_directory_merged(&self, path: &str) -> Result<DirectoryListing> {
-        let mut all_entries = Vec::new();
-        let mut found_any = false;
+    /// Maps extensions, with or without the leading `.`, to MIME types. A
+    /// fetched file or `stat` result whose name has a mapped extension gets
+    /// that `content_type`, whatever the source reported; other files keep
+    /// the source's. Extensions match case-insensitively and only the last
+    /// one counts, so `data.lang.gz` is looked up as `gz`.
+    pub fn with_extension_map<I, K, V>(mut self, map: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: Into<String>,
+    {
+        let map: HashMap<String, String> = map
+            .into_iter()
+            .map(|(extension, mime)| {
+                let extension = extension.as_ref().trim_start_matches('.').to_ascii_lowercase();
+                (extension, mime.into())
+            })
+            .collect();
+        self.extension_map = (!map.is_empty()).then(|| Arc::new(map));
+        self
+    }
+
+    /// The MIME type `with_extension_map` assigns to a normalized path, if any
+    fn mapped_content_type(&self, path: &ContentPath) -> Option<String> {
+        let map = self.extension_map.as_ref()?;
+        map.get(&path.extension()?.to_ascii_lowercase()).cloned()
+    }
+
+    /// Choose which fetched files are written to the cache
+    /// 
+    /// The policy sees each file's normalized path and content before it is
+    /// cached; rejected files are returned as usual, fetched from the sources
+    /// every time, and counted in `ResolverStats::cache_rejections`. Unlike a
+    /// cache's own size limits, policies can look at paths and metadata.
+    /// Defaults to `AdmitAll`.
+    pub fn with_cache_admission(mut self, policy: impl CacheAdmission + 'static) -> Self {
+        self.admission = Arc::new(policy);
+        self
+    }
+
+    /// Fail fetches when the cache can't be read
+    /// 
+    /// By default a failed cache read, e.g. an unreadable file or Redis being
+    /// unavailable, counts as a miss: the file is fetched from the sources,
+    /// and the error goes to the cache error hook and
+    /// `ResolverStats::cache_read_errors`. With `strict` the error is
+    /// returned instead. Failed cache writes never fail a fetch.
+    pub fn with_strict_cache(mut self, strict: bool) -> Self {
+        self.strict_cache = strict;
+        self
+    }
+
+    /// Call `hook` with every cache read or write error the resolver recovers from
+    /// 
+    /// Use it to log or alert on a broken cache, which otherwise only shows
+    /// up as more requests to the sources. Errors carry the path they
+    /// happened on.
+    pub fn with_cache_error_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&ContentError) + Send + Sync + 'static,
+    {
+        self.cache_error_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Assemble the paths a rule matches instead of fetching them from the sources
+    /// 
+    /// Rules are tried in the order they were added, before the sources.
+    /// The assembled file is cached under its own path, and dropped again
+    /// when `invalidate` drops one of the files it was built from. Virtual
+    /// files built from virtual files nest at most 8 deep; deeper, e.g. a
+    /// rule reading its own path, fails with `InvalidConfig`.
+    pub fn with_virtual_file(mut self, rule: VirtualFileRule) -> Self {
+        Arc::make_mut(&mut self.virtual_files).push(rule);
+        self
+    }
+
+    /// Spend `budget` on every request the sources make
+    /// 
+    /// Each source is wrapped in a `BudgetedSource` sharing the budget, so
+    /// once it is used up fetches fail fast with `RateLimited` instead of
+    /// going out, until it refills. Cache hits never reach the sources and
+    /// cost nothing. Call this after adding sources; calling it again replaces
+    /// the budget. To give sources budgets of their own, wrap them in
+    /// `BudgetedSource` instead.
+    pub fn with_request_budget(mut self, budget: Arc<RequestBudget>) -> Self {
+        self.budget = Some(budget);
+        let sources = self.wrapped(self.live_sources().to_vec());
+        self.set_sources(sources);
+        self
+    }
+
+    /// Allow the sources identified as `id` at most `limit` requests in flight
+    /// 
+    /// The bound is shared by everything the resolver and its clones do:
+    /// single fetches, `fetch_files` batches, downloads, listings and walks
+    /// all wait for the same permits, so concurrent batches can't multiply
+    /// the connections to one host. Sources sharing an identifier share the
+    /// bound. A recursive listing takes one permit for the whole walk; a
+    /// download through `fetch_to_writer` or `fetch_stream` gives its permit
+    /// back once the first bytes arrive, so a slow consumer doesn't starve
+    /// other requests. Call this after adding sources.
+    pub fn with_source_concurrency(self, id: impl Into<String>, limit: usize) -> Self {
+        self.with_source_limits(Some(id.into()), limit)
+    }
+
+    /// Allow sources without their own limit `limit` requests in flight each
+    /// 
+    /// Defaults to `DEFAULT_SOURCE_CONCURRENCY`, 16. See
+    /// `with_source_concurrency`.
+    pub fn with_default_source_concurrency(self, limit: usize) -> Self {
+        self.with_source_limits(None, limit)
+    }
+
+    fn with_source_limits(mut self, id: Option<String>, limit: usize) -> Self {
+        self.source_limits = Arc::new(self.source_limits.with_limit(id, limit));
+        let sources = self.source_limits.wrap_all(self.live_sources().to_vec());
+        self.set_sources(sources);
+        self
+    }
+
+    /// Give this value a source list of its own, no longer shared with clones
+    fn set_sources(&mut self, sources: SourceList) {
+        self.configured = without_limits(&sources);
+        self.sources = Arc::new(RwLock::new(sources));
+    }
+
+    /// Requests each source has in flight right now, by identifier
+    /// 
+    /// Counts requests holding one of the permits of
+    /// `with_source_concurrency`; requests waiting for one aren't included.
+    pub fn in_flight(&self) -> BTreeMap<String, usize> {
+        self.source_limits.in_flight()
+    }
+
+    /// Add a hook that sees, and may rewrite, the path of every request
+    /// 
+    /// Hooks run in the order they were added, after the path is normalized
+    /// and before the cache or any source is consulted, so cache keys are
+    /// built from the rewritten path. They see the paths of fetches,
+    /// listings, existence checks and invalidations, but not the prefixes of
+    /// `list_all_files`, `invalidate_prefix` and `scoped`. Values they store
+    /// in the `RequestContext` reach sources through
+    /// `ContentSource::fetch_file_with_ctx` and `list_directory_with_ctx`. A
+    /// hook's error aborts the request and is returned unchanged.
+    pub fn with_request_hook(mut self, hook: Arc<dyn RequestHook>) -> Self {
+        Arc::make_mut(&mut self.request_hooks).push(hook);
+        self
+    }
+
+    /// Report every file fetched from a source to `sink`
+    /// 
+    /// Each fetch a source answers, through `fetch_file` and the methods
+    /// built on it, `fetch_to_writer`, `fetch_stream` or `fetch_file_at`,
+    /// produces one `AuditEvent` with the path, the source, the content's
+    /// SHA-256 and size, and the `AuditPurpose` of the context passed to
+    /// their `_with_ctx` variants. The files
+    /// a virtual file is assembled from are reported, not the virtual file.
+    /// Cache hits aren't reported unless `with_audit_cache_hits` is set. Sink
+    /// errors don't fail the fetch; they are counted in
+    /// `ResolverStats::audit_failures`.
+    pub fn with_audit_sink(mut self, sink: Arc<dyn AuditSink>) -> Self {
+        self.audit_sink = Some(sink);
+        self
+    }
 
-        for source in &self.sources {
-            if let Ok(listing) = source.list_directory(path).await {
-                found_any = true;
-                all_entries.extend(listing.entries);
+    /// Also report `fetch_file` cache hits to the audit sink, with `from_cache` set
+    pub fn with_audit_cache_hits(mut self, enabled: bool) -> Self {
+        self.audit_cache_hits = enabled;
+        self
+    }
+
+    /// Hand an event to the audit sink, counting it if the sink fails
+    fn audit(&self, event: impl FnOnce() -> AuditEvent) {
+        if let Some(sink) = &self.audit_sink {
+            if sink.record(event()).is_err() {
+                self.counters.audit_failures.fetch_add(1, Ordering::Relaxed);
             }
         }
+    }
 
-        if !found_any {
-            return Err(ContentError::NotFound {
-                path: path.to_string(),
-            });
-        }
+    /// Audit a file fetched from `source`
+    fn audit_fetched(&self, path: &str, source: &dyn ContentSource, content: &FileContent, ctx: &RequestContext) {
+        self.audit(|| {
+            let sha256 = content.sha256().to_string();
+            AuditEvent::new(path, source.identifier(), sha256, content.content.len() as u64, ctx)
+        });
+    }
 
-        // Deduplicate by path
-        all_entries.sort_by(|a, b| a.path.cmp(&b.path));
-        all_entries.dedup_by(|a, b| a.path == b.path);
+    /// Wrap sources in the request budget, if there is one, and their concurrency limits
+    fn wrapped(&self, sources: Vec<Arc<dyn ContentSource>>) -> SourceList {
+        let Some(budget) = &self.budget else {
+            return self.source_limits.wrap_all(sources);
+        };
+        let budgeted = sources.into_iter().map(|source| {
+            // A budget set before is replaced, not wrapped again
+            let mut budgeted = BudgetedSource::new(unbudgeted(unlimited(source)), budget.clone());
+            budgeted.managed = true;
+            Arc::new(budgeted) as Arc<dyn ContentSource>
+        });
+        self.source_limits.wrap_all(budgeted.collect())
+    }
 
-        Ok(DirectoryListing {
-            path: path.to_string(),
-            entries: all_entries,
-        })
+    /// What is left of the budget set with `with_request_budget`
+    pub fn budget_status(&self) -> Option<BudgetStatus> {
+        self.budget.as_ref().map(|budget| budget.status())
     }
 
-    /// Check if a file exists in any source
-    pub async fn file_exists(&self, path: &str) -> bool {
-        for source in &self.sources {
-            if source.file_exists(path).await {
-                return true;
-            }
+    /// Ask the non-cacheable sources at the front of the list before the cache
+    /// 
+    /// Files from sources whose `cacheable()` is false are never cached, but
+    /// a cached copy from a later source would still hide a file added to
+    /// them afterwards. With this on, the non-cacheable sources listed before
+    /// the first cacheable one, typically local overrides, are read ahead of
+    /// the cache on every `fetch_file` and `fetch_to_writer`. Off by default.
+    pub fn with_uncacheable_first(mut self, enabled: bool) -> Self {
+        self.uncacheable_first = enabled;
+        self
+    }
+
+    /// How many of `sources` `with_uncacheable_first` reads ahead of the cache
+    fn leading_uncacheable(&self, sources: &[Arc<dyn ContentSource>]) -> usize {
+        if !self.uncacheable_first {
+            return 0;
         }
-        false
+        sources.iter().take_while(|source| !source.cacheable()).count()
     }
 
-    /// Get the list of sources
-    pub fn sources(&self) -> &[Arc<dyn ContentSource>] {
-        &self.sources
+    /// Counters shared by this resolver and its clones
+    pub fn stats(&self) -> ResolverStats {
+        ResolverStats {
+            cache_rejections: self.counters.cache_rejections.load(Ordering::Relaxed),
+            cache_read_errors: self.counters.cache_read_errors.load(Ordering::Relaxed),
+            cache_write_errors: self.counters.cache_write_errors.load(Ordering::Relaxed),
+            audit_failures: self.counters.audit_failures.load(Ordering::Relaxed),
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::cache::MemoryCache;
-    use crate::types::EntryType;
-    use async_trait::async_trait;
-    use bytes::Bytes;
+    /// Background tasks that stop with the resolver
+    /// 
+    /// Spawn watchers, cache janitors and other periodic work here so
+    /// `shutdown` can stop them. Clones share the set; once the last clone
+    /// is dropped without `shutdown`, the tasks are aborted. A task holding a
+    /// clone of the resolver would keep the set alive, so give it a `Weak`
+    /// reference to what it needs instead, as `InvalidationQueue` does.
+    pub fn tasks(&self) -> &TaskSet {
+        &self.tasks
+    }
 
-    struct MockSource {
-        files: Vec<(&'static str, &'static str)>,
+    /// Stop the resolver's background tasks
+    /// 
+    /// Signals every task spawned through `tasks()`, waits up to `grace` for
+    /// them to finish, then aborts the rest and returns how many that was.
+    /// Requests keep working; only the background tasks stop.
+    pub async fn shutdown(&self, grace: Duration) -> usize {
+        self.tasks.shutdown(grace).await
     }
 
-    #[async_trait]
-    impl ContentSource for MockSource {
-        async fn fetch_file(&self, path: &str) -> Result<FileContent> {
-            for (file_path, content) in &self.files {
-                if *file_path == path {
-                    return Ok(FileContent {
-                        content: Bytes::from(*content),
-                        source_path: path.to_string(),
-                        etag: None,
-                    });
-                }
+    /// Read a file from the cache, treating errors as misses unless strict
+    async fn cache_get(&self, cache: &dyn Cache, key: &str, path: &str) -> Result<Option<Bytes>> {
+        match cache.get(key).await.with_path(path) {
+            Ok(cached) => Ok(cached),
+            Err(e) if self.strict_cache => Err(e),
+            Err(e) => {
+                self.counters.cache_read_errors.fetch_add(1, Ordering::Relaxed);
+                self.report_cache_error(&e);
+                Ok(None)
             }
-            Err(ContentError::NotFound {
-                path: path.to_string(),
-            })
         }
+    }
 
-        async fn list_directory(&self, _path: &str) -> Result<DirectoryListing> {
-            Err(ContentError::NotFound {
-                path: "".to_string(),
-            })
+    /// Count and report a failed cache write
+    fn cache_write_failed(&self, error: ContentError, path: &str) {
+        self.counters.cache_write_errors.fetch_add(1, Ordering::Relaxed);
+        self.report_cache_error(&error.with_path(path));
+    }
+
+    fn report_cache_error(&self, error: &ContentError) {
+        if let Some(hook) = &self.cache_error_hook {
+            hook(error);
         }
+    }
 
-        fn identifier(&self) -> String {
-            "mock".to_string()
+    /// Ask the admission policy about caching `content`, counting rejections
+    fn admits(&self, path: &str, content: &FileContent) -> bool {
+        let admitted = self.admission.admit(path, content);
+        if !admitted {
+            self.counters.cache_rejections.fetch_add(1, Ordering::Relaxed);
         }
+        admitted
     }
 
-    #[tokio::test]
-    async fn test_fallback_resolution() {
-        let source1 = Arc::new(MockSource {
-            files: vec![("file1.txt", "from source 1")],
-        });
-        let source2 = Arc::new(MockSource {
-            files: vec![("file2.txt", "from source 2")],
-        });
+    /// Cap the number of entries `list_directory_merged` returns
+    /// 
+    /// The merged listing holds at most `limit` entries however many the
+    /// sources report. This bounds the number of entries, not bytes: each
+    /// source's listing is still received whole, and long names or metadata
+    /// take what they take. The entries kept are the first `limit` by path,
+    /// and the listing is marked `DirectoryListing::truncated` when any were
+    /// dropped. Unlimited by default.
+    pub fn with_entry_limit(mut self, limit: usize) -> Self {
+        self.entry_limit = Some(limit);
+        self
+    }
 
-        let resolver = ResourceResolver::new(vec![
-            source1 as Arc<dyn ContentSource>,
-            source2 as Arc<dyn ContentSource>,
-        ]);
+    /// Cache files streamed by `fetch_to_writer` only up to `limit` bytes
+    /// 
+    /// Filling the cache means holding a copy of the file in memory while
+    /// it is written, which defeats streaming for large files. Once a file
+    /// grows past `limit`, the copy is dropped and the file isn't cached.
+    /// Defaults to 8 MiB; `fetch_file` is not affected.
+    pub fn with_stream_cache_limit(mut self, limit: u64) -> Self {
+        self.stream_cache_limit = limit;
+        self
+    }
 
-        // File from first source
-        let result = resolver.fetch_file("file1.txt").await.unwrap();
-        assert_eq!(result.content, Bytes::from("from source 1"));
+    /// Choose which characters separate path segments
+    /// 
+    /// With `SeparatorPolicy::SlashOrBackslash`, Windows-style paths such as
+    /// `docs\guide.md` resolve and cache like `docs/guide.md`. The default only
+    /// splits on `/`, since `\` is a valid file name character on most backends.
+    pub fn with_separator_policy(mut self, separators: SeparatorPolicy) -> Self {
+        self.separators = separators;
+        self
+    }
 
-        // File from second source
-        let result = resolver.fetch_file("file2.txt").await.unwrap();
-        assert_eq!(result.content, Bytes::from("from source 2"));
+    /// Normalize a path the way every resolver method does
+    /// 
+    /// This is the single place paths are normalized before cache keys are
+    /// built and sources are asked:
+    /// - `\` becomes `/` under `SeparatorPolicy::SlashOrBackslash`
+    /// - leading, trailing and repeated separators are dropped
+    /// - `.` segments are dropped and `..` removes the previous segment
+    /// 
+    /// Fails with `InvalidPath` if the path climbs above the root or contains
+    /// a NUL byte.
+    pub fn normalize_path(&self, path: &str) -> Result<ContentPath> {
+        ContentPath::parse_with(path, self.separators)
+    }
 
-        // File not in any source
-        assert!(matches!(
-            resolver.fetch_file("missing.txt").await,
-            Err(ContentError::NotFound { .. })
-        ));
+    /// Normalize a request's path and run the request hooks on it
+    fn prepare_path(&self, path: &str, ctx: &mut RequestContext) -> Result<ContentPath> {
+        let mut path = self.normalize_path(path)?;
+        for hook in self.request_hooks.iter() {
+            let rewritten = hook.before_fetch(path.as_str(), ctx)?;
+            if rewritten != path.as_str() {
+                path = self.normalize_path(&rewritten)?;
+            }
+        }
+        Ok(path)
     }
 
-    #[tokio::test]
-    async fn test_caching() {
-        let source = Arc::new(MockSource {
-            files: vec![("file.txt", "content")],
-        });
-        let cache = Arc::new(MemoryCache::new());
+    /// `prepare_path` for requests that don't pass a context on
+    fn hooked_path(&self, path: &str) -> Result<ContentPath> {
+        self.prepare_path(path, &mut RequestContext::new())
+    }
 
-        let resolver = ResourceResolver::with_cache(
-            vec![source as Arc<dyn ContentSource>],
-            cache.clone(),
-        );
+    /// Fetch a file by path, searching sources in order
+    /// 
+    /// Returns the first successful match, or NotFound if none match
+    /// 
+    /// Cache hits have `source_path` set to `cache:{path}`. The ETag, MIME
+    /// type and modification time the source reported are kept next to the
+    /// cached content and restored on a hit.
+    pub async fn fetch_file(&self, path: impl AsRef<str>) -> Result<FileContent> {
+        self.fetch_file_with(path, &FetchOptions::default()).await
+    }
 
-        // First fetch - from source
-        let result = resolver.fetch_file("file.txt").await.unwrap();
-        assert_eq!(result.content, Bytes::from("content"));
+    /// Fetch a file and deserialize it as JSON
+    pub async fn fetch_json<T: DeserializeOwned>(&self, path: impl AsRef<str>) -> Result<T> {
+        self.fetch_file(path).await?.json()
+    }
 
-        // Check cache was populated
-        assert!(cache.contains("file:file.txt").await);
+    /// Fetch a file and deserialize it as MessagePack
+    #[cfg(feature = "msgpack")]
+    pub async fn fetch_msgpack<T: DeserializeOwned>(&self, path: impl AsRef<str>) -> Result<T> {
+        self.fetch_file(path).await?.msgpack()
+    }
 
-        // Second fetch - from cache
-        let result = resolver.fetch_file("file.txt").await.unwrap();
-        assert_eq!(result.content, Bytes::from("content"));
-        assert_eq!(result.source_path, "cache:file.txt");
+    /// Fetch a file and deserialize it as CBOR
+    #[cfg(feature = "cbor")]
+    pub async fn fetch_cbor<T: DeserializeOwned>(&self, path: impl AsRef<str>) -> Result<T> {
+        self.fetch_file(path).await?.cbor()
+    }
+
+    /// Fetch a file and deserialize it as JSON, MessagePack or CBOR
+    /// 
+    /// The format is guessed from the leading bytes, see `FileContent::decode`,
+    /// and returned with the value, so a path can move between formats
+    /// without its readers changing:
+    /// 
+    /// ```no_run
+    /// # async fn run(resolver: content_resolver::ResourceResolver) -> content_resolver::Result<()> {
+    /// let (settings, format): (serde_json::Value, _) = resolver.fetch_auto("settings").await?;
+    /// println!("settings are stored as {}", format);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn fetch_auto<T: DeserializeOwned>(
+        &self,
+        path: impl AsRef<str>,
+    ) -> Result<(T, DataFormat)> {
+        self.fetch_file(path).await?.decode()
     }
+
+    /// Fetch a file as it was at `version`, e.g. to audit what it said back then
+    /// 
+    /// ```no_run
+    /// # async fn run(resolver: content_resolver::ResourceResolver) -> content_resolver::Result<()> {
+    /// use content_resolver::ContentVersion;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    /// 
+    /// let march_first = UNIX_EPOCH + Duration::from_secs(1_709_251_200);
+    /// let version = ContentVersion::Timestamp(march_first);
+    /// let limits = resolver.fetch_file_at("policies/limits.json", &version).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    /// 
+    /// Sources are tried in order like `fetch_file`. Those that can't read
+    /// past versions are skipped, and if none can, the call fails with
+    /// `Unsupported`. Full commit SHAs, the only versions that can't change,
+    /// see `ContentVersion::is_immutable`, are cached under
+    /// `file@{version}:{path}`, which `invalidate` leaves alone; branches,
+    /// tags and timestamps are always read from the sources. Virtual files
+    /// don't apply.
+    pub async fn fetch_file_at(
+        &self,
+        path: impl AsRef<str>,
+        version: &ContentVersion,
+    ) -> Result<FileContent> {
+        self.fetch_file_at_with_ctx(path, version, &mut RequestContext::new())
+            .await
+    }
+
+    /// Fetch a past version like `fetch_file_at`, in a caller-provided request context
+    /// 
+    /// The request hooks run with `ctx`, and audit events carry its
+    /// `AuditPurpose`.
+    pub async fn fetch_file_at_with_ctx(
+        &self,
+        path: impl AsRef<str>,
+        version: &ContentVersion,
+        ctx: &mut RequestContext,
+    ) -> Result<FileContent> {
+        let path = self.prepare_path(path.as_ref(), ctx)?;
+        let path = path.as_str();
+        let cache = self.cache.as_ref().filter(|_| version.is_immutable());
+        let cache_key = self.scoped_key(format!("file@{}:{}", version.key_label(), path));
+        let meta_key = self.scoped_key(format!("meta@{}:{}", version.key_label(), path));
+
+        if let Some(cache) = cache {
+            if let Some(cached) = self.cache_get(cache.as_ref(), &cache_key, path).await? {
+                let meta = cache.get(&meta_key).await.ok().flatten();
+                return Ok(cached_file(cached, meta, path));
+            }
+        }
+
+        let mut unsupported = None;
+        let mut not_found = false;
+        let mut last_error = None;
+        for source in self.live_sources().iter() {
+            match source.fetch_file_at(path, version).await {
+                Ok(content) => {
+                    self.audit_fetched(path, source.as_ref(), &content, ctx);
+                    let cacheable = source.cacheable() && self.admits(path, &content);
+                    if let Some(cache) = cache.filter(|_| cacheable) {
+                        let written = match cache.set(&cache_key, content.content.clone()).await {
+                            Ok(()) => match CachedMeta::of(&content) {
+                                Some(meta) => cache.set(&meta_key, meta.encode()).await,
+                                None => Ok(()),
+                            },
+                            Err(e) => Err(e),
+                        };
+                        if let Err(e) = written {
+                            self.cache_write_failed(e, path);
+                        }
+                    }
+                    return Ok(content);
+                }
+                Err(e) if e.is_unsupported() => {
+                    unsupported.get_or_insert(e);
+                }
+                Err(e) if e.is_not_found() => not_found = true,
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        match (last_error, not_found, unsupported) {
+            (Some(error), _, _) => Err(error),
+            (None, false, Some(unsupported)) => Err(unsupported),
+            _ => Err(ContentError::NotFound {
+                path: path.to_string(),
+            }),
+        }
+    }
+
+    /// Fetch a file like `fetch_file`, passing per-call options to the sources
+    /// 
+    /// Use it to tag one request with a correlation ID:
+    /// 
+    /// ```no_run
+    /// # async fn run(resolver: content_resolver::ResourceResolver) -> content_resolver::Result<()> {
+    /// use content_resolver::FetchOptions;
+    /// 
+    /// let options = FetchOptions::new().with_request_id("req-42");
+    /// let file = resolver.fetch_file_with("config.json", &options).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    /// 
+    /// Headers only reach HTTP sources such as `GitHubSource`; for other
+    /// sources, and for cache hits, the options have no effect.
+    pub async fn fetch_file_with(
+        &self,
+        path: impl AsRef<str>,
+        options: &FetchOptions,
+    ) -> Result<FileContent> {
+        self.fetch_file_with_ctx(path, options, &mut RequestContext::new())
+            .await
+    }
+
+    /// Fetch a file like `fetch_file_with`, in a caller-provided request context
+    /// 
+    /// The request hooks run with `ctx`, and sources are handed it once they
+    /// are done, so values the caller put in beforehand reach both:
+    /// 
+    /// ```no_run
+    /// # async fn run(resolver: content_resolver::ResourceResolver) -> content_resolver::Result<()> {
+    /// use content_resolver::{FetchOptions, RequestContext};
+    /// 
+    /// struct Tenant(&'static str);
+    /// 
+    /// let mut ctx = RequestContext::new();
+    /// ctx.insert(Tenant("acme"));
+    /// let file = resolver
+    ///     .fetch_file_with_ctx("config.json", &FetchOptions::default(), &mut ctx)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    /// 
+    /// Cache hits are served without asking the sources, whatever `ctx`
+    /// holds; have a hook rewrite the path if content depends on it.
+    pub async fn fetch_file_with_ctx(
+        &self,
+        path: impl AsRef<str>,
+        options: &FetchOptions,
+        ctx: &mut RequestContext,
+    ) -> Result<FileContent> {
+        let path = self.prepare_path(path.as_ref(), ctx)?;
+        let mut content = self.fetch_normalized(path.as_str(), options, ctx).await?;
+        if let Some(content_type) = self.mapped_content_type(&path) {
+            content.content_type = Some(content_type);
+        }
+        Ok(content)
+    }
+
+    /// Fetch a normalized path through the cache and the sources
+    async fn fetch_normalized(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+        ctx: &RequestContext,
+    ) -> Result<FileContent> {
+        self.record_dependency(path);
+        let cache_key = self.file_key(path);
+        let sources = self.live_sources();
+
+        let leading = match self.virtual_rule(path) {
+            Some(_) => 0,
+            None => self.leading_uncacheable(&sources),
+        };
+        let mut leading_error = None;
+        if leading > 0 {
+            match self.first_source_with(&sources[..leading], path, options, ctx).await {
+                Ok((content, _)) => return Ok(self.served_uncacheable(content)),
+                Err(e) if e.is_not_found() => {}
+                Err(e) => leading_error = Some(e),
+            }
+        }
+
+        let source_first = match self.read_mode {
+            ReadMode::SourceFirstThenCache { timeout } if self.cache.is_some() => Some(timeout),
+            _ => None,
+        };
+
+        // Check cache first if enabled
+        if let Some(cache) = self.cache.as_ref().filter(|_| source_first.is_none()) {
+            if let Some(cached) = self.cache_get(cache.as_ref(), &cache_key, path).await? {
+                if !(cached.is_empty() && self.treat_empty_as_not_found) {
+                    let meta = cache.get(&self.file_meta_key(path)).await.ok().flatten();
+                    let content = cached_file(cached, meta, path);
+                    if self.audit_cache_hits {
+                        self.audit(|| {
+                            let sha256 = content.sha256().to_string();
+                            let mut event =
+                                AuditEvent::new(path, "cache".to_string(), sha256, content.content.len() as u64, ctx);
+                            event.from_cache = true;
+                            event
+                        });
+                    }
+                    return Ok(content);
+                }
+            }
+        }
+
+        let fetch = async {
+            match self.virtual_rule(path) {
+                Some(rule) => self.assemble_virtual(rule, path).await,
+                None => {
+                    let rest = &sources[leading..];
+                    match self.first_source_with(rest, path, options, ctx).await {
+                        Err(e) if e.is_not_found() => Err(leading_error.unwrap_or(e)),
+                        fetched => fetched,
+                    }
+                }
+            }
+        };
+        let fetched = match source_first {
+            None => fetch.await,
+            Some(timeout) => {
+                let mut fetch = std::pin::pin!(fetch);
+                match tokio::time::timeout(timeout, &mut fetch).await {
+                    Ok(Err(e)) if !e.is_not_found() => {
+                        match self.cached_fallback(&cache_key, path).await? {
+                            Some(cached) => return Ok(cached),
+                            None => Err(e),
+                        }
+                    }
+                    Ok(fetched) => fetched,
+                    Err(_) => match self.cached_fallback(&cache_key, path).await? {
+                        Some(cached) => return Ok(cached),
+                        None => fetch.await,
+                    },
+                }
+            }
+        };
+        let (content, cacheable) = match fetched {
+            Ok(content) => content,
+            Err(e) if self.serve_stale_on_error && e.is_retryable() => {
+                return match self.fetch_stale(&cache_key, path).await {
+                    Some(stale) => Ok(stale),
+                    None => Err(e),
+                };
+            }
+            Err(e) => return Err(e),
+        };
+        if !cacheable {
+            return Ok(self.served_uncacheable(content));
+        }
+        // Cache the result if caching is enabled
+        if let Some(cache) = self.cache.as_ref().filter(|_| self.admits(path, &content)) {
+            let written = match cache.set(&cache_key, content.content.clone()).await {
+                Ok(()) => match CachedMeta::of(&content) {
+                    Some(meta) => cache.set(&self.file_meta_key(path), meta.encode()).await,
+                    None => cache.remove(&self.file_meta_key(path)).await,
+                },
+                Err(e) => Err(e),
+            };
+            if let Err(e) = written {
+                self.cache_write_failed(e, path);
+            }
+        }
+        Ok(content)
+    }
+
+    /// The cached copy served when the sources lose a source-first race
+    /// 
+    /// A current copy is preferred over an expired one.
+    async fn cached_fallback(&self, cache_key: &str, path: &str) -> Result<Option<FileContent>> {
+        let Some(cache) = &self.cache else {
+            return Ok(None);
+        };
+        if let Some(cached) = self.cache_get(cache.as_ref(), cache_key, path).await? {
+            if !(cached.is_empty() && self.treat_empty_as_not_found) {
+                let meta = cache.get(&self.file_meta_key(path)).await.ok().flatten();
+                return Ok(Some(cached_file(cached, meta, path)));
+            }
+        }
+        Ok(self.fetch_stale(cache_key, path).await)
+    }
+
+    /// Read a cached copy regardless of its age, ignoring cache errors
+    async fn fetch_stale(&self, cache_key: &str, path: &str) -> Option<FileContent> {
+        let cache = self.cache.as_ref()?;
+        let cached = cache.get_stale(cache_key).await.ok()??;
+        if cached.is_empty() && self.treat_empty_as_not_found {
+            return None;
+        }
+
+        let meta = cache.get_stale(&self.file_meta_key(path)).await.ok().flatten();
+        let mut content = cached_file(cached, meta, path);
+        content.stale = true;
+        Some(content)
+    }
+
+    /// Fetch a normalized path from the first source that has it, bypassing the cache
+    /// 
+    /// Virtual files are assembled instead.
+    async fn fetch_from_sources(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+        ctx: &RequestContext,
+    ) -> Result<FileContent> {
+        let fetched = match self.virtual_rule(path) {
+            Some(rule) => self.assemble_virtual(rule, path).await,
+            None => self.first_source_with(&self.live_sources(), path, options, ctx).await,
+        };
+        fetched.map(|(content, _)| content)
+    }
+
+    /// Note that a virtual file being assembled read uncacheable content
+    fn served_uncacheable(&self, content: FileContent) -> FileContent {
+        if let Some(assembly) = &self.assembly {
+            assembly.lock().unwrap_or_else(|e| e.into_inner()).uncacheable = true;
+        }
+        content
+    }
+
+    /// Fetch a normalized path from the first of `sources` that has it
+    /// 
+    /// Also returns whether that source allows caching the file.
+    async fn first_source_with(
+        &self,
+        sources: &[Arc<dyn ContentSource>],
+        path: &str,
+        options: &FetchOptions,
+        ctx: &RequestContext,
+    ) -> Result<(FileContent, bool)> {
+        // Try each source in order
+        let mut last_error = None;
+
+        for source in sources {
+            match fetch_from(source.as_ref(), path, options, ctx).await {
+                Ok(content) if content.content.is_empty() && self.treat_empty_as_not_found => {
+                    continue;
+                }
+                Ok(content) => {
+                    self.audit_fetched(path, source.as_ref(), &content, ctx);
+                    return Ok((content, source.cacheable()));
+                }
+                Err(e) if e.is_not_found() => {
+                    // Continue to next source on not found
+                    continue;
+                }
+                Err(e) => {
+                    // Store other errors but continue trying
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        // If we got a non-NotFound error, return it
+        if let Some(error) = last_error {
+            return Err(error);
+        }
+
+        // Nothing found in any source
+        Err(ContentError::NotFound {
+            path: path.to_string(),
+        })
+    }
+
+    /// The first virtual file rule matching a normalized path
+    fn virtual_rule(&self, path: &str) -> Option<&VirtualFileRule> {
+        self.virtual_files.iter().find(|rule| rule.matches(path))
+    }
+
+    /// Run a virtual file's assembler and record the files it read
+    /// 
+    /// The assembled file may be cached unless it read uncacheable content.
+    async fn assemble_virtual(
+        &self,
+        rule: &VirtualFileRule,
+        path: &str,
+    ) -> Result<(FileContent, bool)> {
+        if self.virtual_depth >= MAX_VIRTUAL_DEPTH {
+            return Err(ContentError::InvalidConfig {
+                message: format!(
+                    "virtual files nest more than {} deep at {}",
+                    MAX_VIRTUAL_DEPTH, path
+                ),
+            });
+        }
+
+        let assembly = Arc::new(Mutex::new(Assembly::default()));
+        // Assemble from the sources of this request, even if they are replaced meanwhile
+        let sources = self.live_sources();
+        let assembling = Self {
+            configured: without_limits(&sources),
+            sources: Arc::new(RwLock::new(sources)),
+            virtual_depth: self.virtual_depth + 1,
+            assembly: Some(assembly.clone()),
+            ..self.clone()
+        };
+        let content = rule.assemble(&assembling, path).await.with_path(path)?;
+
+        let Assembly {
+            dependencies,
+            uncacheable,
+        } = std::mem::take(&mut *assembly.lock().unwrap_or_else(|e| e.into_inner()));
+        if uncacheable {
+            return Ok((FileContent::new(content, format!("virtual:{}", path)), false));
+        }
+        if let Some(cache) = &self.cache {
+            for dependency in dependencies {
+                if let Err(e) = self.add_dependent(cache.as_ref(), &dependency, path).await {
+                    self.cache_write_failed(e, &dependency);
+                }
+            }
+        }
+        Ok((FileContent::new(content, format!("virtual:{}", path)), true))
+    }
+
+    /// Note that the virtual file being assembled read `path`, a file or a listed directory
+    fn record_dependency(&self, path: &str) {
+        if let Some(assembly) = &self.assembly {
+            assembly.lock().unwrap_or_else(|e| e.into_inner()).dependencies.insert(path.to_string());
+        }
+    }
+
+    /// Note in the cache that `dependent` was built from `path`
+    /// 
+    /// Serialized with the other updates of this resolver and its clones, so
+    /// concurrent assemblies don't drop each other's entries. Resolvers in
+    /// other processes sharing the cache aren't coordinated with.
+    async fn add_dependent(&self, cache: &dyn Cache, path: &str, dependent: &str) -> Result<()> {
+        let key = self.scoped_key(dependents_key(path));
+        let _guard = self.dependents_lock.lock().await;
+        let mut dependents = decode_dependents(cache.get(&key).await?);
+        if dependents.insert(dependent.to_string()) {
+            let encoded = dependents.into_iter().collect::<Vec<_>>().join("\n");
+            cache.set(&key, encoded.into()).await?;
+        }
+        Ok(())
+    }
+
+    /// Fetch a path from every source, for comparing what each one holds
+    /// 
+    /// Returns each source's identifier with its result, NotFound and other
+    /// errors included, in source order. The cache, virtual files and
+    /// `with_empty_as_not_found` are bypassed: results are what the sources
+    /// answered. Sources are asked concurrently.
+    pub async fn fetch_all(&self, path: impl AsRef<str>) -> Vec<(String, Result<FileContent>)> {
+        let path = path.as_ref();
+        let options = FetchOptions::default();
+        // An invalid path is reported by every source's entry
+        let sources = self.live_sources();
+        let fetches = sources.iter().map(|source| async {
+            let mut ctx = RequestContext::new();
+            let result = match self.prepare_path(path, &mut ctx) {
+                Ok(path) => fetch_from(source.as_ref(), path.as_str(), &options, &ctx).await,
+                Err(e) => Err(e),
+            };
+            (source.identifier(), result)
+        });
+        futures::future::join_all(fetches).await
+    }
+
+    /// Check whether the cached copy of a file differs from what the sources serve
+    /// 
+    /// Asks the sources for the file's metadata with `stat`, bypassing the
+    /// cache, and compares its ETag with the cached one, or its size when
+    /// either ETag is unknown. Only when neither tells them apart is the file
+    /// fetched and SHA-256 digests compared. The cache is left untouched. A
+    /// cached file that no source has any more counts as stale. Returns
+    /// false when the file isn't cached or there is no cache.
+    pub async fn is_cache_stale(&self, path: impl AsRef<str>) -> Result<bool> {
+        self.is_cache_stale_with_ctx(path, &mut RequestContext::new()).await
+    }
+
+    /// Check a cached copy like `is_cache_stale`, in a caller-provided request context
+    /// 
+    /// As with `fetch_file_with_ctx`, the request hooks run with `ctx`, and
+    /// sources are handed it when the file is fetched for comparison.
+    pub async fn is_cache_stale_with_ctx(
+        &self,
+        path: impl AsRef<str>,
+        ctx: &mut RequestContext,
+    ) -> Result<bool> {
+        let path = self.prepare_path(path.as_ref(), ctx)?;
+        let path = path.as_str();
+        let ctx = &*ctx;
+        let Some(cache) = &self.cache else {
+            return Ok(false);
+        };
+        let Some(cached) = cache.get(&self.file_key(path)).await.with_path(path)? else {
+            return Ok(false);
+        };
+        let differs = |current: FileContent| current.sha256() != sha256_hex_of(&cached);
+
+        // Virtual files have no metadata of their own to compare
+        if self.virtual_rule(path).is_some() {
+            return match self.fetch_from_sources(path, &FetchOptions::default(), ctx).await {
+                Ok(current) => Ok(differs(current)),
+                Err(e) if e.is_not_found() => Ok(true),
+                Err(e) => Err(e),
+            };
+        }
+
+        let cached_etag = cache
+            .get(&self.file_meta_key(path))
+            .await
+            .ok()
+            .flatten()
+            .and_then(|meta| serde_json::from_slice::<CachedMeta>(&meta).ok())
+            .and_then(|meta| meta.etag);
+        let mut last_error = None;
+
+        for source in self.live_sources().iter() {
+            let current = match source
+                .stat(path)
+                .await
+                .with_source(&source.identifier())
+                .with_path(path)
+            {
+                Ok(current) => current,
+                Err(e) if e.is_not_found() => continue,
+                Err(e) => {
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            if current.size == Some(0) && self.treat_empty_as_not_found {
+                continue;
+            }
+            if !current.is_file() {
+                return Ok(true);
+            }
+            if let (Some(current), Some(cached)) = (&current.etag, &cached_etag) {
+                return Ok(current != cached);
+            }
+            if current.size.is_some_and(|size| size != cached.len() as u64) {
+                return Ok(true);
+            }
+            let options = FetchOptions::default();
+            return fetch_from(source.as_ref(), path, &options, ctx).await.map(differs);
+        }
+
+        match last_error {
+            Some(error) => Err(error),
+            None => Ok(true),
+        }
+    }
+
+    /// Fetch a tar, gzipped tar or zip archive and open it as a source
+    /// 
+    /// The archive is fetched like any file, so its bytes are cached, and
+    /// unpacked into memory on every call. Wrap the result in a resolver of
+    /// its own to read an archived skill like a loose one:
+    /// 
+    /// ```no_run
+    /// # async fn run(resolver: content_resolver::ResourceResolver) -> content_resolver::Result<()> {
+    /// use content_resolver::{ResourceResolver, SkillProvider};
+    /// use std::sync::Arc;
+    /// 
+    /// let archive = resolver.fetch_archive("skills/pdf/skill.tar.gz").await?;
+    /// let unpacked = Arc::new(ResourceResolver::new(vec![Arc::new(archive)]));
+    /// let skills = SkillProvider::new(unpacked, String::new());
+    /// # Ok(())
+    /// # }
+    /// ```
+    /// 
+    /// Fails like `fetch_file`, and like `ArchiveSource::new` if the file
+    /// isn't a readable archive.
+    #[cfg(feature = "archive")]
+    pub async fn fetch_archive(&self, path: impl AsRef<str>) -> Result<ArchiveSource> {
+        let path = self.normalize_path(path.as_ref())?;
+        let file = self.fetch_file(path.as_str()).await?;
+        ArchiveSource::new(format!("archive({})", path), file.content).with_path(path.as_str())
+    }
+
+    /// Stream a file into `writer`, searching sources in order
+    /// 
+    /// Returns the number of bytes written. Sources that don't have the file
+    /// are skipped before anything is written; once a source starts writing,
+    /// its errors are returned without trying further sources. With a cache
+    /// configured, cache hits are written from the cache and fetched content
+    /// is also buffered into it, unless it is larger than
+    /// `with_stream_cache_limit`.
+    pub async fn fetch_to_writer<W>(&self, path: impl AsRef<str>, writer: W) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        self.fetch_to_writer_with_ctx(path, writer, &mut RequestContext::new())
+            .await
+    }
+
+    /// Stream a file into `writer` like `fetch_to_writer`, in a caller-provided request context
+    /// 
+    /// The request hooks run with `ctx`, and audit events carry its
+    /// `AuditPurpose`.
+    pub async fn fetch_to_writer_with_ctx<W>(
+        &self,
+        path: impl AsRef<str>,
+        writer: W,
+        ctx: &mut RequestContext,
+    ) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        let path = self.prepare_path(path.as_ref(), ctx)?;
+        self.stream_to_writer(&path, writer, None, true, ctx).await
+    }
+
+    /// Stream a file into `writer` like `fetch_to_writer`, checking its digest on the way
+    /// 
+    /// `hasher` is fed every chunk as it is written, and its final digest is
+    /// compared with `expected`, hex encoded, ignoring case. On a mismatch
+    /// this fails with `IntegrityMismatch` after the content has been
+    /// written, so discard what `writer` received; nothing is cached. A
+    /// cached copy that doesn't match is skipped in favour of the sources.
+    /// 
+    /// ```no_run
+    /// # async fn run(resolver: content_resolver::ResourceResolver, expected: &str) -> content_resolver::Result<()> {
+    /// use content_resolver::integrity::Sha256;
+    /// 
+    /// let mut file = tokio::fs::File::create("model.bin").await?;
+    /// resolver
+    ///     .fetch_to_writer_verified("assets/model.bin", &mut file, Sha256::default(), expected)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn fetch_to_writer_verified<W, D>(
+        &self,
+        path: impl AsRef<str>,
+        writer: W,
+        hasher: D,
+        expected: &str,
+    ) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin + Send,
+        D: DynDigest + Send + 'static,
+    {
+        let verify = Verify {
+            hasher: Box::new(hasher),
+            expected,
+        };
+        let mut ctx = RequestContext::new();
+        let path = self.prepare_path(path.as_ref(), &mut ctx)?;
+        self.stream_to_writer(&path, writer, Some(verify), true, &ctx).await
+    }
+
+    /// Stream a path the request hooks have already seen into `writer`
+    async fn stream_to_writer<W>(
+        &self,
+        path: &ContentPath,
+        mut writer: W,
+        mut verify: Option<Verify<'_>>,
+        fill_cache: bool,
+        ctx: &RequestContext,
+    ) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        let path = path.as_str();
+        let cache_key = self.file_key(path);
+
+        // Virtual files only exist whole
+        if self.virtual_rule(path).is_some() {
+            let file = self.fetch_normalized(path, &FetchOptions::default(), ctx).await?;
+            if let Some(verify) = &mut verify {
+                verify.hasher.update(&file.content);
+                verify.check(path)?;
+            }
+            writer.write_all(&file.content).await.map_err(ContentError::from).with_path(path)?;
+            writer.flush().await.map_err(ContentError::from).with_path(path)?;
+            return Ok(file.content.len() as u64);
+        }
+
+        let sources = self.live_sources();
+        let leading = self.leading_uncacheable(&sources);
+        if leading == 0 {
+            if let Some(written) = self.write_cached(path, &mut writer, &mut verify).await? {
+                return Ok(written);
+            }
+        }
+
+        let mut last_error = None;
+
+        for (i, source) in sources.iter().enumerate() {
+            if i > 0 && i == leading {
+                if let Some(written) = self.write_cached(path, &mut writer, &mut verify).await? {
+                    return Ok(written);
+                }
+            }
+            let mut tee = TeeWriter {
+                inner: &mut writer,
+                copy: self.cache.as_ref().filter(|_| fill_cache && source.cacheable()).map(|_| BytesMut::new()),
+                copy_limit: self.stream_cache_limit,
+                hasher: verify.as_mut().map(|verify| &mut *verify.hasher),
+                audit: self.audit_sink.as_ref().map(|_| Sha256::default()),
+                written: 0,
+            };
+
+            match source
+                .fetch_to_writer(path, &mut tee)
+                .await
+                .with_source(&source.identifier())
+                .with_path(path)
+            {
+                Ok(written) => {
+                    let copy = tee.copy.take();
+                    let audit = tee.audit.take();
+                    writer.flush().await.map_err(ContentError::from).with_path(path)?;
+                    if let Some(verify) = &mut verify {
+                        verify.check(path).with_source(&source.identifier())?;
+                    }
+                    if let Some(hasher) = audit {
+                        self.audit(|| {
+                            let sha256 = format!("{:x}", sha2::Digest::finalize(hasher));
+                            AuditEvent::new(path, source.identifier(), sha256, written, ctx)
+                        });
+                    }
+                    if let (Some(cache), Some(copy)) = (&self.cache, copy) {
+                        // Streaming doesn't see the source's metadata
+                        let file = FileContent::new(copy.freeze(), source.identifier());
+                        if self.admits(path, &file) {
+                            let written = match cache.set(&cache_key, file.content).await {
+                                Ok(()) => cache.remove(&self.file_meta_key(path)).await,
+                                Err(e) => Err(e),
+                            };
+                            if let Err(e) = written {
+                                self.cache_write_failed(e, path);
+                            }
+                        }
+                    }
+                    return Ok(written);
+                }
+                Err(e) if tee.written > 0 => return Err(e),
+                Err(e) if e.is_not_found() => continue,
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        if let Some(error) = last_error {
+            return Err(error);
+        }
+
+        Err(ContentError::NotFound {
+            path: path.to_string(),
+        })
+    }
+
+    /// Stream a file in chunks, holding at most about `buffer` bytes in memory
+    /// 
+    /// The download runs in a task of `tasks()` and pauses whenever `buffer`
+    /// bytes are waiting to be read, so a slow consumer holds the source back
+    /// rather than the file piling up in memory. Dropping the stream stops the
+    /// download, and so does `shutdown` or dropping the last clone of the
+    /// resolver, which ends the stream with an error. Chunks are at most
+    /// 16 KiB, or `buffer` if that is smaller.
+    /// 
+    /// Resolves like `fetch_to_writer`, except that fetched content isn't
+    /// copied into the cache, since that would hold the whole file; cache hits
+    /// are still served. Returns once the first chunk is ready, so failures
+    /// before any content, like NotFound, come from here and not the stream.
+    /// 
+    /// ```no_run
+    /// # async fn run(resolver: content_resolver::ResourceResolver) -> content_resolver::Result<()> {
+    /// use futures::StreamExt;
+    /// use tokio::io::AsyncWriteExt;
+    /// 
+    /// let mut stream = resolver.fetch_stream("assets/model.bin", 64 * 1024).await?;
+    /// let mut file = tokio::fs::File::create("model.bin").await?;
+    /// while let Some(chunk) = stream.next().await {
+    ///     file.write_all(&chunk?).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn fetch_stream(&self, path: impl AsRef<str>, buffer: usize) -> Result<ContentStream> {
+        self.fetch_stream_with_ctx(path, buffer, RequestContext::new()).await
+    }
+
+    /// Stream a file like `fetch_stream`, in a caller-provided request context
+    /// 
+    /// The request hooks run with `ctx` before this returns, and audit events
+    /// carry its `AuditPurpose`. The context is taken by value, since the
+    /// download outlives the call.
+    pub async fn fetch_stream_with_ctx(
+        &self,
+        path: impl AsRef<str>,
+        buffer: usize,
+        mut ctx: RequestContext,
+    ) -> Result<ContentStream> {
+        let path = self.prepare_path(path.as_ref(), &mut ctx)?;
+        let (writer, stream) = ContentStream::channel(buffer);
+        // The task's copy gets a set of its own; sharing this one would keep
+        // it, and so the task, alive after the last resolver is dropped
+        let resolver = Self {
+            tasks: Arc::default(),
+            ..self.clone()
+        };
+        self.tasks.spawn(|_signal| async move {
+            let mut writer = writer;
+            let outcome = resolver.stream_to_writer(&path, &mut writer, None, false, &ctx).await;
+            writer.finish(outcome).await;
+        });
+        stream.started().await
+    }
+
+    /// Write the cached copy of a file to `writer`, if there is one that verifies
+    async fn write_cached<W>(
+        &self,
+        path: &str,
+        writer: &mut W,
+        verify: &mut Option<Verify<'_>>,
+    ) -> Result<Option<u64>>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        let Some(cache) = &self.cache else {
+            return Ok(None);
+        };
+        let Some(cached) = self.cache_get(cache.as_ref(), &self.file_key(path), path).await? else {
+            return Ok(None);
+        };
+        if let Some(verify) = verify {
+            verify.hasher.update(&cached);
+            if verify.check(path).is_err() {
+                return Ok(None);
+            }
+        }
+        writer.write_all(&cached).await.map_err(ContentError::from).with_path(path)?;
+        writer.flush().await.map_err(ContentError::from).with_path(path)?;
+        Ok(Some(cached.len() as u64))
+    }
+
+    /// List directory contents, searching sources in order
+    /// 
+    /// Returns the first successful match. A source that fails with any error
+    /// (not just NotFound) is skipped; the last non-NotFound error is returned
+    /// only if no source can list the directory. `NotADirectory` falls through
+    /// like NotFound and is returned if no source reports anything else.
+    /// Sources without the `listing` capability aren't asked, here or in the
+    /// other listing methods.
+    pub async fn list_directory(&self, path: impl AsRef<str>) -> Result<DirectoryListing> {
+        self.list_directory_with(path, &FetchOptions::default()).await
+    }
+
+    /// List a directory like `list_directory`, passing per-call options to the sources
+    /// 
+    /// As with `fetch_file_with`, only HTTP sources send the headers.
+    pub async fn list_directory_with(
+        &self,
+        path: impl AsRef<str>,
+        options: &FetchOptions,
+    ) -> Result<DirectoryListing> {
+        self.list_directory_with_ctx(path, options, &mut RequestContext::new())
+            .await
+    }
+
+    /// List a directory like `list_directory_with`, in a caller-provided request context
+    /// 
+    /// As with `fetch_file_with_ctx`, the request hooks run with `ctx` and
+    /// sources are handed it.
+    pub async fn list_directory_with_ctx(
+        &self,
+        path: impl AsRef<str>,
+        options: &FetchOptions,
+        ctx: &mut RequestContext,
+    ) -> Result<DirectoryListing> {
+        let path = self.prepare_path(path.as_ref(), ctx)?;
+        let path = path.as_str();
+        self.record_dependency(path);
+        let mut last_error = None;
+        let mut not_a_directory = None;
+
+        for source in self.listing_sources() {
+            match source
+                .list_directory_with_ctx(path, options, ctx)
+                .await
+                .with_source(&source.identifier())
+                .with_path(path)
+            {
+                Ok(listing) => return Ok(listing),
+                Err(e) if e.is_not_found() => {
+                    continue;
+                }
+                Err(e) if e.kind() == ErrorKind::NotADirectory => {
+                    not_a_directory.get_or_insert(e);
+                }
+                Err(e) => {
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        if let Some(error) = last_error.or(not_a_directory) {
+            return Err(error);
+        }
+
+        Err(ContentError::NotFound {
+            path: path.to_string(),
+        })
+    }
+
+    /// List one page of a directory, searching sources in order
+    /// 
+    /// Sources are tried like `list_directory`, each through
+    /// `ContentSource::list_directory_paginated`. The returned cursor continues
+    /// the listing; since cursors are positions by name, a later page still
+    /// lines up if it ends up served by a different source.
+    pub async fn list_directory_paginated(
+        &self,
+        path: impl AsRef<str>,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<(DirectoryListing, Option<String>)> {
+        let path = self.hooked_path(path.as_ref())?;
+        let path = path.as_str();
+        self.record_dependency(path);
+        let mut last_error = None;
+        let mut not_a_directory = None;
+
+        for source in self.listing_sources() {
+            match source
+                .list_directory_paginated(path, cursor.clone(), page_size)
+                .await
+                .with_source(&source.identifier())
+                .with_path(path)
+            {
+                Ok(page) => return Ok(page),
+                Err(e) if e.is_not_found() => {
+                    continue;
+                }
+                Err(e) if e.kind() == ErrorKind::NotADirectory => {
+                    not_a_directory.get_or_insert(e);
+                }
+                Err(e) => {
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        if let Some(error) = last_error.or(not_a_directory) {
+            return Err(error);
+        }
+
+        Err(ContentError::NotFound {
+            path: path.to_string(),
+        })
+    }
+
+    /// List every entry below a directory, searching sources in order
+    ///
+    /// Returns the first successful recursive listing, sorted by path
+    pub async fn list_directory_recursive(&self, path: impl AsRef<str>) -> Result<Vec<DirectoryEntry>> {
+        let path = self.hooked_path(path.as_ref())?;
+        let path = path.as_str();
+        let mut last_error = None;
+
+        for source in self.listing_sources() {
+            match source
+                .list_directory_recursive(path)
+                .await
+                .with_source(&source.identifier())
+                .with_path(path)
+            {
+                Ok(entries) => return Ok(entries),
+                Err(e) if e.is_not_found() => {
+                    continue;
+                }
+                Err(e) => {
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        if let Some(error) = last_error {
+            return Err(error);
+        }
+
+        Err(ContentError::NotFound {
+            path: path.to_string(),
+        })
+    }
+
+    /// List directory contents across all sources, merging results
+    /// 
+    /// This aggregates entries from all sources that successfully list the directory
+    /// 
+    /// Entries are deduplicated by path with earlier sources taking priority,
+    /// and sorted by path. Sources are queried concurrently, up to
+    /// `BATCH_CONCURRENCY` at a time, but merged in order, so the result
+    /// doesn't depend on which one answers first.
+    /// 
+    /// With `with_entry_limit`, only the first `limit` paths in sorted order
+    /// are kept and the listing is marked `truncated` if any were dropped.
+    /// 
+    /// With a cache, each source's listing is cached with its ETag and
+    /// revalidated through `ContentSource::list_directory_if_none_match`, so
+    /// unchanged directories cost a 304 rather than a full listing, and the
+    /// 304 restarts the cached listing's TTL with `Cache::touch`. The merged
+    /// result is cached too, one entry per directory, with a digest of what
+    /// every source answered: the SHA-256 of each source's identifier and
+    /// ETag, or `-` for sources without the directory, in source order, plus
+    /// the entry limit. When all sources answer as before, which is the case
+    /// when they all return 304, the digest matches and the cached merge is
+    /// served and touched. A source that fails or has no ETag makes the merge
+    /// uncacheable. `invalidate` drops the cached listings of the path and of
+    /// its parent directory.
+    pub async fn list_directory_merged(&self, path: impl AsRef<str>) -> Result<DirectoryListing> {
+        let path = self.hooked_path(path.as_ref())?;
+        let path = path.as_str();
+        self.record_dependency(path);
+        let sources = self.listing_sources();
+        let answers: Vec<Result<(DirectoryListing, Option<String>)>> = stream::iter(&sources)
+            .map(|source| self.revalidated_listing(source.as_ref(), path))
+            .buffered(BATCH_CONCURRENCY)
+            .collect()
+            .await;
+
+        let digest = self.merged_listing_digest(&sources, &answers);
+        let key = self.scoped_key(merged_key(path));
+        if let (Some(cache), Some(digest)) = (&self.cache, &digest) {
+            let cached: Option<CachedMerge> = self
+                .cache_get(cache.as_ref(), &key, path)
+                .await?
+                .and_then(|bytes| serde_json::from_slice(&bytes).ok());
+            if let Some(cached) = cached.filter(|cached| cached.digest == *digest) {
+                if let Err(e) = cache.touch(&key).await {
+                    self.cache_write_failed(e, path);
+                }
+                return Ok(cached.listing);
+            }
+        }
+
+        let mut merged: BTreeMap<String, DirectoryEntry> = BTreeMap::new();
+        let mut truncated = false;
+        let mut found_any = false;
+        for answer in answers {
+            let Ok((listing, _)) = answer else {
+                continue;
+            };
+            found_any = true;
+            truncated |= listing.truncated;
+
+            for entry in listing.entries {
+                if let Some(existing) = merged.get_mut(&entry.path) {
+                    existing.absorb_metadata(entry.metadata);
+                    continue;
+                }
+                merged.insert(entry.path.clone(), entry);
+                // A dropped path sorts after every kept one, so it is dropped
+                // again if a later source lists it too
+                if self.entry_limit.is_some_and(|limit| merged.len() > limit) {
+                    merged.pop_last();
+                    truncated = true;
+                }
+            }
+        }
+
+        if !found_any {
+            return Err(ContentError::NotFound {
+                path: path.to_string(),
+            });
+        }
+
+        let mut listing = DirectoryListing::new(path, merged.into_values().collect());
+        listing.truncated = truncated;
+        if let (Some(cache), Some(digest)) = (&self.cache, digest) {
+            let entry = CachedMerge { digest, listing };
+            let written = match serde_json::to_vec(&entry) {
+                Ok(bytes) => cache.set(&key, bytes.into()).await,
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = written {
+                self.cache_write_failed(e, path);
+            }
+            return Ok(entry.listing);
+        }
+        Ok(listing)
+    }
+
+    /// List `path` in one source, revalidating the copy cached with its ETag
+    /// 
+    /// Returns the listing and its ETag, if the source has one.
+    async fn revalidated_listing(
+        &self,
+        source: &dyn ContentSource,
+        path: &str,
+    ) -> Result<(DirectoryListing, Option<String>)> {
+        let source_id = source.identifier();
+        let list = || async {
+            source
+                .list_directory(path)
+                .await
+                .with_source(&source_id)
+                .with_path(path)
+                .map(|listing| (listing, None))
+        };
+        let Some(cache) = &self.cache else {
+            return list().await;
+        };
+        let key = self.scoped_key(listing_key(&source_id, path));
+        let cached: Option<CachedListing> = self
+            .cache_get(cache.as_ref(), &key, path)
+            .await?
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok());
+
+        let etag = cached.as_ref().map(|cached| cached.etag.as_str());
+        let revalidated = source
+            .list_directory_if_none_match(path, etag)
+            .await
+            .with_source(&source_id)
+            .with_path(path)?;
+        match (revalidated, cached) {
+            (Revalidated::NotModified, Some(cached)) => {
+                // Confirmed current, so its TTL starts over
+                if let Err(e) = cache.touch(&key).await {
+                    self.cache_write_failed(e, path);
+                }
+                Ok((cached.listing, Some(cached.etag)))
+            }
+            (Revalidated::Modified { value, etag: Some(etag) }, _) => {
+                let entry = CachedListing { etag, listing: value };
+                let written = match serde_json::to_vec(&entry) {
+                    Ok(bytes) => cache.set(&key, bytes.into()).await,
+                    Err(e) => Err(e.into()),
+                };
+                if let Err(e) = written {
+                    self.cache_write_failed(e, path);
+                }
+                Ok((entry.listing, Some(entry.etag)))
+            }
+            (Revalidated::Modified { value, etag: None }, _) => Ok((value, None)),
+            // Nothing was sent to compare against, so list again
+            (Revalidated::NotModified, None) => list().await,
+        }
+    }
+
+    /// Digest of what each source answered for a merged listing
+    /// 
+    /// `None` when a source failed, other than with `NotFound`, or listed
+    /// without an ETag: the merge then can't be told apart from another.
+    fn merged_listing_digest(
+        &self,
+        sources: &[Arc<dyn ContentSource>],
+        answers: &[Result<(DirectoryListing, Option<String>)>],
+    ) -> Option<String> {
+        let mut combination = String::new();
+        for (source, answer) in sources.iter().zip(answers) {
+            let state = match answer {
+                Ok((_, Some(etag))) => etag.as_str(),
+                Err(e) if e.is_not_found() => "-",
+                _ => return None,
+            };
+            combination.push_str(&format!("{}\n{}\n", source.identifier(), state));
+        }
+        if let Some(limit) = self.entry_limit {
+            combination.push_str(&format!("limit\n{}\n", limit));
+        }
+        Some(sha256_hex_of(combination.as_bytes()))
+    }
+
+    /// List the paths of every file below a prefix across all sources
+    ///
+    /// Directories are omitted. Paths are deduplicated and sorted. Sources
+    /// without the prefix are skipped; any other error fails the call, so a
+    /// source that couldn't be read doesn't look like an empty subtree.
+    pub async fn list_all_files(&self, prefix: impl AsRef<str>) -> Result<Vec<String>> {
+        let prefix = self.normalize_path(prefix.as_ref())?;
+        let prefix = prefix.as_str();
+        let mut files = BTreeSet::new();
+        let mut found_any = false;
+
+        for source in self.listing_sources() {
+            match source
+                .list_directory_recursive(prefix)
+                .await
+                .with_source(&source.identifier())
+                .with_path(prefix)
+            {
+                Ok(entries) => {
+                    found_any = true;
+                    files.extend(
+                        entries
+                            .into_iter()
+                            .filter(|e| e.entry_type == EntryType::File)
+                            .map(|e| e.path),
+                    );
+                }
+                Err(e) if e.is_not_found() => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if !found_any {
+            return Err(ContentError::NotFound {
+                path: prefix.to_string(),
+            });
+        }
+
+        Ok(files.into_iter().collect())
+    }
+
+    /// Build a manifest of every file below `root`, for sync clients
+    /// 
+    /// Files are listed recursively in every listing source, earlier sources
+    /// winning for paths several have, then downloaded through `fetch_file`
+    /// and hashed, `options` files at a time. Unlike `list_all_files`, any
+    /// listing error other than `NotFound` fails the manifest, since a
+    /// client would take a missing file for a deleted one. The result is
+    /// the same for the same content, whatever order downloads finish in.
+    pub async fn tree_manifest(&self, root: impl AsRef<str>, options: ManifestOptions) -> Result<TreeManifest> {
+        let root = self.hooked_path(root.as_ref())?;
+        let root = root.as_str();
+        let mut files: BTreeMap<String, DirectoryEntry> = BTreeMap::new();
+        let mut found_any = false;
+
+        for source in self.listing_sources() {
+            match source
+                .list_directory_recursive(root)
+                .await
+                .with_source(&source.identifier())
+                .with_path(root)
+            {
+                Ok(entries) => {
+                    found_any = true;
+                    // Names that aren't UTF-8 can't be fetched
+                    for entry in entries {
+                        if entry.entry_type == EntryType::File && entry.raw_name.is_none() {
+                            files.entry(entry.path.clone()).or_insert(entry);
+                        }
+                    }
+                }
+                Err(e) if e.is_not_found() => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if !found_any {
+            return Err(ContentError::NotFound {
+                path: root.to_string(),
+            });
+        }
+
+        let previous: HashMap<&str, &ManifestEntry> = options
+            .previous
+            .iter()
+            .flat_map(|manifest| &manifest.entries)
+            .map(|entry| (entry.path.as_str(), entry))
+            .collect();
+        let options = &options;
+        let entries = stream::iter(files.into_values())
+            .map(|listed| {
+                let previous = previous.get(listed.path.as_str()).copied();
+                manifest_entry(self, listed, previous, options)
+            })
+            .buffered(options.concurrency)
+            .try_collect()
+            .await?;
+
+        Ok(TreeManifest {
+            root: root.to_string(),
+            entries,
+        })
+    }
+
+    /// Check if a file exists in any source
+    /// 
+    /// A cached copy counts as existing without asking the sources. Otherwise
+    /// each source's `ContentSource::file_exists` is asked in order, which for
+    /// `GitHubSource` is a HEAD request rather than a download. Paths that
+    /// fail to normalize never exist.
+    pub async fn file_exists(&self, path: impl AsRef<str>) -> bool {
+        let Ok(path) = self.hooked_path(path.as_ref()) else {
+            return false;
+        };
+        let path = path.as_str();
+        if let Some(exists) = self.existence.as_ref().and_then(|e| e.get(path)) {
+            return exists;
+        }
+
+        let exists = self.file_exists_uncached(path).await;
+        if let Some(existence) = &self.existence {
+            existence.insert(path, exists);
+        }
+        exists
+    }
+
+    /// Check the content cache, then the sources, for a normalized path
+    async fn file_exists_uncached(&self, path: &str) -> bool {
+        if let Some(cache) = &self.cache {
+            if cache.contains(&self.file_key(path)).await {
+                return true;
+            }
+        }
+        if self.virtual_rule(path).is_some() {
+            return self.fetch_normalized(path, &FetchOptions::default(), &RequestContext::new()).await.is_ok();
+        }
+        for source in self.live_sources().iter() {
+            if source.file_exists(path).await {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Check many paths for existence at once
+    /// 
+    /// Runs up to `BATCH_CONCURRENCY` `file_exists` checks concurrently.
+    /// Results are in the same order as `paths`. Takes `String`s and `&str`s
+    /// alike.
+    pub async fn exists_many<P: AsRef<str>>(&self, paths: &[P]) -> Vec<(String, bool)> {
+        stream::iter(paths)
+            .map(|path| async move {
+                let path = path.as_ref();
+                (path.to_string(), self.file_exists(path).await)
+            })
+            .buffered(BATCH_CONCURRENCY)
+            .collect()
+            .await
+    }
+
+    /// Check many paths for existence at once, the same as `exists_many`
+    pub async fn files_exist<P: AsRef<str>>(&self, paths: &[P]) -> Vec<(String, bool)> {
+        self.exists_many(paths).await
+    }
+
+    /// Fetch the first of several candidate paths that exists
+    /// 
+    /// Returns the candidate that matched, as given, with its content.
+    /// Candidates are fetched in order, without probing with `file_exists`.
+    /// With `with_existence_ttl`, misses are remembered and later calls skip
+    /// those candidates without a request. Only NotFound moves on to the next
+    /// candidate; other errors are returned right away. Once every candidate
+    /// has missed, fails with NotFound for the first, wrapped in
+    /// `ContentError::AlsoTried` naming the others.
+    pub async fn fetch_first<P: AsRef<str>>(&self, paths: &[P]) -> Result<(String, FileContent)> {
+        for path in paths {
+            let path = path.as_ref();
+            let key = self.hooked_path(path)?;
+            let existence = self.existence.as_ref();
+            if existence.and_then(|e| e.get(key.as_str())) == Some(false) {
+                continue;
+            }
+            match self.fetch_file(path).await {
+                Ok(content) => return Ok((path.to_string(), content)),
+                Err(e) if e.is_not_found() => {
+                    if let Some(existence) = existence {
+                        existence.insert(key.as_str(), false);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(ContentError::not_found_among(paths))
+    }
+
+    /// Fetch `stem` with the first of `extensions` that exists
+    /// 
+    /// `fetch_with_extensions("data/items", &["json", "msgpack"])` tries
+    /// `data/items.json`, then `data/items.msgpack`, like `fetch_first`.
+    pub async fn fetch_with_extensions(
+        &self,
+        stem: &str,
+        extensions: &[&str],
+    ) -> Result<(String, FileContent)> {
+        let candidates: Vec<String> = extensions
+            .iter()
+            .map(|ext| format!("{}.{}", stem, ext.trim_start_matches('.')))
+            .collect();
+        self.fetch_first(&candidates).await
+    }
+
+    /// Fetch many files at once
+    /// 
+    /// Runs up to `BATCH_CONCURRENCY` fetches concurrently. Each success
+    /// is paired with the path it was requested under; both successes and
+    /// failures keep the order of `paths`. Takes `String`s and `&str`s alike.
+    pub async fn fetch_files<P: AsRef<str>>(&self, paths: &[P]) -> PartialFailure<(String, FileContent)> {
+        stream::iter(paths)
+            .map(|path| async move {
+                let path = path.as_ref();
+                let result = self.fetch_file(path).await.map(|file| (path.to_string(), file));
+                (path.to_string(), result)
+            })
+            .buffered(BATCH_CONCURRENCY)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    /// Find out whether a path is a file or a directory in any source
+    /// 
+    /// Returns the type reported by the first source that has the path, or
+    /// `Ok(None)` if no source has it. As with `fetch_file`, a source error is
+    /// only returned if no later source has the path.
+    pub async fn path_type(&self, path: impl AsRef<str>) -> Result<Option<EntryType>> {
+        let path = self.hooked_path(path.as_ref())?;
+        let path = path.as_str();
+        let mut last_error = None;
+
+        for source in self.live_sources().iter() {
+            match source
+                .path_type(path)
+                .await
+                .with_source(&source.identifier())
+                .with_path(path)
+            {
+                Ok(Some(entry_type)) => return Ok(Some(entry_type)),
+                Ok(None) => {}
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        match last_error {
+            Some(error) => Err(error),
+            None => Ok(None),
+        }
+    }
+
+    /// Get a path's metadata from the first source that has it
+    /// 
+    /// Doesn't download the content from sources that override
+    /// `ContentSource::stat`, such as `GitHubSource` and `LocalFileSource`.
+    /// As with `fetch_file`, a source error is only returned if no later
+    /// source has the path; otherwise this fails with `NotFound`.
+    pub async fn stat(&self, path: impl AsRef<str>) -> Result<FileMetadata> {
+        let content_path = self.hooked_path(path.as_ref())?;
+        let path = content_path.as_str();
+        if self.virtual_rule(path).is_some() {
+            let mut file = self
+                .fetch_normalized(path, &FetchOptions::default(), &RequestContext::new())
+                .await?;
+            if let Some(content_type) = self.mapped_content_type(&content_path) {
+                file.content_type = Some(content_type);
+            }
+            return Ok(FileMetadata::from(&file));
+        }
+        let mut last_error = None;
+
+        for source in self.live_sources().iter() {
+            match source
+                .stat(path)
+                .await
+                .with_source(&source.identifier())
+                .with_path(path)
+            {
+                Ok(mut metadata) => {
+                    if metadata.is_file() {
+                        if let Some(content_type) = self.mapped_content_type(&content_path) {
+                            metadata.content_type = Some(content_type);
+                        }
+                    }
+                    return Ok(metadata);
+                }
+                Err(e) if e.is_not_found() => {}
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| ContentError::NotFound {
+            path: path.to_string(),
+        }))
+    }
+
+    /// Report where `fetch_file` would get a path from, without fetching it
+    /// 
+    /// Reads the cache entry, then asks every source for the path's metadata
+    /// with `ContentSource::stat`, and works out which answer a fetch would
+    /// use and why. Nothing is cached or invalidated, and sources that
+    /// override `stat`, such as `GitHubSource` and `LocalFileSource`, don't
+    /// download the content. Sources are all asked, even after the one that
+    /// would win, so shadowed copies show up too.
+    /// 
+    /// Only fails if the path is invalid; errors from the cache and the
+    /// sources are part of the explanation.
+    pub async fn explain(&self, path: impl AsRef<str>) -> Result<Explanation> {
+        let path = self.hooked_path(path.as_ref())?;
+        let path = path.as_str();
+
+        let cache = match &self.cache {
+            Some(cache) => Some(self.check_cache(cache.as_ref(), path).await),
+            None => None,
+        };
+        let snapshot = self.live_sources();
+        let sources: Vec<SourceCheck> = stream::iter(snapshot.iter().enumerate())
+            .map(|(index, source)| async move {
+                let outcome = match source.stat(path).await {
+                    Ok(metadata) if metadata.is_file() => SourceOutcome::Found {
+                        size: metadata.size,
+                        etag: metadata.etag,
+                    },
+                    Ok(_) => SourceOutcome::Directory,
+                    Err(e) if e.is_not_found() => SourceOutcome::NotFound,
+                    Err(e) => SourceOutcome::Failed {
+                        kind: e.kind(),
+                        message: e.root().to_string(),
+                        retryable: e.is_retryable(),
+                    },
+                };
+                SourceCheck {
+                    index,
+                    source: source.identifier(),
+                    outcome,
+                }
+            })
+            .buffered(BATCH_CONCURRENCY)
+            .collect()
+            .await;
+
+        let (resolution, mut reason) = self.resolve_explained(cache.as_ref(), &sources);
+        if let Some(CacheState::Failed { .. }) = cache.as_ref().map(|c| &c.state) {
+            if !self.strict_cache {
+                reason = format!("the cache can't be read, so it counts as a miss; {}", reason);
+            }
+        }
+        Ok(Explanation {
+            path: path.to_string(),
+            cache,
+            sources,
+            resolution,
+            reason,
+        })
+    }
+
+    /// Read a path's cache entry for `explain`
+    async fn check_cache(&self, cache: &dyn Cache, path: &str) -> CacheCheck {
+        let key = self.file_key(path);
+        let (state, content) = match cache.get(&key).await {
+            Ok(Some(content)) => (CacheState::Fresh, Some(content)),
+            Ok(None) => match cache.get_stale(&key).await {
+                Ok(Some(content)) => (CacheState::Stale, Some(content)),
+                _ => (CacheState::Missing, None),
+            },
+            Err(e) => (CacheState::Failed { message: e.root().to_string() }, None),
+        };
+        let etag = match &content {
+            Some(content) => {
+                let meta = cache.get_stale(&self.file_meta_key(path)).await.ok().flatten();
+                cached_file(content.clone(), meta, path).etag
+            }
+            None => None,
+        };
+
+        CacheCheck {
+            key,
+            state,
+            size: content.map(|c| c.len() as u64),
+            etag,
+        }
+    }
+
+    /// Apply `fetch_file`'s rules to what `explain` found
+    fn resolve_explained(
+        &self,
+        cache: Option<&CacheCheck>,
+        sources: &[SourceCheck],
+    ) -> (Resolution, String) {
+        let usable = |size: Option<u64>| !(self.treat_empty_as_not_found && size == Some(0));
+
+        if let Some(cache) = cache {
+            match &cache.state {
+                CacheState::Fresh if usable(cache.size) => {
+                    let reason = "a fresh copy is cached, so no source is asked".to_string();
+                    return (Resolution::Cache, reason);
+                }
+                CacheState::Failed { message } if self.strict_cache => {
+                    let reason = format!(
+                        "reading the cache fails, which fails the fetch with a strict cache: {}",
+                        message
+                    );
+                    let resolution = Resolution::Error {
+                        kind: ErrorKind::Cache,
+                        message: message.clone(),
+                    };
+                    return (resolution, reason);
+                }
+                _ => {}
+            }
+        }
+
+        let mut skipped_empty = false;
+        for check in sources {
+            if let SourceOutcome::Found { size, .. } = &check.outcome {
+                if usable(*size) {
+                    let mut reason = match check.index {
+                        0 => "it is the first source and has the file".to_string(),
+                        _ => "no earlier source has the file".to_string(),
+                    };
+                    if skipped_empty {
+                        reason.push_str(" (empty files count as missing)");
+                    }
+                    let resolution = Resolution::Source {
+                        index: check.index,
+                        source: check.source.clone(),
+                    };
+                    return (resolution, reason);
+                }
+                skipped_empty = true;
+            }
+        }
+
+        // As in `fetch_file`, the last error wins over NotFound
+        let last_error = sources.iter().rev().find_map(|check| match &check.outcome {
+            SourceOutcome::Failed { kind, message, retryable } => {
+                Some((check, *kind, message, *retryable))
+            }
+            _ => None,
+        });
+        match last_error {
+            Some((check, kind, message, retryable)) => {
+                let stale = cache.is_some_and(|cache| {
+                    matches!(cache.state, CacheState::Fresh | CacheState::Stale) && usable(cache.size)
+                });
+                if self.serve_stale_on_error && retryable && stale {
+                    let reason = format!(
+                        "no source has the file, {} fails with a retryable error and serving stale copies on error is on",
+                        check.source
+                    );
+                    return (Resolution::StaleCache, reason);
+                }
+                let reason = format!("no source has the file and {} fails", check.source);
+                let resolution = Resolution::Error {
+                    kind,
+                    message: message.clone(),
+                };
+                (resolution, reason)
+            }
+            None if skipped_empty => (
+                Resolution::NotFound,
+                "every copy is empty and empty files count as missing".to_string(),
+            ),
+            None => (Resolution::NotFound, "no source has the file".to_string()),
+        }
+    }
+
+    /// Check the configuration before serving, e.g. at startup
+    /// 
+    /// Misconfiguration such as a wrong repository name, base path or token
+    /// otherwise only shows up on the first request. This lists each source's
+    /// base path, and writes, reads back and deletes a test entry in the cache.
+    /// A source that fails, including when its base path doesn't exist, and a
+    /// cache that fails or reads back other content are fatal. An empty base
+    /// path, a rate limited or timed out source, and a cache that doesn't keep
+    /// the entry are warnings. Sources without the `listing` capability aren't
+    /// checked.
+    /// 
+    /// Problems are returned in the report; see `validate_configuration_with`
+    /// to fail instead.
+    pub async fn validate_configuration(&self) -> Result<ValidationReport> {
+        self.validate_configuration_with(&ValidationOptions::default()).await
+    }
+
+    /// Check the configuration like `validate_configuration`, with routes to check
+    /// 
+    /// Each prefix from `ValidationOptions::with_route` has to exist in some
+    /// source, as a file or a directory, or match a virtual file rule; one
+    /// that doesn't is fatal. With `with_fail_on_fatal(true)`, fatal findings
+    /// fail with `InvalidConfig` listing them, so a service can refuse to start.
+    /// 
+    /// ```no_run
+    /// # async fn run(resolver: content_resolver::ResourceResolver) -> content_resolver::Result<()> {
+    /// use content_resolver::ValidationOptions;
+    /// 
+    /// let options = ValidationOptions::default()
+    ///     .with_route("skills")
+    ///     .with_route("languages")
+    ///     .with_fail_on_fatal(true);
+    /// let report = resolver.validate_configuration_with(&options).await?;
+    /// for warning in report.warnings() {
+    ///     eprintln!("{}", warning);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn validate_configuration_with(
+        &self,
+        options: &ValidationOptions,
+    ) -> Result<ValidationReport> {
+        let mut report = ValidationReport::default();
+        let sources = self.live_sources();
+
+        let checked: Vec<_> = sources
+            .iter()
+            .enumerate()
+            .filter(|(_, source)| source.capabilities().listing)
+            .collect();
+        report.sources_checked = checked.len();
+        let findings: Vec<Option<Finding>> = stream::iter(checked)
+            .map(|(index, source)| validate_source(index, source))
+            .buffered(BATCH_CONCURRENCY)
+            .collect()
+            .await;
+        report.findings.extend(findings.into_iter().flatten());
+
+        if let Some(cache) = &self.cache {
+            report.cache_checked = true;
+            report.findings.extend(self.validate_cache(cache.as_ref()).await);
+        }
+
+        report.routes_checked = options.routes.len();
+        for prefix in &options.routes {
+            if let Some(finding) = self.validate_route(prefix, &sources).await {
+                report.findings.push(finding);
+            }
+        }
+
+        match report.fatal_error() {
+            Some(error) if options.fail_on_fatal => Err(error),
+            _ => Ok(report),
+        }
+    }
+
+    /// Write, read back and delete a test entry for `validate_configuration`
+    async fn validate_cache(&self, cache: &dyn Cache) -> Option<Finding> {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos());
+        let key = self.scoped_key(format!("validate:{}", nanos));
+        let value = Bytes::from(key.clone());
+        let fatal = |message: &str, e: &ContentError| Finding::from_error(Severity::Fatal, Subject::Cache, message, e);
+
+        if let Err(e) = cache.set(&key, value.clone()).await {
+            return Some(fatal("writing a test entry failed", &e));
+        }
+        let finding = match cache.get(&key).await {
+            Ok(Some(read)) if read == value => None,
+            Ok(Some(_)) => Some(Finding::new(
+                Severity::Fatal,
+                Subject::Cache,
+                "a test entry read back with other content",
+            )),
+            Ok(None) => Some(Finding::new(
+                Severity::Warning,
+                Subject::Cache,
+                "a test entry was gone right after writing it",
+            )),
+            Err(e) => Some(fatal("reading a test entry failed", &e)),
+        };
+        match cache.remove(&key).await {
+            Err(e) if finding.is_none() => Some(Finding::from_error(
+                Severity::Warning,
+                Subject::Cache,
+                &format!("deleting the test entry {} failed", key),
+                &e,
+            )),
+            _ => finding,
+        }
+    }
+
+    /// Look up a routed prefix in the sources for `validate_configuration`
+    async fn validate_route(&self, prefix: &str, sources: &[Arc<dyn ContentSource>]) -> Option<Finding> {
+        let subject = Subject::Route {
+            prefix: prefix.to_string(),
+        };
+        let path = match self.hooked_path(prefix) {
+            Ok(path) => path,
+            Err(e) => return Some(Finding::from_error(Severity::Fatal, subject, "the prefix is invalid", &e)),
+        };
+        if self.virtual_rule(path.as_str()).is_some() {
+            return None;
+        }
+
+        let mut last_error = None;
+        for source in sources.iter() {
+            match source.path_type(path.as_str()).await {
+                Ok(Some(_)) => return None,
+                Ok(None) => {}
+                Err(e) if e.is_not_found() => {}
+                Err(e) => last_error = Some(e.with_source(source.identifier())),
+            }
+        }
+        Some(match last_error {
+            Some(e) => Finding::from_error(Severity::Fatal, subject, "no source has the prefix", &e),
+            None => Finding::new(Severity::Fatal, subject, "no source has the prefix"),
+        })
+    }
+
+    /// View of this resolver rooted at `prefix`
+    /// 
+    /// Paths given to the view are resolved below `prefix` and can't climb out
+    /// of it. The view shares this resolver's sources and cache.
+    pub fn scoped(&self, prefix: impl AsRef<str>) -> Result<ScopedResolver> {
+        Ok(ScopedResolver::new(self.clone(), self.normalize_path(prefix.as_ref())?))
+    }
+
+    /// Drop the cached copy of a file
+    /// 
+    /// Returns whether an entry was cached. Without a cache this does nothing.
+    /// The metadata kept next to the content is dropped as well, and so are
+    /// virtual files assembled from the file or from a listing of its
+    /// directory.
+    pub async fn invalidate(&self, path: impl AsRef<str>) -> Result<bool> {
+        let path = self.hooked_path(path.as_ref())?;
+        self.invalidate_normalized(&path).await
+    }
+
+    /// `invalidate` for a path the request hooks have already seen
+    async fn invalidate_normalized(&self, path: &ContentPath) -> Result<bool> {
+        if let Some(existence) = &self.existence {
+            existence.remove_prefix(path);
+        }
+        let Some(cache) = &self.cache else {
+            return Ok(false);
+        };
+
+        let removed = self.invalidate_cached(cache.as_ref(), path.as_str()).await?;
+        // The path may be a directory, and its parent lists it
+        for directory in std::iter::once(path.clone()).chain(path.parent()) {
+            self.invalidate_listings(cache.as_ref(), directory.as_str()).await?;
+        }
+        // Files built from the parent's listing change when a file comes or goes
+        let mut pending = Vec::new();
+        for listed in std::iter::once(path.clone()).chain(path.parent()) {
+            pending.extend(self.take_dependents(cache.as_ref(), listed.as_str()).await?);
+        }
+        let mut done = BTreeSet::from([path.to_string()]);
+        while let Some(dependent) = pending.pop() {
+            if done.insert(dependent.clone()) {
+                self.invalidate_cached(cache.as_ref(), &dependent).await?;
+                pending.extend(self.take_dependents(cache.as_ref(), &dependent).await?);
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Drop one normalized path's content and metadata from the cache
+    async fn invalidate_cached(&self, cache: &dyn Cache, path: &str) -> Result<bool> {
+        cache.remove(&self.file_meta_key(path)).await.with_path(path)?;
+        let cache_key = self.file_key(path);
+        if !cache.contains(&cache_key).await {
+            return Ok(false);
+        }
+        cache.remove(&cache_key).await.with_path(path)?;
+        Ok(true)
+    }
+
+    /// Drop the listings of a directory cached by `list_directory_merged`
+    async fn invalidate_listings(&self, cache: &dyn Cache, path: &str) -> Result<()> {
+        cache.remove(&self.scoped_key(merged_key(path))).await.with_path(path)?;
+        for source in self.listing_sources() {
+            let key = self.scoped_key(listing_key(&source.identifier(), path));
+            cache.remove(&key).await.with_path(path)?;
+        }
+        Ok(())
+    }
+
+    /// Remove and return the virtual files recorded as built from `path`
+    async fn take_dependents(&self, cache: &dyn Cache, path: &str) -> Result<Vec<String>> {
+        let key = self.scoped_key(dependents_key(path));
+        let _guard = self.dependents_lock.lock().await;
+        let Some(dependents) = cache.get(&key).await.with_path(path)? else {
+            return Ok(Vec::new());
+        };
+        cache.remove(&key).await.with_path(path)?;
+        Ok(decode_dependents(Some(dependents)).into_iter().collect())
+    }
+
+    /// Drop the cached copies of `prefix` and every file the sources list below it
+    /// 
+    /// Returns the number of entries removed. Cached files that no source
+    /// lists any more are not found this way and stay cached.
+    pub async fn invalidate_prefix(&self, prefix: impl AsRef<str>) -> Result<usize> {
+        let prefix = self.normalize_path(prefix.as_ref())?;
+        if let Some(existence) = &self.existence {
+            existence.remove_prefix(&prefix);
+        }
+        if self.cache.is_none() {
+            return Ok(0);
+        }
+
+        let files = match self.list_all_files(prefix.as_str()).await {
+            Ok(files) => files,
+            Err(e) if e.is_not_found() => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut removed =
+            usize::from(!prefix.is_root() && self.invalidate_normalized(&prefix).await?);
+        for file in files {
+            let file = self.normalize_path(&file)?;
+            removed += usize::from(self.invalidate_normalized(&file).await?);
+        }
+        Ok(removed)
+    }
+
+    /// Queue invalidations and apply them in batches every `debounce`
+    /// 
+    /// See `InvalidationQueue`. Must be called within a tokio runtime.
+    pub fn invalidation_queue(&self, debounce: Duration) -> InvalidationQueue {
+        InvalidationQueue::new(self.clone(), debounce)
+    }
+
+    /// Get the list of sources
+    /// 
+    /// These are the sources the resolver was built with, without its
+    /// concurrency limits, so requests made on them directly don't wait for
+    /// permits. `replace_sources` doesn't change what this returns; use
+    /// `current_sources` to see the sources requests go to after a swap.
+    pub fn sources(&self) -> &[Arc<dyn ContentSource>] {
+        &self.configured
+    }
+
+    /// Get the sources requests go to now, after any `replace_sources`
+    /// 
+    /// This is a snapshot: a later `replace_sources` doesn't change it. Like
+    /// `sources`, it leaves out the resolver's concurrency limits.
+    pub fn current_sources(&self) -> Arc<[Arc<dyn ContentSource>]> {
+        without_limits(&self.live_sources())
+    }
+
+    /// The sources requests go to, each in its concurrency limit
+    fn live_sources(&self) -> SourceList {
+        self.sources.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Replace every source at once, keeping the cache and all settings
+    /// 
+    /// For reloading source configuration without rebuilding the resolver.
+    /// Each request reads the source list once when it starts, so requests
+    /// in flight finish against the old sources, and later ones only ever see
+    /// the new list, never a mix or an empty list. Clones of the resolver
+    /// share the change. New sources are wrapped in the request budget and
+    /// get the concurrency limits, whose in-flight requests carry over, and
+    /// cached `file_exists` answers are dropped; cached files are kept, and
+    /// the cache scope isn't recomputed, so call `invalidate` for paths the
+    /// new sources serve differently.
+    pub fn replace_sources(&self, sources: Vec<Arc<dyn ContentSource>>) {
+        let sources = self.wrapped(sources);
+        *self.sources.write().unwrap_or_else(|e| e.into_inner()) = sources;
+        if let Some(existence) = &self.existence {
+            existence.clear();
+        }
+    }
+
+    /// The first source whose `identifier()` is `id`
+    pub fn source_by_id(&self, id: &str) -> Option<Arc<dyn ContentSource>> {
+        self.current_sources().iter().find(|source| source.identifier() == id).cloned()
+    }
+
+    /// The sources of concrete type `T`, in order
+    /// 
+    /// Wrapped sources have the wrapper's type: look for `RetryingSource` and
+    /// call `inner()` to reach the source it wraps. The `BudgetedSource`
+    /// wrappers added by `with_request_budget` are looked through.
+    pub fn sources_of<T: ContentSource>(&self) -> Vec<Arc<T>> {
+        self.current_sources()
+            .iter()
+            .filter_map(|source| match source.clone().into_any_arc().downcast::<T>() {
+                Ok(source) => Some(source),
+                Err(any) => any
+                    .downcast::<BudgetedSource>()
+                    .ok()
+                    .filter(|budgeted| budgeted.managed)
+                    .and_then(|budgeted| budgeted.inner().clone().into_any_arc().downcast::<T>().ok()),
+            })
+            .collect()
+    }
+
+    /// Sources that can list directories, in order
+    fn listing_sources(&self) -> Vec<Arc<dyn ContentSource>> {
+        let sources = self.live_sources();
+        sources.iter().filter(|source| source.capabilities().listing).cloned().collect()
+    }
+
+    /// Cache key of a normalized path, within the cache scope
+    fn file_key(&self, path: &str) -> String {
+        self.scoped_key(cache_key(path))
+    }
+
+    /// Cache key of a file's metadata, within the cache scope
+    fn file_meta_key(&self, path: &str) -> String {
+        self.scoped_key(meta_key(path))
+    }
+
+    fn scoped_key(&self, key: String) -> String {
+        match &self.cache_scope {
+            Some(scope) => format!("{}:{}", scope, key),
+            None => key,
+        }
+    }
+
+    /// Whether the resolver has no sources, and so finds nothing
+    pub fn is_empty(&self) -> bool {
+        self.live_sources().is_empty()
+    }
+}
+
+/// Fetch a normalized path from one source, naming the source and path in errors
+async fn fetch_from(
+    source: &dyn ContentSource,
+    path: &str,
+    options: &FetchOptions,
+    ctx: &RequestContext,
+) -> Result<FileContent> {
+    source
+        .fetch_file_with_ctx(path, options, ctx)
+        .await
+        .with_source(&source.identifier())
+        .with_path(path)
+}
+
+/// Cache key of a normalized path
+fn cache_key(path: &str) -> String {
+    format!("file:{}", path)
+}
+
+/// Cache key of the metadata stored next to a cached file
+fn meta_key(path: &str) -> String {
+    format!("meta:{}", path)
+}
+
+/// Cache key of one source's listing of a directory, stored with its ETag
+fn listing_key(source_id: &str, path: &str) -> String {
+    format!("listing@{}:{}", source_id, path)
+}
+
+/// Cache key of a merged listing, stored with the digest of the sources' answers
+fn merged_key(path: &str) -> String {
+    format!("merged@{}", path)
+}
+
+/// Cache key listing the virtual files assembled from a file, one per line
+fn dependents_key(path: &str) -> String {
+    format!("deps:{}", path)
+}
+
+fn decode_dependents(value: Option<Bytes>) -> BTreeSet<String> {
+    value
+        .map(|value| {
+            String::from_utf8_lossy(&value)
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// A source's listing of a directory and the ETag it came with
+#[derive(Serialize, Deserialize)]
+struct CachedListing {
+    etag: String,
+    listing: DirectoryListing,
+}
+
+/// A merged listing and the digest of the source answers it was built from
+#[derive(Serialize, Deserialize)]
+struct CachedMerge {
+    digest: String,
+    listing: DirectoryListing,
+}
+
+/// Source metadata a cache hit can't recover from the content alone
+#[derive(Serialize, Deserialize)]
+struct CachedMeta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<SystemTime>,
+}
+
+impl CachedMeta {
+    /// The metadata worth storing for `file`, if it has any
+    fn of(file: &FileContent) -> Option<Self> {
+        if file.etag.is_none() && file.content_type.is_none() && file.last_modified.is_none() {
+            return None;
+        }
+        Some(Self {
+            etag: file.etag.clone(),
+            content_type: file.content_type.clone(),
+            last_modified: file.last_modified,
+        })
+    }
+
+    fn encode(&self) -> bytes::Bytes {
+        serde_json::to_vec(self).unwrap_or_default().into()
+    }
+}
+
+/// Build a cache hit, restoring what the metadata entry holds
+/// 
+/// A missing or unreadable metadata entry leaves the fields unset.
+fn cached_file(content: bytes::Bytes, meta: Option<bytes::Bytes>, path: &str) -> FileContent {
+    let mut file = FileContent::new(content, format!("cache:{}", path));
+    if let Some(meta) = meta.and_then(|m| serde_json::from_slice::<CachedMeta>(&m).ok()) {
+        file.etag = meta.etag;
+        file.content_type = meta.content_type;
+        file.last_modified = meta.last_modified;
+    }
+    file
+}
+
+/// `sources` with the resolver's `LimitedSource` wrappers taken off
+fn without_limits(sources: &[Arc<dyn ContentSource>]) -> SourceList {
+    sources.iter().cloned().map(unlimited).collect()
+}
+
+fn ensure_sources(sources: &[Arc<dyn ContentSource>]) -> Result<()> {
+    if sources.is_empty() {
+        return Err(ContentError::InvalidConfig {
+            message: "ResourceResolver needs at least one source".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Digest checked by `fetch_to_writer_verified`
+struct Verify<'a> {
+    hasher: Box<dyn DynDigest + Send>,
+    expected: &'a str,
+}
+
+impl Verify<'_> {
+    /// Compare the digest of everything hashed so far, and reset the hasher
+    fn check(&mut self, path: &str) -> Result<()> {
+        let actual: String = self.hasher.finalize_reset().iter().map(|b| format!("{:02x}", b)).collect();
+        if actual.eq_ignore_ascii_case(self.expected) {
+            Ok(())
+        } else {
+            Err(ContentError::IntegrityMismatch {
+                path: path.to_string(),
+                expected: self.expected.to_string(),
+                actual,
+            })
+        }
+    }
+}
+
+/// List a source's base path for `validate_configuration`
+async fn validate_source(index: usize, source: &Arc<dyn ContentSource>) -> Option<Finding> {
+    let subject = Subject::Source {
+        index,
+        source: source.identifier(),
+    };
+    match source.list_directory("").await {
+        Ok(listing) if listing.entries.is_empty() => {
+            Some(Finding::new(Severity::Warning, subject, "the base path is empty"))
+        }
+        Ok(_) => None,
+        Err(e) if e.is_not_found() => {
+            Some(Finding::from_error(Severity::Fatal, subject, "the base path doesn't exist", &e))
+        }
+        Err(e) if matches!(e.kind(), ErrorKind::RateLimited | ErrorKind::Timeout) => {
+            Some(Finding::from_error(Severity::Warning, subject, "couldn't be checked", &e))
+        }
+        Err(e) => Some(Finding::from_error(Severity::Fatal, subject, "listing the base path failed", &e)),
+    }
+}
+
+/// Writer that counts what passes through, optionally keeping a copy and hashing it
+struct TeeWriter<'a, W> {
+    inner: &'a mut W,
+    copy: Option<BytesMut>,
+    /// Size past which `copy` is dropped
+    copy_limit: u64,
+    hasher: Option<&'a mut (dyn DynDigest + Send + 'static)>,
+    /// SHA-256 for the audit sink, if there is one
+    audit: Option<Sha256>,
+    written: u64,
+}
+
+impl<W: AsyncWrite + Unpin + Send> AsyncWrite for TeeWriter<'_, W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let poll = Pin::new(&mut *self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.written += n as u64;
+            if self.written > self.copy_limit {
+                self.copy = None;
+            }
+            if let Some(copy) = &mut self.copy {
+                copy.extend_from_slice(&buf[..n]);
+            }
+            if let Some(hasher) = &mut self.hasher {
+                hasher.update(&buf[..n]);
+            }
+            if let Some(audit) = &mut self.audit {
+                sha2::Digest::update(audit, &buf[..n]);
+            }
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+    use crate::error::ErrorKind;
+    use crate::explain::{CacheState, Resolution, SourceOutcome};
+    use crate::source::Capabilities;
+    use crate::testing::{FaultInjectingSource, MemorySource};
+    use crate::types::DedupePolicy;
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use std::borrow::Cow;
+
+    struct MockSource {
+        files: Vec<(&'static str, &'static str)>,
+    }
+
+    #[async_trait]
+    impl ContentSource for MockSource {
+        async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+            for (file_path, content) in &self.files {
+                if *file_path == path {
+                    return Ok(FileContent::new(*content, path));
+                }
+            }
+            Err(ContentError::NotFound {
+                path: path.to_string(),
+            })
+        }
+
+        async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+            let entries: Vec<DirectoryEntry> = self
+                .files
+                .iter()
+                .filter_map(|(file_path, _)| {
+                    let (parent, name) = file_path.rsplit_once('/').unwrap_or(("", file_path));
+                    (parent == path).then(|| DirectoryEntry::file(name, *file_path))
+                })
+                .collect();
+
+            if entries.is_empty() {
+                return Err(ContentError::NotFound {
+                    path: path.to_string(),
+                });
+            }
+            Ok(DirectoryListing::new(path, entries))
+        }
+
+        fn identifier(&self) -> String {
+            "mock".to_string()
+        }
+    }
+
+    /// Cache whose reads always fail
+    struct BrokenCache;
+
+    #[async_trait]
+    impl Cache for BrokenCache {
+        async fn get(&self, _key: &str) -> Result<Option<Bytes>> {
+            Err(ContentError::Cache {
+                message: "broken".to_string(),
+            })
+        }
+
+        async fn set(&self, _key: &str, _value: Bytes) -> Result<()> {
+            Ok(())
+        }
+
+        async fn contains(&self, _key: &str) -> bool {
+            false
+        }
+
+        async fn remove(&self, _key: &str) -> Result<()> {
+            Ok(())
+        }
+
+        async fn clear(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Cache handing control back to the runtime after every read
+    struct YieldingCache(MemoryCache);
+
+    #[async_trait]
+    impl Cache for YieldingCache {
+        async fn get(&self, key: &str) -> Result<Option<Bytes>> {
+            let value = self.0.get(key).await;
+            tokio::task::yield_now().await;
+            value
+        }
+
+        async fn set(&self, key: &str, value: Bytes) -> Result<()> {
+            self.0.set(key, value).await
+        }
+
+        async fn contains(&self, key: &str) -> bool {
+            self.0.contains(key).await
+        }
+
+        async fn remove(&self, key: &str) -> Result<()> {
+            self.0.remove(key).await
+        }
+
+        async fn clear(&self) -> Result<()> {
+            self.0.clear().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_dependents() {
+        let cache = YieldingCache(MemoryCache::new());
+        let resolver = ResourceResolver::new(vec![Arc::new(MemorySource::new())]);
+        let dependents: Vec<String> = (0..20).map(|i| format!("virtual/{}.txt", i)).collect();
+
+        let registrations = dependents
+            .iter()
+            .map(|dependent| resolver.add_dependent(&cache, "shared.txt", dependent));
+        for registered in futures::future::join_all(registrations).await {
+            registered.unwrap();
+        }
+
+        let taken = resolver.take_dependents(&cache, "shared.txt").await.unwrap();
+        assert_eq!(BTreeSet::from_iter(taken), BTreeSet::from_iter(dependents));
+    }
+
+    #[tokio::test]
+    async fn test_validate_configuration() {
+        let resolver = ResourceResolver::with_cache(
+            vec![
+                Arc::new(MemorySource::new().with_file("skills/a.md", "a")) as Arc<dyn ContentSource>,
+                Arc::new(MemorySource::new()),
+                rate_limited_source(),
+            ],
+            Arc::new(BrokenCache),
+        );
+        let options = ValidationOptions::default().with_route("skills").with_route("languages");
+        let report = resolver.validate_configuration_with(&options).await.unwrap();
+
+        assert_eq!((report.sources_checked, report.cache_checked, report.routes_checked), (3, true, 2));
+        let found: Vec<_> = report.findings.iter().map(|f| (f.severity, &f.subject)).collect();
+        assert_eq!(
+            found,
+            vec![
+                (Severity::Fatal, &Subject::Source { index: 1, source: "memory".to_string() }),
+                (Severity::Warning, &Subject::Source { index: 2, source: "faulty(memory)".to_string() }),
+                (Severity::Fatal, &Subject::Cache),
+                (Severity::Fatal, &Subject::Route { prefix: "languages".to_string() }),
+            ]
+        );
+        assert_eq!(report.findings[0].kind, Some(ErrorKind::NotFound));
+        assert_eq!(report.findings[1].kind, Some(ErrorKind::RateLimited));
+        assert_eq!(report.findings[2].kind, Some(ErrorKind::Cache));
+        assert!(!report.is_ok());
+        assert_eq!(report.warnings().count(), 1);
+
+        // Fatal findings fail the check on request
+        let error = resolver
+            .validate_configuration_with(&options.with_fail_on_fatal(true))
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidConfig);
+        assert!(error.to_string().contains("fatal: cache: reading a test entry failed"));
+
+        // A working cache leaves nothing behind
+        let cache = Arc::new(MemoryCache::new());
+        let resolver = ResourceResolver::with_cache(
+            vec![Arc::new(MemorySource::new().with_file("a.md", "a")) as Arc<dyn ContentSource>],
+            cache.clone(),
+        );
+        let report = resolver.validate_configuration().await.unwrap();
+        assert!(report.findings.is_empty(), "{}", report);
+        assert_eq!(cache.len().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_audit_sink() {
+        use crate::audit::{AuditPurpose, ChannelSink};
+
+        let (sink, mut events) = ChannelSink::new(16);
+        let source = Arc::new(MockSource {
+            files: vec![("a.txt", "alpha"), ("b.bin", "bravo")],
+        });
+        let resolver = ResourceResolver::with_cache(vec![source], Arc::new(MemoryCache::new()))
+            .with_audit_sink(Arc::new(sink));
+
+        let mut ctx = RequestContext::new();
+        ctx.insert(AuditPurpose("export".to_string()));
+        resolver
+            .fetch_file_with_ctx("a.txt", &FetchOptions::default(), &mut ctx)
+            .await
+            .unwrap();
+        let event = events.try_recv().unwrap();
+        assert_eq!((event.path.as_str(), event.source.as_str()), ("a.txt", "mock"));
+        assert_eq!((event.sha256, event.size), (sha256_hex_of("alpha"), 5));
+        assert_eq!(event.purpose.as_deref(), Some("export"));
+        assert!(!event.from_cache && event.timestamp_ms > 0);
+
+        // Cache hits aren't audited by default
+        resolver.fetch_file("a.txt").await.unwrap();
+        assert!(events.try_recv().is_err());
+
+        // Streamed content is hashed on the way
+        let mut out = Vec::new();
+        resolver.fetch_to_writer("b.bin", &mut out).await.unwrap();
+        let event = events.try_recv().unwrap();
+        assert_eq!((event.path.as_str(), event.sha256, event.size), ("b.bin", sha256_hex_of("bravo"), 5));
+        assert_eq!(event.purpose, None);
+
+        let resolver = resolver.with_audit_cache_hits(true);
+        resolver.fetch_file("a.txt").await.unwrap();
+        let event = events.try_recv().unwrap();
+        assert!(event.from_cache);
+        assert_eq!(event.source, "cache");
+
+        // A failing sink doesn't fail fetches, but is counted
+        drop(events);
+        resolver.fetch_file("a.txt").await.unwrap();
+        assert_eq!(resolver.stats().audit_failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_audit_purpose_on_every_path() {
+        use crate::audit::{AuditPurpose, ChannelSink};
+        use futures::StreamExt;
+
+        let (sink, mut events) = ChannelSink::new(16);
+        let resolver = ResourceResolver::new(vec![
+            Arc::new(MockSource {
+                files: vec![("a.txt", "alpha")],
+            }) as Arc<dyn ContentSource>,
+            Arc::new(VersionedSource::default()),
+        ])
+        .with_audit_sink(Arc::new(sink));
+        let mut ctx = RequestContext::new();
+        ctx.insert(AuditPurpose("export".to_string()));
+
+        let mut out = Vec::new();
+        resolver.fetch_to_writer_with_ctx("a.txt", &mut out, &mut ctx).await.unwrap();
+        assert_eq!(events.try_recv().unwrap().purpose.as_deref(), Some("export"));
+
+        let mut streamed = RequestContext::new();
+        streamed.insert(AuditPurpose("stream".to_string()));
+        let stream = resolver.fetch_stream_with_ctx("a.txt", 1024, streamed).await.unwrap();
+        assert_eq!(stream.collect::<Vec<_>>().await.len(), 1);
+        assert_eq!(events.try_recv().unwrap().purpose.as_deref(), Some("stream"));
+
+        let branch = ContentVersion::Ref("main".to_string());
+        resolver.fetch_file_at_with_ctx("a.txt", &branch, &mut ctx).await.unwrap();
+        let event = events.try_recv().unwrap();
+        assert_eq!((event.source.as_str(), event.purpose.as_deref()), ("versioned", Some("export")));
+    }
+
+    /// Source that rejects every request as rate limited
+    fn rate_limited_source() -> Arc<dyn ContentSource> {
+        let source = FaultInjectingSource::new(Arc::new(MemorySource::new()));
+        source.set_fail_after(0, || ContentError::RateLimited {
+            message: "rate limited".to_string(),
+            retry_after: None,
+        });
+        Arc::new(source)
+    }
+
+    #[tokio::test]
+    async fn test_fallback_resolution() {
+        let source1 = Arc::new(MockSource {
+            files: vec![("file1.txt", "from source 1")],
+        });
+        let source2 = Arc::new(MockSource {
+            files: vec![("file2.txt", "from source 2")],
+        });
+
+        let resolver = ResourceResolver::new(vec![
+            source1 as Arc<dyn ContentSource>,
+            source2 as Arc<dyn ContentSource>,
+        ]);
+
+        // File from first source
+        let result = resolver.fetch_file("file1.txt").await.unwrap();
+        assert_eq!(result.content, Bytes::from("from source 1"));
+
+        // File from second source
+        let result = resolver.fetch_file("file2.txt").await.unwrap();
+        assert_eq!(result.content, Bytes::from("from source 2"));
+
+        // File not in any source
+        assert!(matches!(
+            resolver.fetch_file("missing.txt").await,
+            Err(ContentError::NotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_caching() {
+        let source = Arc::new(MockSource {
+            files: vec![("file.txt", "content")],
+        });
+        let cache = Arc::new(MemoryCache::new());
+
+        let resolver = ResourceResolver::with_cache(
+            vec![source as Arc<dyn ContentSource>],
+            cache.clone(),
+        );
+
+        // First fetch - from source
+        let result = resolver.fetch_file("file.txt").await.unwrap();
+        assert_eq!(result.content, Bytes::from("content"));
+
+        // Check cache was populated
+        assert!(cache.contains("file:file.txt").await);
+
+        // Second fetch - from cache
+        let result = resolver.fetch_file("file.txt").await.unwrap();
+        assert_eq!(result.content, Bytes::from("content"));
+        assert_eq!(result.source_path, "cache:file.txt");
+    }
+
+    #[tokio::test]
+    async fn test_separator_policy() {
+        let cache = Arc::new(MemoryCache::new());
+        let source = Arc::new(MemorySource::new().with_file("docs/guide.md", "guide"));
+        let resolver = ResourceResolver::with_cache(vec![source], cache.clone())
+            .with_separator_policy(SeparatorPolicy::SlashOrBackslash);
+
+        let file = resolver.fetch_file(r"\docs\\guide.md").await.unwrap();
+        assert_eq!(file.content, "guide");
+        // Both spellings share one cache entry
+        assert!(cache.contains("file:docs/guide.md").await);
+        assert_eq!(
+            resolver.fetch_file("docs//guide.md").await.unwrap().source_path,
+            "cache:docs/guide.md"
+        );
+        assert_eq!(resolver.list_directory(r"docs\").await.unwrap().entries.len(), 1);
+        let scoped = resolver.scoped("docs").unwrap();
+        assert_eq!(scoped.fetch_file(r".\guide.md").await.unwrap().content, "guide");
+
+        // Without the policy the backslash path names a different file
+        let resolver = ResourceResolver::new(vec![
+            Arc::new(MemorySource::new().with_file("docs/guide.md", "guide")) as Arc<dyn ContentSource>,
+        ]);
+        assert!(resolver.fetch_file(r"docs\guide.md").await.unwrap_err().is_not_found());
+    }
+
+    /// Source that writes part of a file, then fails
+    struct TruncatingSource;
+
+    #[async_trait]
+    impl ContentSource for TruncatingSource {
+        async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+            Err(ContentError::NotFound {
+                path: path.to_string(),
+            })
+        }
+
+        async fn fetch_to_writer(
+            &self,
+            _path: &str,
+            writer: &mut (dyn AsyncWrite + Unpin + Send),
+        ) -> Result<u64> {
+            writer.write_all(b"partial").await?;
+            Err(ContentError::Io(std::io::Error::other("connection reset")))
+        }
+
+        async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+            Err(ContentError::NotFound {
+                path: path.to_string(),
+            })
+        }
+
+        fn identifier(&self) -> String {
+            "truncating".to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_to_writer() {
+        let cache = Arc::new(MemoryCache::new());
+        let resolver = ResourceResolver::with_cache(
+            vec![
+                Arc::new(MemorySource::new()) as Arc<dyn ContentSource>,
+                Arc::new(MemorySource::new().with_file("big.bin", "streamed")),
+            ],
+            cache.clone(),
+        );
+
+        // Falls back past the source without the file, and tees into the cache
+        let mut out = Vec::new();
+        assert_eq!(resolver.fetch_to_writer("big.bin", &mut out).await.unwrap(), 8);
+        assert_eq!(out, b"streamed");
+        assert_eq!(cache.get("file:big.bin").await.unwrap().unwrap(), "streamed");
+
+        // Served from the cache next time
+        cache.set("file:big.bin", Bytes::from("cached")).await.unwrap();
+        let mut out = Vec::new();
+        resolver.fetch_to_writer("big.bin", &mut out).await.unwrap();
+        assert_eq!(out, b"cached");
+
+        let mut out = Vec::new();
+        assert!(resolver
+            .fetch_to_writer("missing.bin", &mut out)
+            .await
+            .unwrap_err()
+            .is_not_found());
+        assert!(out.is_empty());
+
+        // Files above the limit are written but not held for the cache
+        cache.clear().await.unwrap();
+        let resolver = resolver.with_stream_cache_limit(7);
+        let mut out = Vec::new();
+        assert_eq!(resolver.fetch_to_writer("big.bin", &mut out).await.unwrap(), 8);
+        assert_eq!(out, b"streamed");
+        assert!(!cache.contains("file:big.bin").await);
+        let resolver = resolver.with_stream_cache_limit(8);
+        resolver.fetch_to_writer("big.bin", &mut Vec::new()).await.unwrap();
+        assert!(cache.contains("file:big.bin").await);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_to_writer_stops_after_partial_write() {
+        let resolver = ResourceResolver::new(vec![
+            Arc::new(TruncatingSource) as Arc<dyn ContentSource>,
+            Arc::new(MemorySource::new().with_file("a.bin", "complete")),
+        ]);
+
+        let mut out = Vec::new();
+        let error = resolver.fetch_to_writer("a.bin", &mut out).await.unwrap_err();
+        assert_eq!(error.source_id(), Some("truncating"));
+        assert_eq!(out, b"partial");
+    }
+
+    /// Source streaming 100 chunks of 1 KiB, counting what it got to write
+    #[derive(Default)]
+    struct ChunkedSource {
+        produced: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ContentSource for ChunkedSource {
+        async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+            Ok(FileContent::new(vec![b'x'; 100 * 1024], path.to_string()))
+        }
+
+        async fn fetch_to_writer(
+            &self,
+            path: &str,
+            writer: &mut (dyn AsyncWrite + Unpin + Send),
+        ) -> Result<u64> {
+            if path != "big.bin" {
+                return Err(ContentError::NotFound {
+                    path: path.to_string(),
+                });
+            }
+            for i in 0..100u8 {
+                writer.write_all(&[i; 1024]).await?;
+                self.produced.fetch_add(1024, Ordering::SeqCst);
+            }
+            Ok(100 * 1024)
+        }
+
+        async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+            Err(ContentError::NotFound {
+                path: path.to_string(),
+            })
+        }
+
+        fn identifier(&self) -> String {
+            "chunked".to_string()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fetch_stream_is_bounded() {
+        let source = Arc::new(ChunkedSource::default());
+        let cache = Arc::new(MemoryCache::new());
+        let resolver = ResourceResolver::with_cache(vec![source.clone()], cache.clone());
+
+        let mut stream = resolver.fetch_stream("big.bin", 4096).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        // The source waits for the consumer instead of buffering the file
+        let produced = source.produced.load(Ordering::SeqCst);
+        assert!(produced <= 8 * 1024, "produced {} bytes", produced);
+
+        let mut content = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            content.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(content.len(), 100 * 1024);
+        assert!(content.chunks(1024).enumerate().all(|(i, chunk)| chunk.iter().all(|&b| b == i as u8)));
+        assert!(!cache.contains("file:big.bin").await);
+
+        // Cache hits are still served
+        cache.set("file:big.bin", Bytes::from("cached")).await.unwrap();
+        let stream = resolver.fetch_stream("big.bin", 4096).await.unwrap();
+        let chunks: Vec<Bytes> = stream.try_collect().await.unwrap();
+        assert_eq!(chunks.concat(), b"cached");
+
+        let error = resolver.fetch_stream("missing.bin", 4096).await.unwrap_err();
+        assert!(error.is_not_found());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_stream_releases_its_permit() {
+        let source = Arc::new(ChunkedSource::default());
+        let id = source.identifier();
+        let resolver = ResourceResolver::new(vec![source.clone()]).with_source_concurrency(id.clone(), 1);
+
+        // The producer waits on a full buffer the stream's reader never drains
+        let mut stream = resolver.fetch_stream("big.bin", 1024).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap().len(), 1024);
+        assert_eq!(resolver.in_flight()[&id], 0);
+        let file = tokio::time::timeout(Duration::from_secs(1), resolver.fetch_file("small.bin"))
+            .await
+            .expect("the stream kept the only permit");
+        assert_eq!(file.unwrap().content.len(), 100 * 1024);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fetch_stream_stops_when_dropped() {
+        let source = Arc::new(ChunkedSource::default());
+        let resolver = ResourceResolver::new(vec![source.clone()]);
+
+        let mut stream = resolver.fetch_stream("big.bin", 1024).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap().len(), 1024);
+        drop(stream);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(source.produced.load(Ordering::SeqCst) < 8 * 1024);
+        assert!(resolver.tasks().is_empty());
+
+        // The download doesn't keep a dropped resolver's tasks running
+        let mut stream = resolver.fetch_stream("big.bin", 1024).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap().len(), 1024);
+        drop(resolver);
+        let rest: Vec<Result<Bytes>> = stream.collect().await;
+        assert!(rest.last().unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_list_directory_falls_back_on_errors() {
+        let resolver = ResourceResolver::new(vec![
+            rate_limited_source(),
+            Arc::new(MockSource {
+                files: vec![("dir/a.txt", "a"), ("dir/b.txt", "b")],
+            }) as Arc<dyn ContentSource>,
+        ]);
+
+        let listing = resolver.list_directory("dir").await.unwrap();
+        let names: Vec<_> = listing.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["a.txt", "b.txt"]);
+
+        // When every source fails, the last non-NotFound error is returned
+        let error = resolver.list_directory("missing").await.unwrap_err();
+        assert!(error.is_rate_limited());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_to_writer_verified() {
+        use crate::integrity::Sha256;
+
+        let source = Arc::new(MockSource {
+            files: vec![("a.bin", "current")],
+        });
+        let cache = Arc::new(MemoryCache::new());
+        let resolver = ResourceResolver::with_cache(vec![source], cache.clone());
+        let expected = sha256_hex_of("current");
+
+        // A mismatch fails after streaming and caches nothing
+        let mut out = Vec::new();
+        let error = resolver
+            .fetch_to_writer_verified("a.bin", &mut out, Sha256::default(), &sha256_hex_of("other"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::IntegrityMismatch);
+        assert_eq!(error.source_id(), Some("mock"));
+        assert!(matches!(error.root(), ContentError::IntegrityMismatch { actual, .. } if *actual == expected));
+        assert!(!cache.contains("file:a.bin").await);
+
+        // A stale cached copy is passed over for the sources
+        cache.set("file:a.bin", Bytes::from("stale")).await.unwrap();
+        let mut out = Vec::new();
+        let written = resolver
+            .fetch_to_writer_verified("a.bin", &mut out, Sha256::default(), &expected.to_uppercase())
+            .await
+            .unwrap();
+        assert_eq!(written, 7);
+        assert_eq!(out, b"current");
+        assert_eq!(cache.get("file:a.bin").await.unwrap().unwrap(), "current");
+
+        // And a verified cached copy is served
+        let mut out = Vec::new();
+        resolver
+            .fetch_to_writer_verified("a.bin", &mut out, Sha256::default(), &expected)
+            .await
+            .unwrap();
+        assert_eq!(out, b"current");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_request_budget() {
+        let source = Arc::new(MockSource {
+            files: vec![("a.txt", "a"), ("b.txt", "b")],
+        });
+        let budget = Arc::new(RequestBudget::new(Duration::from_secs(10)).with_max_requests(1));
+        let resolver = ResourceResolver::with_cache(vec![source], Arc::new(MemoryCache::new()))
+            .with_request_budget(budget);
+
+        resolver.fetch_file("a.txt").await.unwrap();
+        // Cache hits cost nothing
+        for _ in 0..3 {
+            resolver.fetch_file("a.txt").await.unwrap();
+        }
+        let error = resolver.fetch_file("b.txt").await.unwrap_err();
+        assert!(error.is_rate_limited());
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(10)));
+        assert_eq!(error.source_id(), Some("mock"));
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(resolver.budget_status().unwrap().remaining_requests, Some(1));
+        resolver.fetch_file("b.txt").await.unwrap();
+        assert!(ResourceResolver::new(vec![]).budget_status().is_none());
+
+        // A second budget replaces the first instead of adding to it
+        let unlimited = Arc::new(RequestBudget::new(Duration::from_secs(10)));
+        let resolver = resolver.with_request_budget(unlimited.clone());
+        assert!(resolver.fetch_file("c.txt").await.unwrap_err().is_not_found());
+        let budgeted = resolver.sources_of::<BudgetedSource>();
+        assert_eq!(budgeted.len(), 1);
+        assert!(Arc::ptr_eq(budgeted[0].budget(), &unlimited));
+        assert!(budgeted[0].inner().as_any().is::<MockSource>());
+    }
+
+    /// Source counting how many fetches it serves at once, each taking 10ms
+    #[derive(Default)]
+    struct SlowSource {
+        current: std::sync::atomic::AtomicUsize,
+        peak: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ContentSource for SlowSource {
+        async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+            let now = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            self.current.fetch_sub(1, Ordering::SeqCst);
+            Ok(FileContent::new(path.to_string(), format!("slow:{}", path)))
+        }
+
+        async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+            Err(ContentError::NotFound {
+                path: path.to_string(),
+            })
+        }
+
+        fn identifier(&self) -> String {
+            "slow".to_string()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_source_concurrency_is_shared() {
+        let source = Arc::new(SlowSource::default());
+        let resolver = ResourceResolver::new(vec![source.clone()]).with_source_concurrency("slow", 3);
+        let first: Vec<String> = (0..20).map(|i| format!("a/{}.md", i)).collect();
+        let second: Vec<String> = (0..20).map(|i| format!("b/{}.md", i)).collect();
+
+        let probe = async {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            resolver.in_flight()["slow"]
+        };
+        let (a, b, in_flight) = futures::join!(
+            resolver.fetch_files(&first),
+            resolver.fetch_files(&second),
+            probe
+        );
+        assert!(a.is_complete() && b.is_complete());
+        // Each batch runs 8 at a time, but both share the source's 3 permits
+        assert_eq!(source.peak.load(Ordering::SeqCst), 3);
+        assert_eq!(in_flight, 3);
+        assert_eq!(resolver.in_flight()["slow"], 0);
+
+        // Other sources get the default, and survive replace_sources
+        source.peak.store(0, Ordering::SeqCst);
+        let resolver = ResourceResolver::new(vec![]).with_default_source_concurrency(5);
+        resolver.replace_sources(vec![source.clone()]);
+        let (a, b) = futures::join!(resolver.fetch_files(&first), resolver.fetch_files(&second));
+        assert!(a.is_complete() && b.is_complete());
+        assert_eq!(source.peak.load(Ordering::SeqCst), 5);
+        assert_eq!(resolver.sources_of::<SlowSource>().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_replace_sources() {
+        let slow = FaultInjectingSource::new(Arc::new(MemorySource::new().with_file("a.txt", "old")));
+        slow.set_latency(Duration::from_millis(100));
+        let cache = Arc::new(MemoryCache::new());
+        let resolver = ResourceResolver::with_cache(vec![Arc::new(slow)], cache.clone())
+            .with_existence_ttl(Duration::from_secs(60));
+        assert!(!resolver.file_exists("b.txt").await);
+        let clone = resolver.clone();
+
+        // A request in flight finishes against the sources it started with
+        let in_flight = tokio::spawn({
+            let resolver = resolver.clone();
+            async move { resolver.fetch_file("a.txt").await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let new = MemorySource::new()
+            .with_file("a.txt", "new")
+            .with_file("b.txt", "b");
+        resolver.replace_sources(vec![Arc::new(new)]);
+        assert_eq!(in_flight.await.unwrap().unwrap().text().unwrap(), "old");
+
+        // Later ones, and clones, see the new sources; the cache is kept
+        assert_eq!(clone.current_sources()[0].identifier(), "memory");
+        // sources() keeps what the resolver was built with
+        assert_eq!(clone.sources()[0].identifier(), "faulty(memory)");
+        assert!(clone.file_exists("b.txt").await);
+        assert_eq!(resolver.fetch_file("a.txt").await.unwrap().text().unwrap(), "old");
+        resolver.invalidate("a.txt").await.unwrap();
+        assert_eq!(resolver.fetch_file("a.txt").await.unwrap().text().unwrap(), "new");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_replace_sources_concurrently() {
+        let set = |name: &'static str| -> Vec<Arc<dyn ContentSource>> {
+            vec![
+                Arc::new(MemorySource::new().with_file("only-first.txt", name)),
+                Arc::new(MemorySource::new().with_file("a.txt", name)),
+            ]
+        };
+        let resolver = ResourceResolver::new(set("one"));
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let resolver = resolver.clone();
+                tokio::spawn(async move {
+                    for _ in 0..500 {
+                        // Never an empty or partial source list
+                        assert_eq!(resolver.current_sources().len(), 2);
+                        resolver.fetch_file("a.txt").await.unwrap();
+                        resolver.fetch_file("only-first.txt").await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        for i in 0..500 {
+            resolver.replace_sources(set(if i % 2 == 0 { "two" } else { "one" }));
+            tokio::task::yield_now().await;
+        }
+        for reader in readers {
+            reader.await.unwrap();
+        }
+    }
+
+    /// Serves `v2/` paths from the root and records that it did
+    struct StripVersion;
+
+    /// Marker `StripVersion` leaves in the request context
+    struct Versioned;
+
+    impl RequestHook for StripVersion {
+        fn before_fetch<'a>(
+            &self,
+            path: &'a str,
+            ctx: &mut RequestContext,
+        ) -> Result<Cow<'a, str>> {
+            let rewritten = match path.strip_prefix("v2/") {
+                Some(rest) => {
+                    ctx.insert(Versioned);
+                    rest
+                }
+                None => path,
+            };
+            if rewritten.starts_with("private/") {
+                return Err(ContentError::InvalidPath {
+                    path: path.to_string(),
+                    reason: "private".to_string(),
+                });
+            }
+            Ok(Cow::Borrowed(rewritten))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_hook_rewrites_cache_keys() {
+        let source = Arc::new(FaultInjectingSource::new(Arc::new(
+            MemorySource::new()
+                .with_file("a.txt", "a")
+                .with_file("dir/b.txt", "b")
+                .with_file("private/c.txt", "c"),
+        )));
+        let cache = Arc::new(MemoryCache::new());
+        let resolver = ResourceResolver::with_cache(vec![source.clone()], cache.clone())
+            .with_request_hook(Arc::new(StripVersion));
+
+        let file = resolver.fetch_file("v2/a.txt").await.unwrap();
+        assert_eq!(file.content, "a");
+        assert!(cache.contains("file:a.txt").await);
+        assert!(!cache.contains("file:v2/a.txt").await);
+
+        // Both spellings share the cache entry
+        resolver.fetch_file("a.txt").await.unwrap();
+        assert_eq!(resolver.fetch_file("./v2/a.txt").await.unwrap().source_path, "cache:a.txt");
+        assert_eq!(source.call_count(), 1);
+        assert!(resolver.invalidate("v2/a.txt").await.unwrap());
+        assert!(!cache.contains("file:a.txt").await);
+
+        let listing = resolver.list_directory("v2/dir").await.unwrap();
+        assert_eq!(listing.entries[0].name, "b.txt");
+
+        // A hook's error aborts the request before any source is asked
+        let calls = source.call_count();
+        let error = resolver.fetch_file("v2/private/c.txt").await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidPath);
+        assert!(!resolver.file_exists("private/c.txt").await);
+        assert_eq!(source.call_count(), calls);
+    }
+
+    /// Serves a file naming the tenant it was fetched for
+    struct TenantSource;
+
+    /// Tenant a request is made for
+    struct Tenant(&'static str);
+
+    #[async_trait]
+    impl ContentSource for TenantSource {
+        async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+            Ok(FileContent::new("nobody", path))
+        }
+
+        async fn fetch_file_with_ctx(
+            &self,
+            path: &str,
+            _options: &FetchOptions,
+            ctx: &RequestContext,
+        ) -> Result<FileContent> {
+            let tenant = ctx.get::<Tenant>().map_or("nobody", |tenant| tenant.0);
+            let version = if ctx.contains::<Versioned>() { " v2" } else { "" };
+            Ok(FileContent::new(format!("{}{}", tenant, version), path))
+        }
+
+        async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+            Ok(DirectoryListing::new(path, Vec::new()))
+        }
+
+        fn identifier(&self) -> String {
+            "tenant".to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_source_reads_request_context() {
+        // Through a wrapper, which must pass the context on
+        let source = FaultInjectingSource::new(Arc::new(TenantSource));
+        let resolver = ResourceResolver::new(vec![Arc::new(source)])
+            .with_request_hook(Arc::new(StripVersion));
+
+        let mut ctx = RequestContext::new();
+        ctx.insert(Tenant("acme"));
+        let options = FetchOptions::default();
+        let file = resolver.fetch_file_with_ctx("v2/a.txt", &options, &mut ctx).await.unwrap();
+        assert_eq!(file.content, "acme v2");
+        // The hook's additions are visible to the caller afterwards
+        assert!(ctx.contains::<Versioned>());
+
+        assert_eq!(resolver.fetch_file("a.txt").await.unwrap().content, "nobody");
+    }
+
+    #[tokio::test]
+    async fn test_is_cache_stale_with_ctx() {
+        let resolver = ResourceResolver::with_cache(vec![Arc::new(TenantSource)], Arc::new(MemoryCache::new()));
+        let mut ctx = RequestContext::new();
+        ctx.insert(Tenant("globex"));
+        let options = FetchOptions::default();
+        resolver.fetch_file_with_ctx("a.txt", &options, &mut ctx).await.unwrap();
+
+        // Same size, so the comparison fetches the file for this tenant
+        assert!(!resolver.is_cache_stale_with_ctx("a.txt", &mut ctx).await.unwrap());
+        assert!(resolver.is_cache_stale("a.txt").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_all() {
+        let first = Arc::new(MockSource {
+            files: vec![("a.txt", "first")],
+        });
+        let second = Arc::new(MockSource {
+            files: vec![("a.txt", "second")],
+        });
+        let cache = Arc::new(MemoryCache::new());
+        let resolver = ResourceResolver::with_cache(
+            vec![
+                first as Arc<dyn ContentSource>,
+                Arc::new(MemorySource::new()),
+                rate_limited_source(),
+                second,
+            ],
+            cache.clone(),
+        );
+
+        let results = resolver.fetch_all("/a.txt").await;
+        let ids: Vec<_> = results.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["mock", "memory", "faulty(memory)", "mock"]);
+        assert_eq!(results[0].1.as_ref().unwrap().text().unwrap(), "first");
+        assert!(results[1].1.as_ref().unwrap_err().is_not_found());
+        let error = results[2].1.as_ref().unwrap_err();
+        assert!(error.is_rate_limited());
+        assert_eq!(error.source_id(), Some("faulty(memory)"));
+        assert_eq!(results[3].1.as_ref().unwrap().text().unwrap(), "second");
+        // Nothing is cached
+        assert!(!cache.contains("file:a.txt").await);
+
+        for (_, result) in resolver.fetch_all("../a.txt").await {
+            assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidPath);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_read_errors_are_misses() {
+        let source = Arc::new(MockSource {
+            files: vec![("a.txt", "from source")],
+        });
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let seen = errors.clone();
+        let resolver = ResourceResolver::with_cache(vec![source], Arc::new(BrokenCache))
+            .with_cache_error_hook(move |e| seen.lock().unwrap().push(e.to_string()));
+
+        let file = resolver.fetch_file("a.txt").await.unwrap();
+        assert_eq!(file.text().unwrap(), "from source");
+        let mut out = Vec::new();
+        resolver.fetch_to_writer("a.txt", &mut out).await.unwrap();
+        assert_eq!(out, b"from source");
+
+        assert_eq!(resolver.stats().cache_read_errors, 2);
+        assert_eq!(resolver.stats().cache_write_errors, 0);
+        assert_eq!(*errors.lock().unwrap(), vec!["a.txt: Cache error: broken"; 2]);
+
+        let explanation = resolver.explain("a.txt").await.unwrap();
+        assert!(matches!(explanation.resolution, Resolution::Source { index: 0, .. }));
+
+        let strict = resolver.with_strict_cache(true);
+        let error = strict.fetch_file("a.txt").await.unwrap_err();
+        assert!(matches!(error.root(), ContentError::Cache { .. }));
+        let explanation = strict.explain("a.txt").await.unwrap();
+        assert!(matches!(explanation.resolution, Resolution::Error { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_errors_name_source_and_path() {
+        let resolver = ResourceResolver::with_cache(
+            vec![rate_limited_source()],
+            Arc::new(BrokenCache),
+        )
+        .with_strict_cache(true);
+
+        // Cache failures name the path
+        let error = resolver.fetch_file("a.txt").await.unwrap_err();
+        assert!(matches!(error.root(), ContentError::Cache { .. }));
+        assert_eq!(error.path(), Some("a.txt"));
+        assert_eq!(error.to_string(), "a.txt: Cache error: broken");
+
+        // Source failures name the source and the path
+        let resolver =
+            ResourceResolver::new(vec![rate_limited_source()]);
+        for error in [
+            resolver.fetch_file("a.txt").await.unwrap_err(),
+            resolver.list_directory("a.txt").await.map(|_| ()).unwrap_err(),
+            resolver.list_all_files("a.txt").await.map(|_| ()).unwrap_err(),
+        ] {
+            assert!(error.is_rate_limited());
+            assert_eq!(error.source_id(), Some("faulty(memory)"));
+            assert_eq!(error.path(), Some("a.txt"));
+            assert!(error.to_string().starts_with("faulty(memory): a.txt: "), "{}", error);
+        }
+
+        // Another source having the prefix doesn't hide the failure
+        let resolver = ResourceResolver::new(vec![
+            Arc::new(MemorySource::new().with_file("a.txt/b.txt", "b")) as Arc<dyn ContentSource>,
+            rate_limited_source(),
+        ]);
+        assert!(resolver.list_all_files("a.txt").await.unwrap_err().is_rate_limited());
+    }
+
+    #[tokio::test]
+    async fn test_path_type() {
+        let resolver = ResourceResolver::new(vec![
+            rate_limited_source(),
+            Arc::new(MemorySource::new().with_file("dir/sub/a.txt", "a")) as Arc<dyn ContentSource>,
+        ]);
+
+        assert_eq!(resolver.path_type("dir/sub/a.txt").await.unwrap(), Some(EntryType::File));
+        assert_eq!(resolver.path_type("/dir/sub/").await.unwrap(), Some(EntryType::Dir));
+
+        // Absent everywhere, but one source failed: report the failure
+        let error = resolver.path_type("missing").await.unwrap_err();
+        assert!(error.is_rate_limited());
+        assert_eq!(error.source_id(), Some("faulty(memory)"));
+
+        let resolver = ResourceResolver::new(vec![
+            Arc::new(MemorySource::new().with_file("a.txt", "a")) as Arc<dyn ContentSource>,
+        ]);
+        assert_eq!(resolver.path_type("missing").await.unwrap(), None);
+        assert!(resolver.path_type("../a.txt").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stat() {
+        let resolver = ResourceResolver::new(vec![
+            rate_limited_source(),
+            Arc::new(
+                MemorySource::new().with_file("dir/a.txt", "hello"),
+            ) as Arc<dyn ContentSource>,
+        ]);
+
+        // The default implementation falls back past the failing source
+        let file = resolver.stat("/dir/a.txt").await.unwrap();
+        assert!(file.is_file());
+        assert_eq!(file.size, Some(5));
+        assert!(resolver.stat("dir").await.unwrap().is_dir());
+
+        let error = resolver.stat("missing").await.unwrap_err();
+        assert!(error.is_rate_limited());
+
+        let resolver = ResourceResolver::new(vec![
+            Arc::new(MemorySource::new().with_file("a.txt", "a")) as Arc<dyn ContentSource>,
+        ]);
+        assert!(resolver.stat("missing").await.unwrap_err().is_not_found());
+        assert!(resolver.stat("../a.txt").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_exists_many() {
+        let source = FaultInjectingSource::new(Arc::new(
+            MemorySource::new()
+                .with_file("config/a.toml", "a")
+                .with_file("config/c.toml", "c"),
+        ));
+        source.set_latency(std::time::Duration::from_millis(50));
+        let source = Arc::new(source);
+        let resolver = ResourceResolver::new(vec![source.clone() as Arc<dyn ContentSource>]);
+
+        let paths: Vec<String> = (0..16)
+            .map(|i| format!("config/{}.toml", ["a", "b", "c", "d"][i % 4]))
+            .collect();
+
+        let start = std::time::Instant::now();
+        let results = resolver.exists_many(&paths).await;
+        // Sixteen serial checks would take 800ms
+        assert!(start.elapsed() < std::time::Duration::from_millis(400));
+
+        assert_eq!(source.call_count(), 16);
+        for ((path, exists), expected) in results.iter().zip(&paths) {
+            assert_eq!(path, expected);
+            assert_eq!(*exists, path.ends_with("a.toml") || path.ends_with("c.toml"));
+        }
+    }
+
+    /// Source with a cheap existence check that counts both kinds of request
+    #[derive(Default)]
+    struct CountingSource {
+        files: MemorySource,
+        fetches: std::sync::atomic::AtomicUsize,
+        checks: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ContentSource for CountingSource {
+        async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+            self.fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.files.fetch_file(path).await
+        }
+
+        async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+            self.files.list_directory(path).await
+        }
+
+        async fn file_exists(&self, path: &str) -> bool {
+            self.checks.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.files.fetch_file(path).await.is_ok()
+        }
+
+        fn identifier(&self) -> String {
+            "counting".to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_exists_uses_cache_and_cheap_checks() {
+        use std::sync::atomic::Ordering;
+
+        let source = Arc::new(CountingSource {
+            files: MemorySource::new().with_file("a.txt", "a").with_file("b.txt", "b"),
+            ..Default::default()
+        });
+        let cache = Arc::new(MemoryCache::new());
+        let resolver = ResourceResolver::with_cache(vec![source.clone()], cache);
+
+        assert!(resolver.file_exists("a.txt").await);
+        assert!(!resolver.file_exists("missing.txt").await);
+        assert_eq!(source.checks.load(Ordering::SeqCst), 2);
+        assert_eq!(source.fetches.load(Ordering::SeqCst), 0);
+
+        // A cached copy answers without asking the source
+        resolver.fetch_file("b.txt").await.unwrap();
+        assert!(resolver.file_exists("b.txt").await);
+        assert_eq!(source.checks.load(Ordering::SeqCst), 2);
+        assert_eq!(source.fetches.load(Ordering::SeqCst), 1);
+
+        let results = resolver.exists_many(&["a.txt", "/b.txt", "c.txt"]).await;
+        assert_eq!(
+            results,
+            vec![
+                ("a.txt".to_string(), true),
+                ("/b.txt".to_string(), true),
+                ("c.txt".to_string(), false),
+            ]
+        );
+        assert_eq!(resolver.files_exist(&["a.txt", "/b.txt", "c.txt"]).await, results);
+        assert_eq!(source.fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_existence_ttl() {
+        use std::sync::atomic::Ordering;
+
+        let source = Arc::new(CountingSource {
+            files: MemorySource::new().with_file("overrides/a.toml", "a"),
+            ..Default::default()
+        });
+        let resolver = ResourceResolver::new(vec![source.clone() as Arc<dyn ContentSource>])
+            .with_existence_ttl(Duration::from_millis(50));
+        let checks = || source.checks.load(Ordering::SeqCst);
+
+        for _ in 0..3 {
+            assert!(resolver.file_exists("overrides/a.toml").await);
+            assert!(!resolver.file_exists("overrides/b.toml").await);
+        }
+        assert_eq!(checks(), 2);
+
+        // Clones share the answers, and invalidation forgets them
+        assert!(resolver.clone().file_exists("overrides//a.toml").await);
+        assert_eq!(checks(), 2);
+        resolver.invalidate_prefix("overrides").await.unwrap();
+        assert!(!resolver.file_exists("overrides/b.toml").await);
+        assert_eq!(checks(), 3);
+
+        // Answers expire once the TTL has passed
+        tokio::time::advance(Duration::from_millis(49)).await;
+        assert!(!resolver.file_exists("overrides/b.toml").await);
+        assert_eq!(checks(), 3);
+        tokio::time::advance(Duration::from_millis(1)).await;
+        assert!(!resolver.file_exists("overrides/b.toml").await);
+        assert_eq!(checks(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_structured() {
+        let value = serde_json::json!({"name": "api", "retries": 3});
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&value, &mut cbor).unwrap();
+        let source = MemorySource::new()
+            .with_file("a.json", serde_json::to_vec(&value).unwrap())
+            .with_file("a.msgpack", rmp_serde::to_vec_named(&value).unwrap())
+            .with_file("a.cbor", cbor);
+        let resolver = ResourceResolver::new(vec![Arc::new(source)]);
+
+        assert_eq!(resolver.fetch_json::<serde_json::Value>("a.json").await.unwrap(), value);
+        assert_eq!(resolver.fetch_msgpack::<serde_json::Value>("a.msgpack").await.unwrap(), value);
+        assert_eq!(resolver.fetch_cbor::<serde_json::Value>("a.cbor").await.unwrap(), value);
+        for (path, format) in [
+            ("a.json", DataFormat::Json),
+            ("a.msgpack", DataFormat::MessagePack),
+            ("a.cbor", DataFormat::Cbor),
+        ] {
+            assert_eq!(resolver.fetch_auto(path).await.unwrap(), (value.clone(), format));
+        }
+
+        let err = resolver.fetch_cbor::<serde_json::Value>("a.json").await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Validation);
+        assert!(err.to_string().contains("a.json") && err.to_string().contains("CBOR"));
+        assert!(resolver.fetch_auto::<serde_json::Value>("missing").await.unwrap_err().is_not_found());
+    }
+
+    /// Source serving `{version}:{path}` for any ref, counting fetches
+    #[derive(Default)]
+    struct VersionedSource {
+        fetches: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ContentSource for VersionedSource {
+        async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+            Err(ContentError::NotFound { path: path.to_string() })
+        }
+
+        async fn fetch_file_at(&self, path: &str, version: &ContentVersion) -> Result<FileContent> {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            match path {
+                "missing" => Err(ContentError::NotFound { path: path.to_string() }),
+                _ => Ok(FileContent::new(format!("{}:{}", version, path), path)),
+            }
+        }
+
+        async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+            Ok(DirectoryListing::new(path, Vec::new()))
+        }
+
+        fn identifier(&self) -> String {
+            "versioned".to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_file_at() {
+        let versioned = Arc::new(VersionedSource::default());
+        let cache = Arc::new(MemoryCache::new());
+        let resolver = ResourceResolver::with_cache(
+            vec![
+                // Can't read past versions, so it is skipped
+                Arc::new(MemorySource::new().with_file("a.json", "current")) as Arc<dyn ContentSource>,
+                versioned.clone(),
+            ],
+            cache.clone(),
+        );
+        let sha = ContentVersion::Ref("0123456789abcdef0123456789abcdef01234567".to_string());
+        let branch = ContentVersion::Ref("main".to_string());
+
+        let file = resolver.fetch_file_at("/a.json", &sha).await.unwrap();
+        assert_eq!(file.content, format!("{}:a.json", sha));
+        // Commits can't change, so the second read is a cache hit
+        let cached = resolver.fetch_file_at("a.json", &sha).await.unwrap();
+        assert_eq!(cached.content, file.content);
+        assert_eq!(cached.source_path, "cache:a.json");
+        assert_eq!(versioned.fetches.load(Ordering::SeqCst), 1);
+        assert!(cache.contains(&format!("file@{}:a.json", sha)).await);
+        // ... kept apart from the current version
+        assert_eq!(resolver.fetch_file("a.json").await.unwrap().content, "current");
+
+        // Branches move, so they are always fetched
+        resolver.fetch_file_at("a.json", &branch).await.unwrap();
+        resolver.fetch_file_at("a.json", &branch).await.unwrap();
+        assert_eq!(versioned.fetches.load(Ordering::SeqCst), 3);
+        assert!(!cache.contains("file@main:a.json").await);
+
+        // What a timestamp resolves to can change too
+        let past = ContentVersion::Timestamp(SystemTime::UNIX_EPOCH + Duration::from_secs(1_709_251_200));
+        resolver.fetch_file_at("a.json", &past).await.unwrap();
+        assert!(!cache.contains("file@~1709251200:a.json").await);
+        assert!(!past.is_immutable());
+
+        assert!(resolver.fetch_file_at("missing", &sha).await.unwrap_err().is_not_found());
+
+        // No source keeps history
+        let current_only = ResourceResolver::new(vec![Arc::new(MemorySource::new())]);
+        let error = current_only.fetch_file_at("a.json", &branch).await.unwrap_err();
+        assert!(error.is_unsupported(), "{}", error);
+        assert_eq!(error.http_status(), 501);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_first() {
+        use std::sync::atomic::Ordering;
+
+        let json = Arc::new(CountingSource {
+            files: MemorySource::new().with_file("data/new.json", "{}"),
+            ..Default::default()
+        });
+        let msgpack = Arc::new(CountingSource {
+            files: MemorySource::new()
+                .with_file("data/old.msgpack", &b"\x80"[..])
+                .with_file("data/new.msgpack", &b"\x80"[..]),
+            ..Default::default()
+        });
+        let resolver = ResourceResolver::new(vec![json.clone(), msgpack.clone()])
+            .with_existence_ttl(Duration::from_secs(60));
+
+        let (path, content) =
+            resolver.fetch_with_extensions("data/new", &["json", "msgpack"]).await.unwrap();
+        assert_eq!((path.as_str(), content.content.as_ref()), ("data/new.json", &b"{}"[..]));
+        let (path, _) =
+            resolver.fetch_with_extensions("data/old", &["json", ".msgpack"]).await.unwrap();
+        assert_eq!(path, "data/old.msgpack");
+        // Every candidate is looked for in every source, without probing
+        assert_eq!(json.fetches.load(Ordering::SeqCst), 3);
+        assert_eq!(json.checks.load(Ordering::SeqCst) + msgpack.checks.load(Ordering::SeqCst), 0);
+
+        // The remembered miss is skipped
+        resolver.fetch_first(&["data/old.json", "data/old.msgpack"]).await.unwrap();
+        assert_eq!(json.fetches.load(Ordering::SeqCst), 4);
+        assert_eq!(msgpack.fetches.load(Ordering::SeqCst), 3);
+
+        let error = resolver.fetch_first(&["a.json", "/a.msgpack"]).await.unwrap_err();
+        assert!(error.is_not_found());
+        assert_eq!(error.path(), Some("a.json"));
+        assert!(matches!(error.root(), ContentError::NotFound { path } if path == "a.json"));
+        assert!(error.to_string().ends_with("(also tried /a.msgpack)"), "{}", error);
+        assert!(resolver.fetch_first::<&str>(&[]).await.unwrap_err().is_not_found());
+    }
+
+    /// Source that serves files but can't list, failing loudly if asked to
+    struct FetchOnlySource(MemorySource);
+
+    #[async_trait]
+    impl ContentSource for FetchOnlySource {
+        async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+            self.0.fetch_file(path).await
+        }
+
+        async fn list_directory(&self, _path: &str) -> Result<DirectoryListing> {
+            Err(ContentError::InvalidConfig {
+                message: "listing is not supported".to_string(),
+            })
+        }
+
+        fn identifier(&self) -> String {
+            "fetch-only".to_string()
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities::default().with_listing(false)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_listing_skips_sources_without_listing() {
+        let fetch_only = || -> Arc<dyn ContentSource> {
+            Arc::new(FetchOnlySource(MemorySource::new().with_file("docs/a.md", "a")))
+        };
+        let resolver = ResourceResolver::new(vec![
+            fetch_only(),
+            Arc::new(MemorySource::new().with_file("docs/b.md", "b")),
+        ]);
+
+        assert_eq!(resolver.fetch_file("docs/a.md").await.unwrap().content, "a");
+        assert_eq!(resolver.list_directory("docs").await.unwrap().entries.len(), 1);
+        assert_eq!(resolver.list_directory_recursive("docs").await.unwrap().len(), 1);
+        assert_eq!(resolver.list_directory_merged("docs").await.unwrap().entries.len(), 1);
+        assert_eq!(resolver.list_all_files("docs").await.unwrap(), vec!["docs/b.md"]);
+
+        // Nothing to ask is a plain miss, not the source's error
+        let resolver = ResourceResolver::new(vec![fetch_only()]);
+        assert!(resolver.list_directory("docs").await.unwrap_err().is_not_found());
+
+        assert!(MemorySource::new().capabilities().listing);
+        let retrying = crate::retry::RetryingSource::new(fetch_only(), Default::default());
+        assert_eq!(retrying.capabilities(), Capabilities::default().with_listing(false));
+    }
+
+    #[tokio::test]
+    async fn test_list_directory_merged_is_concurrent_and_ordered() {
+        let delayed = |source: MemorySource, ms: u64| -> Arc<dyn ContentSource> {
+            let source = FaultInjectingSource::new(Arc::new(source));
+            source.set_latency(std::time::Duration::from_millis(ms));
+            Arc::new(source)
+        };
+        // `docs/x` is a file in the slowest source and a directory in the others
+        let sources = vec![
+            delayed(MemorySource::new().with_file("docs/x", "1").with_file("docs/a", "1"), 150),
+            delayed(MemorySource::new().with_file("docs/x/y", "2").with_file("docs/c", "2"), 100),
+            delayed(MemorySource::new().with_file("other/z", "3"), 10),
+            delayed(MemorySource::new().with_file("docs/b", "4").with_file("docs/x/z", "4"), 50),
+        ];
+
+        let mut sequential = DirectoryListing::new("docs", Vec::new());
+        for source in &sources {
+            if let Ok(listing) = source.list_directory("docs").await {
+                sequential = sequential.merge(listing, DedupePolicy::KeepFirst);
+            }
+        }
+
+        let resolver = ResourceResolver::new(sources);
+        let start = std::time::Instant::now();
+        let merged = resolver.list_directory_merged("docs").await.unwrap();
+        // Sequential listing takes 310ms
+        assert!(start.elapsed() < std::time::Duration::from_millis(250));
+
+        let entries = |listing: &DirectoryListing| -> Vec<(String, EntryType)> {
+            listing
+                .entries
+                .iter()
+                .map(|e| (e.path.clone(), e.entry_type.clone()))
+                .collect()
+        };
+        assert_eq!(entries(&merged), entries(&sequential));
+        assert_eq!(
+            entries(&merged),
+            ["docs/a", "docs/b", "docs/c", "docs/x"]
+                .map(|path| (path.to_string(), EntryType::File))
+        );
+    }
+
+    /// Listing double answering 304 while its ETag is unchanged
+    struct RevalidatingSource {
+        id: &'static str,
+        files: Mutex<(MemorySource, String)>,
+        listings: std::sync::atomic::AtomicUsize,
+        not_modified: std::sync::atomic::AtomicUsize,
+    }
+
+    impl RevalidatingSource {
+        fn new(id: &'static str, files: MemorySource) -> Self {
+            Self {
+                id,
+                files: Mutex::new((files, format!("\"{}1\"", id))),
+                listings: Default::default(),
+                not_modified: Default::default(),
+            }
+        }
+
+        fn replace(&self, files: MemorySource, etag: &str) {
+            *self.files.lock().unwrap() = (files, etag.to_string());
+        }
+    }
+
+    #[async_trait]
+    impl ContentSource for RevalidatingSource {
+        async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+            let files = self.files.lock().unwrap().0.clone();
+            files.fetch_file(path).await
+        }
+
+        async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+            self.listings.fetch_add(1, Ordering::SeqCst);
+            let files = self.files.lock().unwrap().0.clone();
+            files.list_directory(path).await
+        }
+
+        async fn list_directory_if_none_match(
+            &self,
+            path: &str,
+            etag: Option<&str>,
+        ) -> Result<Revalidated<DirectoryListing>> {
+            let current = self.files.lock().unwrap().1.clone();
+            if etag == Some(current.as_str()) {
+                self.not_modified.fetch_add(1, Ordering::SeqCst);
+                return Ok(Revalidated::NotModified);
+            }
+            Ok(Revalidated::Modified {
+                value: self.list_directory(path).await?,
+                etag: Some(current),
+            })
+        }
+
+        fn identifier(&self) -> String {
+            self.id.to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_directory_merged_revalidates() {
+        let a = Arc::new(RevalidatingSource::new("a", MemorySource::new().with_file("docs/a.md", "a")));
+        let b = Arc::new(RevalidatingSource::new("b", MemorySource::new().with_file("docs/b.md", "b")));
+        let missing = Arc::new(RevalidatingSource::new("m", MemorySource::new().with_file("other/m.md", "m")));
+        let cache = Arc::new(MemoryCache::new());
+        let sources: Vec<Arc<dyn ContentSource>> = vec![a.clone(), missing.clone(), b.clone()];
+        let resolver = ResourceResolver::with_cache(sources, cache.clone());
+        let names = |listing: DirectoryListing| -> Vec<String> {
+            listing.entries.into_iter().map(|e| e.name).collect()
+        };
+
+        let first = resolver.list_directory_merged("docs").await.unwrap();
+        assert_eq!(names(first), ["a.md", "b.md"]);
+        // Two source listings and the merge
+        assert_eq!(cache.len().await, 3);
+
+        let again = resolver.list_directory_merged("docs").await.unwrap();
+        assert_eq!(names(again), ["a.md", "b.md"]);
+        assert_eq!(a.listings.load(Ordering::SeqCst), 1);
+        assert_eq!(b.listings.load(Ordering::SeqCst), 1);
+        assert_eq!(a.not_modified.load(Ordering::SeqCst), 1);
+        // Same answers, same merge key
+        assert_eq!(cache.len().await, 3);
+
+        // Only the changed source lists again
+        b.replace(MemorySource::new().with_file("docs/b.md", "b").with_file("docs/c.md", "c"), "\"b2\"");
+        let changed = resolver.list_directory_merged("docs").await.unwrap();
+        assert_eq!(names(changed), ["a.md", "b.md", "c.md"]);
+        assert_eq!(a.listings.load(Ordering::SeqCst), 1);
+        assert_eq!(b.listings.load(Ordering::SeqCst), 2);
+        // The new merge replaces the old one
+        assert_eq!(cache.len().await, 3);
+
+        // Invalidating the directory, or a file in it, drops its listings
+        assert!(!resolver.invalidate("docs").await.unwrap());
+        assert_eq!(cache.len().await, 0);
+        resolver.list_directory_merged("docs").await.unwrap();
+        assert_eq!(a.listings.load(Ordering::SeqCst), 2);
+        resolver.invalidate("docs/a.md").await.unwrap();
+        assert_eq!(cache.len().await, 0);
+
+        // Without ETags every source lists every time, and the merge isn't cached
+        let plain = ResourceResolver::with_cache(
+            vec![a.clone(), Arc::new(MemorySource::new().with_file("docs/d.md", "d"))],
+            Arc::new(MemoryCache::new()),
+        );
+        for _ in 0..2 {
+            let merged = plain.list_directory_merged("docs").await.unwrap();
+            assert_eq!(names(merged), ["a.md", "d.md"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_revalidated_listing_error_context() {
+        let source = FaultInjectingSource::new(Arc::new(MemorySource::new().with_file("docs/a.md", "a")));
+        source.set_fail_first(usize::MAX, || ContentError::Timeout {
+            path: "docs".to_string(),
+            after: None,
+        });
+        for resolver in [
+            ResourceResolver::new(vec![Arc::new(MemorySource::new())]),
+            ResourceResolver::with_cache(vec![Arc::new(MemorySource::new())], Arc::new(MemoryCache::new())),
+        ] {
+            let err = resolver.revalidated_listing(&source, "docs").await.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::Timeout);
+            assert_eq!(err.source_id(), Some(source.identifier().as_str()));
+            assert_eq!(err.path(), Some("docs"));
+        }
+    }
+
+    /// Move the modification time of every file below `dir` back by `by`
+    #[cfg(feature = "fs")]
+    fn age_files(dir: &std::path::Path, by: Duration) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                age_files(&path, by);
+                continue;
+            }
+            let file = std::fs::File::options().write(true).open(&path).unwrap();
+            let modified = file.metadata().unwrap().modified().unwrap();
+            file.set_modified(modified - by).unwrap();
+        }
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_not_modified_listing_restarts_ttl() {
+        let a = Arc::new(RevalidatingSource::new("a", MemorySource::new().with_file("docs/a.md", "a")));
+        let dir = tempfile::tempdir().unwrap();
+        let cache = crate::cache::DiskCache::new(dir.path().to_path_buf())
+            .await
+            .unwrap()
+            .with_ttl(Duration::from_secs(3600));
+        let resolver = ResourceResolver::with_cache(vec![a.clone() as Arc<dyn ContentSource>], Arc::new(cache));
+
+        resolver.list_directory_merged("docs").await.unwrap();
+        age_files(dir.path(), Duration::from_secs(50 * 60));
+        resolver.list_directory_merged("docs").await.unwrap();
+        assert_eq!(a.not_modified.load(Ordering::SeqCst), 1);
+
+        // 70 minutes after the listings were stored, but 20 after the 304
+        age_files(dir.path(), Duration::from_secs(20 * 60));
+        let cache = resolver.cache.as_ref().unwrap();
+        assert!(cache.contains(&merged_key("docs")).await);
+        resolver.list_directory_merged("docs").await.unwrap();
+        assert_eq!(a.listings.load(Ordering::SeqCst), 1);
+        assert_eq!(a.not_modified.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_list_directory_merged_priority_and_limit() {
+        let dir_then_file = || -> Vec<Arc<dyn ContentSource>> {
+            vec![
+                Arc::new(MemorySource::new().with_file("docs/x/y", "1").with_file("docs/b", "1")),
+                Arc::new(
+                    MemorySource::new()
+                        .with_file("docs/x", "2")
+                        .with_file("docs/a", "2")
+                        .with_file("docs/c", "2"),
+                ),
+            ]
+        };
+
+        // The first source's entry wins, whatever the type
+        let merged = ResourceResolver::new(dir_then_file())
+            .list_directory_merged("docs")
+            .await
+            .unwrap();
+        let kinds: Vec<_> = merged
+            .entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.entry_type.clone()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("a", EntryType::File),
+                ("b", EntryType::File),
+                ("c", EntryType::File),
+                ("x", EntryType::Dir),
+            ]
+        );
+        assert!(!merged.truncated);
+
+        // The limit keeps the first paths in order, still with priority
+        let resolver = ResourceResolver::new(dir_then_file()).with_entry_limit(3);
+        let merged = resolver.list_directory_merged("docs").await.unwrap();
+        let names: Vec<_> = merged.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert!(merged.truncated);
+
+        let resolver = ResourceResolver::new(dir_then_file()).with_entry_limit(4);
+        let merged = resolver.list_directory_merged("docs").await.unwrap();
+        assert_eq!(merged.len(), 4);
+        assert_eq!(merged.find("x").unwrap().entry_type, EntryType::Dir);
+        assert!(!merged.truncated);
+    }
+
+    #[tokio::test]
+    async fn test_list_directory_paginated() {
+        let source = ["e", "b", "d", "a", "c"]
+            .iter()
+            .fold(MemorySource::new(), |source, name| {
+                source.with_file(&format!("docs/{}", name), "x")
+            });
+        let resolver = ResourceResolver::new(vec![Arc::new(source) as Arc<dyn ContentSource>]);
+
+        let mut names = Vec::new();
+        let mut cursor = None;
+        loop {
+            let (page, next) = resolver
+                .list_directory_paginated("/docs/", cursor, 2)
+                .await
+                .unwrap();
+            assert_eq!(page.path, "docs");
+            assert!(page.len() <= 2);
+            names.push(page.entries.iter().map(|e| e.name.clone()).collect::<Vec<_>>());
+            cursor = match next {
+                Some(next) => Some(next),
+                None => break,
+            };
+        }
+        assert_eq!(names, [vec!["a", "b"], vec!["c", "d"], vec!["e"]]);
+
+        // A cursor resumes after its name, even if earlier entries are gone
+        let (_, cursor) = resolver.list_directory_paginated("docs", None, 2).await.unwrap();
+        let shrunk = ResourceResolver::new(vec![Arc::new(
+            MemorySource::new().with_file("docs/c", "x").with_file("docs/d", "x"),
+        ) as Arc<dyn ContentSource>]);
+        let (page, next) = shrunk.list_directory_paginated("docs", cursor, 2).await.unwrap();
+        let names: Vec<_> = page.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["c", "d"]);
+        assert_eq!(next, None);
+
+        let error = resolver.list_directory_paginated("docs", None, 0).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidConfig);
+        let error = resolver.list_directory_paginated("nope", None, 2).await.unwrap_err();
+        assert!(error.is_not_found());
+    }
+
+    #[tokio::test]
+    async fn test_extension_map_overrides_content_type() {
+        let source = MemorySource::new()
+            .with_file("i18n/en.lang", "{}")
+            .with_file("i18n/FR.LANG", "{}")
+            .with_file("i18n/notes.txt", "notes");
+        let resolver = ResourceResolver::with_cache(
+            vec![Arc::new(source) as Arc<dyn ContentSource>],
+            Arc::new(MemoryCache::new()),
+        )
+        .with_extension_map([(".lang", "application/json")]);
+
+        for _ in 0..2 {
+            let file = resolver.fetch_file("i18n/en.lang").await.unwrap();
+            assert_eq!(file.content_type.as_deref(), Some("application/json"));
+        }
+        let file = resolver.fetch_file("i18n/FR.LANG").await.unwrap();
+        assert_eq!(file.content_type.as_deref(), Some("application/json"));
+        assert_eq!(resolver.fetch_file("i18n/notes.txt").await.unwrap().content_type, None);
+
+        let metadata = resolver.stat("i18n/en.lang").await.unwrap();
+        assert_eq!(metadata.content_type.as_deref(), Some("application/json"));
+        assert_eq!(resolver.stat("i18n").await.unwrap().content_type, None);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_files() {
+        let resolver = ResourceResolver::new(vec![
+            Arc::new(MemorySource::new().with_file("a", "1").with_file("c", "3")) as Arc<dyn ContentSource>,
+        ]);
+        let outcome = resolver.fetch_files(&["c", "b", "a", "../d"]).await;
+        let fetched: Vec<_> = outcome
+            .succeeded
+            .iter()
+            .map(|(path, file)| (path.as_str(), file.content.clone()))
+            .collect();
+        assert_eq!(fetched, vec![("c", Bytes::from("3")), ("a", Bytes::from("1"))]);
+
+        let failed: Vec<_> = outcome.failed.iter().map(|(p, e)| (p.as_str(), e.kind())).collect();
+        assert_eq!(
+            failed,
+            vec![("b", crate::ErrorKind::NotFound), ("../d", crate::ErrorKind::InvalidPath)]
+        );
+        assert!(outcome.ok_or_first_error().unwrap_err().is_not_found());
+
+        let owned = vec!["a".to_string()];
+        assert!(resolver.fetch_files(&owned).await.is_complete());
+    }
+
+    #[tokio::test]
+    async fn test_empty_as_not_found() {
+        let sources = || {
+            vec![
+                Arc::new(MemorySource::new().with_file("app.toml", "")) as Arc<dyn ContentSource>,
+                Arc::new(MemorySource::new().with_file("app.toml", "real")),
+            ]
+        };
+
+        // Off by default: the empty file is a valid answer
+        let resolver = ResourceResolver::new(sources());
+        assert!(resolver.fetch_file("app.toml").await.unwrap().content.is_empty());
+
+        let resolver = ResourceResolver::new(sources()).with_empty_as_not_found(true);
+        assert_eq!(resolver.fetch_file("app.toml").await.unwrap().content, "real");
+
+        let resolver = ResourceResolver::new(vec![
+            Arc::new(MemorySource::new().with_file("app.toml", "")) as Arc<dyn ContentSource>,
+        ])
+        .with_empty_as_not_found(true);
+        assert!(resolver.fetch_file("app.toml").await.unwrap_err().is_not_found());
+        // Listings still show the file
+        assert!(resolver.list_directory("").await.unwrap().contains("app.toml"));
+    }
+
+    #[test]
+    fn test_try_new_rejects_empty_sources() {
+        let error = ResourceResolver::try_new(Vec::new()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidConfig);
+        let error = ResourceResolver::try_with_cache(Vec::new(), Arc::new(MemoryCache::new()))
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidConfig);
+        assert!(ResourceResolver::new(Vec::new()).is_empty());
+
+        let resolver =
+            ResourceResolver::try_new(vec![Arc::new(MemorySource::new()) as Arc<dyn ContentSource>])
+                .unwrap();
+        assert!(!resolver.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_prefix() {
+        let source = MemorySource::new()
+            .with_file("docs/a.md", "a")
+            .with_file("docs/sub/b.md", "b")
+            .with_file("other.md", "o");
+        let cache = Arc::new(MemoryCache::new());
+        let resolver = ResourceResolver::with_cache(vec![Arc::new(source)], cache.clone());
+
+        for path in ["docs/a.md", "docs/sub/b.md", "other.md"] {
+            resolver.fetch_file(path).await.unwrap();
+        }
+
+        assert!(resolver.invalidate("/other.md").await.unwrap());
+        assert!(!resolver.invalidate("other.md").await.unwrap());
+        assert_eq!(resolver.invalidate_prefix("docs").await.unwrap(), 2);
+        assert_eq!(resolver.invalidate_prefix("missing").await.unwrap(), 0);
+        assert!(cache.export().await.is_empty());
+
+        let uncached = ResourceResolver::new(vec![Arc::new(MemorySource::new())]);
+        assert_eq!(uncached.invalidate_prefix("docs").await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_is_cache_stale() {
+        let source = Arc::new(FaultInjectingSource::new(Arc::new(
+            MemorySource::new().with_file("a.txt", "v1").with_file("b.txt", "b"),
+        )));
+        let cache = Arc::new(MemoryCache::new());
+        let resolver = ResourceResolver::with_cache(vec![source.clone()], cache.clone());
+
+        assert!(!resolver.is_cache_stale("a.txt").await.unwrap());
+        resolver.fetch_file("a.txt").await.unwrap();
+        assert!(!resolver.is_cache_stale("/a.txt").await.unwrap());
+
+        cache.set("file:a.txt", Bytes::from("v0")).await.unwrap();
+        assert!(resolver.is_cache_stale("a.txt").await.unwrap());
+        // The check doesn't refresh the entry
+        assert_eq!(cache.get("file:a.txt").await.unwrap().unwrap(), "v0");
+
+        cache.set("file:gone.txt", Bytes::from("x")).await.unwrap();
+        assert!(resolver.is_cache_stale("gone.txt").await.unwrap());
+
+        source.set_fail_first(usize::MAX, || ContentError::Cache {
+            message: "down".to_string(),
+        });
+        assert!(resolver.is_cache_stale("a.txt").await.is_err());
+    }
+
+    /// Source answering `stat` with the current ETag, counting full fetches
+    #[derive(Default)]
+    struct StatSource {
+        etag: Mutex<String>,
+        fetches: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ContentSource for StatSource {
+        async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+            self.fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(FileContent::new("content", path).with_etag(self.etag.lock().unwrap().clone()))
+        }
+
+        async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+            Err(ContentError::NotFound {
+                path: path.to_string(),
+            })
+        }
+
+        async fn stat(&self, _path: &str) -> Result<FileMetadata> {
+            Ok(FileMetadata::file().with_size(7).with_etag(self.etag.lock().unwrap().clone()))
+        }
+
+        fn identifier(&self) -> String {
+            "stat".to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_is_cache_stale_compares_metadata() {
+        use std::sync::atomic::Ordering;
+
+        let source = Arc::new(StatSource::default());
+        *source.etag.lock().unwrap() = "\"v1\"".to_string();
+        let cache = Arc::new(MemoryCache::new());
+        let resolver = ResourceResolver::with_cache(vec![source.clone()], cache.clone());
+        resolver.fetch_file("a.txt").await.unwrap();
+        let fetched = || source.fetches.load(Ordering::SeqCst);
+
+        // ETags decide without fetching the file again
+        assert!(!resolver.is_cache_stale("a.txt").await.unwrap());
+        *source.etag.lock().unwrap() = "\"v2\"".to_string();
+        assert!(resolver.is_cache_stale("a.txt").await.unwrap());
+        assert_eq!(fetched(), 1);
+
+        // Without a cached ETag a different size is enough...
+        cache.set("file:b.txt", Bytes::from("longer content")).await.unwrap();
+        assert!(resolver.is_cache_stale("b.txt").await.unwrap());
+        assert_eq!(fetched(), 1);
+        // ...and only a same-sized copy needs the content compared
+        cache.set("file:c.txt", Bytes::from("content")).await.unwrap();
+        assert!(!resolver.is_cache_stale("c.txt").await.unwrap());
+        assert_eq!(fetched(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_source_first_read_mode() {
+        let source = Arc::new(FaultInjectingSource::new(Arc::new(
+            MemorySource::new()
+                .with_file("a.txt", "fresh")
+                .with_file("b.txt", "b"),
+        )));
+        let cache = Arc::new(MemoryCache::new());
+        let resolver = ResourceResolver::with_cache(vec![source.clone()], cache.clone())
+            .with_read_mode(ReadMode::SourceFirstThenCache {
+                timeout: Duration::from_millis(100),
+            });
+
+        // A quick source wins over the cache, and refreshes it
+        cache.set("file:a.txt", Bytes::from("old")).await.unwrap();
+        let file = resolver.fetch_file("a.txt").await.unwrap();
+        assert_eq!(file.content, "fresh");
+        assert_eq!(file.source_path, "memory:a.txt");
+        assert_eq!(cache.get("file:a.txt").await.unwrap().unwrap(), "fresh");
+
+        // A slow one loses to the cached copy
+        cache.set("file:a.txt", Bytes::from("old")).await.unwrap();
+        source.set_latency(Duration::from_secs(1));
+        let started = tokio::time::Instant::now();
+        let file = resolver.fetch_file("a.txt").await.unwrap();
+        assert_eq!(file.content, "old");
+        assert_eq!(file.source_path, "cache:a.txt");
+        assert_eq!(started.elapsed(), Duration::from_millis(100));
+
+        // With nothing cached, the slow fetch is waited for
+        let file = resolver.fetch_file("b.txt").await.unwrap();
+        assert_eq!(file.content, "b");
+        assert_eq!(started.elapsed(), Duration::from_millis(1100));
+
+        // Failures fall back to the cache too, but NotFound doesn't
+        source.set_latency(Duration::ZERO);
+        source.set_fail_first(usize::MAX, || ContentError::UpstreamStatus {
+            path: "a.txt".to_string(),
+            status: 502,
+            message: "bad gateway".to_string(),
+        });
+        assert_eq!(resolver.fetch_file("a.txt").await.unwrap().content, "old");
+        source.clear_faults();
+        cache.set("file:gone.txt", Bytes::from("x")).await.unwrap();
+        assert!(resolver.fetch_file("gone.txt").await.unwrap_err().is_not_found());
+
+        // Cache-first, the default, never asks the source on a hit
+        let calls = source.call_count();
+        let cache_first = resolver.with_read_mode(ReadMode::CacheFirst);
+        assert_eq!(cache_first.fetch_file("a.txt").await.unwrap().content, "old");
+        assert_eq!(source.call_count(), calls);
+    }
+
+    /// Cache whose entries all count as expired once `expire` is called
+    #[derive(Default)]
+    struct ExpiringCache {
+        inner: MemoryCache,
+        expired: std::sync::atomic::AtomicBool,
+    }
+
+    impl ExpiringCache {
+        fn expire(&self) {
+            self.expired.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+
+        fn is_expired(&self) -> bool {
+            self.expired.load(std::sync::atomic::Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait]
+    impl Cache for ExpiringCache {
+        async fn get(&self, key: &str) -> Result<Option<Bytes>> {
+            match self.is_expired() {
+                true => Ok(None),
+                false => self.inner.get(key).await,
+            }
+        }
+
+        async fn get_stale(&self, key: &str) -> Result<Option<Bytes>> {
+            self.inner.get(key).await
+        }
+
+        async fn set(&self, key: &str, value: Bytes) -> Result<()> {
+            self.inner.set(key, value).await
+        }
+
+        async fn contains(&self, key: &str) -> bool {
+            !self.is_expired() && self.inner.contains(key).await
+        }
+
+        async fn remove(&self, key: &str) -> Result<()> {
+            self.inner.remove(key).await
+        }
+
+        async fn clear(&self) -> Result<()> {
+            self.inner.clear().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_serve_stale_on_error() {
+        let cache = Arc::new(ExpiringCache::default());
+        let source = Arc::new(FaultInjectingSource::new(Arc::new(
+            MemorySource::new().with_file("a.txt", "v1"),
+        )));
+        let resolver = ResourceResolver::with_cache(vec![source.clone()], cache.clone())
+            .with_serve_stale_on_error(true);
+
+        let fresh = resolver.fetch_file("a.txt").await.unwrap();
+        assert!(!fresh.stale);
+        cache.expire();
+
+        source.set_fail_first(usize::MAX, || ContentError::Timeout {
+            path: "a.txt".to_string(),
+            after: None,
+        });
+        let stale = resolver.fetch_file("a.txt").await.unwrap();
+        assert!(stale.stale);
+        assert_eq!(stale.content, "v1");
+        assert_eq!(stale.source_path, "cache:a.txt");
+
+        // Nothing cached, so the error comes through
+        let err = resolver.fetch_file("b.txt").await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+
+        // Errors that aren't retryable are returned as-is
+        source.set_fail_first(usize::MAX, || ContentError::UpstreamStatus {
+            path: "a.txt".to_string(),
+            status: 403,
+            message: "forbidden".to_string(),
+        });
+        assert!(resolver.fetch_file("a.txt").await.is_err());
+
+        let strict = resolver.clone().with_serve_stale_on_error(false);
+        source.set_fail_first(usize::MAX, || ContentError::Timeout {
+            path: "a.txt".to_string(),
+            after: None,
+        });
+        assert!(strict.fetch_file("a.txt").await.is_err());
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_explain() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = crate::cache::DiskCache::new(dir.path().to_path_buf())
+            .await
+            .unwrap()
+            .with_ttl(std::time::Duration::from_millis(50));
+        let cache: Arc<dyn Cache> = Arc::new(cache);
+        let primary = Arc::new(FaultInjectingSource::new(Arc::new(
+            MemorySource::new().with_file("a.txt", "v1").with_file("p.txt", "p"),
+        )));
+        let fallback = Arc::new(MemorySource::new().with_file("a.txt", "old"));
+        let resolver = ResourceResolver::with_cache(
+            vec![primary.clone() as Arc<dyn ContentSource>, fallback],
+            cache.clone(),
+        )
+        .with_serve_stale_on_error(true);
+
+        // Every source is asked, and nothing is cached
+        let explanation = resolver.explain("/a.txt").await.unwrap();
+        assert_eq!(explanation.path, "a.txt");
+        assert_eq!(explanation.cache.as_ref().unwrap().state, CacheState::Missing);
+        assert_eq!(
+            explanation.sources[1].outcome,
+            SourceOutcome::Found { size: Some(3), etag: None }
+        );
+        assert!(matches!(explanation.resolution, Resolution::Source { index: 0, .. }));
+        assert!(!cache.contains("file:a.txt").await);
+
+        resolver.fetch_file("a.txt").await.unwrap();
+        resolver.fetch_file("p.txt").await.unwrap();
+        let text = resolver.explain("a.txt").await.unwrap().to_string();
+        assert!(text.contains("cache: fresh, 2 bytes (file:a.txt)"), "{}", text);
+        assert!(text.contains("resolution: cache"), "{}", text);
+
+        // With the primary down, the fallback's copy wins over the stale one
+        tokio::time::sleep(std::time::Duration::from_millis(80)).await;
+        primary.set_fail_first(usize::MAX, || ContentError::Timeout {
+            path: "a.txt".to_string(),
+            after: None,
+        });
+        let text = resolver.explain("a.txt").await.unwrap().to_string();
+        assert!(text.contains("cache: stale"), "{}", text);
+        assert!(text.contains("failed (retryable): Timed out"), "{}", text);
+        assert!(text.contains("resolution: source 2 memory"), "{}", text);
+        assert!(text.contains("reason: no earlier source has the file"), "{}", text);
+
+        let explanation = resolver.explain("p.txt").await.unwrap();
+        assert_eq!(explanation.resolution, Resolution::StaleCache);
+        assert!(explanation.reason.contains("serving stale copies on error"));
+
+        let json = serde_json::to_value(resolver.explain("missing.txt").await.unwrap()).unwrap();
+        assert_eq!(json["resolution"]["from"], "error");
+        assert_eq!(json["resolution"]["kind"], "timeout");
+        assert_eq!(json["sources"][1]["outcome"], "not_found");
+        assert_eq!(json["cache"]["state"], "missing");
+    }
+
+    #[tokio::test]
+    async fn test_cache_admission() {
+        use crate::admission::{All, GlobDeny, MaxSize};
+
+        let source = Arc::new(FaultInjectingSource::new(Arc::new(
+            MemorySource::new()
+                .with_file("docs/a.md", "a")
+                .with_file("tmp/scratch.txt", "s")
+                .with_file("big.bin", vec![0u8; 64]),
+        )));
+        let cache: Arc<dyn Cache> = Arc::new(MemoryCache::new());
+        let policy = All(vec![
+            Box::new(GlobDeny::new(["tmp/**"]).unwrap()),
+            Box::new(MaxSize(16)),
+        ]);
+        let resolver = ResourceResolver::with_cache(vec![source.clone()], cache.clone())
+            .with_cache_admission(policy);
+
+        for _ in 0..3 {
+            assert_eq!(resolver.fetch_file("docs/a.md").await.unwrap().content, "a");
+        }
+        assert_eq!(source.call_count(), 1);
+
+        for _ in 0..3 {
+            assert_eq!(resolver.fetch_file("/tmp//scratch.txt").await.unwrap().content, "s");
+        }
+        assert_eq!(source.call_count(), 4);
+        assert!(!cache.contains("file:tmp/scratch.txt").await);
+
+        let mut out = Vec::new();
+        resolver.fetch_to_writer("big.bin", &mut out).await.unwrap();
+        resolver.fetch_file("big.bin").await.unwrap();
+        assert_eq!(source.call_count(), 6);
+        assert!(!cache.contains("file:big.bin").await);
+
+        assert_eq!(resolver.stats().cache_rejections, 5);
+        assert_eq!(resolver.clone().stats(), resolver.stats());
+    }
+
+    #[tokio::test]
+    async fn test_cache_scope_isolates_shared_cache() {
+        let branch = |content: &'static str| -> Vec<Arc<dyn ContentSource>> {
+            vec![Arc::new(MemorySource::new().with_file("a.txt", content))]
+        };
+        let cache: Arc<dyn Cache> = Arc::new(MemoryCache::new());
+
+        // Without scopes the second resolver is served the first one's file
+        let main = ResourceResolver::with_cache(branch("main"), cache.clone());
+        let dev = ResourceResolver::with_cache(branch("dev"), cache.clone());
+        assert_eq!(main.fetch_file("a.txt").await.unwrap().content, "main");
+        assert_eq!(dev.fetch_file("a.txt").await.unwrap().content, "main");
+
+        cache.clear().await.unwrap();
+        let main = main.with_cache_scope("main");
+        let dev = dev.with_cache_scope("dev");
+        assert_eq!(main.fetch_file("a.txt").await.unwrap().content, "main");
+        assert_eq!(dev.fetch_file("a.txt").await.unwrap().content, "dev");
+        assert_eq!(main.fetch_file("a.txt").await.unwrap().source_path, "cache:a.txt");
+        assert_eq!(dev.fetch_file("a.txt").await.unwrap().content, "dev");
+        assert!(cache.contains("main:file:a.txt").await);
+        assert!(!cache.contains("file:a.txt").await);
+
+        // Invalidation stays within the scope
+        assert!(dev.invalidate("a.txt").await.unwrap());
+        assert!(cache.contains("main:file:a.txt").await);
+
+        // Scopes derived from the sources differ with the sources
+        let a = ResourceResolver::new(branch("x")).with_source_cache_scope();
+        let b = ResourceResolver::new(vec![Arc::new(MockSource { files: vec![] })])
+            .with_source_cache_scope();
+        assert_ne!(a.cache_scope(), b.cache_scope());
+        let same_sources = ResourceResolver::new(branch("y")).with_source_cache_scope();
+        assert_eq!(a.cache_scope(), same_sources.cache_scope());
+        assert_eq!(a.clone().with_cache_scope("").cache_scope(), None);
+    }
+
+    /// Source whose files carry an ETag and MIME type
+    struct TaggedSource;
+
+    #[async_trait]
+    impl ContentSource for TaggedSource {
+        async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+            Ok(FileContent::new("tagged", path)
+                .with_etag("\"v1\"")
+                .with_content_type("text/plain"))
+        }
+
+        async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+            Err(ContentError::NotFound {
+                path: path.to_string(),
+            })
+        }
+
+        fn identifier(&self) -> String {
+            "tagged".to_string()
+        }
+    }
+
+    /// Check that the ETag survives a round trip through `cache`
+    async fn assert_etag_survives_cache(cache: Arc<dyn Cache>) {
+        let resolver = ResourceResolver::with_cache(vec![Arc::new(TaggedSource)], cache.clone());
+
+        assert_eq!(resolver.fetch_file("a.txt").await.unwrap().etag.as_deref(), Some("\"v1\""));
+        let hit = resolver.fetch_file("a.txt").await.unwrap();
+        assert_eq!(hit.source_path, "cache:a.txt");
+        assert_eq!(hit.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(hit.content_type.as_deref(), Some("text/plain"));
+
+        // Invalidation drops the metadata with the content
+        assert!(resolver.invalidate("a.txt").await.unwrap());
+        assert!(!cache.contains("meta:a.txt").await);
+
+        // Content cached without metadata, e.g. by an older version, still hits
+        cache.set("file:b.txt", Bytes::from("old")).await.unwrap();
+        let hit = resolver.fetch_file("b.txt").await.unwrap();
+        assert_eq!(hit.content, "old");
+        assert!(hit.etag.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_etag_survives_memory_cache() {
+        assert_etag_survives_cache(Arc::new(MemoryCache::new())).await;
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_uncacheable_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("override.txt"), "v1").unwrap();
+        let local = crate::local::LocalFileSource::new(dir.path()).with_cacheable(false);
+        let remote = Arc::new(MockSource {
+            files: vec![("a.txt", "remote")],
+        });
+        let cache = Arc::new(MemoryCache::new());
+        let sources: Vec<Arc<dyn ContentSource>> = vec![Arc::new(local), remote];
+        let summary = VirtualFileRule::new("summary.txt", |resolver, _| {
+            Box::pin(async move { Ok(resolver.fetch_file("override.txt").await?.content) })
+        })
+        .unwrap();
+        let resolver = ResourceResolver::with_cache(sources.clone(), cache.clone())
+            .with_virtual_file(summary);
+
+        // Local files are read fresh every time
+        assert_eq!(resolver.fetch_file("override.txt").await.unwrap().text().unwrap(), "v1");
+        assert!(!cache.contains("file:override.txt").await);
+        std::fs::write(dir.path().join("override.txt"), "v2").unwrap();
+        assert_eq!(resolver.fetch_file("override.txt").await.unwrap().text().unwrap(), "v2");
+        let mut out = Vec::new();
+        resolver.fetch_to_writer("override.txt", &mut out).await.unwrap();
+        assert_eq!(out, b"v2");
+        assert!(!cache.contains("file:override.txt").await);
+        // So are virtual files built from them
+        assert_eq!(resolver.fetch_file("summary.txt").await.unwrap().text().unwrap(), "v2");
+        assert!(!cache.contains("file:summary.txt").await);
+
+        resolver.fetch_file("a.txt").await.unwrap();
+        assert!(cache.contains("file:a.txt").await);
+
+        // An override added later is hidden by the cached copy...
+        std::fs::write(dir.path().join("a.txt"), "local").unwrap();
+        assert_eq!(resolver.fetch_file("a.txt").await.unwrap().text().unwrap(), "remote");
+        // ...unless the local source is read ahead of the cache
+        let resolver = ResourceResolver::with_cache(sources, cache.clone()).with_uncacheable_first(true);
+        assert_eq!(resolver.fetch_file("a.txt").await.unwrap().text().unwrap(), "local");
+        let mut out = Vec::new();
+        resolver.fetch_to_writer("a.txt", &mut out).await.unwrap();
+        assert_eq!(out, b"local");
+        std::fs::remove_file(dir.path().join("a.txt")).unwrap();
+        assert_eq!(resolver.fetch_file("a.txt").await.unwrap().text().unwrap(), "remote");
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_etag_survives_disk_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = crate::cache::DiskCache::new(dir.path().to_path_buf()).await.unwrap();
+        assert_etag_survives_cache(Arc::new(cache)).await;
+    }
+
+    mod normalization_properties {
+        use super::*;
+        use proptest::prelude::*;
+
+        /// Paths mixing both separators with `.`, `..` and empty segments
+        fn raw_path() -> impl Strategy<Value = String> {
+            prop::collection::vec(
+                prop::sample::select(vec!["a", "b", ".", "..", "", "c.d", "/", "\\"]),
+                0..10,
+            )
+            .prop_map(|parts| parts.concat())
+        }
+
+        fn resolver(separators: SeparatorPolicy) -> ResourceResolver {
+            ResourceResolver::new(vec![Arc::new(MemorySource::new()) as Arc<dyn ContentSource>])
+                .with_separator_policy(separators)
+        }
+
+        proptest! {
+            #[test]
+            fn normalize_path_is_idempotent(raw in raw_path()) {
+                for separators in [SeparatorPolicy::Slash, SeparatorPolicy::SlashOrBackslash] {
+                    let resolver = resolver(separators);
+                    if let Ok(once) = resolver.normalize_path(&raw) {
+                        prop_assert_eq!(resolver.normalize_path(once.as_str()).unwrap(), once);
+                    }
+                }
+            }
+
+            #[test]
+            fn backslashes_normalize_like_slashes(raw in raw_path()) {
+                let windows = resolver(SeparatorPolicy::SlashOrBackslash).normalize_path(&raw);
+                let unix = resolver(SeparatorPolicy::Slash).normalize_path(&raw.replace('\\', "/"));
+                prop_assert_eq!(windows.ok(), unix.ok());
+            }
+
+            #[test]
+            fn cache_keys_follow_normalized_paths(a in raw_path(), b in raw_path()) {
+                let resolver = resolver(SeparatorPolicy::SlashOrBackslash);
+                if let (Ok(a), Ok(b)) = (resolver.normalize_path(&a), resolver.normalize_path(&b)) {
+                    prop_assert_eq!(cache_key(a.as_str()) == cache_key(b.as_str()), a == b);
+                }
+            }
+        }
+    }
+
 }
@@ -0,0 +1,202 @@
+use serde::Serialize;
+
+use crate::error::{ContentError, ErrorKind};
+use crate::types::ContentPath;
+
+/// Client-facing rendering of a `ContentError`
+///
+/// Reports the error kind, the content path and a fixed message per kind.
+/// The error's own message is never exposed, since it can carry internal
+/// details such as upstream URLs, tokens or cache locations.
+#[derive(Debug)]
+pub struct ErrorResponse(pub ContentError);
+
+/// JSON body of an `ErrorResponse`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorBody {
+    /// Error kind, e.g. `not_found`
+    pub error: String,
+    /// Content path the error relates to, when known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Human-readable description
+    pub message: String,
+}
+
+impl ErrorResponse {
+    /// HTTP status code of the response
+    pub fn status(&self) -> u16 {
+        self.0.http_status()
+    }
+
+    /// Build the JSON body of the response
+    pub fn body(&self) -> ErrorBody {
+        let kind = self.0.kind();
+        ErrorBody {
+            error: kind.as_str().to_string(),
+            path: self.0.path().filter(|p| is_public_path(p)).map(String::from),
+            message: public_message(kind).to_string(),
+        }
+    }
+}
+
+impl From<ContentError> for ErrorResponse {
+    fn from(error: ContentError) -> Self {
+        Self(error)
+    }
+}
+
+/// Content paths are safe to echo back; URLs and query strings may carry credentials
+///
+/// Only paths already in `ContentPath` form qualify, so filesystem paths,
+/// `..` segments and URLs are left out, as are segments that look like a
+/// URL scheme or carry a query.
+fn is_public_path(path: &str) -> bool {
+    match ContentPath::parse(path) {
+        Ok(normalized) => {
+            normalized.as_str() == path
+                && !normalized.is_root()
+                && normalized.segments().all(|s| !s.ends_with(':') && !s.contains('?'))
+        }
+        Err(_) => false,
+    }
+}
+
+fn public_message(kind: ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::NotFound => "The requested content does not exist",
+        ErrorKind::NotADirectory => "The requested path is not a directory",
+        ErrorKind::RateLimited => "The content service is rate limiting requests",
+        ErrorKind::Timeout => "The content service timed out",
+        ErrorKind::Network => "The content service could not be reached",
+        ErrorKind::UpstreamStatus | ErrorKind::InvalidStructure => {
+            "The content service returned an unexpected response"
+        }
+        ErrorKind::TooLarge => "The requested content exceeds the size limit",
+        ErrorKind::Validation => "The requested content failed validation",
+        ErrorKind::IntegrityMismatch => "The requested content failed its integrity check",
+        ErrorKind::InvalidPath => "The requested path is invalid",
+        ErrorKind::Serialization => "The requested content could not be parsed",
+        ErrorKind::Unsupported => "The requested operation is not supported",
+        ErrorKind::Io | ErrorKind::Cache | ErrorKind::InvalidConfig => "Internal error",
+    }
+}
+
+#[cfg(feature = "axum")]
+impl axum::response::IntoResponse for ErrorResponse {
+    fn into_response(self) -> axum::response::Response {
+        use axum::http::{header, HeaderValue, StatusCode};
+
+        let status =
+            StatusCode::from_u16(self.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut response = (status, axum::Json(self.body())).into_response();
+
+        if let Some(value) = self
+            .0
+            .retry_after_header_value()
+            .and_then(|v| HeaderValue::from_str(&v).ok())
+        {
+            response.headers_mut().insert(header::RETRY_AFTER, value);
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body_json(error: ContentError) -> String {
+        serde_json::to_string(&ErrorResponse(error).body()).unwrap()
+    }
+
+    #[test]
+    fn test_body_shape() {
+        let error = ContentError::NotFound {
+            path: "locales/en.lang".to_string(),
+        }
+        .with_source("github://org/repo/main/");
+
+        let body = ErrorResponse(error).body();
+        assert_eq!(body.error, "not_found");
+        assert_eq!(body.path.as_deref(), Some("locales/en.lang"));
+        assert_eq!(body.message, "The requested content does not exist");
+    }
+
+    #[test]
+    fn test_body_redacts_internal_details() {
+        let cases = [
+            ContentError::Io(std::io::Error::other(
+                "permission denied: /var/cache/content/6b2f.bin",
+            ))
+            .with_path("skills/tool/run.sh"),
+            ContentError::Cache {
+                message: "failed to write /home/svc/.cache/content".to_string(),
+            },
+            ContentError::InvalidStructure {
+                message: "Redirect rejected for https://cdn.example.com/a?token=s3cr3t".to_string(),
+            }
+            .with_source("github://org/repo/main/"),
+            ContentError::InvalidConfig {
+                message: "bad token ghp_s3cr3t".to_string(),
+            }
+            .with_path("https://api.example.com/x?access_token=s3cr3t"),
+        ];
+
+        for error in cases {
+            let json = body_json(error);
+            for leak in ["s3cr3t", "/var/", "/home/", "://", "github"] {
+                assert!(!json.contains(leak), "{} leaks {}", json, leak);
+            }
+        }
+    }
+
+    #[test]
+    fn test_body_echoes_only_normalized_paths() {
+        let path_of = |path: &str| {
+            ErrorResponse(ContentError::NotFound {
+                path: path.to_string(),
+            })
+            .body()
+            .path
+        };
+
+        assert_eq!(path_of("public/docs/a.md").as_deref(), Some("public/docs/a.md"));
+        for hidden in [
+            "public/../secret",
+            "/var/cache/content/6b2f.bin",
+            "public//a.md",
+            "./a.md",
+            "c:/windows/system32",
+            "https:/api.example.com/x",
+            "a.md?token=s3cr3t",
+            "",
+        ] {
+            assert_eq!(path_of(hidden), None, "{} is echoed", hidden);
+        }
+    }
+
+    #[cfg(feature = "axum")]
+    #[test]
+    fn test_into_response() {
+        use axum::response::IntoResponse;
+        use std::time::Duration;
+
+        let response = ErrorResponse(ContentError::RateLimited {
+            message: "token ghp_s3cr3t exhausted".to_string(),
+            retry_after: Some(Duration::from_secs(30)),
+        })
+        .into_response();
+
+        assert_eq!(response.status().as_u16(), 429);
+        assert_eq!(response.headers()["retry-after"], "30");
+        assert_eq!(response.headers()["content-type"], "application/json");
+
+        let response = ErrorResponse(ContentError::Cache {
+            message: "broken".to_string(),
+        })
+        .into_response();
+        assert_eq!(response.status().as_u16(), 500);
+        assert!(response.headers().get("retry-after").is_none());
+    }
+}
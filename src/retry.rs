@@ -0,0 +1,267 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{
+    error::{ContentError, Result},
+    hook::RequestContext,
+    source::{Capabilities, ContentSource, FetchOptions, Revalidated},
+    types::{ContentVersion, DirectoryListing, FileContent, FileMetadata},
+};
+
+/// Predicate deciding whether a failed operation should be retried
+pub type RetryPredicate = Arc<dyn Fn(&ContentError) -> bool + Send + Sync>;
+
+/// Retry configuration with exponential backoff
+#[derive(Clone)]
+pub struct RetryConfig {
+    /// Total number of attempts, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry
+    pub initial_delay: Duration,
+    /// Upper bound for the delay between attempts
+    pub max_delay: Duration,
+    /// Multiplier applied to the delay after each failed attempt
+    pub backoff_factor: f64,
+    /// Decides which errors are retried (defaults to `ContentError::is_retryable`)
+    pub predicate: RetryPredicate,
+}
+
+impl RetryConfig {
+    /// Replace the retry predicate
+    ///
+    /// The default predicate is `ContentError::is_retryable`. A custom predicate
+    /// fully overrides it, so callers that only want to extend the default
+    /// should call `is_retryable` themselves.
+    pub fn with_predicate<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&ContentError) -> bool + Send + Sync + 'static,
+    {
+        self.predicate = Arc::new(predicate);
+        self
+    }
+
+    /// Check whether an error should be retried under this configuration
+    pub fn should_retry(&self, error: &ContentError) -> bool {
+        (self.predicate)(error)
+    }
+
+    /// Compute the delay that follows `delay` after a failed attempt
+    fn next_delay(&self, delay: Duration) -> Duration {
+        std::cmp::min(
+            Duration::from_secs_f64(delay.as_secs_f64() * self.backoff_factor),
+            self.max_delay,
+        )
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            backoff_factor: 2.0,
+            predicate: Arc::new(ContentError::is_retryable),
+        }
+    }
+}
+
+impl std::fmt::Debug for RetryConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryConfig")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_delay", &self.initial_delay)
+            .field("max_delay", &self.max_delay)
+            .field("backoff_factor", &self.backoff_factor)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Content source decorator that retries failed operations with backoff
+pub struct RetryingSource {
+    inner: Arc<dyn ContentSource>,
+    config: RetryConfig,
+}
+
+impl RetryingSource {
+    /// Wrap a source with the given retry configuration
+    pub fn new(inner: Arc<dyn ContentSource>, config: RetryConfig) -> Self {
+        Self { inner, config }
+    }
+
+    /// The wrapped source
+    pub fn inner(&self) -> &Arc<dyn ContentSource> {
+        &self.inner
+    }
+
+    /// Run an operation, retrying while the predicate allows it
+    async fn run<T, F, Fut>(&self, mut op: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let mut attempts = 0;
+        let mut delay = self.config.initial_delay;
+
+        loop {
+            attempts += 1;
+
+            match op().await {
+                Ok(value) => return Ok(value),
+                Err(e) if attempts < self.config.max_attempts && self.config.should_retry(&e) => {
+                    // Wait as long as the service asked, within our own bound
+                    let wait = e
+                        .retry_after()
+                        .map_or(delay, |hint| hint.max(delay).min(self.config.max_delay));
+                    tokio::time::sleep(wait).await;
+                    delay = self.config.next_delay(delay);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ContentSource for RetryingSource {
+    async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+        self.run(|| self.inner.fetch_file(path)).await
+    }
+
+    async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+        self.run(|| self.inner.list_directory(path)).await
+    }
+
+    async fn list_directory_if_none_match(
+        &self,
+        path: &str,
+        etag: Option<&str>,
+    ) -> Result<Revalidated<DirectoryListing>> {
+        self.run(|| self.inner.list_directory_if_none_match(path, etag)).await
+    }
+
+    async fn fetch_file_with(&self, path: &str, options: &FetchOptions) -> Result<FileContent> {
+        self.run(|| self.inner.fetch_file_with(path, options)).await
+    }
+
+    async fn fetch_file_at(&self, path: &str, version: &ContentVersion) -> Result<FileContent> {
+        self.run(|| self.inner.fetch_file_at(path, version)).await
+    }
+
+    async fn list_directory_with(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+    ) -> Result<DirectoryListing> {
+        self.run(|| self.inner.list_directory_with(path, options)).await
+    }
+
+    async fn fetch_file_with_ctx(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+        ctx: &RequestContext,
+    ) -> Result<FileContent> {
+        self.run(|| self.inner.fetch_file_with_ctx(path, options, ctx))
+            .await
+    }
+
+    async fn list_directory_with_ctx(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+        ctx: &RequestContext,
+    ) -> Result<DirectoryListing> {
+        self.run(|| self.inner.list_directory_with_ctx(path, options, ctx))
+            .await
+    }
+
+    async fn list_directory_paginated(
+        &self,
+        path: &str,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<(DirectoryListing, Option<String>)> {
+        self.run(|| self.inner.list_directory_paginated(path, cursor.clone(), page_size))
+            .await
+    }
+
+    async fn stat(&self, path: &str) -> Result<FileMetadata> {
+        self.run(|| self.inner.stat(path)).await
+    }
+
+    fn identifier(&self) -> String {
+        format!("retry({})", self.inner.identifier())
+    }
+
+    /// The inner source's, except that writes are buffered to allow retries
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities().with_streaming(false)
+    }
+
+    fn cacheable(&self) -> bool {
+        self.inner.cacheable()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FaultInjectingSource, MemorySource};
+
+    /// Source that fails with `error` on its first `failures` requests
+    fn flaky(failures: usize, error: fn() -> ContentError) -> Arc<FaultInjectingSource> {
+        let source = FaultInjectingSource::new(Arc::new(
+            MemorySource::new().with_file("file.txt", "ok"),
+        ));
+        source.set_fail_first(failures, error);
+        Arc::new(source)
+    }
+
+    fn fast_config() -> RetryConfig {
+        RetryConfig {
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+            ..RetryConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_default_predicate() {
+        let flaky_source = flaky(2, || ContentError::RateLimited {
+            message: "slow down".to_string(),
+            retry_after: None,
+        });
+        let source = RetryingSource::new(flaky_source.clone(), fast_config());
+
+        assert!(source.fetch_file("file.txt").await.is_ok());
+        assert_eq!(flaky_source.call_count(), 3);
+
+        // InvalidStructure is not retried by default
+        let flaky_source = flaky(1, || ContentError::InvalidStructure {
+            message: "bad gateway".to_string(),
+        });
+        let source = RetryingSource::new(flaky_source.clone(), fast_config());
+
+        assert!(matches!(
+            source.fetch_file("file.txt").await,
+            Err(ContentError::InvalidStructure { .. })
+        ));
+        assert_eq!(flaky_source.call_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_custom_predicate() {
+        let flaky_source = flaky(1, || ContentError::InvalidStructure {
+            message: "bad gateway".to_string(),
+        });
+        let config = fast_config().with_predicate(|e| {
+            e.is_retryable() || matches!(e, ContentError::InvalidStructure { .. })
+        });
+        let source = RetryingSource::new(flaky_source.clone(), config);
+
+        assert!(source.fetch_file("file.txt").await.is_ok());
+        assert_eq!(flaky_source.call_count(), 2);
+    }
+}
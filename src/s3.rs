@@ -0,0 +1,448 @@
+//! Content stored in an S3 bucket
+
+use async_trait::async_trait;
+use aws_sdk_s3::{
+    config::http::HttpResponse,
+    error::{DisplayErrorContext, ProvideErrorMetadata, SdkError},
+    Client,
+};
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    error::{ContentError, Result, ResultExt},
+    source::ContentSource,
+    types::{ContentPath, DirectoryEntry, DirectoryListing, EntryType, FileContent, FileMetadata},
+};
+
+/// Content source reading objects from an S3 bucket
+/// 
+/// Paths map to object keys below an optional prefix, and directories to
+/// the key prefixes up to a `/`, like the S3 console shows them. Keys ending
+/// in `/`, which some tools create as directory markers, aren't listed.
+/// 
+/// ```no_run
+/// # fn run(client: aws_sdk_s3::Client) {
+/// use content_resolver::{ResourceResolver, S3Source};
+/// use std::sync::Arc;
+/// 
+/// // `client` built from `aws_config::load_from_env()`
+/// let source = S3Source::new(client, "content-bucket").with_prefix("site/v2");
+/// let resolver = ResourceResolver::new(vec![Arc::new(source)]);
+/// # }
+/// ```
+/// 
+/// The client retries throttling and transient failures itself, with its
+/// own retry settings. Failures it gives up on become `RateLimited`,
+/// `Timeout` or `UpstreamStatus`, and connection failures `Io`.
+#[derive(Debug, Clone)]
+pub struct S3Source {
+    client: Client,
+    bucket: String,
+    prefix: String,
+    max_file_size: Option<u64>,
+}
+
+impl S3Source {
+    /// Read objects from `bucket` through `client`
+    pub fn new(client: Client, bucket: impl Into<String>) -> Self {
+        Self {
+            client,
+            bucket: bucket.into(),
+            prefix: String::new(),
+            max_file_size: None,
+        }
+    }
+
+    /// Serve the keys below `prefix`, so `a.md` reads `{prefix}/a.md`
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Refuse objects larger than `limit` bytes with `TooLarge`
+    pub fn with_max_file_size(mut self, limit: u64) -> Self {
+        self.max_file_size = Some(limit);
+        self
+    }
+
+    /// Bucket the source reads from
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    /// Object key of a path, rejecting `..` escapes
+    fn key(&self, path: &str) -> Result<ContentPath> {
+        ContentPath::parse(&self.prefix)?.join(path)
+    }
+
+    /// Download an object, without error context
+    async fn get_object(&self, path: &str) -> Result<FileContent> {
+        let key = self.key(path)?;
+        let output = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key.as_str())
+            .send()
+            .await
+            .map_err(|e| s3_error(path, e))?;
+
+        if let (Some(limit), Some(length)) = (self.max_file_size, output.content_length()) {
+            if length as u64 > limit {
+                return Err(ContentError::TooLarge {
+                    path: path.to_string(),
+                    size: Some(length as u64),
+                    limit,
+                });
+            }
+        }
+
+        let etag = output.e_tag().map(String::from);
+        let content_type = output.content_type().map(String::from);
+        let last_modified = output.last_modified().and_then(|t| SystemTime::try_from(*t).ok());
+        let content = output
+            .body
+            .collect()
+            .await
+            .map_err(|e| ContentError::Io(std::io::Error::other(e)))?
+            .into_bytes();
+
+        let mut file = FileContent::new(content, format!("s3://{}/{}", self.bucket, key));
+        file.etag = etag;
+        file.content_type = content_type;
+        file.last_modified = last_modified;
+        Ok(file)
+    }
+
+    /// Read an object's metadata with `HeadObject`, without error context
+    async fn head_object(&self, path: &str) -> Result<FileMetadata> {
+        let output = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(self.key(path)?.as_str())
+            .send()
+            .await
+            .map_err(|e| s3_error(path, e))?;
+
+        let mut metadata = FileMetadata::file();
+        metadata.size = output.content_length().map(|length| length as u64);
+        metadata.etag = output.e_tag().map(String::from);
+        metadata.content_type = output.content_type().map(String::from);
+        metadata.last_modified = output.last_modified().and_then(|t| SystemTime::try_from(*t).ok());
+        Ok(metadata)
+    }
+
+    /// List the keys and prefixes one level below a path, without error context
+    async fn list_objects(&self, path: &str) -> Result<DirectoryListing> {
+        let dir = ContentPath::parse(path)?;
+        let key = self.key(path)?;
+        let prefix = match key.is_root() {
+            true => String::new(),
+            false => format!("{}/", key),
+        };
+
+        let mut entries = Vec::new();
+        let mut continuation = None;
+        loop {
+            let output = self
+                .client
+                .list_objects_v2()
+                .bucket(&self.bucket)
+                .prefix(&prefix)
+                .delimiter("/")
+                .set_continuation_token(continuation.take())
+                .send()
+                .await
+                .map_err(|e| s3_error(path, e))?;
+
+            for common in output.common_prefixes() {
+                let Some(name) = common.prefix().and_then(|p| p.strip_prefix(prefix.as_str())) else {
+                    continue;
+                };
+                let name = name.trim_end_matches('/');
+                entries.push(DirectoryEntry::dir(name, dir.join(name)?));
+            }
+            for object in output.contents() {
+                let Some(name) = object.key().and_then(|k| k.strip_prefix(prefix.as_str())) else {
+                    continue;
+                };
+                // Directory markers
+                if name.is_empty() || name.ends_with('/') {
+                    continue;
+                }
+                let mut entry = DirectoryEntry::file(name, dir.join(name)?);
+                entry.metadata = object_metadata(object);
+                entries.push(entry);
+            }
+
+            continuation = output.next_continuation_token().map(String::from);
+            if output.is_truncated() != Some(true) || continuation.is_none() {
+                break;
+            }
+        }
+
+        // S3 has no empty directories: nothing below the prefix means no directory
+        if entries.is_empty() && !dir.is_root() {
+            return match self.head_object(path).await {
+                Ok(_) => Err(ContentError::NotADirectory {
+                    path: path.to_string(),
+                }),
+                Err(e) if e.is_not_found() => Err(ContentError::NotFound {
+                    path: path.to_string(),
+                }),
+                Err(e) => Err(e),
+            };
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(DirectoryListing::new(path, entries))
+    }
+}
+
+/// Well-known `DirectoryEntry` metadata for an object in a listing
+fn object_metadata(object: &aws_sdk_s3::types::Object) -> BTreeMap<String, serde_json::Value> {
+    let mut map = BTreeMap::new();
+    if let Some(size) = object.size() {
+        map.insert("size".to_string(), size.into());
+    }
+    if let Some(etag) = object.e_tag() {
+        map.insert("etag".to_string(), etag.into());
+    }
+    if let Some(secs) = object
+        .last_modified()
+        .and_then(|t| SystemTime::try_from(*t).ok())
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+    {
+        map.insert("last_modified".to_string(), secs.as_secs().into());
+    }
+    map
+}
+
+/// Convert a failed S3 request into the matching error
+fn s3_error<E>(path: &str, error: SdkError<E, HttpResponse>) -> ContentError
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+{
+    let service = match &error {
+        SdkError::ServiceError(service) => service,
+        SdkError::TimeoutError(_) => {
+            return ContentError::Timeout {
+                path: path.to_string(),
+                after: None,
+            }
+        }
+        _ => return ContentError::Io(std::io::Error::other(DisplayErrorContext(&error).to_string())),
+    };
+
+    let status = service.raw().status().as_u16();
+    let code = service.err().code();
+    let message = service.err().message().or(code).unwrap_or_default().to_string();
+    match (status, code) {
+        (_, Some("NoSuchBucket")) => ContentError::InvalidConfig { message },
+        (404, _) | (_, Some("NoSuchKey")) => ContentError::NotFound {
+            path: path.to_string(),
+        },
+        (429, _) | (503, Some("SlowDown")) => ContentError::RateLimited {
+            message,
+            retry_after: None,
+        },
+        _ => ContentError::UpstreamStatus {
+            path: path.to_string(),
+            status,
+            message,
+        },
+    }
+}
+
+#[async_trait]
+impl ContentSource for S3Source {
+    async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+        self.get_object(path)
+            .await
+            .with_source(&self.identifier())
+            .with_path(path)
+    }
+
+    async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+        self.list_objects(path)
+            .await
+            .with_source(&self.identifier())
+            .with_path(path)
+    }
+
+    async fn file_exists(&self, path: &str) -> bool {
+        self.head_object(path).await.is_ok()
+    }
+
+    async fn path_type(&self, path: &str) -> Result<Option<EntryType>> {
+        match self.stat(path).await {
+            Ok(metadata) => Ok(Some(metadata.entry_type)),
+            Err(e) if e.is_not_found() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn stat(&self, path: &str) -> Result<FileMetadata> {
+        let stat = async {
+            match self.head_object(path).await {
+                Err(e) if e.is_not_found() => {}
+                found => return found,
+            }
+            self.list_objects(path).await.map(|_| FileMetadata::dir())
+        };
+        stat.await.with_source(&self.identifier()).with_path(path)
+    }
+
+    fn identifier(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+
+    /// Source talking to `server` as a path-style S3 endpoint
+    fn mock_source(server: &mockito::Server) -> S3Source {
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .endpoint_url(server.url())
+            .force_path_style(true)
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("AKID", "secret", None, None, "test"))
+            .build();
+        S3Source::new(Client::from_conf(config), "bucket").with_prefix("site")
+    }
+
+    fn error_body(code: &str) -> String {
+        format!("<Error><Code>{}</Code><Message>{} message</Message></Error>", code, code)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_file() {
+        let mut server = mockito::Server::new_async().await;
+        let _object = server
+            .mock("GET", "/bucket/site/docs/a.md")
+            .match_query(mockito::Matcher::Any)
+            .with_header("etag", "\"9a0364b9e99bb480dd25e1f0284c8555\"")
+            .with_header("content-type", "text/markdown")
+            .with_header("last-modified", "Fri, 01 Mar 2024 00:00:00 GMT")
+            .with_body("content")
+            .create_async()
+            .await;
+        let _missing = server
+            .mock("GET", "/bucket/site/missing.md")
+            .match_query(mockito::Matcher::Any)
+            .with_status(404)
+            .with_body(error_body("NoSuchKey"))
+            .create_async()
+            .await;
+        let _denied = server
+            .mock("GET", "/bucket/site/secret.md")
+            .match_query(mockito::Matcher::Any)
+            .with_status(403)
+            .with_body(error_body("AccessDenied"))
+            .create_async()
+            .await;
+
+        let source = mock_source(&server);
+        let file = source.fetch_file("docs/a.md").await.unwrap();
+        assert_eq!(file.content, "content");
+        assert_eq!(file.etag.as_deref(), Some("\"9a0364b9e99bb480dd25e1f0284c8555\""));
+        assert_eq!(file.content_type.as_deref(), Some("text/markdown"));
+        assert_eq!(file.last_modified, Some(UNIX_EPOCH + std::time::Duration::from_secs(1_709_251_200)));
+        assert_eq!(file.source_path, "s3://bucket/site/docs/a.md");
+
+        assert!(source.fetch_file("missing.md").await.unwrap_err().is_not_found());
+        let error = source.fetch_file("secret.md").await.unwrap_err();
+        assert!(
+            matches!(error.root(), ContentError::UpstreamStatus { status: 403, .. }),
+            "{}",
+            error
+        );
+        assert!(error.to_string().contains("s3://bucket/site"));
+
+        let limited = mock_source(&server).with_max_file_size(3);
+        let error = limited.fetch_file("docs/a.md").await.unwrap_err();
+        assert!(matches!(error.root(), ContentError::TooLarge { size: Some(7), .. }));
+        assert!(source.fetch_file("../escape").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_list_directory() {
+        let mut server = mockito::Server::new_async().await;
+        let list = |prefix: &str| {
+            mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("list-type".into(), "2".into()),
+                mockito::Matcher::UrlEncoded("prefix".into(), prefix.into()),
+                mockito::Matcher::UrlEncoded("delimiter".into(), "/".into()),
+            ])
+        };
+        let _first_page = server
+            .mock("GET", "/bucket/")
+            .match_query(list("site/docs/"))
+            .with_body(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+  <Name>bucket</Name><Prefix>site/docs/</Prefix><KeyCount>3</KeyCount>
+  <IsTruncated>true</IsTruncated><NextContinuationToken>page2</NextContinuationToken>
+  <Contents><Key>site/docs/</Key><Size>0</Size></Contents>
+  <Contents><Key>site/docs/b.md</Key><Size>5</Size><ETag>"etag-b"</ETag><LastModified>2024-03-01T00:00:00.000Z</LastModified></Contents>
+  <CommonPrefixes><Prefix>site/docs/guides/</Prefix></CommonPrefixes>
+</ListBucketResult>"#,
+            )
+            .create_async()
+            .await;
+        let _second_page = server
+            .mock("GET", "/bucket/")
+            .match_query(mockito::Matcher::AllOf(vec![
+                list("site/docs/"),
+                mockito::Matcher::UrlEncoded("continuation-token".into(), "page2".into()),
+            ]))
+            .with_body(
+                r#"<ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+  <IsTruncated>false</IsTruncated>
+  <Contents><Key>site/docs/a.md</Key><Size>7</Size></Contents>
+</ListBucketResult>"#,
+            )
+            .create_async()
+            .await;
+        let _empty = server
+            .mock("GET", "/bucket/")
+            .match_query(list("site/nothing/"))
+            .with_body(r#"<ListBucketResult><IsTruncated>false</IsTruncated></ListBucketResult>"#)
+            .create_async()
+            .await;
+        let _no_object = server
+            .mock("HEAD", "/bucket/site/nothing")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let source = mock_source(&server);
+        let listing = source.list_directory("docs").await.unwrap();
+        let entries: Vec<_> = listing
+            .entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.path.as_str(), e.entry_type.clone()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("a.md", "docs/a.md", EntryType::File),
+                ("b.md", "docs/b.md", EntryType::File),
+                ("guides", "docs/guides", EntryType::Dir),
+            ]
+        );
+        let b = &listing.entries[1];
+        assert_eq!(b.metadata["size"], 5);
+        assert_eq!(b.metadata["etag"], "\"etag-b\"");
+        assert_eq!(b.metadata["last_modified"], 1_709_251_200);
+
+        assert!(source.list_directory("nothing").await.unwrap_err().is_not_found());
+        assert_eq!(source.path_type("nothing").await.unwrap(), None);
+        assert_eq!(source.identifier(), "s3://bucket/site");
+    }
+}
@@ -0,0 +1,248 @@
+use tokio::io::AsyncWrite;
+
+use crate::{
+    error::Result,
+    resolver::ResourceResolver,
+    source::FetchOptions,
+    stream::ContentStream,
+    types::{ContentPath, DirectoryEntry, DirectoryListing, EntryType, FileContent, FileMetadata},
+};
+
+/// View of a `ResourceResolver` rooted at a prefix
+/// 
+/// Created with `ResourceResolver::scoped`. Paths are joined onto the prefix,
+/// so `fetch_file("a.toml")` on a view scoped to `config` fetches
+/// `config/a.toml`, and paths that climb out of the prefix fail with
+/// `InvalidPath`. Listed entry paths, and the path of a listing, are
+/// normalized and relative to the prefix, so listing `./env//` reports `env`.
+/// 
+/// Cheap to create and clone: the sources and the cache are shared.
+#[derive(Clone)]
+pub struct ScopedResolver {
+    resolver: ResourceResolver,
+    prefix: ContentPath,
+}
+
+impl ScopedResolver {
+    pub(crate) fn new(resolver: ResourceResolver, prefix: ContentPath) -> Self {
+        Self { resolver, prefix }
+    }
+
+    /// Prefix this view is rooted at
+    pub fn prefix(&self) -> &ContentPath {
+        &self.prefix
+    }
+
+    /// Resolver this view reads through
+    pub fn resolver(&self) -> &ResourceResolver {
+        &self.resolver
+    }
+
+    /// Narrow the view further below `prefix`
+    pub fn scoped(&self, prefix: impl AsRef<str>) -> Result<ScopedResolver> {
+        Ok(Self::new(self.resolver.clone(), self.full_path(prefix.as_ref())?))
+    }
+
+    /// Full path of `path` below the prefix, normalized by the resolver
+    fn full_path(&self, path: &str) -> Result<ContentPath> {
+        self.prefix.join(self.resolver.normalize_path(path)?)
+    }
+
+    /// `path` normalized by the resolver, and its full path below the prefix
+    fn paths(&self, path: &str) -> Result<(ContentPath, ContentPath)> {
+        let relative = self.resolver.normalize_path(path)?;
+        let full = self.prefix.join(&relative)?;
+        Ok((relative, full))
+    }
+
+    /// Make an entry's path relative to the prefix
+    fn relative(&self, mut entry: DirectoryEntry) -> DirectoryEntry {
+        if let Some(relative) = ContentPath::parse(&entry.path)
+            .ok()
+            .and_then(|path| path.strip_prefix(&self.prefix))
+        {
+            entry.path = relative.into();
+        }
+        entry
+    }
+
+    /// Fetch a file below the prefix
+    pub async fn fetch_file(&self, path: impl AsRef<str>) -> Result<FileContent> {
+        self.fetch_file_with(path, &FetchOptions::default()).await
+    }
+
+    /// Fetch a file below the prefix with per-call options
+    pub async fn fetch_file_with(
+        &self,
+        path: impl AsRef<str>,
+        options: &FetchOptions,
+    ) -> Result<FileContent> {
+        self.resolver
+            .fetch_file_with(self.full_path(path.as_ref())?, options)
+            .await
+    }
+
+    /// Stream a file below the prefix into `writer`
+    pub async fn fetch_to_writer<W>(&self, path: impl AsRef<str>, writer: W) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        let path = self.full_path(path.as_ref())?;
+        self.resolver.fetch_to_writer(path, writer).await
+    }
+
+    /// Stream a file below the prefix in chunks, see `ResourceResolver::fetch_stream`
+    pub async fn fetch_stream(&self, path: impl AsRef<str>, buffer: usize) -> Result<ContentStream> {
+        let path = self.full_path(path.as_ref())?;
+        self.resolver.fetch_stream(path, buffer).await
+    }
+
+    /// List a directory below the prefix
+    pub async fn list_directory(&self, path: impl AsRef<str>) -> Result<DirectoryListing> {
+        self.list_directory_with(path, &FetchOptions::default()).await
+    }
+
+    /// List a directory below the prefix with per-call options
+    pub async fn list_directory_with(
+        &self,
+        path: impl AsRef<str>,
+        options: &FetchOptions,
+    ) -> Result<DirectoryListing> {
+        let (path, full) = self.paths(path.as_ref())?;
+        let listing = self.resolver.list_directory_with(full, options).await?;
+        Ok(DirectoryListing {
+            path: path.into(),
+            entries: listing.entries.into_iter().map(|e| self.relative(e)).collect(),
+            truncated: listing.truncated,
+        })
+    }
+
+    /// List one page of a directory below the prefix
+    pub async fn list_directory_paginated(
+        &self,
+        path: impl AsRef<str>,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<(DirectoryListing, Option<String>)> {
+        let (path, full) = self.paths(path.as_ref())?;
+        let (listing, next) = self
+            .resolver
+            .list_directory_paginated(full, cursor, page_size)
+            .await?;
+        let listing = DirectoryListing {
+            path: path.into(),
+            entries: listing.entries.into_iter().map(|e| self.relative(e)).collect(),
+            truncated: listing.truncated,
+        };
+        Ok((listing, next))
+    }
+
+    /// List every entry below a directory under the prefix
+    pub async fn list_directory_recursive(
+        &self,
+        path: impl AsRef<str>,
+    ) -> Result<Vec<DirectoryEntry>> {
+        let entries = self
+            .resolver
+            .list_directory_recursive(self.full_path(path.as_ref())?)
+            .await?;
+        Ok(entries.into_iter().map(|e| self.relative(e)).collect())
+    }
+
+    /// Check if a file below the prefix exists in any source
+    pub async fn file_exists(&self, path: impl AsRef<str>) -> bool {
+        match self.full_path(path.as_ref()) {
+            Ok(path) => self.resolver.file_exists(path).await,
+            Err(_) => false,
+        }
+    }
+
+    /// Find out whether a path below the prefix is a file or a directory
+    pub async fn path_type(&self, path: impl AsRef<str>) -> Result<Option<EntryType>> {
+        self.resolver.path_type(self.full_path(path.as_ref())?).await
+    }
+
+    /// Get the metadata of a path below the prefix
+    pub async fn stat(&self, path: impl AsRef<str>) -> Result<FileMetadata> {
+        self.resolver.stat(self.full_path(path.as_ref())?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{Cache, MemoryCache};
+    use crate::error::ContentError;
+    use crate::source::ContentSource;
+    use crate::testing::MemorySource;
+    use std::sync::Arc;
+
+    fn resolver() -> (ResourceResolver, Arc<MemoryCache>) {
+        let source = MemorySource::new()
+            .with_file("config/app.toml", "app")
+            .with_file("config/env/prod.toml", "prod")
+            .with_file("secrets.toml", "secret");
+        let cache = Arc::new(MemoryCache::new());
+        let resolver = ResourceResolver::with_cache(
+            vec![Arc::new(source) as Arc<dyn ContentSource>],
+            cache.clone(),
+        );
+        (resolver, cache)
+    }
+
+    #[tokio::test]
+    async fn test_scoped_fetch_and_list() {
+        let (resolver, cache) = resolver();
+        let config = resolver.scoped("/config/").unwrap();
+        assert_eq!(config.prefix().as_str(), "config");
+
+        assert_eq!(config.fetch_file("app.toml").await.unwrap().content, "app");
+        assert_eq!(config.fetch_file("/env//prod.toml").await.unwrap().content, "prod");
+        // Shares the parent's cache
+        assert!(cache.contains("file:config/app.toml").await);
+
+        let listing = config.list_directory("").await.unwrap();
+        let paths: Vec<_> = listing.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["app.toml", "env"]);
+
+        let all = config.list_directory_recursive("env").await.unwrap();
+        assert_eq!(all[0].path, "env/prod.toml");
+
+        assert!(config.file_exists("app.toml").await);
+        assert_eq!(config.path_type("env").await.unwrap(), Some(EntryType::Dir));
+
+        let env = config.scoped("env").unwrap();
+        assert_eq!(env.fetch_file("prod.toml").await.unwrap().content, "prod");
+    }
+
+    #[tokio::test]
+    async fn test_scoped_paths_are_normalized() {
+        let (resolver, _) = resolver();
+        let config = resolver.scoped("config").unwrap();
+
+        let listing = config.list_directory("./env//").await.unwrap();
+        assert_eq!(listing.path, "env");
+        assert_eq!(listing.entries[0].path, "env/prod.toml");
+
+        let (page, _) = config.list_directory_paginated("/./env", None, 10).await.unwrap();
+        assert_eq!(page.path, "env");
+        assert_eq!(page.entries[0].path, "env/prod.toml");
+
+        let all = config.list_directory_recursive(".//env").await.unwrap();
+        assert_eq!(all[0].path, "env/prod.toml");
+    }
+
+    #[tokio::test]
+    async fn test_scoped_cannot_escape_prefix() {
+        let (resolver, _) = resolver();
+        let config = resolver.scoped("config").unwrap();
+
+        assert!(matches!(
+            config.fetch_file("../secrets.toml").await,
+            Err(ContentError::InvalidPath { .. })
+        ));
+        assert!(!config.file_exists("../secrets.toml").await);
+        assert!(config.scoped("..").is_err());
+        assert!(resolver.scoped("../x").is_err());
+    }
+}
@@ -0,0 +1,351 @@
+//! Content served through short-lived signed URLs
+
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use reqwest::{Client, StatusCode};
+use std::sync::Arc;
+
+use crate::{
+    error::{ContentError, Result, ResultExt},
+    github::MAX_BODY_PREALLOCATION,
+    secret::redact_url,
+    source::{Capabilities, ContentSource},
+    types::{DirectoryListing, EntryType, FileContent, FileMetadata},
+};
+
+/// Produces a fresh signed URL for a path
+pub type UrlSigner = Arc<dyn for<'a> Fn(&'a str) -> BoxFuture<'a, Result<String>> + Send + Sync>;
+
+/// Source reading each file from a URL signed for it on demand
+/// 
+/// For content behind pre-signed S3 or GCS URLs, or any auth endpoint that
+/// hands out short-lived download links. The signer is asked for a URL on
+/// every fetch; when the download is refused with 403, as expired
+/// signatures are, it is asked once more and the download retried.
+/// 
+/// ```no_run
+/// use content_resolver::SignedUrlSource;
+/// use futures::FutureExt;
+/// 
+/// let source = SignedUrlSource::new("private-docs", |path| {
+///     async move { Ok(format!("https://auth.example.com/sign?path={}", path)) }.boxed()
+/// });
+/// ```
+/// 
+/// Signed URLs can't list directories, so the resolver skips the source for
+/// listings. Neither the URLs nor their signatures end up in errors,
+/// `source_path` or `identifier`.
+#[derive(Clone)]
+pub struct SignedUrlSource {
+    name: String,
+    signer: UrlSigner,
+    client: Client,
+    max_file_size: Option<u64>,
+}
+
+impl SignedUrlSource {
+    /// Read files from the URLs `signer` returns, naming the source `name`
+    /// 
+    /// `name` shows up in `identifier` as `signed://{name}`.
+    pub fn new<F>(name: impl Into<String>, signer: F) -> Self
+    where
+        F: for<'a> Fn(&'a str) -> BoxFuture<'a, Result<String>> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            signer: Arc::new(signer),
+            client: Client::new(),
+            max_file_size: None,
+        }
+    }
+
+    /// Send requests through `client`, e.g. for timeouts or a proxy
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Refuse files larger than `limit` bytes with `TooLarge`
+    pub fn with_max_file_size(mut self, limit: u64) -> Self {
+        self.max_file_size = Some(limit);
+        self
+    }
+
+    /// Sign and download a file, without error context
+    async fn fetch_signed(&self, path: &str) -> Result<FileContent> {
+        let mut response = self.download(path).await?;
+        if response.status() == StatusCode::FORBIDDEN {
+            // Most likely an expired signature; a fresh one gets one more try
+            response = self.download(path).await?;
+        }
+
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::NOT_FOUND => {
+                return Err(ContentError::NotFound {
+                    path: path.to_string(),
+                })
+            }
+            status => {
+                return Err(ContentError::UpstreamStatus {
+                    path: path.to_string(),
+                    status: status.as_u16(),
+                    message: response.text().await.unwrap_or_default(),
+                })
+            }
+        }
+
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from)
+        };
+        let etag = header("etag");
+        let content_type = header("content-type");
+        let last_modified = header("last-modified")
+            .and_then(|v| httpdate::parse_http_date(&v).ok());
+        // Without the query string, which carries the signature
+        let source_path = redact_url(response.url());
+
+        let content = self.read_body(path, response).await?;
+        let mut file = FileContent::new(content, source_path);
+        file.etag = etag;
+        file.content_type = content_type;
+        file.last_modified = last_modified;
+        Ok(file)
+    }
+
+    /// Ask the signer for a URL and request it
+    async fn download(&self, path: &str) -> Result<reqwest::Response> {
+        let url = (self.signer)(path).await?;
+        let url = reqwest::Url::parse(&url).map_err(|e| ContentError::InvalidConfig {
+            message: format!("signer returned an invalid URL for {}: {}", path, e),
+        })?;
+        self.client.get(url).send().await.map_err(|e| {
+            if e.is_timeout() {
+                ContentError::Timeout {
+                    path: path.to_string(),
+                    after: None,
+                }
+            } else {
+                ContentError::from(e)
+            }
+        })
+    }
+
+    /// Read a response body, enforcing `max_file_size`
+    async fn read_body(&self, path: &str, mut response: reqwest::Response) -> Result<bytes::Bytes> {
+        let too_large = |size, limit| ContentError::TooLarge {
+            path: path.to_string(),
+            size,
+            limit,
+        };
+        if let (Some(limit), Some(length)) = (self.max_file_size, response.content_length()) {
+            if length > limit {
+                return Err(too_large(Some(length), limit));
+            }
+        }
+
+        let capacity = response
+            .content_length()
+            .unwrap_or(0)
+            .min(MAX_BODY_PREALLOCATION);
+        let mut body = Vec::with_capacity(capacity as usize);
+        while let Some(chunk) = response.chunk().await.map_err(ContentError::from)? {
+            body.extend_from_slice(&chunk);
+            if let Some(limit) = self.max_file_size {
+                if body.len() as u64 > limit {
+                    return Err(too_large(None, limit));
+                }
+            }
+        }
+        Ok(body.into())
+    }
+}
+
+impl std::fmt::Debug for SignedUrlSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SignedUrlSource")
+            .field("name", &self.name)
+            .field("max_file_size", &self.max_file_size)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl ContentSource for SignedUrlSource {
+    async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+        self.fetch_signed(path)
+            .await
+            .with_source(&self.identifier())
+            .with_path(path)
+    }
+
+    async fn list_directory(&self, _path: &str) -> Result<DirectoryListing> {
+        Err(ContentError::InvalidConfig {
+            message: format!("{} can't list directories", self.identifier()),
+        })
+    }
+
+    async fn path_type(&self, path: &str) -> Result<Option<EntryType>> {
+        match self.fetch_file(path).await {
+            Ok(_) => Ok(Some(EntryType::File)),
+            Err(e) if e.is_not_found() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn stat(&self, path: &str) -> Result<FileMetadata> {
+        Ok(FileMetadata::from(&self.fetch_file(path).await?))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default().with_listing(false)
+    }
+
+    fn identifier(&self) -> String {
+        format!("signed://{}", self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Source signing `/files/{path}` on `server` with an increasing counter
+    fn signing_source(server: &mockito::Server, signed: Arc<AtomicUsize>) -> SignedUrlSource {
+        let base = server.url();
+        SignedUrlSource::new("private", move |path| {
+            let n = signed.fetch_add(1, Ordering::SeqCst) + 1;
+            let url = format!("{}/files/{}?sig=secret{}", base, path, n);
+            async move { Ok(url) }.boxed()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_fetch_signed() {
+        let mut server = mockito::Server::new_async().await;
+        let file = server
+            .mock("GET", "/files/docs/a.md")
+            .match_query(mockito::Matcher::UrlEncoded("sig".into(), "secret1".into()))
+            .with_header("etag", "\"v1\"")
+            .with_body("hello")
+            .create_async()
+            .await;
+
+        let signed = Arc::new(AtomicUsize::new(0));
+        let source = signing_source(&server, signed.clone());
+        let content = source.fetch_file("docs/a.md").await.unwrap();
+        assert_eq!(content.content, "hello");
+        assert_eq!(content.etag.as_deref(), Some("\"v1\""));
+        assert!(!content.source_path.contains("secret"));
+        assert_eq!(signed.load(Ordering::SeqCst), 1);
+        file.assert_async().await;
+
+        assert_eq!(source.identifier(), "signed://private");
+        assert!(!source.capabilities().listing);
+    }
+
+    #[tokio::test]
+    async fn test_expired_signature_is_renewed_once() {
+        let mut server = mockito::Server::new_async().await;
+        let expired = server
+            .mock("GET", "/files/a.md")
+            .match_query(mockito::Matcher::UrlEncoded("sig".into(), "secret1".into()))
+            .with_status(403)
+            .with_body("Request has expired")
+            .create_async()
+            .await;
+        let fresh = server
+            .mock("GET", "/files/a.md")
+            .match_query(mockito::Matcher::UrlEncoded("sig".into(), "secret2".into()))
+            .with_body("fresh")
+            .create_async()
+            .await;
+
+        let signed = Arc::new(AtomicUsize::new(0));
+        let source = signing_source(&server, signed.clone());
+        assert_eq!(source.fetch_file("a.md").await.unwrap().content, "fresh");
+        assert_eq!(signed.load(Ordering::SeqCst), 2);
+        expired.assert_async().await;
+        fresh.assert_async().await;
+
+        // A second refusal is final
+        let denied = server
+            .mock("GET", "/files/b.md")
+            .match_query(mockito::Matcher::Any)
+            .with_status(403)
+            .expect(2)
+            .create_async()
+            .await;
+        let err = source.fetch_file("b.md").await.unwrap_err();
+        assert!(matches!(err.root(), ContentError::UpstreamStatus { status: 403, .. }));
+        assert!(!err.to_string().contains("secret"));
+        assert_eq!(signed.load(Ordering::SeqCst), 4);
+        denied.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_signer_and_status_errors() {
+        let mut server = mockito::Server::new_async().await;
+        let _missing = server
+            .mock("GET", "/files/missing.md")
+            .match_query(mockito::Matcher::Any)
+            .with_status(404)
+            .create_async()
+            .await;
+        let source = signing_source(&server, Arc::default()).with_max_file_size(4);
+        assert!(source.fetch_file("missing.md").await.unwrap_err().is_not_found());
+        assert_eq!(source.path_type("missing.md").await.unwrap(), None);
+
+        let _large = server
+            .mock("GET", "/files/large.md")
+            .match_query(mockito::Matcher::Any)
+            .with_body("too large")
+            .create_async()
+            .await;
+        let err = source.fetch_file("large.md").await.unwrap_err();
+        assert!(matches!(err.root(), ContentError::TooLarge { limit: 4, .. }));
+
+        let refusing = SignedUrlSource::new("denied", |path| {
+            async move {
+                Err(ContentError::InvalidConfig {
+                    message: format!("no access to {}", path),
+                })
+            }
+            .boxed()
+        });
+        let err = refusing.fetch_file("a.md").await.unwrap_err();
+        assert!(err.to_string().contains("no access to a.md"));
+    }
+
+    #[tokio::test]
+    async fn test_huge_content_length_without_limit() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Declares far more than it sends, which mockito refuses to do
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let tasks = crate::task::TaskSet::new();
+        tasks.spawn(|_| async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let _ = socket.read(&mut request).await;
+                let head = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n", 1u64 << 60);
+                let _ = socket.write_all(head.as_bytes()).await;
+                let _ = socket.write_all(b"short").await;
+            }
+        });
+        let source = SignedUrlSource::new("huge", move |path| {
+            let url = format!("{}/files/{}", base, path);
+            async move { Ok(url) }.boxed()
+        });
+
+        // The body ends early; nothing is reserved for what it claimed
+        assert!(source.fetch_file("a.md").await.is_err());
+    }
+}
@@ -1,29 +1,395 @@
 use async_trait::async_trait;
-use crate::{error::Result, types::{DirectoryListing, FileContent}};
+use std::any::Any;
+use std::sync::Arc;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    error::{ContentError, Result},
+    hook::RequestContext,
+    types::{ContentVersion, DirectoryEntry, DirectoryListing, EntryType, FileContent, FileMetadata},
+};
+
+/// Operations a source supports beyond fetching whole files
+/// 
+/// Lets callers check before relying on an operation instead of handling the
+/// error afterwards. `Capabilities::default()` is the baseline every source
+/// is assumed to have: reading files and listing directories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// Fetching a byte range of a file
+    pub ranges: bool,
+    /// `fetch_to_writer` streams the body instead of buffering the whole file
+    pub streaming: bool,
+    /// `list_directory` and `list_directory_recursive`
+    pub listing: bool,
+    /// Writing content back to the source
+    pub writing: bool,
+    /// Conditional requests, e.g. `If-None-Match` with an ETag
+    pub conditional: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            ranges: false,
+            streaming: false,
+            listing: true,
+            writing: false,
+            conditional: false,
+        }
+    }
+}
+
+impl Capabilities {
+    /// Set whether byte range requests are supported
+    pub const fn with_ranges(mut self, enabled: bool) -> Self {
+        self.ranges = enabled;
+        self
+    }
+
+    /// Set whether `fetch_to_writer` streams
+    pub const fn with_streaming(mut self, enabled: bool) -> Self {
+        self.streaming = enabled;
+        self
+    }
+
+    /// Set whether directories can be listed
+    pub const fn with_listing(mut self, enabled: bool) -> Self {
+        self.listing = enabled;
+        self
+    }
+
+    /// Set whether content can be written
+    pub const fn with_writing(mut self, enabled: bool) -> Self {
+        self.writing = enabled;
+        self
+    }
+
+    /// Set whether conditional requests are supported
+    pub const fn with_conditional(mut self, enabled: bool) -> Self {
+        self.conditional = enabled;
+        self
+    }
+}
+
+/// Per-call settings for `fetch_file_with` and `list_directory_with`
+/// 
+/// Headers are sent with every HTTP request the call makes, e.g. to carry a
+/// correlation ID into upstream logs. Sources that don't speak HTTP, like
+/// `LocalFileSource` and `MemorySource`, ignore them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FetchOptions {
+    /// Extra request headers as `(name, value)` pairs, sent in order
+    pub headers: Vec<(String, String)>,
+}
+
+impl FetchOptions {
+    /// Options that change nothing
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a request header
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Tag the request with a correlation ID, sent as `X-Request-Id`
+    pub fn with_request_id(self, id: impl Into<String>) -> Self {
+        self.with_header("X-Request-Id", id)
+    }
+}
+
+/// Answer to a conditional request, see `ContentSource::list_directory_if_none_match`
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Revalidated<T> {
+    /// Unchanged since the ETag that was sent
+    NotModified,
+    /// Changed, or never seen: the current value, with its ETag if the source has one
+    Modified { value: T, etag: Option<String> },
+}
+
+/// Access to a source's concrete type, implemented for every `ContentSource`
+/// 
+/// A supertrait of `ContentSource`, so a `dyn ContentSource` can be turned
+/// back into, say, a `GitHubSource`. Wrappers return themselves; use their
+/// `inner()` accessor to unwrap them. `Arc<dyn ContentSource>` isn't a source
+/// itself, so `as_any()` on one reaches the source inside, not the `Arc`.
+pub trait AsAny: Any + Send + Sync {
+    /// The value as `Any`, for `downcast_ref`
+    fn as_any(&self) -> &dyn Any;
+
+    /// The value as `Any` behind an `Arc`, for `Arc::downcast`
+    fn into_any_arc(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
+}
+
+impl<T: ContentSource> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any_arc(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
 
 /// Core abstraction for content sources
 /// 
 /// Implementors provide read-only access to files and directories
 /// from various backends (Git repositories, local filesystem, etc.)
 #[async_trait]
-pub trait ContentSource: Send + Sync {
+pub trait ContentSource: AsAny {
     /// Fetch a single file by its path
     /// 
     /// Returns `ContentError::NotFound` if the file doesn't exist
     async fn fetch_file(&self, path: &str) -> Result<FileContent>;
 
+    /// Fetch a single file with per-call options
+    /// 
+    /// The default ignores `options` and calls `fetch_file`; HTTP sources
+    /// override it to send the headers, and wrappers to pass them on.
+    async fn fetch_file_with(&self, path: &str, options: &FetchOptions) -> Result<FileContent> {
+        let _ = options;
+        self.fetch_file(path).await
+    }
+
+    /// Fetch a single file with per-call options and the request's context
+    /// 
+    /// `ctx` holds what the resolver's `RequestHook`s stored for this
+    /// request. The default ignores it and calls `fetch_file_with`.
+    async fn fetch_file_with_ctx(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+        ctx: &RequestContext,
+    ) -> Result<FileContent> {
+        let _ = ctx;
+        self.fetch_file_with(path, options).await
+    }
+
+    /// Fetch a file as it was at `version`
+    /// 
+    /// Fails with `ContentError::NotFound` if the file didn't exist then. The
+    /// default fails with `ContentError::Unsupported`; sources that keep
+    /// history override it, and wrappers pass it on.
+    async fn fetch_file_at(&self, path: &str, version: &ContentVersion) -> Result<FileContent> {
+        let _ = (path, version);
+        Err(ContentError::Unsupported {
+            message: format!("{} can't read past versions", self.identifier()),
+        })
+    }
+
+    /// Stream a file into `writer`, returning the number of bytes written
+    /// 
+    /// Must fail with `ContentError::NotFound` before writing anything if the
+    /// file doesn't exist. The default implementation fetches the whole file
+    /// first; sources that can stream should override it.
+    async fn fetch_to_writer(
+        &self,
+        path: &str,
+        writer: &mut (dyn AsyncWrite + Unpin + Send),
+    ) -> Result<u64> {
+        let content = self.fetch_file(path).await?;
+        writer.write_all(&content.content).await?;
+        Ok(content.content.len() as u64)
+    }
+
     /// List the contents of a directory
     /// 
     /// Returns `ContentError::NotFound` if the directory doesn't exist
     async fn list_directory(&self, path: &str) -> Result<DirectoryListing>;
 
+    /// List the contents of a directory with per-call options
+    /// 
+    /// The default ignores `options` and calls `list_directory`.
+    async fn list_directory_with(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+    ) -> Result<DirectoryListing> {
+        let _ = options;
+        self.list_directory(path).await
+    }
+
+    /// List a directory unless it still has the ETag `etag`
+    /// 
+    /// Sources that can revalidate, like `GitHubSource` with a conditional
+    /// request, answer `Revalidated::NotModified` when the listing behind
+    /// `etag` is current, and otherwise return the listing with its ETag.
+    /// The default calls `list_directory` and reports no ETag, so nothing is
+    /// ever skipped. Wrappers pass it on.
+    async fn list_directory_if_none_match(
+        &self,
+        path: &str,
+        etag: Option<&str>,
+    ) -> Result<Revalidated<DirectoryListing>> {
+        let _ = etag;
+        Ok(Revalidated::Modified {
+            value: self.list_directory(path).await?,
+            etag: None,
+        })
+    }
+
+    /// List a directory with per-call options and the request's context
+    /// 
+    /// The default ignores `ctx` and calls `list_directory_with`.
+    async fn list_directory_with_ctx(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+        ctx: &RequestContext,
+    ) -> Result<DirectoryListing> {
+        let _ = ctx;
+        self.list_directory_with(path, options).await
+    }
+
+    /// List one page of a directory
+    /// 
+    /// Returns up to `page_size` entries sorted by name, and a cursor for the
+    /// next page, or `None` after the last one. Pass `None` to start and the
+    /// returned cursor, unchanged, to continue. Cursors resume after the last
+    /// name returned, so entries added or removed between calls don't shift
+    /// later pages.
+    /// 
+    /// The default implementation lists the whole directory and pages through
+    /// it in memory; sources with a paginated listing API should override it.
+    async fn list_directory_paginated(
+        &self,
+        path: &str,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<(DirectoryListing, Option<String>)> {
+        let listing = self.list_directory(path).await?;
+        paginate_listing(listing, cursor.as_deref(), page_size)
+    }
+
+    /// List every entry below a directory, at any depth
+    /// 
+    /// Entries are returned sorted by path. The default implementation walks
+    /// the tree with `list_directory`; sources with a native recursive listing
+    /// should override it to save round trips.
+    async fn list_directory_recursive(&self, path: &str) -> Result<Vec<DirectoryEntry>> {
+        walk_directory(self, path).await
+    }
+
     /// Get a human-readable identifier for this source (for logging/debugging)
     fn identifier(&self) -> String;
 
+    /// Report what the source supports
+    /// 
+    /// Defaults to `Capabilities::default()`, reading and listing. Sources that
+    /// can't list directories, or that stream, should override it; wrappers
+    /// should pass through the inner source's capabilities.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    /// Whether the resolver may cache what this source serves
+    /// 
+    /// Defaults to true. Sources that are cheap to read and change under the
+    /// resolver, like a local override directory, can return false: their
+    /// files are then never written to the cache, so an edit shows up on the
+    /// next fetch. Wrappers should pass through the inner source's answer.
+    fn cacheable(&self) -> bool {
+        true
+    }
+
     /// Check if a file exists without fetching it
     /// 
     /// Default implementation attempts to fetch and returns true if successful
     async fn file_exists(&self, path: &str) -> bool {
         self.fetch_file(path).await.is_ok()
     }
+
+    /// Find out whether a path is a file, a directory, or absent
+    /// 
+    /// Returns `Ok(None)` if nothing exists at `path`. The default
+    /// implementation tries `fetch_file`, then `list_directory`; sources that
+    /// can tell the type from metadata should override it.
+    async fn path_type(&self, path: &str) -> Result<Option<EntryType>> {
+        match self.fetch_file(path).await {
+            Ok(_) => return Ok(Some(EntryType::File)),
+            Err(e) if e.is_not_found() => {}
+            Err(e) => return Err(e),
+        }
+        match self.list_directory(path).await {
+            Ok(_) => Ok(Some(EntryType::Dir)),
+            Err(e) if e.is_not_found() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get a path's size, ETag, MIME type and type without its content
+    /// 
+    /// Returns `ContentError::NotFound` if nothing exists at `path`. The
+    /// default implementation derives the metadata from a full `fetch_file`,
+    /// falling back to `list_directory` for directories; sources that can
+    /// answer from headers or filesystem metadata should override it.
+    async fn stat(&self, path: &str) -> Result<FileMetadata> {
+        let not_found = match self.fetch_file(path).await {
+            Ok(file) => return Ok(FileMetadata::from(&file)),
+            Err(e) if e.is_not_found() => e,
+            Err(e) => return Err(e),
+        };
+        match self.list_directory(path).await {
+            Ok(_) => Ok(FileMetadata::dir()),
+            Err(e) if e.is_not_found() => Err(not_found),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Cut one page out of a complete listing, for `list_directory_paginated`
+pub(crate) fn paginate_listing(
+    mut listing: DirectoryListing,
+    cursor: Option<&str>,
+    page_size: usize,
+) -> Result<(DirectoryListing, Option<String>)> {
+    if page_size == 0 {
+        return Err(ContentError::InvalidConfig {
+            message: "page size must be at least 1".to_string(),
+        });
+    }
+
+    listing.entries.sort_by(|a, b| a.name.cmp(&b.name));
+    let start = cursor.map_or(0, |after| {
+        listing.entries.partition_point(|e| e.name.as_str() <= after)
+    });
+    let mut entries = listing.entries.split_off(start);
+    let next = if entries.len() > page_size {
+        entries.truncate(page_size);
+        entries.last().map(|e| e.name.clone())
+    } else {
+        None
+    };
+
+    listing.entries = entries;
+    Ok((listing, next))
+}
+
+/// Recursively list a directory by walking it with `list_directory`
+pub async fn walk_directory<S>(source: &S, path: &str) -> Result<Vec<DirectoryEntry>>
+where
+    S: ContentSource + ?Sized,
+{
+    let mut entries = Vec::new();
+    let mut pending = vec![path.to_string()];
+
+    while let Some(dir) = pending.pop() {
+        let listing = source.list_directory(&dir).await?;
+        for entry in listing.entries {
+            // A lossy path would fail to list
+            if entry.entry_type == EntryType::Dir && entry.raw_name.is_none() {
+                pending.push(entry.path.clone());
+            }
+            entries.push(entry);
+        }
+    }
+
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
 }
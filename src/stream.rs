@@ -0,0 +1,149 @@
+//! Streaming fetches with a bounded buffer
+
+use bytes::Bytes;
+use futures::channel::mpsc;
+use futures::Stream;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tokio::io::AsyncWrite;
+
+use crate::error::{ContentError, Result};
+
+/// Largest chunk a `ContentStream` hands out
+const MAX_CHUNK: usize = 16 * 1024;
+
+/// A file arriving in chunks, from `ResourceResolver::fetch_stream`
+/// 
+/// At most about `buffer` bytes wait in the stream at a time. Once they do,
+/// the source isn't read further until the consumer catches up, so a slow
+/// consumer slows the download down instead of filling memory. Dropping the
+/// stream stops the download.
+/// 
+/// A failure after the first chunk ends the stream with an `Err` item; the
+/// chunks before it are then only part of the file.
+pub struct ContentStream {
+    first: Option<Bytes>,
+    chunks: mpsc::Receiver<Result<Bytes>>,
+    /// Set by the producer once the whole file was sent
+    complete: Arc<AtomicBool>,
+    finished: bool,
+}
+
+impl ContentStream {
+    /// A stream holding about `buffer` bytes, and the writer feeding it
+    pub(crate) fn channel(buffer: usize) -> (ChannelWriter, Self) {
+        let chunk = buffer.clamp(1, MAX_CHUNK);
+        // The sender has a slot of its own on top of the channel's
+        let slots = (buffer / chunk).max(1) - 1;
+        let (sender, chunks) = mpsc::channel(slots);
+        let complete = Arc::new(AtomicBool::new(false));
+        let writer = ChannelWriter {
+            sender,
+            chunk,
+            complete: complete.clone(),
+        };
+        let stream = Self {
+            first: None,
+            chunks,
+            complete,
+            finished: false,
+        };
+        (writer, stream)
+    }
+
+    /// Wait for the first chunk, so failures before any content surface as errors
+    pub(crate) async fn started(mut self) -> Result<Self> {
+        use futures::StreamExt;
+        match self.chunks.next().await {
+            Some(Ok(first)) => self.first = Some(first),
+            Some(Err(e)) => return Err(e),
+            None => self.finished = !self.complete.load(Ordering::SeqCst),
+        }
+        if self.finished {
+            return Err(interrupted());
+        }
+        Ok(self)
+    }
+}
+
+impl Stream for ContentStream {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(first) = self.first.take() {
+            return Poll::Ready(Some(Ok(first)));
+        }
+        if self.finished {
+            return Poll::Ready(None);
+        }
+        match ready!(Pin::new(&mut self.chunks).poll_next(cx)) {
+            Some(Err(e)) => {
+                self.finished = true;
+                Poll::Ready(Some(Err(e)))
+            }
+            Some(chunk) => Poll::Ready(Some(chunk)),
+            None => {
+                self.finished = true;
+                // The producer went away without finishing, e.g. on shutdown
+                match self.complete.load(Ordering::SeqCst) {
+                    true => Poll::Ready(None),
+                    false => Poll::Ready(Some(Err(interrupted()))),
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for ContentStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ContentStream").field("finished", &self.finished).finish_non_exhaustive()
+    }
+}
+
+fn interrupted() -> ContentError {
+    ContentError::Io(std::io::Error::new(
+        std::io::ErrorKind::UnexpectedEof,
+        "stream ended before the whole file was read",
+    ))
+}
+
+/// Writer feeding a `ContentStream`, waiting while its buffer is full
+pub(crate) struct ChannelWriter {
+    sender: mpsc::Sender<Result<Bytes>>,
+    chunk: usize,
+    complete: Arc<AtomicBool>,
+}
+
+impl ChannelWriter {
+    /// Hand the stream the outcome of the download
+    pub(crate) async fn finish(mut self, outcome: Result<u64>) {
+        use futures::SinkExt;
+        match outcome {
+            Ok(_) => self.complete.store(true, Ordering::SeqCst),
+            // Fails only if the consumer is gone, and then nobody is told
+            Err(e) => {
+                let _ = self.sender.send(Err(e)).await;
+            }
+        }
+    }
+}
+
+impl AsyncWrite for ChannelWriter {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        let closed = |_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "content stream dropped");
+        ready!(self.sender.poll_ready(cx)).map_err(closed)?;
+        let n = buf.len().min(self.chunk);
+        self.sender.start_send(Ok(Bytes::copy_from_slice(&buf[..n]))).map_err(closed)?;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,175 @@
+//! Background tasks tied to a resolver's lifetime
+
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// Tells a background task to stop
+/// 
+/// Cheap to clone; every clone sees the same signal.
+#[derive(Debug, Clone)]
+pub struct ShutdownSignal {
+    receiver: watch::Receiver<bool>,
+}
+
+impl ShutdownSignal {
+    /// Whether shutdown was requested
+    pub fn is_cancelled(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Wait until shutdown is requested
+    /// 
+    /// Also returns once the owning `TaskSet` is gone.
+    pub async fn cancelled(&self) {
+        let mut receiver = self.receiver.clone();
+        let _ = receiver.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+/// Background tasks that stop together
+/// 
+/// Tasks get a `ShutdownSignal` to watch. `shutdown` raises it and gives
+/// them a grace period to finish before aborting the rest; dropping the set
+/// aborts them right away.
+#[derive(Debug)]
+pub struct TaskSet {
+    cancel: watch::Sender<bool>,
+    handles: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl TaskSet {
+    /// An empty set
+    pub fn new() -> Self {
+        Self {
+            cancel: watch::Sender::new(false),
+            handles: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn handles(&self) -> std::sync::MutexGuard<'_, Vec<JoinHandle<()>>> {
+        self.handles.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Spawn a task on the current tokio runtime
+    /// 
+    /// `task` is called with the set's shutdown signal. Tasks spawned after
+    /// `shutdown` start with the signal already raised.
+    /// 
+    /// # Panics
+    /// 
+    /// Panics outside a tokio runtime, like `tokio::spawn`.
+    pub fn spawn<F, Fut>(&self, task: F)
+    where
+        F: FnOnce(ShutdownSignal) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let signal = ShutdownSignal {
+            receiver: self.cancel.subscribe(),
+        };
+        let handle = tokio::spawn(task(signal));
+        let mut handles = self.handles();
+        handles.retain(|handle| !handle.is_finished());
+        handles.push(handle);
+    }
+
+    /// Number of tasks still running
+    pub fn len(&self) -> usize {
+        self.handles().iter().filter(|handle| !handle.is_finished()).count()
+    }
+
+    /// Whether no tasks are running
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Signal every task to stop, wait up to `grace` for them, then abort the rest
+    /// 
+    /// Returns the number of tasks that had to be aborted. An aborted task
+    /// stops at its next `.await`; `DiskCache` writes go through a temporary
+    /// file, so one cut short leaves the previous entry in place.
+    pub async fn shutdown(&self, grace: Duration) -> usize {
+        self.cancel.send_replace(true);
+        let mut handles = std::mem::take(&mut *self.handles());
+        let deadline = tokio::time::Instant::now() + grace;
+
+        let mut aborted = 0;
+        for handle in &mut handles {
+            if tokio::time::timeout_at(deadline, &mut *handle).await.is_err() {
+                handle.abort();
+                aborted += 1;
+            }
+        }
+        aborted
+    }
+}
+
+impl Default for TaskSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TaskSet {
+    fn drop(&mut self) {
+        for handle in self.handles().iter() {
+            handle.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_waits_then_aborts() {
+        let tasks = TaskSet::new();
+        let (stopped, mut stopped_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        // Stops as soon as it is told to
+        let tx = stopped.clone();
+        tasks.spawn(|signal| async move {
+            signal.cancelled().await;
+            tx.send("prompt").unwrap();
+        });
+        // Needs longer than the grace period to wind down
+        tasks.spawn(|signal| async move {
+            signal.cancelled().await;
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            stopped.send("slow").unwrap();
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(tasks.len(), 2);
+
+        let start = tokio::time::Instant::now();
+        assert_eq!(tasks.shutdown(Duration::from_secs(5)).await, 1);
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+        assert_eq!(stopped_rx.recv().await, Some("prompt"));
+        assert_eq!(stopped_rx.recv().await, None);
+        assert!(tasks.is_empty());
+
+        // Late tasks are told to stop from the start
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        tasks.spawn(|signal| async move { tx.send(signal.is_cancelled()).unwrap() });
+        assert!(rx.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_drop_aborts() {
+        let held = Arc::new(());
+        let tasks = TaskSet::new();
+        let task_held = held.clone();
+        tasks.spawn(|_| async move {
+            let _held = task_held;
+            std::future::pending::<()>().await;
+        });
+        drop(tasks);
+        tokio::task::yield_now().await;
+        assert_eq!(Arc::strong_count(&held), 1);
+    }
+}
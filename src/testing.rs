@@ -0,0 +1,424 @@
+//! Test doubles for code built on `ContentSource`
+//!
+//! Available with the `testing` feature.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{
+    error::{ContentError, Result},
+    hook::RequestContext,
+    source::{Capabilities, ContentSource, FetchOptions, Revalidated},
+    types::{ContentPath, ContentVersion, DirectoryEntry, DirectoryListing, EntryType, FileContent, FileMetadata},
+};
+
+/// Source serving files held in memory
+///
+/// Directories are derived from the file paths, so adding `a/b/c.txt`
+/// makes `a` and `a/b` listable.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySource {
+    files: BTreeMap<String, Bytes>,
+}
+
+impl MemorySource {
+    /// Create an empty source
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if `path` climbs out of the root.
+    pub fn with_file(mut self, path: &str, content: impl Into<Bytes>) -> Self {
+        let path = ContentPath::parse(path).expect("invalid MemorySource path");
+        self.files.insert(path.into(), content.into());
+        self
+    }
+}
+
+#[async_trait]
+impl ContentSource for MemorySource {
+    async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+        self.files
+            .get(ContentPath::parse(path)?.as_str())
+            .map(|content| FileContent::new(content.clone(), format!("memory:{}", path)))
+            .ok_or_else(|| ContentError::NotFound {
+                path: path.to_string(),
+            })
+    }
+
+    async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+        let dir = ContentPath::parse(path)?;
+        let dir = dir.as_str();
+        let mut children: BTreeMap<String, EntryType> = BTreeMap::new();
+
+        for file in self.files.keys() {
+            let rest = if dir.is_empty() {
+                Some(file.as_str())
+            } else {
+                file.strip_prefix(dir).and_then(|rest| rest.strip_prefix('/'))
+            };
+            let Some(rest) = rest else { continue };
+            match rest.split_once('/') {
+                Some((name, _)) => children.insert(name.to_string(), EntryType::Dir),
+                None => children.insert(rest.to_string(), EntryType::File),
+            };
+        }
+
+        if children.is_empty() {
+            return Err(ContentError::NotFound {
+                path: path.to_string(),
+            });
+        }
+
+        Ok(DirectoryListing {
+            path: path.to_string(),
+            entries: children
+                .into_iter()
+                .map(|(name, entry_type)| {
+                    let path = if dir.is_empty() {
+                        name.clone()
+                    } else {
+                        format!("{}/{}", dir, name)
+                    };
+                    DirectoryEntry::new(name, path, entry_type)
+                })
+                .collect(),
+            truncated: false,
+        })
+    }
+
+    fn identifier(&self) -> String {
+        "memory".to_string()
+    }
+}
+
+type ErrorFactory = Arc<dyn Fn() -> ContentError + Send + Sync>;
+
+#[derive(Default)]
+struct Faults {
+    latency: Duration,
+    fail_first: Option<(usize, ErrorFactory)>,
+    fail_after: Option<(usize, ErrorFactory)>,
+    failure_rate: Option<(f64, ErrorFactory)>,
+    rng_state: u64,
+}
+
+impl Faults {
+    /// Next value of a xorshift generator, in `[0, 1)`
+    fn next_random(&mut self) -> f64 {
+        let mut x = self.rng_state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng_state = x;
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Pick the error to inject for the `call`-th request (0-based), if any
+    fn error_for(&mut self, call: usize) -> Option<ContentError> {
+        if let Some((count, make_error)) = &self.fail_first {
+            if call < *count {
+                return Some(make_error());
+            }
+        }
+        if let Some((count, make_error)) = &self.fail_after {
+            if call >= *count {
+                return Some(make_error());
+            }
+        }
+        if let Some((rate, make_error)) = self.failure_rate.clone() {
+            if self.next_random() < rate {
+                return Some(make_error());
+            }
+        }
+        None
+    }
+}
+
+/// Source wrapper that delays or fails requests on demand
+///
+/// Faults apply to `fetch_file` and `list_directory`; the default trait
+/// methods built on them see the same faults. Every knob can be changed
+/// through `&self` while the source is in use.
+pub struct FaultInjectingSource {
+    inner: Arc<dyn ContentSource>,
+    faults: Mutex<Faults>,
+    calls: AtomicUsize,
+}
+
+impl FaultInjectingSource {
+    /// Wrap a source, initially without any faults
+    pub fn new(inner: Arc<dyn ContentSource>) -> Self {
+        Self {
+            inner,
+            faults: Mutex::new(Faults {
+                rng_state: 0x2545_f491_4f6c_dd1d,
+                ..Faults::default()
+            }),
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    /// The wrapped source
+    pub fn inner(&self) -> &Arc<dyn ContentSource> {
+        &self.inner
+    }
+
+    fn faults(&self) -> std::sync::MutexGuard<'_, Faults> {
+        self.faults.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Delay every request by `latency`
+    pub fn set_latency(&self, latency: Duration) {
+        self.faults().latency = latency;
+    }
+
+    /// Fail the first `count` requests with the error built by `make_error`
+    pub fn set_fail_first<F>(&self, count: usize, make_error: F)
+    where
+        F: Fn() -> ContentError + Send + Sync + 'static,
+    {
+        self.faults().fail_first = Some((count, Arc::new(make_error)));
+    }
+
+    /// Let `count` requests through, then fail every later one
+    pub fn set_fail_after<F>(&self, count: usize, make_error: F)
+    where
+        F: Fn() -> ContentError + Send + Sync + 'static,
+    {
+        self.faults().fail_after = Some((count, Arc::new(make_error)));
+    }
+
+    /// Fail a fraction of requests, chosen pseudo-randomly
+    ///
+    /// `rate` is clamped to `[0, 1]`. The sequence is reproducible for a given seed.
+    pub fn set_failure_rate<F>(&self, rate: f64, make_error: F)
+    where
+        F: Fn() -> ContentError + Send + Sync + 'static,
+    {
+        self.faults().failure_rate = Some((rate.clamp(0.0, 1.0), Arc::new(make_error)));
+    }
+
+    /// Seed the generator used by `set_failure_rate`
+    pub fn set_seed(&self, seed: u64) {
+        // Xorshift gets stuck on zero
+        self.faults().rng_state = seed.max(1);
+    }
+
+    /// Remove every fault, keeping the call count
+    pub fn clear_faults(&self) {
+        let mut faults = self.faults();
+        faults.latency = Duration::ZERO;
+        faults.fail_first = None;
+        faults.fail_after = None;
+        faults.failure_rate = None;
+    }
+
+    /// Number of requests received so far, including failed ones
+    pub fn call_count(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    /// Count a request and apply the configured faults
+    async fn inject(&self) -> Result<()> {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst);
+        let (latency, error) = {
+            let mut faults = self.faults();
+            (faults.latency, faults.error_for(call))
+        };
+
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+        match error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl ContentSource for FaultInjectingSource {
+    async fn fetch_file(&self, path: &str) -> Result<FileContent> {
+        self.inject().await?;
+        self.inner.fetch_file(path).await
+    }
+
+    async fn list_directory(&self, path: &str) -> Result<DirectoryListing> {
+        self.inject().await?;
+        self.inner.list_directory(path).await
+    }
+
+    async fn list_directory_if_none_match(
+        &self,
+        path: &str,
+        etag: Option<&str>,
+    ) -> Result<Revalidated<DirectoryListing>> {
+        self.inject().await?;
+        self.inner.list_directory_if_none_match(path, etag).await
+    }
+
+    async fn fetch_file_with(&self, path: &str, options: &FetchOptions) -> Result<FileContent> {
+        self.inject().await?;
+        self.inner.fetch_file_with(path, options).await
+    }
+
+    async fn fetch_file_at(&self, path: &str, version: &ContentVersion) -> Result<FileContent> {
+        self.inject().await?;
+        self.inner.fetch_file_at(path, version).await
+    }
+
+    async fn list_directory_with(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+    ) -> Result<DirectoryListing> {
+        self.inject().await?;
+        self.inner.list_directory_with(path, options).await
+    }
+
+    async fn fetch_file_with_ctx(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+        ctx: &RequestContext,
+    ) -> Result<FileContent> {
+        self.inject().await?;
+        self.inner.fetch_file_with_ctx(path, options, ctx).await
+    }
+
+    async fn list_directory_with_ctx(
+        &self,
+        path: &str,
+        options: &FetchOptions,
+        ctx: &RequestContext,
+    ) -> Result<DirectoryListing> {
+        self.inject().await?;
+        self.inner.list_directory_with_ctx(path, options, ctx).await
+    }
+
+    async fn list_directory_paginated(
+        &self,
+        path: &str,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<(DirectoryListing, Option<String>)> {
+        self.inject().await?;
+        self.inner.list_directory_paginated(path, cursor, page_size).await
+    }
+
+    async fn stat(&self, path: &str) -> Result<FileMetadata> {
+        self.inject().await?;
+        self.inner.stat(path).await
+    }
+
+    fn identifier(&self) -> String {
+        format!("faulty({})", self.inner.identifier())
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities().with_streaming(false)
+    }
+
+    fn cacheable(&self) -> bool {
+        self.inner.cacheable()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn source() -> FaultInjectingSource {
+        FaultInjectingSource::new(Arc::new(
+            MemorySource::new()
+                .with_file("a.txt", "a")
+                .with_file("dir/b.txt", "b")
+                .with_file("dir/sub/c.txt", "c"),
+        ))
+    }
+
+    fn cache_error() -> ContentError {
+        ContentError::Cache {
+            message: "injected".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_source() {
+        let source = MemorySource::new()
+            .with_file("dir/b.txt", "b")
+            .with_file("dir/sub/c.txt", "c");
+
+        assert_eq!(source.fetch_file("dir/b.txt").await.unwrap().content, "b");
+        assert_eq!(source.fetch_file("/dir/./b.txt").await.unwrap().content, "b");
+        assert!(source.fetch_file("dir").await.unwrap_err().is_not_found());
+
+        let listing = source.list_directory("dir").await.unwrap();
+        let paths: Vec<_> = listing.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["dir/b.txt", "dir/sub"]);
+        assert_eq!(listing.find("sub").unwrap().entry_type, EntryType::Dir);
+
+        let all = source.list_directory_recursive("").await.unwrap();
+        assert_eq!(all.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_fail_first_and_after() {
+        let source = source();
+        source.set_fail_first(2, cache_error);
+
+        assert!(source.fetch_file("a.txt").await.is_err());
+        assert!(source.list_directory("dir").await.is_err());
+        assert!(source.fetch_file("a.txt").await.is_ok());
+
+        source.clear_faults();
+        source.set_fail_after(4, cache_error);
+        assert!(source.fetch_file("a.txt").await.is_ok());
+        assert!(matches!(
+            source.fetch_file("a.txt").await,
+            Err(ContentError::Cache { .. })
+        ));
+        assert_eq!(source.call_count(), 5);
+        assert_eq!(source.identifier(), "faulty(memory)");
+    }
+
+    #[tokio::test]
+    async fn test_failure_rate() {
+        let source = source();
+        source.set_seed(42);
+        source.set_failure_rate(0.25, cache_error);
+
+        let mut failures = 0;
+        for _ in 0..400 {
+            if source.fetch_file("a.txt").await.is_err() {
+                failures += 1;
+            }
+        }
+        assert!((60..140).contains(&failures), "{} failures", failures);
+
+        source.set_failure_rate(1.0, cache_error);
+        assert!(source.fetch_file("a.txt").await.is_err());
+        source.set_failure_rate(0.0, cache_error);
+        assert!(source.fetch_file("a.txt").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_latency() {
+        let source = source();
+        source.set_latency(Duration::from_millis(30));
+
+        let start = Instant::now();
+        source.fetch_file("a.txt").await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+}
@@ -1,7 +1,43 @@
-use serde::{Deserialize, Serialize};
+use base64::Engine;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::{ContentError, Result};
+use crate::integrity::sha256_hex_of;
 
 /// Represents a file's content and metadata
-#[derive(Debug, Clone)]
+/// 
+/// Serializes with the raw bytes base64-encoded under `content_base64`,
+/// alongside a `size` field that always equals the decoded length, and
+/// `sha256` once the digest of the current content has been computed.
+/// 
+/// The struct is `#[non_exhaustive]`, so code outside this crate builds it
+/// with `new` or `from_static` and the `with_*` methods rather than a struct
+/// literal:
+/// 
+/// ```compile_fail,E0639
+/// let file = content_resolver::FileContent {
+///     content: bytes::Bytes::from_static(b"hi"),
+///     source_path: "a.txt".to_string(),
+///     etag: None,
+///     content_type: None,
+///     last_modified: None,
+///     stale: false,
+/// };
+/// ```
+/// 
+/// ```
+/// let file = content_resolver::FileContent::new("hi", "a.txt")
+///     .with_etag("\"v1\"")
+///     .with_content_type("text/plain");
+/// assert_eq!(file.etag.as_deref(), Some("\"v1\""));
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(into = "FileContentRepr", try_from = "FileContentRepr")]
+#[non_exhaustive]
 pub struct FileContent {
     /// The raw bytes of the file
     pub content: bytes::Bytes,
@@ -9,10 +45,694 @@ pub struct FileContent {
     pub source_path: String,
     /// Optional ETag or version identifier for caching
     pub etag: Option<String>,
+    /// MIME type reported by the source, if known
+    pub content_type: Option<String>,
+    /// Last modification time reported by the source, if known
+    pub last_modified: Option<SystemTime>,
+    /// Set when this is an expired cached copy, served because every source
+    /// failed with a retryable error; see `ResourceResolver::with_serve_stale_on_error`
+    pub stale: bool,
+    /// Digest computed on the first `sha256()` call, with the content it was
+    /// computed from, so a replaced `content` can be told apart
+    sha256: OnceLock<(bytes::Bytes, String)>,
+}
+
+impl FileContent {
+    /// Create a file from its content and the path it was found at
+    pub fn new(content: impl Into<bytes::Bytes>, source_path: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            source_path: source_path.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Create a file from embedded content without copying it
+    pub fn from_static(content: &'static [u8], source_path: impl Into<String>) -> Self {
+        Self::new(bytes::Bytes::from_static(content), source_path)
+    }
+
+    /// Set the ETag or version identifier
+    pub fn with_etag(mut self, etag: impl Into<String>) -> Self {
+        self.etag = Some(etag.into());
+        self
+    }
+
+    /// Set the MIME type
+    pub fn with_content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    /// Set the last modification time
+    pub fn with_last_modified(mut self, last_modified: SystemTime) -> Self {
+        self.last_modified = Some(last_modified);
+        self
+    }
+
+    /// Size of the content in bytes
+    pub fn size(&self) -> u64 {
+        self.content.len() as u64
+    }
+
+    /// Hex-encoded SHA-256 of the content
+    /// 
+    /// Computed on the first call and reused afterwards, including by clones
+    /// made after that call. Replacing `content` on an existing value doesn't
+    /// reset what this returns; build a new `FileContent` instead.
+    /// `verify_sha256` and serialization notice the replacement and hash the
+    /// new content.
+    pub fn sha256(&self) -> &str {
+        let (_, digest) = self
+            .sha256
+            .get_or_init(|| (self.content.clone(), sha256_hex_of(&self.content)));
+        digest
+    }
+
+    /// The memoized digest, if it was computed from the current content
+    fn current_sha256(&self) -> Option<&str> {
+        let (hashed, digest) = self.sha256.get()?;
+        // The memo holds on to the hashed buffer, so its address can't be
+        // reused by different content
+        let same = hashed.as_ptr() == self.content.as_ptr() && hashed.len() == self.content.len();
+        same.then_some(digest.as_str())
+    }
+
+    /// Borrow the content as UTF-8 text
+    /// 
+    /// The content is returned verbatim, including any leading byte order mark.
+    /// Fails with `Validation` naming `source_path` if it isn't valid UTF-8.
+    pub fn text(&self) -> Result<&str> {
+        std::str::from_utf8(&self.content).map_err(|e| self.validation_error(format!(
+            "not valid UTF-8: {}",
+            e
+        )))
+    }
+
+    /// Decode the content as UTF-8, replacing invalid sequences with U+FFFD
+    pub fn text_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.content)
+    }
+
+    /// Convert the content into an owned UTF-8 string
+    pub fn into_string(self) -> Result<String> {
+        match String::from_utf8(self.content.to_vec()) {
+            Ok(text) => Ok(text),
+            Err(e) => Err(self.validation_error(format!("not valid UTF-8: {}", e.utf8_error()))),
+        }
+    }
+
+    /// Iterate over the lines of the content as UTF-8 text
+    pub fn lines(&self) -> Result<std::str::Lines<'_>> {
+        Ok(self.text()?.lines())
+    }
+
+    /// Deserialize the content as JSON
+    /// 
+    /// A leading UTF-8 byte order mark is skipped, as editors on Windows
+    /// often write one and serde_json rejects it.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T> {
+        let content = self.content.strip_prefix(b"\xef\xbb\xbf").unwrap_or(&self.content);
+        serde_json::from_slice(content)
+            .map_err(|e| self.validation_error(format!("not valid JSON: {}", e)))
+    }
+
+    /// Deserialize the content as MessagePack
+    #[cfg(feature = "msgpack")]
+    pub fn msgpack<T: DeserializeOwned>(&self) -> Result<T> {
+        rmp_serde::from_slice(&self.content)
+            .map_err(|e| self.validation_error(format!("not valid MessagePack: {}", e)))
+    }
+
+    /// Deserialize the content as CBOR
+    #[cfg(feature = "cbor")]
+    pub fn cbor<T: DeserializeOwned>(&self) -> Result<T> {
+        ciborium::from_reader(&self.content[..])
+            .map_err(|e| self.validation_error(format!("not valid CBOR: {}", e)))
+    }
+
+    /// Deserialize the content in whichever format it looks like
+    /// 
+    /// The format is picked by `DataFormat::sniff` and returned with the
+    /// value. Leading bytes that MessagePack and CBOR share are tried as
+    /// MessagePack first, then as CBOR. Formats whose feature is disabled
+    /// fail with `Validation`.
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<(T, DataFormat)> {
+        let Some(format) = DataFormat::sniff(&self.content) else {
+            return Err(self.validation_error("not JSON, MessagePack or CBOR".to_string()));
+        };
+        match self.decode_as(format) {
+            Err(e) if DataFormat::is_ambiguous(&self.content) => {
+                match self.decode_as(DataFormat::Cbor) {
+                    Ok(value) => Ok((value, DataFormat::Cbor)),
+                    Err(_) => Err(e),
+                }
+            }
+            decoded => decoded.map(|value| (value, format)),
+        }
+    }
+
+    /// Deserialize the content in a given format
+    pub fn decode_as<T: DeserializeOwned>(&self, format: DataFormat) -> Result<T> {
+        match format {
+            DataFormat::Json => self.json(),
+            #[cfg(feature = "msgpack")]
+            DataFormat::MessagePack => self.msgpack(),
+            #[cfg(feature = "cbor")]
+            DataFormat::Cbor => self.cbor(),
+            #[allow(unreachable_patterns)]
+            _ => Err(self.validation_error(format!(
+                "{} support is not enabled (feature `{}`)",
+                format,
+                format.feature()
+            ))),
+        }
+    }
+
+    fn validation_error(&self, message: String) -> ContentError {
+        ContentError::Validation {
+            path: self.source_path.clone(),
+            message,
+            pointers: Vec::new(),
+        }
+    }
+
+    /// Check the content against an expected hex-encoded SHA-256 digest
+    /// 
+    /// Fails with `IntegrityMismatch` if they differ. Uses the memoized digest
+    /// only if `content` hasn't been replaced since.
+    pub fn verify_sha256(&self, expected: &str) -> Result<()> {
+        let actual = match self.current_sha256() {
+            Some(digest) => Cow::Borrowed(digest),
+            None if self.sha256.get().is_none() => Cow::Borrowed(self.sha256()),
+            None => Cow::Owned(sha256_hex_of(&self.content)),
+        };
+        if actual.eq_ignore_ascii_case(expected) {
+            Ok(())
+        } else {
+            Err(ContentError::IntegrityMismatch {
+                path: self.source_path.clone(),
+                expected: expected.to_string(),
+                actual: actual.to_string(),
+            })
+        }
+    }
+
+    /// Guess whether the content is binary rather than text
+    /// 
+    /// Looks at the first 8 KiB: any NUL byte, or more than 10% control
+    /// characters other than common whitespace and escapes, means binary.
+    /// Empty content is not binary.
+    pub fn is_probably_binary(&self) -> bool {
+        let sample = &self.content[..self.content.len().min(8192)];
+        if sample.is_empty() {
+            return false;
+        }
+        if sample.contains(&0) {
+            return true;
+        }
+
+        let control = sample
+            .iter()
+            .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b))
+            .count();
+        control * 10 > sample.len()
+    }
+}
+
+/// Wire format of `FileContent`
+#[derive(Serialize, Deserialize)]
+struct FileContentRepr {
+    content_base64: String,
+    size: u64,
+    source_path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<SystemTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    stale: bool,
+}
+
+impl From<FileContent> for FileContentRepr {
+    fn from(file: FileContent) -> Self {
+        let sha256 = file.current_sha256().map(str::to_string);
+        Self {
+            content_base64: base64::engine::general_purpose::STANDARD.encode(&file.content),
+            size: file.size(),
+            source_path: file.source_path,
+            etag: file.etag,
+            content_type: file.content_type,
+            last_modified: file.last_modified,
+            sha256,
+            stale: file.stale,
+        }
+    }
+}
+
+impl TryFrom<FileContentRepr> for FileContent {
+    type Error = String;
+
+    fn try_from(repr: FileContentRepr) -> std::result::Result<Self, Self::Error> {
+        let content = base64::engine::general_purpose::STANDARD
+            .decode(&repr.content_base64)
+            .map_err(|e| format!("invalid content_base64: {}", e))?;
+
+        if content.len() as u64 != repr.size {
+            return Err(format!(
+                "size {} doesn't match decoded content length {}",
+                repr.size,
+                content.len()
+            ));
+        }
+
+        Ok(Self {
+            content: bytes::Bytes::from(content),
+            source_path: repr.source_path,
+            etag: repr.etag,
+            content_type: repr.content_type,
+            last_modified: repr.last_modified,
+            stale: repr.stale,
+            // The stored digest isn't trusted; it is recomputed on demand
+            sha256: OnceLock::new(),
+        })
+    }
+}
+
+/// Serialization format of structured content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DataFormat {
+    Json,
+    /// Decoded with the `msgpack` feature
+    MessagePack,
+    /// Decoded with the `cbor` feature
+    Cbor,
+}
+
+impl DataFormat {
+    /// Guess the format of `content` from its leading bytes
+    /// 
+    /// `{`, `[` or whitespace, after an optional byte order mark, mean JSON,
+    /// and so does a CBOR self-describe tag mean CBOR. Otherwise the first
+    /// byte decides: map and array headers that only MessagePack uses mean
+    /// MessagePack, CBOR map headers mean CBOR. The headers both formats use
+    /// for small collections (`0x80` to `0x9f`) are reported as MessagePack.
+    /// Scalars at the top level aren't recognized.
+    pub fn sniff(content: &[u8]) -> Option<Self> {
+        let content = content.strip_prefix(b"\xef\xbb\xbf").unwrap_or(content);
+        match content {
+            [b'{' | b'[' | b' ' | b'\t' | b'\n' | b'\r', ..] => Some(Self::Json),
+            [0xd9, 0xd9, 0xf7, ..] => Some(Self::Cbor),
+            // fixmap and fixarray in MessagePack, arrays in CBOR
+            [0x80..=0x9f, ..] => Some(Self::MessagePack),
+            // CBOR maps; fixstr in MessagePack
+            [0xa0..=0xbb | 0xbf, ..] => Some(Self::Cbor),
+            // array and map 16/32 in MessagePack, reserved in CBOR
+            [0xdc..=0xdf, ..] => Some(Self::MessagePack),
+            _ => None,
+        }
+    }
+
+    /// Whether `sniff` had to choose between MessagePack and CBOR
+    fn is_ambiguous(content: &[u8]) -> bool {
+        matches!(content, [0x80..=0x9f, ..])
+    }
+
+    /// Human-readable name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataFormat::Json => "JSON",
+            DataFormat::MessagePack => "MessagePack",
+            DataFormat::Cbor => "CBOR",
+        }
+    }
+
+    /// Cargo feature that enables decoding
+    fn feature(&self) -> &'static str {
+        match self {
+            DataFormat::Json => "default",
+            DataFormat::MessagePack => "msgpack",
+            DataFormat::Cbor => "cbor",
+        }
+    }
+}
+
+impl std::fmt::Display for DataFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Point in a source's history to read a file at
+/// 
+/// See `ContentSource::fetch_file_at` and `ResourceResolver::fetch_file_at`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ContentVersion {
+    /// A branch, tag or commit SHA
+    Ref(String),
+    /// The latest content committed at or before this time, to the second
+    Timestamp(SystemTime),
+}
+
+impl ContentVersion {
+    /// Whether the content at this version can't change any more
+    /// 
+    /// True only for full commit SHAs. Branches and tags can move, and so can
+    /// what a timestamp resolves to: a commit pushed late or a force-push can
+    /// put a different commit at or before a time already in the past.
+    pub fn is_immutable(&self) -> bool {
+        match self {
+            ContentVersion::Ref(name) => {
+                matches!(name.len(), 40 | 64) && name.bytes().all(|b| b.is_ascii_hexdigit())
+            }
+            ContentVersion::Timestamp(_) => false,
+        }
+    }
+
+    /// Label for cache keys
+    /// 
+    /// Timestamps start with `~`, which git doesn't allow in ref names.
+    pub(crate) fn key_label(&self) -> String {
+        match self {
+            ContentVersion::Ref(name) => name.clone(),
+            ContentVersion::Timestamp(time) => {
+                let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                format!("~{}", secs)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ContentVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContentVersion::Ref(name) => f.write_str(name),
+            ContentVersion::Timestamp(time) => f.write_str(&httpdate::fmt_http_date(*time)),
+        }
+    }
+}
+
+/// Metadata for a path, without its content
+/// 
+/// Returned by `ContentSource::stat`. Fields the source can't report
+/// cheaply are `None`; directories usually only carry `entry_type`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FileMetadata {
+    /// Whether the path is a file or a directory
+    pub entry_type: EntryType,
+    /// Size in bytes, if known
+    pub size: Option<u64>,
+    /// ETag or version identifier, if known
+    pub etag: Option<String>,
+    /// MIME type reported by the source, if known
+    pub content_type: Option<String>,
+    /// Last modification time reported by the source, if known
+    pub last_modified: Option<SystemTime>,
+}
+
+impl FileMetadata {
+    /// Create metadata for a file with no other fields set
+    pub fn file() -> Self {
+        Self::new(EntryType::File)
+    }
+
+    /// Create metadata for a directory
+    pub fn dir() -> Self {
+        Self::new(EntryType::Dir)
+    }
+
+    /// Create metadata for an entry of the given type
+    pub fn new(entry_type: EntryType) -> Self {
+        Self {
+            entry_type,
+            size: None,
+            etag: None,
+            content_type: None,
+            last_modified: None,
+        }
+    }
+
+    /// Set the size in bytes
+    pub fn with_size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Set the ETag or version identifier
+    pub fn with_etag(mut self, etag: impl Into<String>) -> Self {
+        self.etag = Some(etag.into());
+        self
+    }
+
+    /// Set the MIME type
+    pub fn with_content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    /// Set the last modification time
+    pub fn with_last_modified(mut self, last_modified: SystemTime) -> Self {
+        self.last_modified = Some(last_modified);
+        self
+    }
+
+    /// Whether the path is a file
+    pub fn is_file(&self) -> bool {
+        self.entry_type == EntryType::File
+    }
+
+    /// Whether the path is a directory
+    pub fn is_dir(&self) -> bool {
+        self.entry_type == EntryType::Dir
+    }
+}
+
+impl From<&FileContent> for FileMetadata {
+    fn from(file: &FileContent) -> Self {
+        Self {
+            entry_type: EntryType::File,
+            size: Some(file.size()),
+            etag: file.etag.clone(),
+            content_type: file.content_type.clone(),
+            last_modified: file.last_modified,
+        }
+    }
+}
+
+/// Which characters `ContentPath::parse_with` treats as separators
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SeparatorPolicy {
+    /// Only `/`; a `\` is part of the file name
+    #[default]
+    Slash,
+    /// `/` and `\`, for callers handing over Windows-style paths
+    SlashOrBackslash,
+}
+
+/// Normalized path of content inside a source
+/// 
+/// Always relative and `/`-separated, with no empty, `.` or `..` segments, so
+/// it can never point outside the source. The empty path is the root.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ContentPath(String);
+
+impl ContentPath {
+    /// The root path
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    /// Normalize a path
+    /// 
+    /// Leading, trailing and repeated `/` and `.` segments are dropped, and
+    /// `..` removes the previous segment. Fails with `InvalidPath` if `..`
+    /// would climb above the root or the path contains a NUL byte.
+    pub fn parse(path: &str) -> Result<Self> {
+        Self::parse_with(path, SeparatorPolicy::Slash)
+    }
+
+    /// Normalize a path, choosing which characters separate segments
+    /// 
+    /// With `SeparatorPolicy::SlashOrBackslash`, `a\b` and `a/b` are the same
+    /// path; the other rules are those of `parse`.
+    pub fn parse_with(path: &str, separators: SeparatorPolicy) -> Result<Self> {
+        let invalid = |reason: &str| ContentError::InvalidPath {
+            path: path.to_string(),
+            reason: reason.to_string(),
+        };
+
+        if path.contains('\0') {
+            return Err(invalid("contains a NUL byte"));
+        }
+
+        let is_separator = |c: char| match separators {
+            SeparatorPolicy::Slash => c == '/',
+            SeparatorPolicy::SlashOrBackslash => c == '/' || c == '\\',
+        };
+        let mut segments: Vec<&str> = Vec::new();
+        for segment in path.split(is_separator) {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    segments.pop().ok_or_else(|| invalid("escapes the root"))?;
+                }
+                segment => segments.push(segment),
+            }
+        }
+
+        Ok(Self(segments.join("/")))
+    }
+
+    /// The normalized path as a string, empty for the root
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Check whether this is the root path
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterate over the segments of the path
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.0.split('/').filter(|s| !s.is_empty())
+    }
+
+    /// Append a relative path
+    /// 
+    /// `other` is normalized on its own, so its `..` segments can't climb
+    /// out of `self`.
+    pub fn join(&self, other: impl AsRef<str>) -> Result<Self> {
+        let other = Self::parse(other.as_ref())?;
+        Ok(match (self.is_root(), other.is_root()) {
+            (true, _) => other,
+            (_, true) => self.clone(),
+            _ => Self(format!("{}/{}", self.0, other.0)),
+        })
+    }
+
+    /// The path without its last segment, or None for the root
+    pub fn parent(&self) -> Option<Self> {
+        if self.is_root() {
+            return None;
+        }
+        Some(match self.0.rsplit_once('/') {
+            Some((parent, _)) => Self(parent.to_string()),
+            None => Self::root(),
+        })
+    }
+
+    /// The last segment, or None for the root
+    pub fn file_name(&self) -> Option<&str> {
+        self.segments().last()
+    }
+
+    /// Extension of the last segment, without the dot
+    /// 
+    /// Dotfiles such as `.gitignore` have no extension.
+    pub fn extension(&self) -> Option<&str> {
+        let (stem, extension) = self.file_name()?.rsplit_once('.')?;
+        (!stem.is_empty() && !extension.is_empty()).then_some(extension)
+    }
+
+    /// Check whether `prefix` is an ancestor of (or equal to) this path
+    /// 
+    /// Compares whole segments: `docs` is a prefix of `docs/a` but not `docs2`.
+    pub fn starts_with(&self, prefix: &ContentPath) -> bool {
+        self.strip_prefix(prefix).is_some()
+    }
+
+    /// The remainder of this path below `prefix`
+    /// 
+    /// Returns the root when the paths are equal, and None when `prefix` isn't
+    /// an ancestor.
+    pub fn strip_prefix(&self, prefix: &ContentPath) -> Option<Self> {
+        if prefix.is_root() {
+            return Some(self.clone());
+        }
+        match self.0.strip_prefix(&prefix.0)? {
+            "" => Some(Self::root()),
+            rest => rest.strip_prefix('/').map(|rest| Self(rest.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for ContentPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for ContentPath {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::str::FromStr for ContentPath {
+    type Err = ContentError;
+
+    fn from_str(path: &str) -> Result<Self> {
+        Self::parse(path)
+    }
+}
+
+impl TryFrom<&str> for ContentPath {
+    type Error = ContentError;
+
+    fn try_from(path: &str) -> Result<Self> {
+        Self::parse(path)
+    }
+}
+
+impl TryFrom<String> for ContentPath {
+    type Error = ContentError;
+
+    fn try_from(path: String) -> Result<Self> {
+        Self::parse(&path)
+    }
+}
+
+impl From<ContentPath> for String {
+    fn from(path: ContentPath) -> Self {
+        path.0
+    }
 }
 
 /// Represents an entry in a directory
+/// 
+/// The struct is `#[non_exhaustive]`; outside this crate, build entries with
+/// `DirectoryEntry::file` or `DirectoryEntry::dir` and the `with_*` methods.
+/// 
+/// ```compile_fail,E0639
+/// use content_resolver::{DirectoryEntry, EntryType};
+/// 
+/// let entry = DirectoryEntry {
+///     name: "a.txt".to_string(),
+///     path: "dir/a.txt".to_string(),
+///     entry_type: EntryType::File,
+///     mode: None,
+///     metadata: Default::default(),
+///     raw_name: None,
+/// };
+/// ```
+/// 
+/// ```
+/// use content_resolver::DirectoryEntry;
+/// 
+/// let entry = DirectoryEntry::file("run.sh", "bin/run.sh").with_mode(0o100755);
+/// assert!(entry.is_executable());
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct DirectoryEntry {
     /// Name of the file or folder
     pub name: String,
@@ -20,21 +740,918 @@ pub struct DirectoryEntry {
     pub path: String,
     /// Type of entry
     pub entry_type: EntryType,
+    /// Unix mode bits (e.g. `0o100755`), when the source exposes them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// Source-specific extras
+    /// 
+    /// Well-known keys, read through the typed accessors:
+    /// - `sha`: git blob sha (string)
+    /// - `size`: size in bytes (integer)
+    /// - `etag`: ETag or version identifier (string)
+    /// - `last_modified`: modification time in seconds since the Unix epoch (integer)
+    /// - `download_url`: direct download URL (string)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, serde_json::Value>,
+    /// The name's original bytes, when it isn't valid UTF-8
+    /// 
+    /// `name` and `path` then hold a lossy conversion with U+FFFD in place of
+    /// the invalid bytes. Since paths are `&str`, such entries can't be
+    /// fetched or listed through the API; recursive listings don't descend
+    /// into them and skill downloads skip them. Only `LocalFileSource` sets
+    /// it: GitHub only serves UTF-8 names.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_name: Option<Vec<u8>>,
+}
+
+impl DirectoryEntry {
+    /// Create a file entry
+    pub fn file(name: impl Into<String>, path: impl Into<String>) -> Self {
+        Self::new(name, path, EntryType::File)
+    }
+
+    /// Create a directory entry
+    pub fn dir(name: impl Into<String>, path: impl Into<String>) -> Self {
+        Self::new(name, path, EntryType::Dir)
+    }
+
+    /// Create an entry of the given type
+    pub fn new(name: impl Into<String>, path: impl Into<String>, entry_type: EntryType) -> Self {
+        Self {
+            name: name.into(),
+            path: path.into(),
+            entry_type,
+            mode: None,
+            metadata: BTreeMap::new(),
+            raw_name: None,
+        }
+    }
+
+    /// Set the Unix mode bits
+    pub fn with_mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Add a metadata value
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Record the bytes of a name that isn't valid UTF-8
+    pub fn with_raw_name(mut self, raw_name: impl Into<Vec<u8>>) -> Self {
+        self.raw_name = Some(raw_name.into());
+        self
+    }
+
+    /// Check whether the entry is marked executable
+    /// 
+    /// Returns false when the source doesn't expose mode bits
+    pub fn is_executable(&self) -> bool {
+        self.mode.is_some_and(|mode| mode & 0o111 != 0)
+    }
+
+    /// Git blob sha from the `sha` metadata key
+    pub fn sha(&self) -> Option<&str> {
+        self.metadata.get("sha")?.as_str()
+    }
+
+    /// Size in bytes from the `size` metadata key
+    pub fn size(&self) -> Option<u64> {
+        self.metadata.get("size")?.as_u64()
+    }
+
+    /// ETag from the `etag` metadata key
+    pub fn etag(&self) -> Option<&str> {
+        self.metadata.get("etag")?.as_str()
+    }
+
+    /// Modification time from the `last_modified` metadata key
+    pub fn last_modified(&self) -> Option<SystemTime> {
+        let secs = self.metadata.get("last_modified")?.as_u64()?;
+        UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+    }
+
+    /// Direct download URL from the `download_url` metadata key
+    pub fn download_url(&self) -> Option<&str> {
+        self.metadata.get("download_url")?.as_str()
+    }
+
+    /// Web page for the entry from the `html_url` metadata key
+    pub fn html_url(&self) -> Option<&str> {
+        self.metadata.get("html_url")?.as_str()
+    }
+
+    /// Fill in metadata keys this entry lacks from `other`
+    pub(crate) fn absorb_metadata(&mut self, other: BTreeMap<String, serde_json::Value>) {
+        for (key, value) in other {
+            self.metadata.entry(key).or_insert(value);
+        }
+    }
 }
 
 /// Type of directory entry
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
+/// 
+/// Serializes as a lowercase string (`"file"`, `"dir"`, ...). Kinds a
+/// source reports but this crate doesn't model become `Other` with the
+/// source's own name, and round-trip through serde unchanged.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+#[non_exhaustive]
 pub enum EntryType {
     File,
     Dir,
+    /// Symbolic link; the target isn't followed
+    Symlink,
+    /// Reference to another repository, e.g. a git submodule
+    Submodule,
+    /// Any other kind, named as the source reported it
+    Other(String),
+}
+
+impl EntryType {
+    /// Name used in serialized form
+    pub fn as_str(&self) -> &str {
+        match self {
+            EntryType::File => "file",
+            EntryType::Dir => "dir",
+            EntryType::Symlink => "symlink",
+            EntryType::Submodule => "submodule",
+            EntryType::Other(kind) => kind,
+        }
+    }
+}
+
+impl From<String> for EntryType {
+    fn from(kind: String) -> Self {
+        match kind.as_str() {
+            "file" => EntryType::File,
+            "dir" => EntryType::Dir,
+            "symlink" => EntryType::Symlink,
+            "submodule" => EntryType::Submodule,
+            _ => EntryType::Other(kind),
+        }
+    }
+}
+
+impl From<&str> for EntryType {
+    fn from(kind: &str) -> Self {
+        EntryType::from(kind.to_string())
+    }
+}
+
+impl From<EntryType> for String {
+    fn from(entry_type: EntryType) -> Self {
+        match entry_type {
+            EntryType::Other(kind) => kind,
+            known => known.as_str().to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for EntryType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 /// Result of listing a directory
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DirectoryListing {
     /// The path that was listed
     pub path: String,
     /// Entries found in the directory
     pub entries: Vec<DirectoryEntry>,
+    /// Set when entries were dropped to stay under a limit; see
+    /// `ResourceResolver::with_entry_limit`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+/// Which entry survives when merged listings contain the same path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DedupePolicy {
+    /// The earliest entry wins, whatever its type
+    #[default]
+    KeepFirst,
+    /// The latest entry wins, whatever its type
+    KeepLast,
+    /// A directory wins over a file at the same path; otherwise the earliest wins
+    PreferDir,
+}
+
+impl DedupePolicy {
+    /// Decide whether `candidate` replaces the `existing` entry at the same path
+    fn replaces(self, existing: &DirectoryEntry, candidate: &DirectoryEntry) -> bool {
+        match self {
+            DedupePolicy::KeepFirst => false,
+            DedupePolicy::KeepLast => true,
+            DedupePolicy::PreferDir => {
+                existing.entry_type == EntryType::File && candidate.entry_type == EntryType::Dir
+            }
+        }
+    }
+}
+
+impl DirectoryListing {
+    /// Create a complete listing of `path`
+    pub fn new(path: impl Into<String>, entries: Vec<DirectoryEntry>) -> Self {
+        Self {
+            path: path.into(),
+            entries,
+            truncated: false,
+        }
+    }
+
+    /// Iterate over the file entries
+    pub fn files(&self) -> impl Iterator<Item = &DirectoryEntry> {
+        self.entries.iter().filter(|e| e.entry_type == EntryType::File)
+    }
+
+    /// Iterate over the directory entries
+    pub fn dirs(&self) -> impl Iterator<Item = &DirectoryEntry> {
+        self.entries.iter().filter(|e| e.entry_type == EntryType::Dir)
+    }
+
+    /// Find an entry by name
+    pub fn find(&self, name: &str) -> Option<&DirectoryEntry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    /// Find an entry by path
+    pub fn find_by_path(&self, path: &str) -> Option<&DirectoryEntry> {
+        self.entries.iter().find(|e| e.path == path)
+    }
+
+    /// Check if an entry with the given name exists
+    pub fn contains(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if the listing has no entries
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Sort entries by name
+    pub fn sorted_by_name(mut self) -> Self {
+        self.entries.sort_by(|a, b| a.name.cmp(&b.name));
+        self
+    }
+
+    /// Sort entries with directories before files, each group by name
+    pub fn sort_dirs_first(mut self) -> Self {
+        self.entries.sort_by(|a, b| {
+            (a.entry_type != EntryType::Dir)
+                .cmp(&(b.entry_type != EntryType::Dir))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        self
+    }
+
+    /// Merge another listing's entries into this one
+    /// 
+    /// Entries are deduplicated by path, including duplicates already present
+    /// in either listing, with `policy` choosing the survivor (this listing's
+    /// entries count as earlier than `other`'s). The survivor also gets the
+    /// metadata keys it lacks from the entries it replaced or beat, with the
+    /// earlier entry winning per key. The merged entries are sorted by path,
+    /// which makes merging associative for every policy. The result is
+    /// `truncated` if either listing was.
+    pub fn merge(mut self, other: DirectoryListing, policy: DedupePolicy) -> Self {
+        self.truncated |= other.truncated;
+        let mut merged: BTreeMap<String, DirectoryEntry> = BTreeMap::new();
+
+        for mut entry in self.entries.into_iter().chain(other.entries) {
+            match merged.get_mut(&entry.path) {
+                Some(existing) if !policy.replaces(existing, &entry) => {
+                    existing.absorb_metadata(entry.metadata);
+                }
+                Some(existing) => {
+                    // The earlier entry's metadata still wins per key
+                    let earlier = std::mem::take(&mut existing.metadata);
+                    entry.metadata.extend(earlier);
+                    *existing = entry;
+                }
+                None => {
+                    merged.insert(entry.path.clone(), entry);
+                }
+            }
+        }
+
+        self.entries = merged.into_values().collect();
+        self
+    }
+}
+
+impl IntoIterator for DirectoryListing {
+    type Item = DirectoryEntry;
+    type IntoIter = std::vec::IntoIter<DirectoryEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a DirectoryListing {
+    type Item = &'a DirectoryEntry;
+    type IntoIter = std::slice::Iter<'a, DirectoryEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+impl Extend<DirectoryEntry> for DirectoryListing {
+    fn extend<I: IntoIterator<Item = DirectoryEntry>>(&mut self, iter: I) {
+        self.entries.extend(iter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_file_content_serde_round_trip() {
+        let file = FileContent::from_static(b"\x00binary\xff", "assets/blob.bin")
+            .with_etag("\"abc\"")
+            .with_content_type("application/octet-stream")
+            .with_last_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+
+        let json = serde_json::to_value(&file).unwrap();
+        assert_eq!(json["content_base64"], "AGJpbmFyef8=");
+        assert_eq!(json["size"], 8);
+        assert!(json.get("sha256").is_none());
+        assert!(json.get("stale").is_none());
+
+        let decoded: FileContent = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded.content, file.content);
+        assert_eq!(decoded.source_path, file.source_path);
+        assert_eq!(decoded.etag, file.etag);
+        assert_eq!(decoded.content_type, file.content_type);
+        assert_eq!(decoded.last_modified, file.last_modified);
+    }
+
+    #[test]
+    fn test_file_content_serde_rejects_inconsistent_size() {
+        let json = r#"{"content_base64":"aGVsbG8=","size":3,"source_path":"a.txt"}"#;
+        assert!(serde_json::from_str::<FileContent>(json).is_err());
+
+        let json = r#"{"content_base64":"aGVsbG8=","size":5,"source_path":"a.txt"}"#;
+        let file: FileContent = serde_json::from_str(json).unwrap();
+        assert_eq!(file.content, bytes::Bytes::from("hello"));
+        assert!(file.etag.is_none());
+    }
+
+    fn file(content: &'static [u8]) -> FileContent {
+        FileContent::from_static(content, "dir/file.txt")
+    }
+
+    #[test]
+    fn test_text_accessors() {
+        let utf8 = file("héllo\nworld\r\n".as_bytes());
+        assert_eq!(utf8.text().unwrap(), "héllo\nworld\r\n");
+        assert_eq!(utf8.lines().unwrap().collect::<Vec<_>>(), vec!["héllo", "world"]);
+        assert_eq!(utf8.clone().into_string().unwrap(), "héllo\nworld\r\n");
+
+        // A byte order mark is preserved verbatim
+        let bom = file(b"\xef\xbb\xbfkey=value");
+        assert_eq!(bom.text().unwrap(), "\u{feff}key=value");
+
+        let empty = file(b"");
+        assert_eq!(empty.text().unwrap(), "");
+        assert_eq!(empty.lines().unwrap().count(), 0);
+
+        let invalid = file(b"ok\xff\xfe");
+        match invalid.text() {
+            Err(ContentError::Validation { path, message, .. }) => {
+                assert_eq!(path, "dir/file.txt");
+                assert!(message.contains("UTF-8"));
+            }
+            other => panic!("Expected Validation, got {:?}", other),
+        }
+        assert!(invalid.clone().into_string().is_err());
+        assert_eq!(invalid.text_lossy(), "ok\u{fffd}\u{fffd}");
+    }
+
+    #[test]
+    fn test_json_accessor() {
+        let value: serde_json::Value = file(br#"{"a": [1, 2]}"#).json().unwrap();
+        assert_eq!(value["a"][1], 2);
+
+        match file(b"{not json").json::<serde_json::Value>() {
+            Err(ContentError::Validation { path, message, .. }) => {
+                assert_eq!(path, "dir/file.txt");
+                assert!(message.contains("JSON"));
+            }
+            other => panic!("Expected Validation, got {:?}", other),
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Settings {
+        name: String,
+        retries: u32,
+        tags: Vec<String>,
+    }
+
+    fn settings() -> Settings {
+        Settings {
+            name: "api".to_string(),
+            retries: 3,
+            tags: vec!["a".to_string(), "b".to_string()],
+        }
+    }
+
+    fn owned(content: Vec<u8>) -> FileContent {
+        FileContent::new(content, "dir/settings")
+    }
+
+    #[test]
+    fn test_decode_round_trips() {
+        let json = serde_json::to_vec(&settings()).unwrap();
+        let msgpack_map = rmp_serde::to_vec_named(&settings()).unwrap();
+        let msgpack_array = rmp_serde::to_vec(&settings()).unwrap();
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&settings(), &mut cbor).unwrap();
+        let mut pretty = b"\xef\xbb\xbf\n  ".to_vec();
+        pretty.extend(serde_json::to_vec_pretty(&settings()).unwrap());
+
+        assert_eq!(owned(json.clone()).json::<Settings>().unwrap(), settings());
+        assert_eq!(owned(msgpack_map.clone()).msgpack::<Settings>().unwrap(), settings());
+        assert_eq!(owned(cbor.clone()).cbor::<Settings>().unwrap(), settings());
+
+        for (content, format) in [
+            (json, DataFormat::Json),
+            (msgpack_map, DataFormat::MessagePack),
+            (msgpack_array, DataFormat::MessagePack),
+            (cbor, DataFormat::Cbor),
+        ] {
+            assert_eq!(DataFormat::sniff(&content), Some(format));
+            assert_eq!(owned(content).decode::<Settings>().unwrap(), (settings(), format));
+        }
+        // A byte order mark is skipped for sniffing and for decoding
+        assert_eq!(DataFormat::sniff(&pretty), Some(DataFormat::Json));
+        assert_eq!(owned(pretty).decode::<Settings>().unwrap(), (settings(), DataFormat::Json));
+    }
+
+    #[test]
+    fn test_decode_sniffing() {
+        assert_eq!(DataFormat::sniff(b""), None);
+        assert_eq!(DataFormat::sniff(b"name = 1"), None);
+        assert_eq!(DataFormat::sniff(b"\xd9\xd9\xf7\xa0"), Some(DataFormat::Cbor));
+        assert_eq!(DataFormat::sniff(b"\xde\x00\x00"), Some(DataFormat::MessagePack));
+
+        // 0x83 opens a 3-entry map in MessagePack and a 3-item array in CBOR
+        let cbor_array = owned(vec![0x83, 1, 2, 3]);
+        assert_eq!(DataFormat::sniff(&cbor_array.content), Some(DataFormat::MessagePack));
+        assert_eq!(cbor_array.decode::<Vec<u8>>().unwrap(), (vec![1, 2, 3], DataFormat::Cbor));
+        // 0x93 opens a 3-item array in MessagePack and a 19-item one in CBOR
+        let msgpack_array = owned(vec![0x93, 1, 2, 3]);
+        assert_eq!(msgpack_array.decode::<Vec<u8>>().unwrap(), (vec![1, 2, 3], DataFormat::MessagePack));
+
+        // Failures name the path and the format tried
+        for (content, format) in [
+            (&b"{\"name\": "[..], "JSON"),
+            (&b"\x81\xff"[..], "MessagePack"),
+            (&b"\xa1\xff"[..], "CBOR"),
+            (&b"plain text"[..], "not JSON, MessagePack or CBOR"),
+        ] {
+            match owned(content.to_vec()).decode::<Settings>() {
+                Err(ContentError::Validation { path, message, .. }) => {
+                    assert_eq!(path, "dir/settings");
+                    assert!(message.contains(format), "{} should mention {}", message, format);
+                }
+                other => panic!("Expected Validation, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_probably_binary() {
+        assert!(!file(b"").is_probably_binary());
+        assert!(!file(b"plain text\twith tabs\r\n").is_probably_binary());
+        assert!(!file("unicode ✓".as_bytes()).is_probably_binary());
+        assert!(file(b"PK\x03\x04\x00\x00").is_probably_binary());
+        assert!(file(b"\x01\x02\x03\x04abcdef").is_probably_binary());
+    }
+
+    fn entry(path: &str, entry_type: EntryType) -> DirectoryEntry {
+        DirectoryEntry::new(path.rsplit('/').next().unwrap(), path, entry_type)
+    }
+
+    fn listing(entries: Vec<DirectoryEntry>) -> DirectoryListing {
+        DirectoryListing::new("dir", entries)
+    }
+
+    #[test]
+    fn test_directory_listing_helpers() {
+        let dir = listing(vec![
+            entry("dir/b.txt", EntryType::File),
+            entry("dir/sub", EntryType::Dir),
+            entry("dir/a.txt", EntryType::File),
+        ]);
+
+        assert_eq!(dir.len(), 3);
+        assert!(!dir.is_empty());
+        assert!(listing(vec![]).is_empty());
+        assert_eq!(dir.files().count(), 2);
+        assert_eq!(dir.dirs().map(|e| e.name.as_str()).collect::<Vec<_>>(), vec!["sub"]);
+        assert_eq!(dir.find("a.txt").unwrap().path, "dir/a.txt");
+        assert_eq!(dir.find_by_path("dir/sub").unwrap().name, "sub");
+        assert!(dir.find("missing").is_none());
+        assert!(dir.contains("b.txt"));
+        assert!(!dir.contains("dir/b.txt"));
+
+        let dir = dir.sort_dirs_first();
+        let names: Vec<_> = (&dir).into_iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["sub", "a.txt", "b.txt"]);
+
+        let dir = dir.sorted_by_name();
+        let names: Vec<_> = dir.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["a.txt", "b.txt", "sub"]);
+
+        let mut dir = dir;
+        dir.extend(vec![entry("dir/c.txt", EntryType::File)]);
+        let owned: Vec<DirectoryEntry> = dir.into_iter().collect();
+        assert_eq!(owned.len(), 4);
+    }
+
+    #[test]
+    fn test_directory_listing_merge_policies() {
+        let first = listing(vec![
+            entry("dir/conflict", EntryType::File),
+            entry("dir/only-first", EntryType::File),
+        ]);
+        let second = listing(vec![
+            entry("dir/conflict", EntryType::Dir),
+            entry("dir/only-second", EntryType::Dir),
+        ]);
+
+        let kind = |merged: &DirectoryListing| merged.find("conflict").unwrap().entry_type.clone();
+
+        let merged = first.clone().merge(second.clone(), DedupePolicy::KeepFirst);
+        assert_eq!(merged.len(), 3);
+        assert_eq!(kind(&merged), EntryType::File);
+
+        let merged = first.clone().merge(second.clone(), DedupePolicy::KeepLast);
+        assert_eq!(kind(&merged), EntryType::Dir);
+
+        let merged = second.clone().merge(first.clone(), DedupePolicy::PreferDir);
+        assert_eq!(kind(&merged), EntryType::Dir);
+        let merged = first.merge(second, DedupePolicy::PreferDir);
+        assert_eq!(kind(&merged), EntryType::Dir);
+
+        let paths: Vec<_> = merged.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["dir/conflict", "dir/only-first", "dir/only-second"]);
+    }
+
+    #[test]
+    fn test_directory_listing_merge_metadata() {
+        let with_meta = |entry_type, pairs: &[(&str, serde_json::Value)]| {
+            let mut e = entry("dir/conflict", entry_type);
+            e.metadata = pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect();
+            e
+        };
+        let first = listing(vec![with_meta(
+            EntryType::File,
+            &[("sha", "aaa".into()), ("size", 1.into())],
+        )]);
+        let second = listing(vec![with_meta(
+            EntryType::Dir,
+            &[("sha", "bbb".into()), ("download_url", "https://x".into())],
+        )]);
+
+        for policy in [DedupePolicy::KeepFirst, DedupePolicy::KeepLast] {
+            let merged = first.clone().merge(second.clone(), policy);
+            let entry = merged.find("conflict").unwrap();
+            assert_eq!(entry.sha(), Some("aaa"), "{:?}", policy);
+            assert_eq!(entry.size(), Some(1));
+            assert_eq!(entry.download_url(), Some("https://x"));
+        }
+
+        // The survivor is still chosen by the policy
+        let merged = first.merge(second, DedupePolicy::KeepLast);
+        assert_eq!(merged.find("conflict").unwrap().entry_type, EntryType::Dir);
+    }
+
+    #[test]
+    fn test_directory_entry_metadata_serde() {
+        // Payloads written before `metadata` existed
+        let old = r#"{"name":"a.txt","path":"dir/a.txt","entry_type":"file"}"#;
+        let entry: DirectoryEntry = serde_json::from_str(old).unwrap();
+        assert!(entry.metadata.is_empty());
+        assert_eq!(entry.sha(), None);
+        assert_eq!(serde_json::to_string(&entry).unwrap(), old);
+
+        let new = r#"{"name":"a.txt","path":"dir/a.txt","entry_type":"file","metadata":{"last_modified":1700000000,"sha":"abc","size":12}}"#;
+        let entry: DirectoryEntry = serde_json::from_str(new).unwrap();
+        assert_eq!(entry.sha(), Some("abc"));
+        assert_eq!(entry.size(), Some(12));
+        assert_eq!(
+            entry.last_modified(),
+            Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+        assert_eq!(serde_json::to_string(&entry).unwrap(), new);
+    }
+
+    #[test]
+    fn test_directory_listing_merge_is_associative() {
+        // Small deterministic generator so the test needs no extra dependencies
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        let mut random_listing = |tag: &str| {
+            let count = next() % 5;
+            listing(
+                (0..count)
+                    .map(|_| {
+                        let path = format!("dir/{}", next() % 4);
+                        let entry_type = if next() % 2 == 0 {
+                            EntryType::File
+                        } else {
+                            EntryType::Dir
+                        };
+                        DirectoryEntry::new(tag, path, entry_type)
+                    })
+                    .collect(),
+            )
+        };
+        let summary = |merged: DirectoryListing| -> Vec<(String, String, EntryType)> {
+            merged.into_iter().map(|e| (e.path, e.name, e.entry_type)).collect()
+        };
+
+        for policy in [DedupePolicy::KeepFirst, DedupePolicy::KeepLast, DedupePolicy::PreferDir] {
+            for _ in 0..200 {
+                let (a, b, c) = (random_listing("a"), random_listing("b"), random_listing("c"));
+
+                let left = a.clone().merge(b.clone(), policy).merge(c.clone(), policy);
+                let right = a.merge(b.merge(c, policy), policy);
+                assert_eq!(summary(left), summary(right), "policy {:?}", policy);
+            }
+        }
+    }
+
+    #[test]
+    fn test_file_content_sha256() {
+        let file = FileContent::new("abc", "a.txt");
+        assert_eq!(
+            file.sha256(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        assert!(file
+            .verify_sha256("BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD")
+            .is_ok());
+        match file.verify_sha256("0000") {
+            Err(ContentError::IntegrityMismatch { expected, actual, .. }) => {
+                assert_eq!(expected, "0000");
+                assert!(actual.starts_with("ba7816bf"));
+            }
+            other => panic!("Expected IntegrityMismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_file_content_sha256_is_memoized() {
+        use crate::integrity::SHA256_CALLS;
+        let calls = || SHA256_CALLS.with(|c| c.get());
+
+        let file = FileContent::new("abc", "a.txt");
+        let before = calls();
+        file.sha256();
+        file.sha256();
+        file.verify_sha256(&file.sha256().to_uppercase()).unwrap();
+        assert_eq!(calls() - before, 1);
+
+        // Clones share the computed digest
+        let copy = file.clone();
+        copy.sha256();
+        assert_eq!(calls() - before, 1);
+
+        // New content starts without one
+        let other = FileContent::new("abd", "a.txt");
+        assert_ne!(other.sha256(), file.sha256());
+        assert_eq!(calls() - before, 2);
+
+        // Round trips carry the digest, but it is recomputed on the way in
+        let json = serde_json::to_string(&file).unwrap();
+        assert!(json.contains(r#""sha256":"ba7816bf"#));
+        let tampered = json.replace(r#""sha256":"ba7816bf"#, r#""sha256":"00"#);
+        let back: FileContent = serde_json::from_str(&tampered).unwrap();
+        assert!(back.sha256().starts_with("ba7816bf"));
+
+        // Replaced content isn't vouched for by the old digest
+        let mut replaced = file.clone();
+        replaced.content = bytes::Bytes::from_static(b"abd");
+        assert!(replaced.verify_sha256(file.sha256()).is_err());
+        replaced.verify_sha256(other.sha256()).unwrap();
+        let json = serde_json::to_value(&replaced).unwrap();
+        assert!(json.get("sha256").is_none());
+    }
+
+    fn path(s: &str) -> ContentPath {
+        ContentPath::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_content_path_normalization() {
+        assert_eq!(path("a/b/c.txt").as_str(), "a/b/c.txt");
+        assert_eq!(path("/a//b/./c.txt/").as_str(), "a/b/c.txt");
+        assert_eq!(path("a/x/../b").as_str(), "a/b");
+        assert!(path("").is_root());
+        assert!(path("/").is_root());
+        assert!(path("a/..").is_root());
+
+        for invalid in ["..", "../a", "a/../../b", "a\0b"] {
+            assert!(matches!(
+                ContentPath::parse(invalid),
+                Err(ContentError::InvalidPath { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_content_path_backslashes() {
+        let windows = |p: &str| ContentPath::parse_with(p, SeparatorPolicy::SlashOrBackslash);
+        assert_eq!(windows(r"\docs\\sub\.\guide.md").unwrap().as_str(), "docs/sub/guide.md");
+        assert_eq!(windows(r"docs/a\..\b.md").unwrap().as_str(), "docs/b.md");
+        assert!(windows(r"..\secrets").is_err());
+
+        // By default a backslash is part of the name
+        assert_eq!(path(r"docs\guide.md").as_str(), r"docs\guide.md");
+        assert_eq!(path(r"docs\guide.md").segments().count(), 1);
+    }
+
+    #[test]
+    fn test_content_path_join() {
+        // Mirrors GitHubSource's base path joining
+        let base = path("base/path");
+        assert_eq!(base.join("file.txt").unwrap().as_str(), "base/path/file.txt");
+        assert_eq!(base.join("/file.txt").unwrap().as_str(), "base/path/file.txt");
+        assert_eq!(base.join("").unwrap(), base);
+
+        let root = ContentPath::root();
+        assert_eq!(root.join("file.txt").unwrap().as_str(), "file.txt");
+        assert_eq!(root.join("/file.txt").unwrap().as_str(), "file.txt");
+
+        // The joined part can't climb out of the base
+        assert!(base.join("../secrets").is_err());
+    }
+
+    #[test]
+    fn test_content_path_strip_prefix() {
+        // Mirrors GitHubSource's base path stripping
+        let base = path("config");
+        assert_eq!(path("config/subdir").strip_prefix(&base).unwrap().as_str(), "subdir");
+        assert_eq!(
+            path("config/subdir/file.txt").strip_prefix(&base).unwrap().as_str(),
+            "subdir/file.txt"
+        );
+        assert!(path("config").strip_prefix(&base).unwrap().is_root());
+        assert_eq!(path("/config/subdir").strip_prefix(&base).unwrap().as_str(), "subdir");
+        assert_eq!(
+            path("base/path/config/sub").strip_prefix(&path("base/path")).unwrap().as_str(),
+            "config/sub"
+        );
+
+        let root = ContentPath::root();
+        assert_eq!(path("config/subdir").strip_prefix(&root).unwrap().as_str(), "config/subdir");
+
+        // Only whole segments match
+        assert_eq!(path("configs/a").strip_prefix(&base), None);
+        assert!(!path("configs/a").starts_with(&base));
+        assert!(path("config/a").starts_with(&base));
+    }
+
+    #[test]
+    fn test_content_path_components() {
+        let file = path("skills/nlp/summarize.tar.gz");
+        assert_eq!(file.file_name(), Some("summarize.tar.gz"));
+        assert_eq!(file.extension(), Some("gz"));
+        assert_eq!(file.parent().unwrap().as_str(), "skills/nlp");
+        assert_eq!(file.segments().collect::<Vec<_>>(), vec!["skills", "nlp", "summarize.tar.gz"]);
+
+        assert_eq!(path(".gitignore").extension(), None);
+        assert_eq!(path("Makefile").extension(), None);
+        assert!(path("top").parent().unwrap().is_root());
+        assert_eq!(ContentPath::root().parent(), None);
+        assert_eq!(ContentPath::root().file_name(), None);
+
+        assert_eq!(file.to_string(), "skills/nlp/summarize.tar.gz");
+        let json = serde_json::to_string(&file).unwrap();
+        assert_eq!(json, r#""skills/nlp/summarize.tar.gz""#);
+        assert_eq!(serde_json::from_str::<ContentPath>(r#""/a/./b/""#).unwrap(), path("a/b"));
+        assert!(serde_json::from_str::<ContentPath>(r#""../a""#).is_err());
+    }
+
+    mod content_path_properties {
+        use super::*;
+        use proptest::prelude::*;
+
+        /// Paths built from a small alphabet so `.`, `..` and empty segments are common
+        fn raw_path() -> impl Strategy<Value = String> {
+            prop::collection::vec(prop::sample::select(vec!["a", "b", ".", "..", "", "c.d"]), 0..8)
+                .prop_map(|segments| segments.join("/"))
+        }
+
+        proptest! {
+            #[test]
+            fn normalization_is_idempotent(raw in raw_path()) {
+                if let Ok(once) = ContentPath::parse(&raw) {
+                    prop_assert_eq!(ContentPath::parse(once.as_str()).unwrap(), once.clone());
+                    prop_assert!(once
+                        .segments()
+                        .all(|s| !s.is_empty() && s != "." && s != ".."));
+                }
+            }
+
+            #[test]
+            fn join_then_strip_round_trips(base in raw_path(), rest in raw_path()) {
+                if let (Ok(base), Ok(joined)) = (
+                    ContentPath::parse(&base),
+                    ContentPath::parse(&base).and_then(|b| b.join(&rest)),
+                ) {
+                    let stripped = joined.strip_prefix(&base).unwrap();
+                    prop_assert_eq!(stripped, ContentPath::parse(&rest).unwrap());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_file_content_constructors() {
+        let file = FileContent::new(vec![1u8, 2, 3], String::from("a.bin"));
+        assert_eq!(file.content, bytes::Bytes::from_static(&[1, 2, 3]));
+        assert_eq!(file.source_path, "a.bin");
+        assert!(file.etag.is_none() && file.content_type.is_none() && file.last_modified.is_none());
+
+        static EMBEDDED: &[u8] = b"embedded";
+        let file = FileContent::from_static(EMBEDDED, "e.txt");
+        assert_eq!(file.content.as_ptr(), EMBEDDED.as_ptr());
+
+        let when = UNIX_EPOCH + Duration::from_secs(5);
+        let file = FileContent::new("x", "x.txt")
+            .with_etag("v1")
+            .with_content_type("text/plain")
+            .with_last_modified(when);
+        assert_eq!(file.etag.as_deref(), Some("v1"));
+        assert_eq!(file.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(file.last_modified, Some(when));
+    }
+
+    #[test]
+    fn test_directory_entry_constructors() {
+        let file = DirectoryEntry::file("a.txt", "dir/a.txt");
+        assert_eq!((file.name.as_str(), file.path.as_str()), ("a.txt", "dir/a.txt"));
+        assert_eq!(file.entry_type, EntryType::File);
+        assert!(file.mode.is_none() && file.metadata.is_empty());
+
+        let dir = DirectoryEntry::dir("sub", "dir/sub");
+        assert_eq!(dir.entry_type, EntryType::Dir);
+
+        let entry = DirectoryEntry::new("run.sh", "run.sh", EntryType::File)
+            .with_mode(0o100755)
+            .with_metadata("sha", "abc")
+            .with_metadata("size", 3);
+        assert!(entry.is_executable());
+        assert_eq!(entry.sha(), Some("abc"));
+        assert_eq!(entry.size(), Some(3));
+    }
+
+    #[test]
+    fn test_entry_type_serde() {
+        for (entry_type, json) in [
+            (EntryType::File, r#""file""#),
+            (EntryType::Dir, r#""dir""#),
+            (EntryType::Symlink, r#""symlink""#),
+            (EntryType::Submodule, r#""submodule""#),
+            (EntryType::Other("glacier".to_string()), r#""glacier""#),
+        ] {
+            assert_eq!(serde_json::to_string(&entry_type).unwrap(), json);
+            assert_eq!(serde_json::from_str::<EntryType>(json).unwrap(), entry_type);
+        }
+
+        // Entries written before the new kinds existed still load
+        let entry: DirectoryEntry =
+            serde_json::from_str(r#"{"name":"a","path":"a","entry_type":"lock"}"#).unwrap();
+        assert_eq!(entry.entry_type, EntryType::Other("lock".to_string()));
+        assert_eq!(entry.entry_type.to_string(), "lock");
+        assert!(serde_json::to_string(&entry).unwrap().contains(r#""entry_type":"lock""#));
+    }
 }
@@ -0,0 +1,174 @@
+//! Startup checks of a resolver's sources, cache and routes
+
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+use crate::error::{ContentError, ErrorKind};
+
+/// What `ResourceResolver::validate_configuration` found
+/// 
+/// Serializes to JSON for tooling; `Display` lists the findings one per line.
+#[derive(Debug, Clone, Default, Serialize)]
+#[non_exhaustive]
+pub struct ValidationReport {
+    /// Problems, in the order sources, cache and routes were checked
+    pub findings: Vec<Finding>,
+    /// Sources whose base path was listed
+    pub sources_checked: usize,
+    /// Whether the cache round trip ran
+    pub cache_checked: bool,
+    /// Routed prefixes that were looked up
+    pub routes_checked: usize,
+}
+
+impl ValidationReport {
+    /// Whether nothing fatal was found; warnings are fine
+    pub fn is_ok(&self) -> bool {
+        self.fatal().next().is_none()
+    }
+
+    /// Findings that should stop a service from starting
+    pub fn fatal(&self) -> impl Iterator<Item = &Finding> {
+        self.findings.iter().filter(|f| f.severity == Severity::Fatal)
+    }
+
+    /// Findings worth logging that don't stop anything working
+    pub fn warnings(&self) -> impl Iterator<Item = &Finding> {
+        self.findings.iter().filter(|f| f.severity == Severity::Warning)
+    }
+
+    /// `InvalidConfig` listing the fatal findings, if there are any
+    pub(crate) fn fatal_error(&self) -> Option<ContentError> {
+        let fatal: Vec<String> = self.fatal().map(ToString::to_string).collect();
+        if fatal.is_empty() {
+            return None;
+        }
+        Some(ContentError::InvalidConfig {
+            message: format!("configuration check failed: {}", fatal.join("; ")),
+        })
+    }
+}
+
+/// One problem found by a configuration check
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct Finding {
+    /// Whether it should stop a service from starting
+    pub severity: Severity,
+    /// What was checked
+    pub subject: Subject,
+    /// What is wrong, in a sentence
+    pub message: String,
+    /// Kind of the error behind the finding, if one was returned
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_kind")]
+    pub kind: Option<ErrorKind>,
+}
+
+impl Finding {
+    pub(crate) fn new(severity: Severity, subject: Subject, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            subject,
+            message: message.into(),
+            kind: None,
+        }
+    }
+
+    /// A finding for `error`, with its root message after `message`
+    pub(crate) fn from_error(severity: Severity, subject: Subject, message: &str, error: &ContentError) -> Self {
+        Self {
+            kind: Some(error.kind()),
+            ..Self::new(severity, subject, format!("{}: {}", message, error.root()))
+        }
+    }
+}
+
+/// How bad a finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Requests will fail; the configuration is wrong
+    Fatal,
+    /// Suspicious, or not checkable right now, e.g. an empty base path or a
+    /// rate limited source
+    Warning,
+}
+
+/// The part of the configuration a finding is about
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "subject")]
+pub enum Subject {
+    /// The source at `index` in the resolver's source list
+    Source { index: usize, source: String },
+    /// The resolver's cache
+    Cache,
+    /// A prefix from `ValidationOptions::with_route`
+    Route { prefix: String },
+}
+
+/// Options for `ResourceResolver::validate_configuration_with`
+#[derive(Debug, Clone, Default)]
+pub struct ValidationOptions {
+    pub(crate) routes: Vec<String>,
+    pub(crate) fail_on_fatal: bool,
+}
+
+impl ValidationOptions {
+    /// Also check that some source has `prefix`, e.g. a path a route serves from
+    pub fn with_route(mut self, prefix: impl Into<String>) -> Self {
+        self.routes.push(prefix.into());
+        self
+    }
+
+    /// Return `InvalidConfig` instead of a report when anything fatal is found
+    pub fn with_fail_on_fatal(mut self, fail: bool) -> Self {
+        self.fail_on_fatal = fail;
+        self
+    }
+}
+
+fn serialize_kind<S: Serializer>(kind: &Option<ErrorKind>, serializer: S) -> Result<S::Ok, S::Error> {
+    match kind {
+        Some(kind) => serializer.serialize_str(kind.as_str()),
+        None => serializer.serialize_none(),
+    }
+}
+
+impl fmt::Display for Subject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Subject::Source { index, source } => write!(f, "source {} {}", index + 1, source),
+            Subject::Cache => write!(f, "cache"),
+            Subject::Route { prefix } => write!(f, "route {}", prefix),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Fatal => "fatal",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}: {}", severity, self.subject, self.message)
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "checked {} sources, {}, {} routes",
+            self.sources_checked,
+            if self.cache_checked { "the cache" } else { "no cache" },
+            self.routes_checked
+        )?;
+        if self.findings.is_empty() {
+            return write!(f, "\nno problems found");
+        }
+        for finding in &self.findings {
+            write!(f, "\n{}", finding)?;
+        }
+        Ok(())
+    }
+}
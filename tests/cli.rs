@@ -0,0 +1,189 @@
+//! Tests driving the `content-resolver` binary against local directories
+
+#![cfg(feature = "cli")]
+
+use assert_cmd::Command;
+use content_resolver::{DirectoryListing, EntryType};
+use std::path::Path;
+use tempfile::TempDir;
+
+fn tree() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    let write = |path: &str, content: &str| {
+        let path = dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    };
+    write("primary/docs/guide.md", "# Guide\n");
+    write("primary/docs/api/index.md", "api\n");
+    write("fallback/docs/guide.md", "old guide\n");
+    write("fallback/docs/extra.md", "extra\n");
+    dir
+}
+
+fn cli(dir: &Path) -> Command {
+    let mut cmd = Command::cargo_bin("content-resolver").unwrap();
+    cmd.current_dir(dir).env_remove("GITHUB_TOKEN");
+    cmd
+}
+
+#[test]
+fn test_fetch_uses_fallback_chain() {
+    let dir = tree();
+    let chain = ["--local", "primary", "--local", "fallback"];
+
+    cli(dir.path())
+        .args(chain)
+        .args(["fetch", "docs/guide.md"])
+        .assert()
+        .success()
+        .stdout("# Guide\n");
+    cli(dir.path())
+        .args(chain)
+        .args(["fetch", "docs/extra.md", "-o", "extra.md"])
+        .assert()
+        .success()
+        .stdout("");
+    assert_eq!(std::fs::read_to_string(dir.path().join("extra.md")).unwrap(), "extra\n");
+}
+
+#[test]
+fn test_exit_codes() {
+    let dir = tree();
+
+    cli(dir.path())
+        .args(["--local", "primary", "fetch", "docs/missing.md"])
+        .assert()
+        .code(2);
+    // No sources at all is a configuration error, not a missing file
+    cli(dir.path()).args(["fetch", "docs/guide.md"]).assert().code(1);
+    cli(dir.path())
+        .args(["--github", "not-a-repo", "fetch", "docs/guide.md"])
+        .assert()
+        .code(1);
+    cli(dir.path()).arg("frobnicate").assert().code(1);
+}
+
+#[test]
+fn test_ls() {
+    let dir = tree();
+
+    let output = cli(dir.path())
+        .args(["--local", "primary", "ls", "docs"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let table = String::from_utf8(output).unwrap();
+    let names: Vec<_> = table.lines().map(|l| l.split_whitespace().last().unwrap()).collect();
+    assert_eq!(names, vec!["api/", "guide.md"]);
+
+    let output = cli(dir.path())
+        .args(["--local", "primary", "ls", "docs", "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let listing: DirectoryListing = serde_json::from_slice(&output).unwrap();
+    assert_eq!(listing.path, "docs");
+    assert_eq!(listing.find("api").unwrap().entry_type, EntryType::Dir);
+    assert_eq!(listing.find("guide.md").unwrap().size(), Some(8));
+}
+
+#[test]
+fn test_download() {
+    let dir = tree();
+
+    let output = cli(dir.path())
+        .args(["--local", "primary", "download", "docs", "out"])
+        .assert()
+        .success()
+        .get_output()
+        .stderr
+        .clone();
+    let progress = String::from_utf8(output).unwrap();
+    assert!(progress.contains("[2/2]"), "{}", progress);
+
+    let out = dir.path().join("out");
+    assert_eq!(std::fs::read_to_string(out.join("guide.md")).unwrap(), "# Guide\n");
+    assert_eq!(std::fs::read_to_string(out.join("api/index.md")).unwrap(), "api\n");
+}
+
+#[test]
+fn test_explain() {
+    let dir = tree();
+    let chain = ["--local", "primary", "--local", "fallback"];
+
+    let output = cli(dir.path())
+        .args(chain)
+        .args(["explain", "docs/extra.md"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let text = String::from_utf8(output).unwrap();
+    assert!(text.contains("cache: none"), "{}", text);
+    assert!(text.contains(": not found\n"), "{}", text);
+    assert!(text.contains(": found, 6 bytes\n"), "{}", text);
+    assert!(text.contains("resolution: source 2 "), "{}", text);
+
+    let output = cli(dir.path())
+        .args(chain)
+        .args(["explain", "docs/guide.md", "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let explanation: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(explanation["resolution"]["from"], "source");
+    assert_eq!(explanation["resolution"]["index"], 0);
+    assert_eq!(explanation["sources"][1]["outcome"], "found");
+}
+
+#[test]
+fn test_config_file_and_cache() {
+    let dir = tree();
+    std::fs::write(
+        dir.path().join("resolver.toml"),
+        "cache_dir = \"cache\"\n\n[[sources]]\nlocal = \"primary\"\n",
+    )
+    .unwrap();
+    let config = ["--config", "resolver.toml"];
+
+    cli(dir.path())
+        .args(config)
+        .args(["fetch", "docs/guide.md"])
+        .assert()
+        .success()
+        .stdout("# Guide\n");
+    cli(dir.path())
+        .args(config)
+        .args(["cache", "stats"])
+        .assert()
+        .success()
+        .stdout("1 entries, 8 bytes\n");
+    cli(dir.path())
+        .args(config)
+        .args(["cache", "invalidate", "docs"])
+        .assert()
+        .success()
+        .stdout("Invalidated 1 entries\n");
+    cli(dir.path())
+        .args(config)
+        .args(["cache", "clear"])
+        .assert()
+        .success();
+    cli(dir.path())
+        .args(config)
+        .args(["cache", "stats"])
+        .assert()
+        .success()
+        .stdout("0 entries, 0 bytes\n");
+
+    // Cache commands without a cache directory
+    cli(dir.path()).args(["cache", "stats"]).assert().code(1);
+}
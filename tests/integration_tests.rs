@@ -1,12 +1,16 @@
-/// Integration tests for the content resolution system
-///
-/// These tests demonstrate proper usage and verify behavior
+//! Integration tests for the content resolution system
+//!
+//! These tests demonstrate proper usage and verify behavior
 
 use content_resolver::{
     Cache, ContentError, ContentSource, DirectoryEntry, DirectoryListing, DiskCache, EntryType,
-    FileContent, GitHubSource, LanguageProvider, MemoryCache, ResourceResolver, SkillProvider,
+    ErrorKind, EvictionPolicy, FailurePolicy, FileContent, LanguageProvider, MemoryCache,
+    ResourceResolver, SkillLayout, SkillProvider,
 };
+use content_resolver::testing::{FaultInjectingSource, MemorySource};
+use content_resolver::{RetryConfig, RetryingSource};
 use std::sync::Arc;
+use std::time::Duration;
 use tempfile::TempDir;
 
 // Mock source for testing without network access
@@ -37,11 +41,7 @@ impl ContentSource for MockContentSource {
     async fn fetch_file(&self, path: &str) -> content_resolver::Result<FileContent> {
         self.files
             .get(path)
-            .map(|content| FileContent {
-                content: bytes::Bytes::from(content.clone()),
-                source_path: path.to_string(),
-                etag: None,
-            })
+            .map(|content| FileContent::new(content.clone(), path))
             .ok_or_else(|| ContentError::NotFound {
                 path: path.to_string(),
             })
@@ -50,10 +50,7 @@ impl ContentSource for MockContentSource {
     async fn list_directory(&self, path: &str) -> content_resolver::Result<DirectoryListing> {
         self.dirs
             .get(path)
-            .map(|entries| DirectoryListing {
-                path: path.to_string(),
-                entries: entries.clone(),
-            })
+            .map(|entries| DirectoryListing::new(path, entries.clone()))
             .ok_or_else(|| ContentError::NotFound {
                 path: path.to_string(),
             })
@@ -128,6 +125,81 @@ async fn test_memory_cache() {
     assert_eq!(content2.source_path, "cache:cached.txt");
 }
 
+/// Read a few hot files repeatedly, then scan a long tail once each
+async fn hot_files_after_scan(policy: EvictionPolicy) -> Vec<bool> {
+    let mut memory = MemorySource::new();
+    for i in 0..4 {
+        memory = memory.with_file(&format!("locales/hot{}.lang", i), "hot");
+    }
+    for i in 0..50 {
+        memory = memory.with_file(&format!("docs/page{}.md", i), "tail");
+    }
+    let cache = Arc::new(MemoryCache::bounded(10, policy));
+    let resolver = ResourceResolver::with_cache(vec![Arc::new(memory)], cache.clone());
+
+    for _ in 0..3 {
+        for i in 0..4 {
+            resolver.fetch_file(format!("locales/hot{}.lang", i)).await.unwrap();
+        }
+    }
+    for i in 0..50 {
+        resolver.fetch_file(format!("docs/page{}.md", i)).await.unwrap();
+    }
+    let mut survived = Vec::new();
+    for i in 0..4 {
+        survived.push(cache.contains(&format!("file:locales/hot{}.lang", i)).await);
+    }
+    survived
+}
+
+#[tokio::test]
+async fn test_bounded_cache_resists_scans() {
+    let segmented = EvictionPolicy::SegmentedLru { protected_ratio: 0.5 };
+    assert_eq!(hot_files_after_scan(segmented).await, [true; 4]);
+    assert_eq!(hot_files_after_scan(EvictionPolicy::Lfu).await, [true; 4]);
+    assert_eq!(hot_files_after_scan(EvictionPolicy::Lru).await, [false; 4]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_resolver_shutdown_stops_background_tasks() {
+    let source = MemorySource::new().with_file("config.json", "{}");
+    let cache = Arc::new(MemoryCache::new());
+    let resolver = ResourceResolver::with_cache(vec![Arc::new(source)], cache.clone());
+    resolver.fetch_file("config.json").await.unwrap();
+    let (stopped, mut stopped_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    // Watcher: polls for drift until told to stop
+    let (watcher, tx) = (resolver.clone(), stopped.clone());
+    resolver.tasks().spawn(|signal| async move {
+        loop {
+            tokio::select! {
+                _ = signal.cancelled() => break,
+                _ = tokio::time::sleep(Duration::from_secs(30)) => {
+                    watcher.is_cache_stale("config.json").await.unwrap();
+                }
+            }
+        }
+        tx.send("watcher").unwrap();
+    });
+    // Janitor: sweeps the cache, finishing the sweep it is in
+    resolver.tasks().spawn(|signal| async move {
+        while !signal.is_cancelled() {
+            cache.clear().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        stopped.send("janitor").unwrap();
+    });
+    tokio::time::sleep(Duration::from_secs(90)).await;
+
+    let start = tokio::time::Instant::now();
+    assert_eq!(resolver.shutdown(Duration::from_secs(5)).await, 0);
+    assert!(start.elapsed() < Duration::from_secs(5));
+    let mut seen = vec![stopped_rx.recv().await.unwrap(), stopped_rx.recv().await.unwrap()];
+    seen.sort();
+    assert_eq!(seen, ["janitor", "watcher"]);
+    assert!(resolver.tasks().is_empty());
+}
+
 #[tokio::test]
 async fn test_disk_cache() {
     let temp_dir = TempDir::new().unwrap();
@@ -166,8 +238,8 @@ async fn test_disk_cache() {
 async fn test_language_provider() {
     let mut source = MockContentSource::new();
     source.add_file("locales/en.lang", b"Hello");
-    source.add_file("locales/pt.lang", b"Olá");
-    source.add_file("locales/pt-BR.lang", b"Olá (Brasil)");
+    source.add_file("locales/pt.lang", "Olá".as_bytes());
+    source.add_file("locales/pt-BR.lang", "Olá (Brasil)".as_bytes());
 
     let resolver = Arc::new(ResourceResolver::new(vec![
         Arc::new(source) as Arc<dyn ContentSource>
@@ -209,21 +281,9 @@ async fn test_skill_provider_list() {
     source.add_directory(
         "skills",
         vec![
-            DirectoryEntry {
-                name: "skill1".to_string(),
-                path: "skills/skill1".to_string(),
-                entry_type: EntryType::Dir,
-            },
-            DirectoryEntry {
-                name: "skill2".to_string(),
-                path: "skills/skill2".to_string(),
-                entry_type: EntryType::Dir,
-            },
-            DirectoryEntry {
-                name: "README.md".to_string(),
-                path: "skills/README.md".to_string(),
-                entry_type: EntryType::File,
-            },
+            DirectoryEntry::dir("skill1", "skills/skill1"),
+            DirectoryEntry::dir("skill2", "skills/skill2"),
+            DirectoryEntry::file("README.md", "skills/README.md"),
         ],
     );
 
@@ -246,26 +306,14 @@ async fn test_skill_provider_download() {
     source.add_directory(
         "skills/test_skill",
         vec![
-            DirectoryEntry {
-                name: "main.py".to_string(),
-                path: "skills/test_skill/main.py".to_string(),
-                entry_type: EntryType::File,
-            },
-            DirectoryEntry {
-                name: "config".to_string(),
-                path: "skills/test_skill/config".to_string(),
-                entry_type: EntryType::Dir,
-            },
+            DirectoryEntry::file("main.py", "skills/test_skill/main.py"),
+            DirectoryEntry::dir("config", "skills/test_skill/config"),
         ],
     );
 
     source.add_directory(
         "skills/test_skill/config",
-        vec![DirectoryEntry {
-            name: "settings.json".to_string(),
-            path: "skills/test_skill/config/settings.json".to_string(),
-            entry_type: EntryType::File,
-        }],
+        vec![DirectoryEntry::file("settings.json", "skills/test_skill/config/settings.json")],
     );
 
     // Add file contents
@@ -300,6 +348,12 @@ async fn test_skill_provider_download() {
             .await
             .unwrap();
     assert_eq!(config_content, "{\"key\": \"value\"}");
+
+    // Downloading again overwrites the files without leaving their old content behind
+    std::fs::write(temp_dir.path().join("main.py"), "edited").unwrap();
+    provider.download_skill("test_skill", temp_dir.path()).await.unwrap();
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join("main.py")).unwrap(), "print('Hello')");
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 2);
 }
 
 #[tokio::test]
@@ -307,27 +361,15 @@ async fn test_merged_directory_listing() {
     let mut source1 = MockContentSource::new();
     source1.add_directory(
         "dir",
-        vec![DirectoryEntry {
-            name: "file1.txt".to_string(),
-            path: "dir/file1.txt".to_string(),
-            entry_type: EntryType::File,
-        }],
+        vec![DirectoryEntry::file("file1.txt", "dir/file1.txt")],
     );
 
     let mut source2 = MockContentSource::new();
     source2.add_directory(
         "dir",
         vec![
-            DirectoryEntry {
-                name: "file2.txt".to_string(),
-                path: "dir/file2.txt".to_string(),
-                entry_type: EntryType::File,
-            },
-            DirectoryEntry {
-                name: "file1.txt".to_string(),
-                path: "dir/file1.txt".to_string(),
-                entry_type: EntryType::File,
-            },
+            DirectoryEntry::file("file2.txt", "dir/file2.txt"),
+            DirectoryEntry::file("file1.txt", "dir/file1.txt"),
         ],
     );
 
@@ -386,9 +428,10 @@ async fn test_concurrent_operations() {
     assert_eq!(result3.unwrap().content, bytes::Bytes::from("Content 3"));
 }
 
+#[cfg(feature = "github")]
 #[test]
 fn test_github_source_path_joining() {
-    let source = GitHubSource::new(
+    let source = content_resolver::GitHubSource::new(
         "owner".to_string(),
         "repo".to_string(),
         "main".to_string(),
@@ -402,3 +445,889 @@ fn test_github_source_path_joining() {
     assert!(id.contains("main"));
     assert!(id.contains("base/path"));
 }
+
+/// Build a mock source whose directories are derived from a list of file paths
+fn mock_tree(files: &[&str]) -> MockContentSource {
+    let mut source = MockContentSource::new();
+    let mut dirs: std::collections::BTreeMap<String, Vec<DirectoryEntry>> =
+        std::collections::BTreeMap::new();
+
+    for file in files {
+        source.add_file(file, file.as_bytes());
+
+        let mut child = file.to_string();
+        let mut entry_type = EntryType::File;
+        while let Some((parent, name)) = child.rsplit_once('/') {
+            let entries = dirs.entry(parent.to_string()).or_default();
+            if !entries.iter().any(|e| e.path == child) {
+                entries.push(DirectoryEntry::new(name, child.clone(), entry_type));
+            }
+            child = parent.to_string();
+            entry_type = EntryType::Dir;
+        }
+    }
+
+    for (path, entries) in dirs {
+        source.add_directory(&path, entries);
+    }
+    source
+}
+
+fn nested_skill_provider(layout: SkillLayout) -> SkillProvider {
+    let source = mock_tree(&[
+        "skills/README.md",
+        "skills/standalone/skill.json",
+        "skills/nlp/summarize/skill.json",
+        "skills/nlp/summarize/main.py",
+        "skills/nlp/translate/skill.json",
+        "skills/vision/detect/skill.json",
+        "skills/vision/detect/main.py",
+        "skills/vision/detect/models/skill.json",
+        "skills/vision/detect/models/weights.bin",
+    ]);
+    let resolver = Arc::new(ResourceResolver::new(vec![
+        Arc::new(source) as Arc<dyn ContentSource>
+    ]));
+    SkillProvider::new(resolver, "skills".to_string()).with_layout(layout)
+}
+
+async fn skill_ids(provider: &SkillProvider) -> Vec<String> {
+    provider
+        .list_skills()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|s| s.id)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_skill_layout_flat() {
+    let provider = nested_skill_provider(SkillLayout::Flat);
+    assert_eq!(skill_ids(&provider).await, vec!["nlp", "standalone", "vision"]);
+}
+
+#[tokio::test]
+async fn test_skill_layout_categorized() {
+    let provider = nested_skill_provider(SkillLayout::Categorized { depth: 1 });
+    assert_eq!(
+        skill_ids(&provider).await,
+        vec!["nlp/summarize", "nlp/translate", "vision/detect"]
+    );
+
+    let skills = provider.list_skills().await.unwrap();
+    assert_eq!(skills[0].name, "summarize");
+    assert_eq!(skills[0].path, "skills/nlp/summarize");
+}
+
+#[tokio::test]
+async fn test_skill_layout_detect() {
+    let provider = nested_skill_provider(SkillLayout::Detect { max_depth: 3 });
+
+    // vision/detect/models has a manifest but belongs to vision/detect
+    assert_eq!(
+        skill_ids(&provider).await,
+        vec!["nlp/summarize", "nlp/translate", "standalone", "vision/detect"]
+    );
+
+    let shallow = nested_skill_provider(SkillLayout::Detect { max_depth: 1 });
+    assert_eq!(skill_ids(&shallow).await, vec!["standalone"]);
+}
+
+#[tokio::test]
+async fn test_skill_qualified_id_download_and_manifest() {
+    let provider = nested_skill_provider(SkillLayout::Detect { max_depth: 3 });
+
+    let temp_dir = TempDir::new().unwrap();
+    let result = provider
+        .download_skill("vision/detect", temp_dir.path())
+        .await
+        .unwrap();
+
+    assert_eq!(result.files_written.len(), 4);
+    assert!(temp_dir.path().join("models/weights.bin").exists());
+
+    // The mock serves each file's path as its content, which isn't JSON
+    let error = provider.fetch_manifest("nlp/summarize").await.unwrap_err();
+    assert!(matches!(error.root(), ContentError::Serialization(_)));
+    assert!(matches!(
+        provider.download_skill("../secrets", temp_dir.path()).await,
+        Err(ContentError::InvalidPath { .. })
+    ));
+    assert!(matches!(
+        provider.download_skill("vision/./detect", temp_dir.path()).await,
+        Err(ContentError::InvalidPath { .. })
+    ));
+
+    // Ids are normalized like any other path
+    let structure = provider.get_skill_structure("/vision//detect/").await.unwrap();
+    assert_eq!(structure.len(), provider.get_skill_structure("vision/detect").await.unwrap().len());
+}
+
+fn dependency_provider(manifests: &[(&str, &str)]) -> SkillProvider {
+    let mut source = MemorySource::new().with_file("skills/plain/run.sh", "echo");
+    for (id, manifest) in manifests {
+        source = source
+            .with_file(&format!("skills/{}/skill.json", id), manifest.to_string())
+            .with_file(&format!("skills/{}/main.py", id), id.to_string());
+    }
+    let resolver = Arc::new(ResourceResolver::new(vec![
+        Arc::new(source) as Arc<dyn ContentSource>
+    ]));
+    SkillProvider::new(resolver, "skills".to_string())
+}
+
+#[tokio::test]
+async fn test_skill_dependencies() {
+    // app needs db and web; web needs db and plain, which has no manifest
+    let provider = dependency_provider(&[
+        ("app", r#"{"dependencies": ["web", "db"]}"#),
+        ("web", r#"{"dependencies": ["db", "plain"]}"#),
+        ("db", r#"{"name": "db"}"#),
+    ]);
+
+    assert_eq!(
+        provider.resolve_dependencies("app").await.unwrap(),
+        vec!["db", "plain", "web"]
+    );
+    assert_eq!(provider.resolve_dependencies("db").await.unwrap(), Vec::<String>::new());
+    assert_eq!(provider.resolve_dependencies("plain").await.unwrap(), Vec::<String>::new());
+
+    let temp_dir = TempDir::new().unwrap();
+    let results = provider
+        .download_skill_with_deps("app", temp_dir.path())
+        .await
+        .unwrap();
+    let ids: Vec<_> = results.iter().map(|(id, _)| id.as_str()).collect();
+    assert_eq!(ids, vec!["db", "plain", "web", "app"]);
+    assert!(temp_dir.path().join("db/main.py").exists());
+    assert!(temp_dir.path().join("plain/run.sh").exists());
+    assert!(temp_dir.path().join("app/skill.json").exists());
+}
+
+#[tokio::test]
+async fn test_skill_dependency_errors() {
+    let provider = dependency_provider(&[
+        ("a", r#"{"dependencies": ["b"]}"#),
+        ("b", r#"{"dependencies": ["c"]}"#),
+        ("c", r#"{"dependencies": ["a"]}"#),
+        ("self", r#"{"dependencies": ["self"]}"#),
+        ("broken", r#"{"dependencies": ["a", 7]}"#),
+        ("orphan", r#"{"dependencies": ["missing"]}"#),
+    ]);
+
+    let error = provider.resolve_dependencies("a").await.unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Validation);
+    assert!(error.to_string().contains("dependency cycle: a -> b -> c -> a"), "{}", error);
+    assert_eq!(error.path(), Some("skills/c/skill.json"));
+
+    let error = provider.resolve_dependencies("self").await.unwrap_err();
+    assert!(error.to_string().contains("self -> self"), "{}", error);
+
+    let error = provider.resolve_dependencies("broken").await.unwrap_err();
+    assert!(matches!(
+        error.root(),
+        ContentError::Validation { pointers, .. } if pointers == &["/dependencies/1"]
+    ));
+
+    let error = provider.resolve_dependencies("orphan").await.unwrap_err();
+    assert!(error.is_not_found());
+    assert_eq!(error.path(), Some("skills/missing"));
+
+    // Nothing is written when resolution fails
+    let temp_dir = TempDir::new().unwrap();
+    assert!(provider.download_skill_with_deps("a", temp_dir.path()).await.is_err());
+    assert!(!temp_dir.path().join("a").exists());
+}
+
+#[tokio::test]
+async fn test_language_provider_strips_bom() {
+    let mut source = MockContentSource::new();
+    source.add_file("locales/en.lang", "\u{FEFF}Hello".as_bytes());
+    source.add_file("locales/pt.lang", "Olá\u{FEFF}".as_bytes());
+    let resolver = Arc::new(ResourceResolver::new(vec![
+        Arc::new(source) as Arc<dyn ContentSource>
+    ]));
+
+    let provider = LanguageProvider::new(resolver.clone(), "locales".to_string());
+    assert_eq!(provider.fetch_language("en").await.unwrap(), "Hello");
+    // Only a leading BOM is a byte order mark
+    assert_eq!(provider.fetch_language("pt").await.unwrap(), "Olá\u{FEFF}");
+
+    let provider = LanguageProvider::new(resolver, "locales".to_string()).with_bom_stripping(false);
+    assert_eq!(provider.fetch_language("en").await.unwrap(), "\u{FEFF}Hello");
+}
+
+#[tokio::test]
+async fn test_language_provider_mixed_extensions() {
+    // Older locales are `.lang` files in one repository, newer ones `.json`
+    // in another, and `pt` was migrated but its old file kept
+    let mut legacy = MockContentSource::new();
+    legacy.add_file("locales/en.lang", b"Hello");
+    legacy.add_file("locales/pt.lang", b"Ola");
+    let mut current = MockContentSource::new();
+    current.add_file("locales/pt.json", "{\"hello\": \"Olá\"}".as_bytes());
+    current.add_file("locales/fr.json", b"{\"hello\": \"Bonjour\"}");
+    let resolver = Arc::new(ResourceResolver::new(vec![
+        Arc::new(legacy) as Arc<dyn ContentSource>,
+        Arc::new(current),
+    ]));
+
+    let provider = LanguageProvider::new(resolver.clone(), "locales".to_string())
+        .with_extensions(&["json", "lang"]);
+    assert_eq!(provider.fetch_language("en").await.unwrap(), "Hello");
+    assert_eq!(provider.fetch_language("pt").await.unwrap(), "{\"hello\": \"Olá\"}");
+    assert_eq!(
+        provider.fetch_with_fallback("fr-CA", "fr").await.unwrap(),
+        "{\"hello\": \"Bonjour\"}"
+    );
+
+    let error = provider.fetch_with_fallbacks(&["de", "es"]).await.unwrap_err();
+    assert!(error.is_not_found());
+    assert_eq!(error.path(), Some("locales/de.json"));
+    assert_eq!(
+        error.to_string(),
+        "Content not found: locales/de.json \
+         (also tried locales/de.lang, locales/es.json, locales/es.lang)"
+    );
+
+    // The default still reads `.lang` files only
+    let provider = LanguageProvider::new(resolver, "locales".to_string());
+    assert_eq!(provider.fetch_language("pt").await.unwrap(), "Ola");
+    assert!(provider.fetch_language("fr").await.unwrap_err().is_not_found());
+}
+
+#[tokio::test]
+async fn test_provider_errors_name_path() {
+    let provider = nested_skill_provider(SkillLayout::Detect { max_depth: 3 });
+    let error = provider.fetch_manifest("nlp/summarize").await.unwrap_err();
+    assert_eq!(error.path(), Some("skills/nlp/summarize/skill.json"));
+    assert!(error
+        .to_string()
+        .starts_with("skills/nlp/summarize/skill.json: Serialization error"));
+
+    let mut source = MockContentSource::new();
+    source.add_file("locales/xx.lang", &[0xff, 0xfe, 0x00]);
+    let resolver = Arc::new(ResourceResolver::new(vec![
+        Arc::new(source) as Arc<dyn ContentSource>
+    ]));
+    let provider = LanguageProvider::new(resolver, "locales".to_string());
+    let error = provider.fetch_language("xx").await.unwrap_err();
+    assert_eq!(error.path(), Some("locales/xx.lang"));
+    assert_eq!(error.kind(), ErrorKind::Validation);
+}
+
+#[tokio::test]
+async fn test_skill_download_skips_unmodelled_entries() {
+    let mut source = MockContentSource::new();
+    source.add_file("skills/tool/main.py", b"print('hi')");
+    source.add_directory(
+        "skills/tool",
+        vec![
+            DirectoryEntry::file("main.py", "skills/tool/main.py"),
+            DirectoryEntry::new("latest", "skills/tool/latest", EntryType::Symlink),
+            DirectoryEntry::new(
+                "model.bin",
+                "skills/tool/model.bin",
+                EntryType::Other("lfs-pointer".to_string()),
+            ),
+        ],
+    );
+    let resolver = Arc::new(ResourceResolver::new(vec![
+        Arc::new(source) as Arc<dyn ContentSource>
+    ]));
+    let provider = SkillProvider::new(resolver, "skills".to_string());
+
+    let temp_dir = TempDir::new().unwrap();
+    let result = provider.download_skill("tool", temp_dir.path()).await.unwrap();
+
+    assert_eq!(result.files_written, vec![temp_dir.path().join("main.py")]);
+    let skipped: Vec<_> = result
+        .skipped
+        .iter()
+        .map(|e| (e.name.as_str(), e.entry_type.as_str()))
+        .collect();
+    assert_eq!(skipped, vec![("latest", "symlink"), ("model.bin", "lfs-pointer")]);
+    assert!(!temp_dir.path().join("latest").exists());
+    assert!(!temp_dir.path().join("model.bin").exists());
+}
+
+#[tokio::test]
+async fn test_skill_download_failure_policy() {
+    // Two of the four listed files are gone by the time they're fetched
+    let provider = |policy: FailurePolicy| {
+        let mut source = MockContentSource::new();
+        source.add_directory(
+            "skills/tool",
+            vec![
+                DirectoryEntry::file("a.py", "skills/tool/a.py"),
+                DirectoryEntry::file("gone.py", "skills/tool/gone.py"),
+                DirectoryEntry::file("kept.py", "skills/tool/kept.py"),
+                DirectoryEntry::file("lost.py", "skills/tool/lost.py"),
+            ],
+        );
+        source.add_file("skills/tool/a.py", b"a");
+        source.add_file("skills/tool/kept.py", b"kept");
+        let resolver = Arc::new(ResourceResolver::new(vec![
+            Arc::new(source) as Arc<dyn ContentSource>
+        ]));
+        SkillProvider::new(resolver, "skills".to_string()).with_failure_policy(policy)
+    };
+
+    // The default stops at the first failure
+    let temp_dir = TempDir::new().unwrap();
+    let error = provider(FailurePolicy::default())
+        .download_skill("tool", temp_dir.path())
+        .await
+        .unwrap_err();
+    assert!(error.is_not_found());
+    assert_eq!(error.path(), Some("skills/tool/gone.py"));
+    assert!(!temp_dir.path().join("kept.py").exists());
+
+    let temp_dir = TempDir::new().unwrap();
+    let result = provider(FailurePolicy::SkipAndReport)
+        .download_skill("tool", temp_dir.path())
+        .await
+        .unwrap();
+    assert_eq!(result.files_written.len(), 2);
+    assert!(temp_dir.path().join("kept.py").exists());
+    let failed: Vec<_> = result.failed.iter().map(|(path, e)| (path.as_str(), e.kind())).collect();
+    assert_eq!(
+        failed,
+        vec![
+            ("skills/tool/gone.py", ErrorKind::NotFound),
+            ("skills/tool/lost.py", ErrorKind::NotFound),
+        ]
+    );
+
+    // AbortAfter(n) gives up on the nth failure
+    let temp_dir = TempDir::new().unwrap();
+    let error = provider(FailurePolicy::AbortAfter(2))
+        .download_skill("tool", temp_dir.path())
+        .await
+        .unwrap_err();
+    assert_eq!(error.path(), Some("skills/tool/lost.py"));
+    assert!(!temp_dir.path().join("kept.py").exists());
+
+    let result = provider(FailurePolicy::AbortAfter(3))
+        .download_skill("tool", TempDir::new().unwrap().path())
+        .await
+        .unwrap();
+    assert_eq!(result.failed.len(), 2);
+}
+
+#[tokio::test]
+async fn test_skill_download_rolls_back_on_failure() {
+    let mut source = MockContentSource::new();
+    source.add_directory(
+        "skills/tool",
+        vec![
+            DirectoryEntry::file("a.py", "skills/tool/a.py"),
+            DirectoryEntry::dir("config", "skills/tool/config"),
+            DirectoryEntry::file("z.py", "skills/tool/z.py"),
+        ],
+    );
+    source.add_directory(
+        "skills/tool/config",
+        vec![DirectoryEntry::file("settings.json", "skills/tool/config/settings.json")],
+    );
+    source.add_file("skills/tool/a.py", b"a");
+    source.add_file("skills/tool/config/settings.json", b"{}");
+    // z.py is listed but gone by the time it is fetched
+    let resolver = Arc::new(ResourceResolver::new(vec![
+        Arc::new(source) as Arc<dyn ContentSource>
+    ]));
+    let provider = SkillProvider::new(resolver, "skills".to_string());
+
+    let temp_dir = TempDir::new().unwrap();
+    let output_dir = temp_dir.path().join("installed/tool");
+    let error = provider.download_skill("tool", &output_dir).await.unwrap_err();
+    assert_eq!(error.path(), Some("skills/tool/z.py"));
+    assert!(!temp_dir.path().join("installed").exists());
+
+    // Only what the download added is removed, and what it overwrote is restored
+    std::fs::create_dir_all(output_dir.join("config")).unwrap();
+    std::fs::write(output_dir.join("local.txt"), "mine").unwrap();
+    std::fs::write(output_dir.join("a.py"), "installed a").unwrap();
+    std::fs::write(output_dir.join("config/settings.json"), "installed settings").unwrap();
+    provider.download_skill("tool", &output_dir).await.unwrap_err();
+    let mut left: Vec<_> = std::fs::read_dir(&output_dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    left.sort();
+    assert_eq!(left, ["a.py", "config", "local.txt"]);
+    assert_eq!(std::fs::read_to_string(output_dir.join("a.py")).unwrap(), "installed a");
+    assert_eq!(
+        std::fs::read_to_string(output_dir.join("config/settings.json")).unwrap(),
+        "installed settings"
+    );
+    assert_eq!(std::fs::read_dir(output_dir.join("config")).unwrap().count(), 1);
+}
+
+#[tokio::test]
+async fn test_skill_download_size_limit() {
+    // `a.py` is listed with its size, `b.py` only turns out big once fetched
+    let provider = |limit: u64| {
+        let mut source = MockContentSource::new();
+        source.add_directory(
+            "skills/tool",
+            vec![
+                DirectoryEntry::file("a.py", "skills/tool/a.py").with_metadata("size", 4),
+                DirectoryEntry::file("b.py", "skills/tool/b.py"),
+            ],
+        );
+        source.add_directory(
+            "skills/huge",
+            vec![DirectoryEntry::file("model.bin", "skills/huge/model.bin")
+                .with_metadata("size", 1 << 30)],
+        );
+        source.add_file("skills/tool/a.py", b"aaaa");
+        source.add_file("skills/tool/b.py", b"bbbbbbbb");
+        let resolver = Arc::new(ResourceResolver::new(vec![
+            Arc::new(source) as Arc<dyn ContentSource>
+        ]));
+        SkillProvider::new(resolver, "skills".to_string()).with_max_total_bytes(limit)
+    };
+
+    // Refused from the listing alone, before the file is fetched
+    let temp_dir = TempDir::new().unwrap();
+    let output_dir = temp_dir.path().join("huge");
+    let error = provider(10).download_skill("huge", &output_dir).await.unwrap_err();
+    match error {
+        ContentError::TooLarge { path, size, limit } => {
+            assert_eq!((path.as_str(), size, limit), ("skills/huge", Some(1 << 30), 10));
+        }
+        other => panic!("Expected TooLarge, got {:?}", other),
+    }
+    assert!(!output_dir.exists());
+
+    // Caught by the running total, with the files written so far removed
+    let temp_dir = TempDir::new().unwrap();
+    let error = provider(10).download_skill("tool", temp_dir.path()).await.unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::TooLarge);
+    assert_eq!(error.path(), Some("skills/tool"));
+    assert!(!temp_dir.path().join("a.py").exists());
+    assert!(!temp_dir.path().join("b.py").exists());
+
+    let result = provider(12).download_skill("tool", temp_dir.path()).await.unwrap();
+    assert_eq!(result.total_bytes, 12);
+}
+
+#[tokio::test]
+async fn test_skill_download_skips_non_utf8_names() {
+    let mut source = MockContentSource::new();
+    source.add_directory(
+        "skills/tool",
+        vec![
+            DirectoryEntry::file("caf\u{FFFD}.py", "skills/tool/caf\u{FFFD}.py")
+                .with_raw_name(&b"caf\xe9.py"[..]),
+            DirectoryEntry::file("main.py", "skills/tool/main.py"),
+        ],
+    );
+    source.add_file("skills/tool/main.py", b"main");
+    let resolver = Arc::new(ResourceResolver::new(vec![
+        Arc::new(source) as Arc<dyn ContentSource>
+    ]));
+    let provider = SkillProvider::new(resolver, "skills".to_string());
+
+    let temp_dir = TempDir::new().unwrap();
+    let result = provider.download_skill("tool", temp_dir.path()).await.unwrap();
+    assert_eq!(result.files_written, vec![temp_dir.path().join("main.py")]);
+    assert_eq!(result.skipped.len(), 1);
+    assert!(result.failed.is_empty());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_skill_download_applies_executable_bit() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut source = MockContentSource::new();
+    source.add_file("skills/tool/run.sh", b"#!/bin/sh\necho hi\n");
+    source.add_file("skills/tool/README.md", b"docs");
+    source.add_directory(
+        "skills/tool",
+        vec![
+            DirectoryEntry::file("run.sh", "skills/tool/run.sh").with_mode(0o100755),
+            DirectoryEntry::file("README.md", "skills/tool/README.md").with_mode(0o100644),
+        ],
+    );
+    let resolver = Arc::new(ResourceResolver::new(vec![
+        Arc::new(source) as Arc<dyn ContentSource>
+    ]));
+    let provider = SkillProvider::new(resolver, "skills".to_string());
+
+    let temp_dir = TempDir::new().unwrap();
+    provider.download_skill("tool", temp_dir.path()).await.unwrap();
+
+    let mode = |name: &str| {
+        std::fs::metadata(temp_dir.path().join(name))
+            .unwrap()
+            .permissions()
+            .mode()
+    };
+    assert_ne!(mode("run.sh") & 0o100, 0);
+    assert_eq!(mode("README.md") & 0o111, 0);
+}
+
+#[tokio::test]
+async fn test_retry_and_fallback_with_injected_faults() {
+    let primary = Arc::new(FaultInjectingSource::new(Arc::new(
+        MemorySource::new().with_file("config.json", "primary"),
+    )));
+    primary.set_latency(Duration::from_millis(5));
+    primary.set_fail_first(2, || ContentError::RateLimited {
+        message: "slow down".to_string(),
+        retry_after: Some(Duration::from_millis(1)),
+    });
+    let fallback = Arc::new(MemorySource::new().with_file("config.json", "fallback"));
+
+    let retrying = RetryingSource::new(
+        primary.clone(),
+        RetryConfig {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(1),
+            ..RetryConfig::default()
+        },
+    );
+    let resolver = ResourceResolver::new(vec![
+        Arc::new(retrying) as Arc<dyn ContentSource>,
+        fallback as Arc<dyn ContentSource>,
+    ]);
+
+    // Two rate-limited attempts are absorbed by the retry layer
+    let content = resolver.fetch_file("config.json").await.unwrap();
+    assert_eq!(content.content, bytes::Bytes::from("primary"));
+    assert_eq!(primary.call_count(), 3);
+
+    // Once the primary fails for good, the fallback serves the file
+    primary.set_fail_after(0, || ContentError::Cache {
+        message: "broken".to_string(),
+    });
+    let content = resolver.fetch_file("config.json").await.unwrap();
+    assert_eq!(content.content, bytes::Bytes::from("fallback"));
+}
+
+#[tokio::test]
+async fn test_list_all_files() {
+    let primary = mock_tree(&["docs/index.md", "docs/guide/intro.md"]);
+    let secondary = mock_tree(&["docs/guide/intro.md", "docs/api/client.md", "other/file.txt"]);
+
+    let resolver = ResourceResolver::new(vec![
+        Arc::new(primary) as Arc<dyn ContentSource>,
+        Arc::new(secondary) as Arc<dyn ContentSource>,
+    ]);
+
+    let files = resolver.list_all_files("docs").await.unwrap();
+    assert_eq!(
+        files,
+        vec!["docs/api/client.md", "docs/guide/intro.md", "docs/index.md"]
+    );
+
+    assert!(matches!(
+        resolver.list_all_files("missing").await,
+        Err(ContentError::NotFound { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_root_listing_forms_agree() {
+    let dir = TempDir::new().unwrap();
+    std::fs::create_dir_all(dir.path().join("greeter")).unwrap();
+    std::fs::write(dir.path().join("greeter/skill.json"), "{}").unwrap();
+    std::fs::write(dir.path().join("README.md"), "readme").unwrap();
+
+    let sources: Vec<Arc<dyn ContentSource>> = vec![
+        Arc::new(
+            MemorySource::new()
+                .with_file("greeter/skill.json", "{}")
+                .with_file("README.md", "readme"),
+        ),
+        Arc::new(content_resolver::LocalFileSource::new(dir.path())),
+    ];
+
+    for source in sources {
+        let resolver = Arc::new(ResourceResolver::new(vec![source.clone()]));
+        let mut listings = Vec::new();
+        for root in ["", "/", ".", "./", "//"] {
+            let listing = resolver.list_directory(root).await.unwrap();
+            assert_eq!(listing.path, "", "{}", source.identifier());
+            let entries: Vec<_> = listing
+                .entries
+                .iter()
+                .map(|e| (e.name.clone(), e.path.clone(), e.entry_type.clone()))
+                .collect();
+            for (_, path, _) in &entries {
+                assert!(!path.starts_with('/') && !path.starts_with("./"), "{}", path);
+            }
+            listings.push(entries);
+        }
+        assert!(listings.windows(2).all(|w| w[0] == w[1]), "{}", source.identifier());
+        assert_eq!(listings[0].len(), 2);
+
+        for base in ["", "/", "."] {
+            let provider = SkillProvider::new(resolver.clone(), base.to_string());
+            let skills = provider.list_skills().await.unwrap();
+            let ids: Vec<_> = skills.iter().map(|s| (s.id.as_str(), s.path.as_str())).collect();
+            assert_eq!(ids, vec![("greeter", "greeter")], "{:?}", base);
+        }
+    }
+}
+
+#[cfg(feature = "github")]
+#[tokio::test]
+async fn test_github_root_listing() {
+    let mut server = mockito::Server::new_async().await;
+    let _root = server
+        .mock("GET", "/repos/owner/repo/contents?ref=main")
+        .with_body(r#"[{"name": "docs", "path": "docs", "type": "dir"}]"#)
+        .create_async()
+        .await;
+    let _docs = server
+        .mock("GET", "/repos/owner/repo/contents/docs?ref=main")
+        .with_body(r#"[{"name": "a.md", "path": "docs/a.md", "type": "file"}]"#)
+        .create_async()
+        .await;
+
+    for (base, expected) in [("", "docs"), ("/docs/", "a.md"), ("./docs", "a.md")] {
+        let source = content_resolver::GitHubSource::new(
+            "owner".to_string(),
+            "repo".to_string(),
+            "main".to_string(),
+            base.to_string(),
+        )
+        .with_endpoints(server.url(), server.url());
+        let resolver = ResourceResolver::new(vec![Arc::new(source) as Arc<dyn ContentSource>]);
+
+        for root in ["", "/", "."] {
+            let listing = resolver.list_directory(root).await.unwrap();
+            let paths: Vec<_> = listing.entries.iter().map(|e| e.path.as_str()).collect();
+            assert_eq!(paths, vec![expected], "base {:?}, root {:?}", base, root);
+        }
+    }
+}
+
+#[cfg(feature = "github")]
+#[tokio::test]
+async fn test_validate_configuration_finds_bad_base_path() {
+    use content_resolver::{Severity, Subject, ValidationOptions};
+
+    let mut server = mockito::Server::new_async().await;
+    let _typo = server
+        .mock("GET", "/repos/owner/repo/contents/skils?ref=main")
+        .with_status(404)
+        .with_body(r#"{"message": "Not Found"}"#)
+        .create_async()
+        .await;
+    let _empty = server
+        .mock("GET", "/repos/owner/repo/contents/languages?ref=main")
+        .with_body("[]")
+        .create_async()
+        .await;
+    let github = |base: &str| {
+        let source = content_resolver::GitHubSource::new(
+            "owner".to_string(),
+            "repo".to_string(),
+            "main".to_string(),
+            base.to_string(),
+        )
+        .with_endpoints(server.url(), server.url());
+        Arc::new(source) as Arc<dyn ContentSource>
+    };
+    let resolver = ResourceResolver::with_cache(
+        vec![github("skils"), github("languages")],
+        Arc::new(MemoryCache::new()),
+    );
+
+    let report = resolver.validate_configuration().await.unwrap();
+    assert_eq!(report.findings.len(), 2, "{}", report);
+    assert_eq!(report.findings[0].severity, Severity::Fatal);
+    assert_eq!(report.findings[0].kind, Some(ErrorKind::NotFound));
+    assert!(matches!(&report.findings[0].subject, Subject::Source { index: 0, .. }));
+    assert_eq!(report.findings[1].severity, Severity::Warning);
+    assert!(matches!(&report.findings[1].subject, Subject::Source { index: 1, .. }));
+
+    let error = resolver
+        .validate_configuration_with(&ValidationOptions::default().with_fail_on_fatal(true))
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidConfig);
+    assert!(error.to_string().contains("the base path doesn't exist"));
+}
+
+/// `test_github_root_listing` recorded once, then replayed with the server gone
+#[cfg(feature = "github")]
+#[tokio::test]
+async fn test_github_root_listing_replay() {
+    use content_resolver::{RecordingSource, ReplaySource};
+
+    let dir = tempfile::tempdir().unwrap();
+    let cassette = dir.path().join("github_root_listing.json");
+    {
+        let mut server = mockito::Server::new_async().await;
+        let _root = server
+            .mock("GET", "/repos/owner/repo/contents?ref=main")
+            .with_body(r#"[{"name": "docs", "path": "docs", "type": "dir"}]"#)
+            .create_async()
+            .await;
+        let _missing = server
+            .mock("GET", "/repos/owner/repo/contents/gone?ref=main")
+            .with_status(404)
+            .create_async()
+            .await;
+        let github = content_resolver::GitHubSource::new(
+            "owner".to_string(),
+            "repo".to_string(),
+            "main".to_string(),
+            "".to_string(),
+        )
+        .with_token("ghp_recorded")
+        .with_endpoints(server.url(), server.url());
+        let recording = RecordingSource::wrap(Arc::new(github), &cassette);
+        let resolver = ResourceResolver::new(vec![Arc::new(recording) as Arc<dyn ContentSource>]);
+        resolver.list_directory("").await.unwrap();
+        assert!(resolver.list_directory("gone").await.unwrap_err().is_not_found());
+    }
+
+    let written = std::fs::read_to_string(&cassette).unwrap();
+    assert!(!written.contains("ghp_recorded"));
+    let replay = ReplaySource::open(&cassette).await.unwrap();
+    let resolver = ResourceResolver::new(vec![Arc::new(replay) as Arc<dyn ContentSource>]);
+    for root in ["", "/", "."] {
+        let listing = resolver.list_directory(root).await.unwrap();
+        let paths: Vec<_> = listing.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["docs"], "root {:?}", root);
+    }
+    assert!(resolver.list_directory("gone").await.unwrap_err().is_not_found());
+}
+
+#[cfg(feature = "github")]
+#[test]
+fn test_typed_source_lookup() {
+    use content_resolver::GitHubSource;
+
+    let github = GitHubSource::new(
+        "owner".to_string(),
+        "repo".to_string(),
+        "main".to_string(),
+        String::new(),
+    );
+    let github_id = github.identifier();
+    let retrying = RetryingSource::new(Arc::new(github), RetryConfig::default());
+    let resolver = ResourceResolver::new(vec![
+        Arc::new(MemorySource::new()) as Arc<dyn ContentSource>,
+        Arc::new(retrying),
+    ]);
+
+    // Through one wrapper layer to the concrete source
+    let retrying = resolver.sources_of::<RetryingSource>();
+    assert_eq!(retrying.len(), 1);
+    let github = retrying[0]
+        .inner()
+        .as_any()
+        .downcast_ref::<GitHubSource>()
+        .expect("RetryingSource wraps a GitHubSource");
+    assert_eq!(github.pinned_tag(), None);
+    assert_eq!(github.identifier(), github_id);
+
+    // Wrapped sources don't have the inner type
+    assert!(resolver.sources_of::<GitHubSource>().is_empty());
+    assert_eq!(resolver.sources_of::<MemorySource>().len(), 1);
+
+    let memory = resolver.source_by_id("memory").unwrap();
+    assert!(memory.as_any().is::<MemorySource>());
+    assert!(resolver.source_by_id(&github_id).is_none());
+    assert!(resolver.source_by_id(&format!("retry({})", github_id)).is_some());
+}
+
+#[test]
+fn test_downcast_through_arc() {
+    // With the trait in scope, method calls on the `Arc` still reach the source
+    use content_resolver::AsAny;
+
+    let source: Arc<dyn ContentSource> = Arc::new(MemorySource::new());
+    assert!(source.as_any().is::<MemorySource>());
+    assert!(AsAny::as_any(source.as_ref()).is::<MemorySource>());
+    assert!(source.clone().into_any_arc().downcast::<MemorySource>().is_ok());
+
+    let retrying = RetryingSource::new(source, RetryConfig::default());
+    assert!(retrying.inner().as_any().is::<MemorySource>());
+}
+
+#[tokio::test]
+async fn test_jsonl_audit_log() {
+    use content_resolver::{AuditEvent, AuditPurpose, FetchOptions, JsonlFileSink, RequestContext};
+
+    let dir = TempDir::new().unwrap();
+    let log = dir.path().join("audit.jsonl");
+    let source = MemorySource::new()
+        .with_file("docs/a.md", "alpha")
+        .with_file("docs/b.md", "bravo");
+    let resolver = ResourceResolver::with_cache(
+        vec![Arc::new(source) as Arc<dyn ContentSource>],
+        Arc::new(MemoryCache::new()),
+    );
+    let sink = Arc::new(JsonlFileSink::open(&log, resolver.tasks()).await.unwrap());
+    let resolver = resolver.with_audit_sink(sink.clone());
+
+    let mut ctx = RequestContext::new();
+    ctx.insert(AuditPurpose("backup".to_string()));
+    resolver
+        .fetch_file_with_ctx("docs/a.md", &FetchOptions::default(), &mut ctx)
+        .await
+        .unwrap();
+    resolver.fetch_file("docs/a.md").await.unwrap();
+    sink.flush().await.unwrap();
+    assert_eq!(std::fs::read_to_string(&log).unwrap().lines().count(), 1);
+
+    // Shutdown writes out what is still queued
+    resolver.fetch_file("docs/b.md").await.unwrap();
+    resolver.shutdown(Duration::from_secs(1)).await;
+    let events: Vec<AuditEvent> = std::fs::read_to_string(&log)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let summary: Vec<_> = events
+        .iter()
+        .map(|e| (e.path.as_str(), e.source.as_str(), e.size, e.purpose.as_deref()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("docs/a.md", "memory", 5, Some("backup")),
+            ("docs/b.md", "memory", 5, None),
+        ]
+    );
+    assert_eq!(events[0].sha256, content_resolver::sha256_hex_of("alpha"));
+    assert_eq!(sink.write_failures(), 0);
+
+    // The writer is gone, so further events are counted as failures
+    resolver.invalidate("docs/b.md").await.unwrap();
+    resolver.fetch_file("docs/b.md").await.unwrap();
+    assert_eq!(resolver.stats().audit_failures, 1);
+}
+
+#[tokio::test]
+async fn test_jsonl_audit_log_overflow() {
+    use content_resolver::JsonlFileSink;
+
+    let dir = TempDir::new().unwrap();
+    let log = dir.path().join("audit.jsonl");
+    let source = MemorySource::new()
+        .with_file("docs/a.md", "alpha")
+        .with_file("docs/b.md", "bravo");
+    let resolver = ResourceResolver::new(vec![Arc::new(source) as Arc<dyn ContentSource>]);
+    let sink = Arc::new(JsonlFileSink::open_with_capacity(&log, resolver.tasks(), 1).await.unwrap());
+    let resolver = resolver.with_audit_sink(sink.clone());
+
+    // The writer doesn't run until this task yields, so the second event finds the queue full
+    resolver.fetch_file("docs/a.md").await.unwrap();
+    resolver.fetch_file("docs/b.md").await.unwrap();
+    assert_eq!(sink.write_failures(), 1);
+    assert_eq!(resolver.stats().audit_failures, 1);
+
+    sink.flush().await.unwrap();
+    assert_eq!(std::fs::read_to_string(&log).unwrap().lines().count(), 1);
+}